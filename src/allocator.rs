@@ -7,7 +7,9 @@ use x86_64::{
 };
 
 const HEAP_START: usize = 0x_4444_4444_0000;
-const HEAP_SIZE: usize = 1024 * 1024; // 1 MiB
+/// Holds the scrollbacks of the consoles (up to about 2.2 MiB, see `SCROLLBACK_SIZE` in main.rs),
+/// their frame buffers (96 KiB) and the block cache (128 KiB by default), with room for the rest
+const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
 
 pub fn init(
     mapper: &mut impl Mapper<Size4KiB>,
@@ -0,0 +1,112 @@
+use core::arch::asm;
+use core::fmt::{Display, Formatter};
+
+use x86_64::VirtAddr;
+
+use crate::backtrace::symbol_table::SymbolTable;
+use crate::memory;
+
+pub mod symbol_table;
+
+const MAX_DEPTH: usize = 32;
+
+/// Walks the stack by the saved frame pointers, which are forced by the rustflags in `.cargo/config.toml`.
+/// Every frame starts with the frame pointer of the caller followed by the return address.
+#[derive(Clone)]
+pub struct Backtrace {
+    /// Instruction pointer reported before walking the frames, e.g. the one of the faulting instruction
+    instruction_pointer: Option<u64>,
+    frame_pointer: u64,
+    depth: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub address: u64,
+    /// Return addresses point after the call, so the previous byte is resolved
+    is_return_address: bool,
+}
+
+impl Backtrace {
+    /// Starts at the frame of the caller
+    #[inline(always)]
+    pub fn capture() -> Self {
+        Self::from_frame_pointer(read_frame_pointer())
+    }
+
+    /// Starts at the frame, e.g. of the code interrupted by an exception
+    pub fn from_frame_pointer(frame_pointer: u64) -> Self {
+        Self { instruction_pointer: None, frame_pointer, depth: 0 }
+    }
+
+    /// Called directly in an exception handler, starts at the interrupted instruction.
+    /// The frame of the handler holds the interrupt stack frame instead of a return address, so it's skipped.
+    #[inline(always)]
+    pub fn capture_exception(instruction_pointer: VirtAddr) -> Self {
+        let handler_frame_pointer = read_frame_pointer();
+        let frame_pointer = if is_readable(handler_frame_pointer, 8) {
+            unsafe { (handler_frame_pointer as *const u64).read() }
+        } else {
+            0
+        };
+        Self {
+            instruction_pointer: Some(instruction_pointer.as_u64()),
+            frame_pointer,
+            depth: 0,
+        }
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if let Some(instruction_pointer) = self.instruction_pointer.take() {
+            self.depth += 1;
+            return Some(Frame { address: instruction_pointer, is_return_address: false });
+        }
+        let frame_pointer = self.frame_pointer;
+        if self.depth == MAX_DEPTH || frame_pointer == 0 || !frame_pointer.is_multiple_of(8) || !is_readable(frame_pointer, 16) {
+            return None;
+        }
+        let (caller_frame_pointer, return_address) = unsafe {
+            let frame = frame_pointer as *const u64;
+            (frame.read(), frame.add(1).read())
+        };
+        // The stack grows down, so the frames of the callers are at higher addresses
+        if return_address == 0 || caller_frame_pointer <= frame_pointer {
+            self.frame_pointer = 0;
+        } else {
+            self.frame_pointer = caller_frame_pointer;
+        }
+        self.depth += 1;
+        if return_address == 0 {
+            return None;
+        }
+        Some(Frame { address: return_address, is_return_address: true })
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#018x}", self.address)?;
+        let address = if self.is_return_address { self.address - 1 } else { self.address };
+        if let Some(mut symbol) = SymbolTable::get_kernel().and_then(|table| table.resolve(address)) {
+            symbol.offset += self.address - address;
+            write!(f, " {}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+#[inline(always)]
+fn read_frame_pointer() -> u64 {
+    let frame_pointer: u64;
+    unsafe { asm!("mov {}, rbp", out(reg) frame_pointer, options(nomem, nostack, preserves_flags)) };
+    frame_pointer
+}
+
+fn is_readable(address: u64, length: u64) -> bool {
+    let is_mapped = |address: u64| VirtAddr::try_new(address).is_ok_and(memory::is_mapped);
+    is_mapped(address) && is_mapped(address + length - 1)
+}
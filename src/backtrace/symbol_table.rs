@@ -0,0 +1,121 @@
+use core::fmt::{Display, Formatter};
+
+/// Filled after the linking by `tools/embed_symbols.py`, the format is described there.
+/// The table is patched in place, so its size is fixed before the linking. A debug build has about
+/// 10 000 functions taking about 130 bytes each with their names, which leaves room for the kernel to grow.
+const SYMBOL_TABLE_SIZE: usize = 2 * 1024 * 1024;
+const MAGIC: &[u8; 8] = b"JOSSYMS1";
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 16;
+
+#[used]
+#[link_section = ".symbols"]
+static mut SYMBOL_TABLE: [u8; SYMBOL_TABLE_SIZE] = [0; SYMBOL_TABLE_SIZE];
+
+pub struct SymbolTable<'a> {
+    entries: &'a [u8],
+    names: &'a [u8],
+}
+
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    /// Offset of the address from the start of the function
+    pub offset: u64,
+}
+
+impl SymbolTable<'static> {
+    /// Returns `None` if the symbols weren't embedded, e.g. the kernel wasn't started by the cargo runner
+    pub fn get_kernel() -> Option<Self> {
+        // Read through a pointer, as the compiler can't know the content changes after the linking
+        let data = unsafe { &*core::ptr::addr_of!(SYMBOL_TABLE) };
+        Self::new(data)
+    }
+}
+
+impl<'a> SymbolTable<'a> {
+    pub fn new(data: &'a [u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || &data[0..8] != MAGIC {
+            return None;
+        }
+        let count = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        let names_offset = HEADER_SIZE.checked_add(count.checked_mul(ENTRY_SIZE)?)?;
+        if names_offset > data.len() {
+            return None;
+        }
+        Some(Self {
+            entries: &data[HEADER_SIZE..names_offset],
+            names: &data[names_offset..],
+        })
+    }
+
+    /// Finds the function containing the address
+    pub fn resolve(&self, address: u64) -> Option<Symbol<'a>> {
+        let count = self.entries.len() / ENTRY_SIZE;
+        // Index of the first entry after the address
+        let index = partition_point(count, |index| self.get_address(index) <= address);
+        let index = index.checked_sub(1)?;
+        let start = self.get_address(index);
+        let size = u32::from_le_bytes(self.get_entry(index)[8..12].try_into().unwrap()) as u64;
+        if size != 0 && address >= start + size {
+            return None;
+        }
+        let name_offset = u32::from_le_bytes(self.get_entry(index)[12..16].try_into().unwrap()) as usize;
+        let name_length = *self.names.get(name_offset)? as usize;
+        let name = self.names.get(name_offset + 1..name_offset + 1 + name_length)?;
+        Some(Symbol {
+            name: core::str::from_utf8(name).ok()?,
+            offset: address - start,
+        })
+    }
+
+    fn get_entry(&self, index: usize) -> &'a [u8] {
+        &self.entries[index * ENTRY_SIZE..(index + 1) * ENTRY_SIZE]
+    }
+
+    fn get_address(&self, index: usize) -> u64 {
+        u64::from_le_bytes(self.get_entry(index)[0..8].try_into().unwrap())
+    }
+}
+
+fn partition_point(count: usize, predicate: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if predicate(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+impl Display for Symbol<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+#[test_case]
+fn test_symbol_table_resolve() {
+    let mut data = [0u8; HEADER_SIZE + 2 * ENTRY_SIZE + 12];
+    data[0..8].copy_from_slice(MAGIC);
+    data[8..12].copy_from_slice(&2u32.to_le_bytes());
+    let entries: [(u64, u32, u32); 2] = [(0x1000, 0x20, 0), (0x1040, 0x10, 5)];
+    for (index, (address, size, name_offset)) in entries.iter().enumerate() {
+        let entry = &mut data[HEADER_SIZE + index * ENTRY_SIZE..][..ENTRY_SIZE];
+        entry[0..8].copy_from_slice(&address.to_le_bytes());
+        entry[8..12].copy_from_slice(&size.to_le_bytes());
+        entry[12..16].copy_from_slice(&name_offset.to_le_bytes());
+    }
+    data[HEADER_SIZE + 2 * ENTRY_SIZE..].copy_from_slice(b"\x04main\x06kernel");
+
+    let table = SymbolTable::new(&data).unwrap();
+    let symbol = table.resolve(0x1004).unwrap();
+    assert_eq!((symbol.name, symbol.offset), ("main", 4));
+    assert_eq!(table.resolve(0x1040).unwrap().name, "kernel");
+    assert!(table.resolve(0x0fff).is_none());
+    assert!(table.resolve(0x1020).is_none());
+    assert!(table.resolve(0x1050).is_none());
+    assert!(SymbolTable::new(&[0u8; 16]).is_none());
+}
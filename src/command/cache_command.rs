@@ -0,0 +1,34 @@
+use crate::command::command::Command;
+use crate::println;
+use crate::storage::block_cache::{self, BLOCK_CACHE};
+
+pub fn cache_command(command: Command) {
+    let arguments: alloc::vec::Vec<&str> = command.arguments.iter().map(|argument| argument.as_str()).collect();
+    match arguments.as_slice() {
+        [] => {
+            let statistics = BLOCK_CACHE.lock().get_statistics();
+            println!("Blocks:      {} ({} dirty)", statistics.blocks_count, statistics.dirty_count);
+            println!("Memory:      {} / {} KiB", statistics.used_bytes / 1024, statistics.budget / 1024);
+            println!("Hits:        {}", statistics.hits);
+            println!("Misses:      {}", statistics.misses);
+            println!("Write-backs: {}", statistics.write_backs);
+        }
+        ["sync"] => {
+            if let Err(error) = block_cache::write_back_all() {
+                println!("cache: {}", error);
+            }
+        }
+        ["budget", kibibytes] => match kibibytes.parse::<usize>() {
+            Ok(kibibytes) => {
+                let write_backs = BLOCK_CACHE.lock().set_budget(kibibytes * 1024);
+                for write_back in write_backs {
+                    if let Err(error) = write_back.write() {
+                        println!("cache: {}", error);
+                    }
+                }
+            }
+            Err(_) => println!("cache: invalid budget: {}", kibibytes),
+        },
+        _ => println!("Usage: cache [sync | budget <KiB>]"),
+    }
+}
@@ -0,0 +1,17 @@
+use alloc::string::String;
+
+use crate::command::command::Command;
+use crate::fs::with_file_system;
+use crate::println;
+
+pub fn cat_command(command: Command) {
+    if command.arguments.is_empty() {
+        println!("Usage: cat <path>...");
+    }
+    for path in &command.arguments {
+        match with_file_system(path, |file_system, path| file_system.read_file(path)) {
+            Ok(content) => println!("{}", String::from_utf8_lossy(&content)),
+            Err(error) => println!("cat: {}: {}", path, error),
+        }
+    }
+}
@@ -14,16 +14,22 @@ impl Command {
             return None;
         }
 
-        let mut chunks: Vec<&str> = text.split(' ').collect();
-        return if let Some(command) = chunks.pop() {
-            Some(
-                Self {
-                    command: String::from(command),
-                    arguments: chunks.iter().map(|&arg| arg.into()).collect(),
-                }
-            )
-        } else {
-            None
-        }
+        let mut chunks = text.split(' ').filter(|chunk| !chunk.is_empty());
+        let command = chunks.next()?;
+        Some(
+            Self {
+                command: String::from(command),
+                arguments: chunks.map(|argument| argument.into()).collect(),
+            }
+        )
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_parse_command_with_arguments() {
+    let command = Command::parse(String::from("  mount  vda1 /mnt ")).unwrap();
+    assert_eq!(command.command, "mount");
+    assert_eq!(command.arguments, ["vda1", "/mnt"]);
+
+    assert!(Command::parse(String::from("   ")).is_none());
+}
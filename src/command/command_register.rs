@@ -1,12 +1,19 @@
 use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::command::command::Command;
+use crate::command::completion::{Completer, Completion, complete_words};
 use crate::println;
 
+struct RegisteredCommand {
+    handler: Box<dyn Fn(Command)>,
+    completer: Option<Completer>,
+}
+
 pub struct CommandRegister {
-    commands: BTreeMap<String, Box<dyn Fn(Command)>>
+    commands: BTreeMap<String, RegisteredCommand>
 }
 
 impl CommandRegister {
@@ -17,14 +24,62 @@ impl CommandRegister {
     }
 
     pub fn register(&mut self, command: &str, handler: Box<dyn Fn(Command)>) {
-        self.commands.insert(command.into(), handler);
+        self.commands.insert(command.into(), RegisteredCommand { handler, completer: None });
+    }
+
+    /// Registers the command with the completion of its arguments
+    pub fn register_with_completer(&mut self, command: &str, handler: Box<dyn Fn(Command)>, completer: Completer) {
+        self.commands.insert(command.into(), RegisteredCommand { handler, completer: Some(completer) });
     }
 
     pub fn perform(&self, command: Command) {
-        if let Some(handler) = self.commands.get(&*command.command) {
-            handler(command);
+        if let Some(registered_command) = self.commands.get(&*command.command) {
+            (registered_command.handler)(command);
         } else {
             println!("Not found command: {}", command.command);
         }
     }
+
+    /// Completes the last word of the line, the command name or one of its arguments
+    pub fn complete(&self, line: &str) -> Completion {
+        let word_start = line.rfind(' ').map_or(0, |index| index + 1);
+        let word = &line[word_start..];
+        let words: Vec<&str> = line[..word_start].split(' ')
+            .filter(|word| !word.is_empty())
+            .collect();
+        let candidates = match words.split_first() {
+            None => complete_words(self.commands.keys().map(String::as_str), word),
+            Some((command, arguments)) => match self.commands.get(*command) {
+                Some(RegisteredCommand { completer: Some(completer), .. }) => completer(arguments, word).into_iter()
+                    .filter(|candidate| candidate.starts_with(word))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        };
+        Completion {
+            word_start: line[..word_start].chars().count(),
+            candidates,
+        }
+    }
+}
+
+#[test_case]
+fn test_complete_command_and_arguments() {
+    let mut command_register = CommandRegister::new();
+    command_register.register("mount", Box::new(|_| {}));
+    command_register.register("mkdir", Box::new(|_| {}));
+    command_register.register_with_completer("log", Box::new(|_| {}), Box::new(|arguments, word| match arguments {
+        [] => complete_words(["listener", "module"], word),
+        _ => Vec::new(),
+    }));
+
+    let completion = command_register.complete("m");
+    assert_eq!(completion.word_start, 0);
+    assert_eq!(completion.candidates, ["mkdir", "mount"]);
+
+    let completion = command_register.complete("log  mo");
+    assert_eq!(completion.word_start, 5);
+    assert_eq!(completion.candidates, ["module"]);
+
+    assert!(command_register.complete("mount vd").candidates.is_empty());
 }
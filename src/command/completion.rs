@@ -0,0 +1,86 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::command::ls_command::mount_points_under;
+use crate::fs::file_system::FileKind;
+use crate::fs::{path, with_file_system};
+
+/// Returns the candidates for the word being typed, the arguments before it are passed first
+pub type Completer = Box<dyn Fn(&[&str], &str) -> Vec<String>>;
+
+/// Candidates for the word before the cursor
+pub struct Completion {
+    /// Index of the first character of the word in the line
+    pub word_start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// Candidates from the fixed words
+pub fn complete_words<'a>(words: impl IntoIterator<Item=&'a str>, word: &str) -> Vec<String> {
+    words.into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(String::from)
+        .collect()
+}
+
+/// Completer of the commands taking only paths
+pub fn complete_path_argument(_arguments: &[&str], word: &str) -> Vec<String> {
+    complete_path(word)
+}
+
+/// Candidates from the entries of the directory of the word, directories end with a slash
+pub fn complete_path(word: &str) -> Vec<String> {
+    let (directory, name) = match word.rfind('/') {
+        Some(index) => word.split_at(index + 1),
+        None => return complete_words(["/"], word),
+    };
+    let entries = with_file_system(directory, |file_system, path| file_system.read_dir(path));
+    let mut names: Vec<String> = match entries {
+        Ok(entries) => entries.into_iter()
+            .filter(|entry| entry.name != "." && entry.name != "..")
+            .map(|entry| match entry.kind {
+                FileKind::Directory => format!("{}/", entry.name),
+                _ => entry.name,
+            })
+            .collect(),
+        Err(_) => mount_points_under(&path::normalize(directory)).into_iter()
+            .map(|name| format!("{}/", name))
+            .collect(),
+    };
+    names.sort();
+    names.into_iter()
+        .filter(|candidate| candidate.starts_with(name))
+        .map(|candidate| format!("{}{}", directory, candidate))
+        .collect()
+}
+
+/// The longest text all the candidates start with
+pub fn get_common_prefix(candidates: &[String]) -> &str {
+    let first = match candidates.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+    let mut length = first.len();
+    for candidate in &candidates[1..] {
+        length = first.char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, first_character), character)| first_character == character)
+            .map(|((index, first_character), _)| index + first_character.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(length);
+    }
+    &first[..length]
+}
+
+#[test_case]
+fn test_get_common_prefix() {
+    let candidates = [String::from("mount"), String::from("mkdir"), String::from("mv")];
+    assert_eq!(get_common_prefix(&candidates), "m");
+    let candidates = [String::from("/mnt/disk/"), String::from("/mnt/data.txt")];
+    assert_eq!(get_common_prefix(&candidates), "/mnt/d");
+    assert_eq!(get_common_prefix(&[String::from("ls")]), "ls");
+    assert_eq!(get_common_prefix(&[]), "");
+}
@@ -0,0 +1,96 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use futures_util::future::poll_fn;
+
+use crate::command::command::Command;
+use crate::command::completion::complete_words;
+use crate::log::{Level, LOG_BUFFER};
+use crate::println;
+use crate::task::executor;
+
+static FOLLOWING: AtomicBool = AtomicBool::new(false);
+
+pub fn dmesg_command(command: Command) {
+    let mut minimum_level = Level::DEBUG;
+    let mut follow = false;
+    let mut arguments = command.arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--level" => match arguments.next().and_then(|level| Level::parse(level)) {
+                Some(level) => minimum_level = level,
+                None => {
+                    println!("dmesg: invalid level");
+                    return;
+                }
+            },
+            "--follow" => follow = true,
+            "--stop" => {
+                FOLLOWING.store(false, Ordering::Relaxed);
+                LOG_BUFFER.wake();
+                return;
+            }
+            "--clear" => {
+                LOG_BUFFER.clear();
+                return;
+            }
+            _ => {
+                println!("Usage: dmesg [--level <debug|info|warning|error>] [--follow]");
+                println!("       dmesg --stop | --clear");
+                return;
+            }
+        }
+    }
+
+    let next_index = print_logs(LOG_BUFFER.get_first_index(), minimum_level);
+    if follow {
+        if FOLLOWING.swap(true, Ordering::Relaxed) {
+            println!("dmesg: already following, stop with dmesg --stop");
+            return;
+        }
+        if let Err(error) = executor::spawn(follow_logs(next_index, minimum_level)) {
+            FOLLOWING.store(false, Ordering::Relaxed);
+            println!("dmesg: {}", error);
+        }
+    }
+}
+
+pub fn dmesg_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    match arguments.last() {
+        Some(&"--level") => complete_words(Level::NAMES, word),
+        _ => complete_words(["--level", "--follow", "--stop", "--clear"], word),
+    }
+}
+
+/// Prints the logs from the index to the newest one, returns the index after the last printed log
+fn print_logs(first_index: u64, minimum_level: Level) -> u64 {
+    let next_index = LOG_BUFFER.get_next_index();
+    for index in first_index.max(LOG_BUFFER.get_first_index())..next_index {
+        match LOG_BUFFER.get(index) {
+            Some(log) if log.level >= minimum_level => println!("{}", log),
+            _ => {}
+        }
+    }
+    next_index
+}
+
+async fn follow_logs(mut next_index: u64, minimum_level: Level) {
+    loop {
+        let is_following = poll_fn(|context| {
+            LOG_BUFFER.register_waker(context.waker());
+            if !FOLLOWING.load(Ordering::Relaxed) {
+                Poll::Ready(false)
+            } else if LOG_BUFFER.get_next_index() > next_index {
+                Poll::Ready(true)
+            } else {
+                Poll::Pending
+            }
+        }).await;
+        if !is_following {
+            return;
+        }
+        next_index = print_logs(next_index, minimum_level);
+    }
+}
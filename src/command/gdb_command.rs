@@ -0,0 +1,13 @@
+use crate::command::command::Command;
+use crate::println;
+use crate::serial::{self, COM_PORTS, SerialChannel};
+
+pub fn gdb_command(_command: Command) {
+    match serial::get_binding(SerialChannel::Gdb) {
+        Some(index) => {
+            println!("Waiting for GDB on {}...", COM_PORTS[index].name);
+            x86_64::instructions::interrupts::int3();
+        }
+        None => println!("gdb: no port bound to the GDB channel, use e.g. serial bind gdb COM2"),
+    }
+}
@@ -0,0 +1,78 @@
+use alloc::vec;
+
+use crate::command::command::Command;
+use crate::net::NETWORK_STACK;
+use crate::net::ipv4::Ipv4Address;
+use crate::net::stack::Ipv4Config;
+use crate::println;
+
+pub fn ifconfig_command(command: Command) {
+    match command.arguments.as_slice() {
+        [] => print_interfaces(),
+        [name, address] => configure(name, address, None),
+        [name, address, gateway] => configure(name, address, Some(gateway)),
+        _ => println!("Usage: ifconfig [<device> <address>/<prefix length> [gateway]]"),
+    }
+}
+
+fn print_interfaces() {
+    let stack = NETWORK_STACK.lock();
+    for interface in stack.get_interfaces() {
+        let device = interface.device.lock();
+        println!("{}: {}", interface.name, device.get_description());
+        println!("    ether {}, link {}", interface.mac_address, if device.is_link_up() { "up" } else { "down" });
+        if let Some(config) = &interface.config {
+            println!("    inet {}/{}, broadcast {}", config.address, config.netmask.get_prefix_length(), config.get_broadcast_address());
+            if let Some(gateway) = config.gateway {
+                println!("    gateway {}", gateway);
+            }
+            for dns_server in &config.dns_servers {
+                println!("    dns {}", dns_server);
+            }
+        }
+    }
+}
+
+fn configure(name: &str, address: &str, gateway: Option<&str>) {
+    let (address, prefix_length) = match address.split_once('/') {
+        Some((address, prefix_length)) => (address, prefix_length),
+        None => (address, "24"),
+    };
+    let address = match Ipv4Address::parse(address) {
+        Some(address) => address,
+        None => {
+            println!("ifconfig: invalid IPv4 address: {}", address);
+            return;
+        }
+    };
+    let prefix_length = match prefix_length.parse::<u8>() {
+        Ok(prefix_length) if prefix_length <= 32 => prefix_length,
+        _ => {
+            println!("ifconfig: invalid prefix length: {}", prefix_length);
+            return;
+        }
+    };
+    let gateway = match gateway.map(|gateway| (gateway, Ipv4Address::parse(gateway))) {
+        None => None,
+        Some((_, Some(gateway))) => Some(gateway),
+        Some((gateway, None)) => {
+            println!("ifconfig: invalid gateway address: {}", gateway);
+            return;
+        }
+    };
+
+    let mut stack = NETWORK_STACK.lock();
+    let index = match stack.find_interface(name) {
+        Some(index) => index,
+        None => {
+            println!("ifconfig: {}: no such device", name);
+            return;
+        }
+    };
+    stack.set_config(index, Some(Ipv4Config {
+        address,
+        netmask: Ipv4Address::netmask(prefix_length),
+        gateway,
+        dns_servers: vec![],
+    }));
+}
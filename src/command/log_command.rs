@@ -0,0 +1,70 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::command::command::Command;
+use crate::command::completion::complete_words;
+use crate::log::{KERNEL_LOGGER, Level};
+use crate::println;
+
+pub fn log_command(command: Command) {
+    let arguments: Vec<&str> = command.arguments.iter().map(|argument| argument.as_str()).collect();
+    match arguments.as_slice() {
+        [] => print_levels(),
+        ["listener", name, level] => match Level::parse(level) {
+            Some(level) => {
+                if !without_interrupts(|| KERNEL_LOGGER.lock().set_listener_level(name, level)) {
+                    println!("log: {}: no such listener", name);
+                }
+            }
+            None => println!("log: invalid level: {}", level),
+        },
+        ["module", module, "default"] => without_interrupts(|| KERNEL_LOGGER.lock().set_module_level(module, None)),
+        ["module", module, level] => match Level::parse(level) {
+            Some(level) => without_interrupts(|| KERNEL_LOGGER.lock().set_module_level(module, Some(level))),
+            None => println!("log: invalid level: {}", level),
+        },
+        _ => {
+            println!("Usage: log [listener <name> <level>]");
+            println!("       log [module <module path> <level|default>]");
+            println!("Levels: debug, info, warning, error");
+        }
+    }
+}
+
+/// The levels are copied first, printing could log and wait for the logger lock
+fn print_levels() {
+    let (listeners, module_levels) = without_interrupts(|| {
+        let logger = KERNEL_LOGGER.lock();
+        let listeners: Vec<(String, Level)> = logger.get_listeners().map(|(name, level)| (String::from(name), level)).collect();
+        (listeners, logger.get_module_levels().to_vec())
+    });
+    for (name, level) in listeners {
+        println!("listener {}: {:?}", name, level);
+    }
+    for (module, level) in module_levels {
+        println!("module {}: {:?}", module, level);
+    }
+}
+
+pub fn log_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    match arguments {
+        [] => complete_words(["listener", "module"], word),
+        ["listener"] => {
+            let names: Vec<String> = without_interrupts(|| {
+                KERNEL_LOGGER.lock().get_listeners().map(|(name, _)| String::from(name)).collect()
+            });
+            complete_words(names.iter().map(String::as_str), word)
+        }
+        ["module"] => {
+            let modules: Vec<String> = without_interrupts(|| {
+                KERNEL_LOGGER.lock().get_module_levels().iter().map(|(module, _)| module.clone()).collect()
+            });
+            complete_words(modules.iter().map(String::as_str), word)
+        }
+        ["listener", _] => complete_words(Level::NAMES, word),
+        ["module", _] => complete_words(Level::NAMES.into_iter().chain(["default"]), word),
+        _ => Vec::new(),
+    }
+}
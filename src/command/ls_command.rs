@@ -0,0 +1,59 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::command::command::Command;
+use crate::fs::{MOUNTS, path, with_file_system};
+use crate::fs::file_system::FileKind;
+use crate::println;
+
+pub fn ls_command(command: Command) {
+    let path = path::normalize(command.arguments.first().map(String::as_str).unwrap_or("/"));
+    let entries = with_file_system(&path, |file_system, path| {
+        let stat = file_system.stat(path)?;
+        let (directory, entries) = match stat.kind {
+            FileKind::Directory => (path, file_system.read_dir(path)?),
+            _ => (path::split_parent(path).0, alloc::vec![stat]),
+        };
+        Ok(entries.into_iter()
+            .map(|entry| {
+                let target = match entry.kind {
+                    FileKind::Symlink => file_system.read_link(&format!("{}/{}", directory, entry.name)).ok(),
+                    _ => None,
+                };
+                (entry, target)
+            })
+            .collect::<Vec<_>>())
+    });
+    match entries {
+        Ok(entries) => {
+            for (entry, target) in entries {
+                match entry.kind {
+                    FileKind::Directory => println!("{:>10} {}/", "", entry.name),
+                    FileKind::Symlink => println!("{:>10} {} -> {}", "", entry.name, target.unwrap_or_default()),
+                    FileKind::File => println!("{:>10} {}", entry.size, entry.name),
+                }
+            }
+        }
+        Err(error) => {
+            let mount_points = mount_points_under(&path);
+            if mount_points.is_empty() {
+                println!("ls: {}: {}", path, error);
+            }
+            for name in mount_points {
+                println!("{:>10} {}/", "", name);
+            }
+        }
+    }
+}
+
+/// Lists directories leading to mount points, so that e.g. `/` shows `mnt/` when only `/mnt/disk` is mounted
+pub fn mount_points_under(path: &str) -> Vec<String> {
+    let mut names: Vec<String> = MOUNTS.lock().iter()
+        .filter_map(|mount| path::strip_mount_point(&mount.mount_point, path))
+        .filter_map(|remainder| path::components(remainder).next())
+        .map(String::from)
+        .collect();
+    names.dedup();
+    names
+}
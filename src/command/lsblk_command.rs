@@ -0,0 +1,30 @@
+use alloc::format;
+use alloc::string::String;
+
+use crate::command::command::Command;
+use crate::println;
+use crate::storage::BLOCK_DEVICES;
+
+pub fn lsblk_command(_command: Command) {
+    println!("{:<10} {:>10} {}", "NAME", "SIZE", "TYPE");
+    for (name, device) in BLOCK_DEVICES.lock().iter() {
+        let device = device.lock();
+        println!("{:<10} {:>10} {}", name, format_size(device.get_size()), device.get_description());
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
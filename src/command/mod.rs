@@ -1,4 +1,23 @@
 pub mod command;
 pub mod command_register;
+pub mod completion;
 pub mod ping_pong_command;
 pub mod cpuid_command;
+pub mod lsblk_command;
+pub mod mount_command;
+pub mod ls_command;
+pub mod cat_command;
+pub mod write_command;
+pub mod mkdir_command;
+pub mod rm_command;
+pub mod cache_command;
+pub mod ifconfig_command;
+pub mod netsend_command;
+pub mod ping_command;
+pub mod tcpsend_command;
+pub mod tcplisten_command;
+pub mod udpsend_command;
+pub mod serial_command;
+pub mod gdb_command;
+pub mod log_command;
+pub mod dmesg_command;
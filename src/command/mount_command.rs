@@ -0,0 +1,53 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::command::command::Command;
+use crate::command::completion::{complete_path, complete_words};
+use crate::fs;
+use crate::fs::MOUNTS;
+use crate::println;
+use crate::storage::BLOCK_DEVICES;
+
+pub fn mount_command(command: Command) {
+    match command.arguments.as_slice() {
+        [] => {
+            for mount in MOUNTS.lock().iter() {
+                let file_system = &mount.file_system;
+                println!("{} on {} type {}{}", mount.device_name, mount.mount_point, file_system.get_type_name(),
+                    file_system.get_label().map(|label| alloc::format!(" [{}]", label)).unwrap_or_default());
+            }
+        }
+        [device_name, mount_point] => {
+            if let Err(error) = fs::mount_device(device_name, mount_point) {
+                println!("mount: {}: {}", device_name, error);
+            }
+        }
+        _ => println!("Usage: mount [<device> <path>]"),
+    }
+}
+
+pub fn mount_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    match arguments {
+        [] => complete_words(BLOCK_DEVICES.lock().iter().map(|(name, _)| name.as_str()), word),
+        [_] => complete_path(word),
+        _ => Vec::new(),
+    }
+}
+
+pub fn umount_command(command: Command) {
+    match command.arguments.as_slice() {
+        [mount_point] => {
+            if let Err(error) = MOUNTS.lock().unmount(mount_point) {
+                println!("umount: {}: {}", mount_point, error);
+            }
+        }
+        _ => println!("Usage: umount <path>"),
+    }
+}
+
+pub fn umount_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    match arguments {
+        [] => complete_words(MOUNTS.lock().iter().map(|mount| mount.mount_point.as_str()), word),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,49 @@
+use crate::command::command::Command;
+use crate::net::NETWORK_DEVICES;
+use crate::net::ethernet::EthernetHeader;
+use crate::net::network_device::MacAddress;
+use crate::println;
+
+/// IEEE 802 local experimental EtherType
+const ETHER_TYPE_EXPERIMENTAL: u16 = 0x88B5;
+
+pub fn netsend_command(command: Command) {
+    let (device_name, destination, words) = match command.arguments.as_slice() {
+        [device_name, destination, words @ ..] => (device_name, destination, words),
+        _ => {
+            println!("Usage: netsend <device> <MAC address | broadcast> <text>...");
+            return;
+        }
+    };
+
+    let device = match NETWORK_DEVICES.lock().get(device_name) {
+        Some(device) => device,
+        None => {
+            println!("netsend: {}: no such device", device_name);
+            return;
+        }
+    };
+    let destination = match destination.as_str() {
+        "broadcast" => MacAddress::BROADCAST,
+        text => match MacAddress::parse(text) {
+            Some(address) => address,
+            None => {
+                println!("netsend: invalid MAC address: {}", text);
+                return;
+            }
+        },
+    };
+
+    let mut device = device.lock();
+    let header = EthernetHeader {
+        destination,
+        source: device.get_mac_address(),
+        ether_type: ETHER_TYPE_EXPERIMENTAL,
+    };
+    let frame = header.build_frame(words.join(" ").as_bytes());
+
+    match device.send(&frame) {
+        Ok(()) => println!("Sent {} bytes to {}", frame.len(), destination),
+        Err(error) => println!("netsend: {}", error),
+    }
+}
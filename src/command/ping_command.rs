@@ -0,0 +1,55 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::command::command::Command;
+use crate::net::icmp;
+use crate::net::ipv4::Ipv4Address;
+use crate::net::stack::NetError;
+use crate::println;
+use crate::task::{executor, timer};
+
+const PING_COUNT: u16 = 4;
+const PING_TIMEOUT_MS: u64 = 2000;
+const PING_INTERVAL_MS: u64 = 1000;
+const PING_DATA: &[u8] = b"just-os ping";
+
+pub fn ping_command(command: Command) {
+    let destination = match command.arguments.as_slice() {
+        [address] => match Ipv4Address::parse(address) {
+            Some(address) => address,
+            None => {
+                println!("ping: invalid IPv4 address: {}", address);
+                return;
+            }
+        },
+        _ => {
+            println!("Usage: ping <IPv4 address>");
+            return;
+        }
+    };
+
+    static NEXT_IDENTIFIER: AtomicU16 = AtomicU16::new(1);
+    let identifier = NEXT_IDENTIFIER.fetch_add(1, Ordering::Relaxed);
+    let spawned = executor::spawn(async move {
+        let mut received = 0;
+        for sequence in 0..PING_COUNT {
+            if sequence > 0 {
+                timer::sleep(PING_INTERVAL_MS).await;
+            }
+            match icmp::ping(destination, identifier, sequence, PING_DATA, PING_TIMEOUT_MS).await {
+                Ok(time_ms) => {
+                    received += 1;
+                    println!("Reply from {}: seq={} time={} ms", destination, sequence, time_ms);
+                }
+                Err(NetError::TimedOut) => println!("Request timed out: seq={}", sequence),
+                Err(error) => {
+                    println!("ping: {}", error);
+                    return;
+                }
+            }
+        }
+        println!("{} packets transmitted, {} received", PING_COUNT, received);
+    });
+    if let Err(error) = spawned {
+        println!("ping: {}", error);
+    }
+}
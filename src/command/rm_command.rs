@@ -0,0 +1,14 @@
+use crate::command::command::Command;
+use crate::fs::with_file_system;
+use crate::println;
+
+pub fn rm_command(command: Command) {
+    if command.arguments.is_empty() {
+        println!("Usage: rm <path>...");
+    }
+    for path in &command.arguments {
+        if let Err(error) = with_file_system(path, |file_system, path| file_system.remove(path)) {
+            println!("rm: {}: {}", path, error);
+        }
+    }
+}
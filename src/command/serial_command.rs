@@ -0,0 +1,95 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::command::command::Command;
+use crate::command::completion::complete_words;
+use crate::println;
+use crate::serial::{self, COM_PORTS, SerialChannel};
+use crate::serial::serial_config::SerialConfig;
+
+pub fn serial_command(command: Command) {
+    let arguments: Vec<&str> = command.arguments.iter().map(|argument| argument.as_str()).collect();
+    match arguments.as_slice() {
+        [] => print_ports(),
+        ["bind", channel, port] => bind(channel, port),
+        [port, baud_rate] => configure(port, baud_rate, None),
+        [port, baud_rate, frame_format] => configure(port, baud_rate, Some(frame_format)),
+        _ => {
+            println!("Usage: serial [<port> <baud rate> [<data bits><parity><stop bits>]]");
+            println!("       serial bind <log|shell|debug|gdb> <port|none>");
+        }
+    }
+}
+
+fn print_ports() {
+    for (index, com_port) in COM_PORTS.iter().enumerate() {
+        let config = match serial::get_port_config(index) {
+            Some(config) => config,
+            None => {
+                println!("{}: not present", com_port.name);
+                continue;
+            }
+        };
+        let channels: Vec<&str> = SerialChannel::ALL.iter()
+            .filter(|channel| serial::get_binding(**channel) == Some(index))
+            .map(|channel| channel.get_name())
+            .collect();
+        println!("{}: {:#x}, IRQ {}, {}", com_port.name, com_port.base, com_port.irq_line, config);
+        if !channels.is_empty() {
+            println!("    channels: {}", channels.join(", "));
+        }
+    }
+}
+
+fn configure(port: &str, baud_rate: &str, frame_format: Option<&str>) {
+    let index = match serial::find_port(port) {
+        Some(index) => index,
+        None => {
+            println!("serial: {}: no such port", port);
+            return;
+        }
+    };
+    let config = match SerialConfig::parse(baud_rate, frame_format) {
+        Some(config) => config,
+        None => {
+            println!("serial: invalid configuration: {} {}", baud_rate, frame_format.unwrap_or(""));
+            return;
+        }
+    };
+    if let Err(error) = serial::configure_port(index, config) {
+        println!("serial: {}", error);
+    }
+}
+
+fn bind(channel: &str, port: &str) {
+    let channel = match SerialChannel::parse(channel) {
+        Some(channel) => channel,
+        None => {
+            println!("serial: {}: no such channel", channel);
+            return;
+        }
+    };
+    let port = match (port, serial::find_port(port)) {
+        ("none", _) => None,
+        (_, Some(index)) => Some(index),
+        (port, None) => {
+            println!("serial: {}: no such port", port);
+            return;
+        }
+    };
+    if let Err(error) = serial::bind(channel, port) {
+        println!("serial: {}", error);
+    }
+}
+
+pub fn serial_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    let port_names = COM_PORTS.iter().map(|com_port| com_port.name);
+    match arguments {
+        [] => complete_words(core::iter::once("bind").chain(port_names), word),
+        ["bind"] => complete_words(SerialChannel::ALL.iter().map(|channel| channel.get_name()), word),
+        ["bind", _] => complete_words(port_names.chain(core::iter::once("none")), word),
+        [_] => complete_words(["9600", "19200", "38400", "57600", "115200"], word),
+        [_, _] => complete_words(["8N1", "7E1", "8N2"], word),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,60 @@
+use alloc::string::String;
+
+use crate::command::command::Command;
+use crate::net::stack::NetError;
+use crate::net::tcp::TcpListener;
+use crate::print;
+use crate::println;
+use crate::task::executor;
+
+/// Accepts one TCP connection on the port and prints the received data until the peer closes it
+pub fn tcplisten_command(command: Command) {
+    let port = match command.arguments.as_slice() {
+        [port] => match port.parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => {
+                println!("tcplisten: invalid port: {}", port);
+                return;
+            }
+        },
+        _ => {
+            println!("Usage: tcplisten <port>");
+            return;
+        }
+    };
+    let listener = match TcpListener::bind(port) {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!("tcplisten: {}", error);
+            return;
+        }
+    };
+
+    let spawned = executor::spawn(async move {
+        if let Err(error) = accept_and_print(listener).await {
+            println!("tcplisten: {}", error);
+        }
+    });
+    if let Err(error) = spawned {
+        println!("tcplisten: {}", error);
+    }
+}
+
+async fn accept_and_print(listener: TcpListener) -> Result<(), NetError> {
+    let stream = listener.accept().await;
+    drop(listener);
+    let (address, port) = stream.get_remote_endpoint();
+    println!("Connection from {}:{}", address, port);
+
+    let mut buffer = [0u8; 512];
+    loop {
+        let length = stream.read(&mut buffer).await?;
+        if length == 0 {
+            break;
+        }
+        print!("{}", String::from_utf8_lossy(&buffer[..length]));
+    }
+    stream.close();
+    println!("Connection closed");
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use alloc::string::String;
+
+use crate::command::command::Command;
+use crate::net::ipv4::Ipv4Address;
+use crate::net::tcp::TcpStream;
+use crate::print;
+use crate::println;
+use crate::task::{executor, timer};
+
+const RESPONSE_TIMEOUT_MS: u64 = 5000;
+
+/// Sends a line of text over a TCP connection and prints the response until the peer closes the connection
+pub fn tcpsend_command(command: Command) {
+    let (address, port, words) = match command.arguments.as_slice() {
+        [address, port, words @ ..] => (address, port, words),
+        _ => {
+            println!("Usage: tcpsend <IPv4 address> <port> <text>...");
+            return;
+        }
+    };
+    let address = match Ipv4Address::parse(address) {
+        Some(address) => address,
+        None => {
+            println!("tcpsend: invalid IPv4 address: {}", address);
+            return;
+        }
+    };
+    let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => {
+            println!("tcpsend: invalid port: {}", port);
+            return;
+        }
+    };
+    let mut text = words.join(" ");
+    text.push_str("\r\n");
+
+    let spawned = executor::spawn(async move {
+        if let Err(error) = send_and_print_response(address, port, text).await {
+            println!("tcpsend: {}", error);
+        }
+    });
+    if let Err(error) = spawned {
+        println!("tcpsend: {}", error);
+    }
+}
+
+async fn send_and_print_response(address: Ipv4Address, port: u16, text: String) -> Result<(), crate::net::stack::NetError> {
+    let stream = TcpStream::connect(address, port).await?;
+    stream.write_all(text.as_bytes()).await?;
+
+    let mut buffer = [0u8; 512];
+    loop {
+        let length = match timer::with_timeout(RESPONSE_TIMEOUT_MS, stream.read(&mut buffer)).await {
+            Some(result) => result?,
+            None => break,
+        };
+        if length == 0 {
+            break;
+        }
+        print!("{}", String::from_utf8_lossy(&buffer[..length]));
+    }
+    stream.close();
+    println!();
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use alloc::string::String;
+
+use crate::command::command::Command;
+use crate::net::ipv4::Ipv4Address;
+use crate::net::stack::NetError;
+use crate::net::udp::UdpSocket;
+use crate::println;
+use crate::task::{executor, timer};
+
+const RESPONSE_TIMEOUT_MS: u64 = 5000;
+
+/// Sends a line of text in a UDP datagram and prints the first response
+pub fn udpsend_command(command: Command) {
+    let (address, port, words) = match command.arguments.as_slice() {
+        [address, port, words @ ..] => (address, port, words),
+        _ => {
+            println!("Usage: udpsend <IPv4 address> <port> <text>...");
+            return;
+        }
+    };
+    let address = match Ipv4Address::parse(address) {
+        Some(address) => address,
+        None => {
+            println!("udpsend: invalid IPv4 address: {}", address);
+            return;
+        }
+    };
+    let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => {
+            println!("udpsend: invalid port: {}", port);
+            return;
+        }
+    };
+    let mut text = words.join(" ");
+    text.push('\n');
+
+    let spawned = executor::spawn(async move {
+        if let Err(error) = send_and_print_response(address, port, text).await {
+            println!("udpsend: {}", error);
+        }
+    });
+    if let Err(error) = spawned {
+        println!("udpsend: {}", error);
+    }
+}
+
+async fn send_and_print_response(address: Ipv4Address, port: u16, text: String) -> Result<(), NetError> {
+    let socket = UdpSocket::bind(0)?;
+    socket.send_to(text.as_bytes(), address, port)?;
+
+    let datagram = timer::with_timeout(RESPONSE_TIMEOUT_MS, socket.receive_from()).await
+        .ok_or(NetError::TimedOut)?;
+    println!("{}:{}: {}", datagram.source, datagram.source_port, String::from_utf8_lossy(&datagram.data).trim_end());
+    Ok(())
+}
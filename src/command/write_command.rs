@@ -0,0 +1,31 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::command::command::Command;
+use crate::command::completion::complete_path;
+use crate::fs::with_file_system;
+use crate::println;
+
+pub fn write_command(command: Command) {
+    let (path, words) = match command.arguments.split_first() {
+        Some(arguments) => arguments,
+        None => {
+            println!("Usage: write <path> <text>...");
+            return;
+        }
+    };
+
+    let mut content = words.join(" ");
+    content.push('\n');
+    if let Err(error) = with_file_system(path, |file_system, path| file_system.write_file(path, content.as_bytes())) {
+        println!("write: {}: {}", path, error);
+    }
+}
+
+/// Only the path is completed, the rest is the written text
+pub fn write_completion(arguments: &[&str], word: &str) -> Vec<String> {
+    match arguments {
+        [] => complete_path(word),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,139 @@
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering, compiler_fence};
+
+use x86_64::VirtAddr;
+
+use crate::backtrace::Backtrace;
+use crate::crc32::crc32;
+use crate::log::LOG_BUFFER;
+use crate::log_warning;
+use crate::task::timer;
+
+/// Frames reserved for the crash log by `BootInfoFrameAllocator::reserve_last_frames`
+pub const CRASH_LOG_FRAMES: usize = 4;
+const CRASH_LOG_SIZE: usize = CRASH_LOG_FRAMES * 4096;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"JOSCRASH");
+/// Magic (8 bytes), length of the text (4 bytes) and CRC-32 of the text (4 bytes)
+const HEADER_SIZE: usize = 16;
+const RECENT_LOGS_COUNT: u64 = 32;
+
+/// Virtual address of the reserved region, zero until `init`
+static CRASH_LOG_ADDRESS: AtomicU64 = AtomicU64::new(0);
+
+/// Reports the crash log left by the previous boot, if any, and prepares the region for the next one.
+/// RAM usually survives a warm reboot, after a power cycle the magic or the checksum don't match.
+pub fn init(address: VirtAddr) {
+    let region = unsafe { get_region(address) };
+    if let Some(text) = read(region) {
+        log_warning!("The previous boot crashed:");
+        for line in text.lines() {
+            log_warning!("{}", line);
+        }
+    }
+    clear(region);
+    CRASH_LOG_ADDRESS.store(address.as_u64(), Ordering::Release);
+}
+
+/// Saves the panic, the stack trace and the recent logs. Doesn't allocate, so it's safe in the panic handler.
+pub fn write(info: &PanicInfo, backtrace: Backtrace) {
+    let address = CRASH_LOG_ADDRESS.swap(0, Ordering::AcqRel);
+    if address == 0 {
+        return;
+    }
+    let region = unsafe { get_region(VirtAddr::new(address)) };
+    let mut writer = CrashLogWriter::new(region);
+
+    let uptime = timer::get_uptime_ms();
+    let _ = writeln!(writer, "{}", info);
+    let _ = writeln!(writer, "Uptime: {}.{:03} s", uptime / 1000, uptime % 1000);
+    let _ = writeln!(writer, "Stack trace:");
+    for (index, frame) in backtrace.enumerate() {
+        let _ = writeln!(writer, "  #{} {}", index, frame);
+    }
+    let _ = writeln!(writer, "Recent logs:");
+    let next_index = LOG_BUFFER.get_next_index();
+    let first_index = LOG_BUFFER.get_first_index().max(next_index.saturating_sub(RECENT_LOGS_COUNT));
+    for index in first_index..next_index {
+        if let Some(record) = LOG_BUFFER.get_record(index) {
+            let _ = writeln!(writer, "{}", record);
+        }
+    }
+    writer.finish();
+}
+
+unsafe fn get_region(address: VirtAddr) -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(address.as_mut_ptr(), CRASH_LOG_SIZE)
+}
+
+fn read(region: &[u8]) -> Option<&str> {
+    let magic = u64::from_le_bytes(region[0..8].try_into().unwrap());
+    let length = u32::from_le_bytes(region[8..12].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(region[12..16].try_into().unwrap());
+    if magic != MAGIC || length > region.len() - HEADER_SIZE {
+        return None;
+    }
+    let text = &region[HEADER_SIZE..HEADER_SIZE + length];
+    if crc32(text) != checksum {
+        return None;
+    }
+    core::str::from_utf8(text).ok()
+}
+
+fn clear(region: &mut [u8]) {
+    region[0..HEADER_SIZE].fill(0);
+}
+
+/// Writes the text after the header, what doesn't fit is dropped
+struct CrashLogWriter<'a> {
+    region: &'a mut [u8],
+    length: usize,
+}
+
+impl<'a> CrashLogWriter<'a> {
+    fn new(region: &'a mut [u8]) -> Self {
+        clear(region);
+        Self { region, length: 0 }
+    }
+
+    /// The magic is written last, so a crash while writing leaves no half-written log
+    fn finish(self) {
+        let text = &self.region[HEADER_SIZE..HEADER_SIZE + self.length];
+        let checksum = crc32(text);
+        self.region[8..12].copy_from_slice(&(self.length as u32).to_le_bytes());
+        self.region[12..16].copy_from_slice(&checksum.to_le_bytes());
+        compiler_fence(Ordering::SeqCst);
+        self.region[0..8].copy_from_slice(&MAGIC.to_le_bytes());
+    }
+}
+
+impl Write for CrashLogWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let available = self.region.len() - HEADER_SIZE - self.length;
+        let mut length = s.len().min(available);
+        while !s.is_char_boundary(length) {
+            length -= 1;
+        }
+        let start = HEADER_SIZE + self.length;
+        self.region[start..start + length].copy_from_slice(&s.as_bytes()[..length]);
+        self.length += length;
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_crash_log_detects_corruption() {
+    let mut region = [0xAAu8; 64];
+    assert_eq!(read(&region), None);
+
+    let mut writer = CrashLogWriter::new(&mut region);
+    let _ = write!(writer, "panicked at 'oops'\nand the text doesn't fit in the region");
+    writer.finish();
+    assert_eq!(read(&region), Some("panicked at 'oops'\nand the text doesn't fit in t"));
+
+    region[HEADER_SIZE + 1] ^= 0x01;
+    assert_eq!(read(&region), None);
+    clear(&mut region);
+    assert_eq!(read(&region), None);
+}
@@ -1,5 +1,11 @@
+use alloc::boxed::Box;
 use core::fmt::{Debug, Display};
 
 pub trait Error: Debug + Display {
 }
 
+impl<E: Error + 'static> From<E> for Box<dyn Error> {
+    fn from(error: E) -> Self {
+        Box::new(error)
+    }
+}
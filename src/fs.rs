@@ -0,0 +1,104 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::fs::ext2::Ext2FileSystem;
+use crate::fs::fat::FatFileSystem;
+use crate::fs::file_system::{FileSystem, FsError};
+use crate::storage::BLOCK_DEVICES;
+use crate::storage::block_cache::CachedBlockDevice;
+use crate::storage::block_device::SharedBlockDevice;
+
+pub mod file_system;
+pub mod path;
+pub mod fat;
+pub mod ext2;
+
+lazy_static! {
+    pub static ref MOUNTS: Mutex<MountTable> = Mutex::new(MountTable::new());
+}
+
+pub struct Mount {
+    pub mount_point: String,
+    pub device_name: String,
+    pub device: SharedBlockDevice,
+    pub file_system: Box<dyn FileSystem>,
+}
+
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    pub fn new() -> Self {
+        Self { mounts: Vec::new() }
+    }
+}
+
+impl MountTable {
+    pub fn mount(&mut self, mount_point: &str, device_name: &str, device: SharedBlockDevice, file_system: Box<dyn FileSystem>) -> Result<(), FsError> {
+        let mount_point = path::normalize(mount_point);
+        if self.mounts.iter().any(|mount| mount.mount_point == mount_point) {
+            return Err(FsError::AlreadyExists);
+        }
+        self.mounts.push(Mount {
+            mount_point,
+            device_name: device_name.into(),
+            device,
+            file_system,
+        });
+        Ok(())
+    }
+
+    /// Writes back cached blocks of the device before removing the mount
+    pub fn unmount(&mut self, mount_point: &str) -> Result<(), FsError> {
+        let mount_point = path::normalize(mount_point);
+        let index = self.mounts.iter()
+            .position(|mount| mount.mount_point == mount_point)
+            .ok_or(FsError::NotFound)?;
+        self.mounts[index].device.lock().flush()?;
+        self.mounts.remove(index);
+        Ok(())
+    }
+
+    /// Finds the most specific mount containing the path and returns the path relative to it
+    pub fn resolve(&mut self, path: &str) -> Option<(&mut dyn FileSystem, String)> {
+        let path = path::normalize(path);
+        let mount = self.mounts.iter_mut()
+            .filter(|mount| path::strip_mount_point(&path, &mount.mount_point).is_some())
+            .max_by_key(|mount| mount.mount_point.len())?;
+        let relative_path = path::strip_mount_point(&path, &mount.mount_point)?.into();
+        Some((mount.file_system.as_mut(), relative_path))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Mount> {
+        self.mounts.iter()
+    }
+}
+
+pub fn open_file_system(device: SharedBlockDevice) -> Result<Box<dyn FileSystem>, FsError> {
+    if FatFileSystem::probe(&mut *device.lock()) {
+        return Ok(Box::new(FatFileSystem::new(device)?));
+    }
+    if Ext2FileSystem::probe(&mut *device.lock()) {
+        return Ok(Box::new(Ext2FileSystem::new(device)?));
+    }
+    Err(FsError::Unsupported)
+}
+
+pub fn mount_device(device_name: &str, mount_point: &str) -> Result<(), FsError> {
+    let device = BLOCK_DEVICES.lock().get(device_name).ok_or(FsError::NotFound)?;
+    let device: SharedBlockDevice = Arc::new(Mutex::new(CachedBlockDevice::new(device)));
+    let file_system = open_file_system(device.clone())?;
+    MOUNTS.lock().mount(mount_point, device_name, device, file_system)
+}
+
+pub fn with_file_system<T>(path: &str, operation: impl FnOnce(&mut dyn FileSystem, &str) -> Result<T, FsError>) -> Result<T, FsError> {
+    let mut mounts = MOUNTS.lock();
+    let (file_system, relative_path) = mounts.resolve(path).ok_or(FsError::NotFound)?;
+    operation(file_system, &relative_path)
+}
@@ -0,0 +1,430 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::file_system::{DirectoryEntry, FileKind, FileSystem, FsError};
+use crate::fs::path;
+use crate::storage::block_device::{BlockDevice, SharedBlockDevice};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const EXT2_MAGIC: u16 = 0xEF53;
+const ROOT_INODE: u32 = 2;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+const GROUP_DESCRIPTOR_SIZE: usize = 32;
+
+const INCOMPAT_COMPRESSION: u32 = 0x0001;
+const INCOMPAT_JOURNAL_DEV: u32 = 0x0008;
+const INCOMPAT_META_BG: u32 = 0x0010;
+const INCOMPAT_EXTENTS: u32 = 0x0040;
+const INCOMPAT_64BIT: u32 = 0x0080;
+const INCOMPAT_INLINE_DATA: u32 = 0x8000;
+const INCOMPAT_UNSUPPORTED: u32 = INCOMPAT_COMPRESSION | INCOMPAT_JOURNAL_DEV | INCOMPAT_META_BG
+    | INCOMPAT_EXTENTS | INCOMPAT_64BIT | INCOMPAT_INLINE_DATA;
+
+const MODE_TYPE_MASK: u16 = 0xF000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+const MODE_SYMLINK: u16 = 0xA000;
+
+const DIRECT_BLOCKS: usize = 12;
+const SINGLE_INDIRECT_BLOCK: usize = 12;
+const DOUBLE_INDIRECT_BLOCK: usize = 13;
+const TRIPLE_INDIRECT_BLOCK: usize = 14;
+const FAST_SYMLINK_MAX_LENGTH: u64 = 60;
+
+const MAX_SYMLINK_HOPS: usize = 8;
+
+struct Inode {
+    mode: u16,
+    size: u64,
+    sectors_count: u32,
+    file_acl: u32,
+    blocks: [u32; 15],
+    raw_blocks: [u8; 60],
+}
+
+impl Inode {
+    fn get_kind(&self) -> FileKind {
+        match self.mode & MODE_TYPE_MASK {
+            MODE_DIRECTORY => FileKind::Directory,
+            MODE_SYMLINK => FileKind::Symlink,
+            _ => FileKind::File,
+        }
+    }
+
+    fn is_regular_file(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_REGULAR
+    }
+}
+
+pub struct Ext2FileSystem {
+    device: SharedBlockDevice,
+    block_size: usize,
+    inode_size: usize,
+    inodes_per_group: u32,
+    inodes_count: u32,
+    blocks_count: u32,
+    groups_count: u32,
+    group_descriptors_block: u64,
+    label: Option<String>,
+}
+
+impl Ext2FileSystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        match read_superblock(device) {
+            Ok(superblock) => read_u16(&superblock, 56) == EXT2_MAGIC,
+            Err(_) => false,
+        }
+    }
+
+    pub fn new(device: SharedBlockDevice) -> Result<Self, FsError> {
+        let superblock = read_superblock(&mut *device.lock())?;
+        if read_u16(&superblock, 56) != EXT2_MAGIC {
+            return Err(FsError::Corrupted("invalid superblock magic"));
+        }
+
+        let incompatible_features = if read_u32(&superblock, 76) >= 1 { read_u32(&superblock, 96) } else { 0 };
+        if incompatible_features & INCOMPAT_UNSUPPORTED != 0 {
+            return Err(FsError::Unsupported);
+        }
+
+        let log_block_size = read_u32(&superblock, 24);
+        if log_block_size > 6 {
+            return Err(FsError::Corrupted("invalid block size"));
+        }
+        let block_size = 1024 << log_block_size;
+        let inode_size = if read_u32(&superblock, 76) >= 1 { read_u16(&superblock, 88) as usize } else { GOOD_OLD_INODE_SIZE };
+        let inodes_count = read_u32(&superblock, 0);
+        let blocks_count = read_u32(&superblock, 4);
+        let first_data_block = read_u32(&superblock, 20);
+        let blocks_per_group = read_u32(&superblock, 32);
+        let inodes_per_group = read_u32(&superblock, 40);
+        if blocks_per_group == 0 || inodes_per_group == 0 || first_data_block >= blocks_count
+            || inode_size < GOOD_OLD_INODE_SIZE || inode_size > block_size {
+            return Err(FsError::Corrupted("invalid superblock"));
+        }
+
+        let label: String = String::from_utf8_lossy(&superblock[120..136]).trim_end_matches('\0').into();
+        let label = if label.is_empty() { None } else { Some(label) };
+
+        Ok(Self {
+            device,
+            block_size,
+            inode_size,
+            inodes_per_group,
+            inodes_count,
+            blocks_count,
+            groups_count: (blocks_count - first_data_block).div_ceil(blocks_per_group),
+            group_descriptors_block: first_data_block as u64 + 1,
+            label,
+        })
+    }
+}
+
+impl Ext2FileSystem {
+    fn read_block(&mut self, block: u32) -> Result<Vec<u8>, FsError> {
+        let mut buffer = vec![0u8; self.block_size];
+        if block == 0 {
+            return Ok(buffer);
+        }
+        if block >= self.blocks_count {
+            return Err(FsError::Corrupted("block out of range"));
+        }
+        let mut device = self.device.lock();
+        let sectors_per_block = (self.block_size / device.get_sector_size()) as u64;
+        device.read_sectors(block as u64 * sectors_per_block, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn read_inode(&mut self, number: u32) -> Result<Inode, FsError> {
+        if number == 0 || number > self.inodes_count {
+            return Err(FsError::Corrupted("inode out of range"));
+        }
+        let group = (number - 1) / self.inodes_per_group;
+        let index = ((number - 1) % self.inodes_per_group) as usize;
+        if group >= self.groups_count {
+            return Err(FsError::Corrupted("inode out of range"));
+        }
+
+        let descriptors_per_block = self.block_size / GROUP_DESCRIPTOR_SIZE;
+        let descriptor_block = self.group_descriptors_block + (group as usize / descriptors_per_block) as u64;
+        let descriptors = self.read_block(descriptor_block as u32)?;
+        let descriptor_offset = (group as usize % descriptors_per_block) * GROUP_DESCRIPTOR_SIZE;
+        let inode_table = read_u32(&descriptors, descriptor_offset + 8);
+
+        let inodes_per_block = self.block_size / self.inode_size;
+        let table_block = self.read_block(inode_table + (index / inodes_per_block) as u32)?;
+        let offset = (index % inodes_per_block) * self.inode_size;
+        let raw = &table_block[offset..offset + GOOD_OLD_INODE_SIZE];
+
+        let mode = read_u16(raw, 0);
+        let size_high = if mode & MODE_TYPE_MASK == MODE_REGULAR { read_u32(raw, 108) } else { 0 };
+        let mut blocks = [0u32; 15];
+        for (index, block) in blocks.iter_mut().enumerate() {
+            *block = read_u32(raw, 40 + index * 4);
+        }
+        let mut raw_blocks = [0u8; 60];
+        raw_blocks.copy_from_slice(&raw[40..100]);
+
+        Ok(Inode {
+            mode,
+            size: (size_high as u64) << 32 | read_u32(raw, 4) as u64,
+            sectors_count: read_u32(raw, 28),
+            file_acl: read_u32(raw, 104),
+            blocks,
+            raw_blocks,
+        })
+    }
+
+    /// Maps the index of a block within the file to the block on disk, zero means a hole
+    fn map_block(&mut self, inode: &Inode, index: usize) -> Result<u32, FsError> {
+        let pointers_per_block = self.block_size / 4;
+        if index < DIRECT_BLOCKS {
+            return Ok(inode.blocks[index]);
+        }
+
+        let mut index = index - DIRECT_BLOCKS;
+        let mut span = pointers_per_block;
+        for (level, root) in [SINGLE_INDIRECT_BLOCK, DOUBLE_INDIRECT_BLOCK, TRIPLE_INDIRECT_BLOCK].into_iter().enumerate() {
+            if index < span {
+                let mut block = inode.blocks[root];
+                for depth in (0..=level).rev() {
+                    if block == 0 {
+                        return Ok(0);
+                    }
+                    let pointers = self.read_block(block)?;
+                    let divisor = pointers_per_block.pow(depth as u32);
+                    block = read_u32(&pointers, (index / divisor % pointers_per_block) * 4);
+                }
+                return Ok(block);
+            }
+            index -= span;
+            span *= pointers_per_block;
+        }
+        Err(FsError::Corrupted("file too large"))
+    }
+
+    fn read_content(&mut self, inode: &Inode) -> Result<Vec<u8>, FsError> {
+        let size: usize = inode.size.try_into().map_err(|_| FsError::Unsupported)?;
+        let blocks_count = size.div_ceil(self.block_size);
+        let mut content = Vec::with_capacity(blocks_count * self.block_size);
+        for index in 0..blocks_count {
+            let block = self.map_block(inode, index)?;
+            content.extend(self.read_block(block)?);
+        }
+        content.truncate(size);
+        Ok(content)
+    }
+
+    fn read_link_target(&mut self, inode: &Inode) -> Result<String, FsError> {
+        let acl_sectors = if inode.file_acl != 0 { (self.block_size / 512) as u32 } else { 0 };
+        let is_fast_symlink = inode.size < FAST_SYMLINK_MAX_LENGTH && inode.sectors_count == acl_sectors;
+        let target = if is_fast_symlink {
+            inode.raw_blocks[..inode.size as usize].to_vec()
+        } else {
+            self.read_content(inode)?
+        };
+        String::from_utf8(target).map_err(|_| FsError::Corrupted("invalid symlink target"))
+    }
+
+    fn read_directory(&mut self, inode: &Inode) -> Result<Vec<(String, u32)>, FsError> {
+        let content = self.read_content(inode)?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset + 8 <= content.len() {
+            let inode_number = read_u32(&content, offset);
+            let record_length = read_u16(&content, offset + 4) as usize;
+            let name_length = content[offset + 6] as usize;
+            if record_length < 8 || offset + record_length > content.len() || 8 + name_length > record_length {
+                return Err(FsError::Corrupted("invalid directory entry"));
+            }
+            if inode_number != 0 {
+                let name = String::from_utf8_lossy(&content[offset + 8..offset + 8 + name_length]).into();
+                entries.push((name, inode_number));
+            }
+            offset += record_length;
+        }
+        Ok(entries)
+    }
+
+    fn lookup(&mut self, path: &str, follow_last_symlink: bool) -> Result<(u32, Inode), FsError> {
+        let mut pending: Vec<String> = path::components(path).rev().map(String::from).collect();
+        let mut current = ROOT_INODE;
+        let mut current_inode = self.read_inode(ROOT_INODE)?;
+        let mut symlink_hops = 0;
+
+        while let Some(component) = pending.pop() {
+            if current_inode.get_kind() != FileKind::Directory {
+                return Err(FsError::NotADirectory);
+            }
+            let child = self.read_directory(&current_inode)?.into_iter()
+                .find(|(name, _)| *name == component)
+                .map(|(_, inode_number)| inode_number)
+                .ok_or(FsError::NotFound)?;
+            let child_inode = self.read_inode(child)?;
+
+            if child_inode.get_kind() == FileKind::Symlink && (follow_last_symlink || !pending.is_empty()) {
+                symlink_hops += 1;
+                if symlink_hops > MAX_SYMLINK_HOPS {
+                    return Err(FsError::SymlinkLoop);
+                }
+                let target = self.read_link_target(&child_inode)?;
+                pending.extend(path::components(&target).rev().map(String::from));
+                if target.starts_with('/') {
+                    current = ROOT_INODE;
+                    current_inode = self.read_inode(ROOT_INODE)?;
+                }
+                continue;
+            }
+
+            current = child;
+            current_inode = child_inode;
+        }
+        Ok((current, current_inode))
+    }
+}
+
+impl FileSystem for Ext2FileSystem {
+    fn get_type_name(&self) -> &str {
+        "ext2"
+    }
+
+    fn get_label(&self) -> Option<String> {
+        self.label.clone()
+    }
+
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, FsError> {
+        let (_, inode) = self.lookup(path, true)?;
+        if inode.get_kind() != FileKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+
+        let mut entries = Vec::new();
+        for (name, inode_number) in self.read_directory(&inode)? {
+            if name == "." || name == ".." {
+                continue;
+            }
+            let child = self.read_inode(inode_number)?;
+            entries.push(DirectoryEntry {
+                name,
+                kind: child.get_kind(),
+                size: child.size,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<DirectoryEntry, FsError> {
+        let (_, inode) = self.lookup(path, false)?;
+        let (_, name) = path::split_parent(path);
+        Ok(DirectoryEntry {
+            name: if name.is_empty() { "/".into() } else { name.into() },
+            kind: inode.get_kind(),
+            size: inode.size,
+        })
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FsError> {
+        let (_, inode) = self.lookup(path, true)?;
+        if !inode.is_regular_file() {
+            return Err(FsError::IsADirectory);
+        }
+        self.read_content(&inode)
+    }
+
+    fn read_link(&mut self, path: &str) -> Result<String, FsError> {
+        let (_, inode) = self.lookup(path, false)?;
+        if inode.get_kind() != FileKind::Symlink {
+            return Err(FsError::InvalidName);
+        }
+        self.read_link_target(&inode)
+    }
+
+    fn write_file(&mut self, _path: &str, _data: &[u8]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn create_dir(&mut self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn remove(&mut self, _path: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+}
+
+fn read_superblock(device: &mut dyn BlockDevice) -> Result<Vec<u8>, FsError> {
+    let sector_size = device.get_sector_size();
+    let first_sector = SUPERBLOCK_OFFSET / sector_size as u64;
+    let sectors_count = SUPERBLOCK_SIZE.div_ceil(sector_size);
+    let mut buffer = vec![0u8; sectors_count * sector_size];
+    device.read_sectors(first_sector, &mut buffer)?;
+
+    let offset = (SUPERBLOCK_OFFSET % sector_size as u64) as usize;
+    Ok(buffer[offset..offset + SUPERBLOCK_SIZE].to_vec())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[cfg(test)]
+fn open_test_image() -> Ext2FileSystem {
+    use alloc::sync::Arc;
+    use spin::Mutex;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut device = MockBlockDevice::from_image(include_bytes!("fixtures/ext2.img"));
+    assert!(Ext2FileSystem::probe(&mut device));
+    Ext2FileSystem::new(Arc::new(Mutex::new(device))).unwrap()
+}
+
+#[test_case]
+fn test_ext2_read_directories_and_files() {
+    let mut file_system = open_test_image();
+    assert_eq!(file_system.get_label().as_deref(), Some("just-os"));
+
+    let mut names: Vec<String> = file_system.read_dir("/").unwrap().into_iter().map(|entry| entry.name).collect();
+    names.sort();
+    assert_eq!(names, ["docs", "hello.txt", "loop", "lost+found", "readme"]);
+
+    assert_eq!(file_system.read_file("/hello.txt").unwrap(), b"Hello from ext2!\n");
+    let big = file_system.read_file("/docs/big.bin").unwrap();
+    assert_eq!(big.len(), 20000);
+    assert!(big.iter().enumerate().all(|(index, &byte)| byte == (index % 251) as u8));
+
+    assert!(matches!(file_system.read_file("/docs"), Err(FsError::IsADirectory)));
+    assert!(matches!(file_system.read_file("/missing"), Err(FsError::NotFound)));
+    assert!(matches!(file_system.write_file("/new.txt", b""), Err(FsError::ReadOnly)));
+}
+
+#[test_case]
+fn test_ext2_reject_invalid_superblock() {
+    use alloc::sync::Arc;
+    use spin::Mutex;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut image = include_bytes!("fixtures/ext2.img").to_vec();
+    // The first data block past the end of the file system
+    let superblock = SUPERBLOCK_OFFSET as usize;
+    let blocks_count = read_u32(&image, superblock + 4);
+    image[superblock + 20..superblock + 24].copy_from_slice(&blocks_count.to_le_bytes());
+    let device = MockBlockDevice::from_image(&image);
+    assert!(matches!(Ext2FileSystem::new(Arc::new(Mutex::new(device))), Err(FsError::Corrupted(_))));
+}
+
+#[test_case]
+fn test_ext2_follow_symlinks() {
+    let mut file_system = open_test_image();
+    assert_eq!(file_system.stat("/readme").unwrap().kind, FileKind::Symlink);
+    assert_eq!(file_system.read_link("/readme").unwrap(), "docs/readme.md");
+
+    assert_eq!(file_system.read_file("/readme").unwrap(), b"# Documentation\n");
+    assert_eq!(file_system.read_file("/docs/hello").unwrap(), b"Hello from ext2!\n");
+    assert!(matches!(file_system.read_file("/loop"), Err(FsError::SymlinkLoop)));
+}
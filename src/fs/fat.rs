@@ -0,0 +1,1143 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::fs::file_system::{DirectoryEntry, FileKind, FileSystem, FsError};
+use crate::fs::path;
+use crate::storage::block_device::{BlockDevice, SharedBlockDevice};
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+const END_OF_DIRECTORY: u8 = 0x00;
+const DELETED_ENTRY: u8 = 0xE5;
+const ESCAPED_E5: u8 = 0x05;
+
+const ATTRIBUTE_READ_ONLY: u8 = 0x01;
+const ATTRIBUTE_HIDDEN: u8 = 0x02;
+const ATTRIBUTE_SYSTEM: u8 = 0x04;
+const ATTRIBUTE_VOLUME_ID: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+const ATTRIBUTE_ARCHIVE: u8 = 0x20;
+const ATTRIBUTE_LONG_NAME: u8 = ATTRIBUTE_READ_ONLY | ATTRIBUTE_HIDDEN | ATTRIBUTE_SYSTEM | ATTRIBUTE_VOLUME_ID;
+
+const NT_LOWERCASE_BASE: u8 = 0x08;
+const NT_LOWERCASE_EXTENSION: u8 = 0x10;
+
+const LFN_LAST_ENTRY: u8 = 0x40;
+const LFN_ORDINAL_MASK: u8 = 0x1F;
+const LFN_CHARACTERS_PER_ENTRY: usize = 13;
+const LFN_CHARACTER_OFFSETS: [usize; LFN_CHARACTERS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME_LENGTH: usize = 255;
+
+/// 1980-01-01, the FAT epoch, used until the kernel has a wall clock for file times
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_FREE_COUNT_OFFSET: usize = 488;
+const FS_INFO_NEXT_FREE_OFFSET: usize = 492;
+const FS_INFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    fn end_of_chain(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn is_end_of_chain(&self, value: u32) -> bool {
+        match self {
+            FatType::Fat12 => value >= 0xFF8,
+            FatType::Fat16 => value >= 0xFFF8,
+            FatType::Fat32 => value >= 0x0FFF_FFF8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Directory {
+    /// The fixed-size root directory region of FAT12 and FAT16
+    FixedRoot,
+    Clusters(u32),
+}
+
+struct DirectoryData {
+    sectors: Vec<u64>,
+    data: Vec<u8>,
+}
+
+impl DirectoryData {
+    fn slots_count(&self) -> usize {
+        self.data.len() / DIRECTORY_ENTRY_SIZE
+    }
+
+    fn slot(&self, index: usize) -> &[u8] {
+        &self.data[index * DIRECTORY_ENTRY_SIZE..(index + 1) * DIRECTORY_ENTRY_SIZE]
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut [u8] {
+        &mut self.data[index * DIRECTORY_ENTRY_SIZE..(index + 1) * DIRECTORY_ENTRY_SIZE]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RawEntry {
+    name: String,
+    short_name: [u8; 11],
+    attributes: u8,
+    first_cluster: u32,
+    size: u32,
+    /// Index of the first long name slot, or of the short entry if there is no long name
+    first_slot: usize,
+    slot: usize,
+}
+
+impl RawEntry {
+    fn is_directory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+
+    fn is_dot_entry(&self) -> bool {
+        self.short_name[0] == b'.'
+    }
+
+    fn to_directory_entry(&self) -> DirectoryEntry {
+        DirectoryEntry {
+            name: self.name.clone(),
+            kind: if self.is_directory() { FileKind::Directory } else { FileKind::File },
+            size: self.size as u64,
+        }
+    }
+}
+
+enum Node {
+    Root,
+    Entry(RawEntry),
+}
+
+pub struct FatFileSystem {
+    device: SharedBlockDevice,
+    fat_type: FatType,
+    sector_size: usize,
+    sectors_per_cluster: u64,
+    fat_start: u64,
+    fats_count: u64,
+    sectors_per_fat: u64,
+    root_directory_start: u64,
+    root_directory_sectors: u64,
+    data_start: u64,
+    clusters_count: u32,
+    root_cluster: u32,
+    fs_info_sector: Option<u64>,
+    fs_info_invalidated: bool,
+    label: Option<String>,
+    next_free_cluster: u32,
+    fat_cache: Option<(u64, Vec<u8>)>,
+}
+
+impl FatFileSystem {
+    pub fn probe(device: &mut dyn BlockDevice) -> bool {
+        let mut sector = vec![0u8; device.get_sector_size()];
+        if sector.len() < 512 || device.read_sectors(0, &mut sector).is_err() {
+            return false;
+        }
+
+        let bytes_per_sector = read_u16(&sector, 11) as usize;
+        let sectors_per_cluster = sector[13];
+        let reserved_sectors = read_u16(&sector, 14);
+        let fats_count = sector[16];
+        let total_sectors = if read_u16(&sector, 19) != 0 { read_u16(&sector, 19) as u32 } else { read_u32(&sector, 32) };
+        let sectors_per_fat = if read_u16(&sector, 22) != 0 { read_u16(&sector, 22) as u32 } else { read_u32(&sector, 36) };
+
+        sector[510..512] == BOOT_SIGNATURE
+            && (sector[0] == 0xEB || sector[0] == 0xE9)
+            && bytes_per_sector == device.get_sector_size()
+            && sectors_per_cluster.is_power_of_two()
+            && reserved_sectors > 0
+            && fats_count > 0
+            && total_sectors > 0
+            && sectors_per_fat > 0
+    }
+
+    pub fn new(device: SharedBlockDevice) -> Result<Self, FsError> {
+        let (sector_size, sector) = {
+            let mut device = device.lock();
+            let mut sector = vec![0u8; device.get_sector_size()];
+            device.read_sectors(0, &mut sector)?;
+            (device.get_sector_size(), sector)
+        };
+        if read_u16(&sector, 11) as usize != sector_size {
+            return Err(FsError::Unsupported);
+        }
+
+        let sectors_per_cluster = sector[13] as u64;
+        let reserved_sectors = read_u16(&sector, 14) as u64;
+        let fats_count = sector[16] as u64;
+        let root_entries_count = read_u16(&sector, 17) as u64;
+        let total_sectors = match read_u16(&sector, 19) {
+            0 => read_u32(&sector, 32) as u64,
+            count => count as u64,
+        };
+        let sectors_per_fat = match read_u16(&sector, 22) {
+            0 => read_u32(&sector, 36) as u64,
+            count => count as u64,
+        };
+        if sectors_per_cluster == 0 || fats_count == 0 || sectors_per_fat == 0 {
+            return Err(FsError::Corrupted("invalid BIOS parameter block"));
+        }
+
+        let root_directory_start = reserved_sectors + fats_count * sectors_per_fat;
+        let root_directory_sectors = (root_entries_count * DIRECTORY_ENTRY_SIZE as u64).div_ceil(sector_size as u64);
+        let data_start = root_directory_start + root_directory_sectors;
+        if data_start >= total_sectors {
+            return Err(FsError::Corrupted("no data region"));
+        }
+        let clusters_count = ((total_sectors - data_start) / sectors_per_cluster) as u32;
+
+        // Like in Linux, the FAT32 is recognized by its BIOS parameter block, as mkfs.vfat formats it
+        // also on the volumes with fewer clusters than the specification expects
+        let fat_type = if read_u16(&sector, 22) == 0 && root_entries_count == 0 {
+            FatType::Fat32
+        } else if clusters_count < 4085 {
+            FatType::Fat12
+        } else if clusters_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let (root_cluster, fs_info_sector, label_offset) = if fat_type == FatType::Fat32 {
+            let fs_info_sector = match read_u16(&sector, 48) {
+                0 | 0xFFFF => None,
+                sector => Some(sector as u64),
+            };
+            (read_u32(&sector, 44), fs_info_sector, 71)
+        } else {
+            (0, None, 43)
+        };
+        let extended_signature = sector[label_offset - 5];
+        let label = if extended_signature == 0x29 {
+            let label: String = String::from_utf8_lossy(&sector[label_offset..label_offset + 11]).trim_end().into();
+            if label == "NO NAME" || label.is_empty() { None } else { Some(label) }
+        } else {
+            None
+        };
+
+        let mut file_system = Self {
+            device,
+            fat_type,
+            sector_size,
+            sectors_per_cluster,
+            fat_start: reserved_sectors,
+            fats_count,
+            sectors_per_fat,
+            root_directory_start,
+            root_directory_sectors,
+            data_start,
+            clusters_count,
+            root_cluster,
+            fs_info_sector,
+            fs_info_invalidated: false,
+            label,
+            next_free_cluster: 2,
+            fat_cache: None,
+        };
+        file_system.read_fs_info()?;
+        Ok(file_system)
+    }
+}
+
+impl FatFileSystem {
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * self.sector_size
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.clusters_count + 2
+    }
+
+    fn read_sectors(&mut self, lba: u64, count: usize) -> Result<Vec<u8>, FsError> {
+        let mut buffer = vec![0u8; count * self.sector_size];
+        self.device.lock().read_sectors(lba, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), FsError> {
+        self.device.lock().write_sectors(lba, buffer)?;
+        Ok(())
+    }
+
+    fn read_fs_info(&mut self) -> Result<(), FsError> {
+        if let Some(fs_info_sector) = self.fs_info_sector {
+            let sector = self.read_sectors(fs_info_sector, 1)?;
+            if read_u32(&sector, 0) != FS_INFO_LEAD_SIGNATURE || read_u32(&sector, 484) != FS_INFO_STRUCT_SIGNATURE {
+                self.fs_info_sector = None;
+                return Ok(());
+            }
+            let next_free = read_u32(&sector, FS_INFO_NEXT_FREE_OFFSET);
+            if self.is_valid_cluster(next_free) {
+                self.next_free_cluster = next_free;
+            }
+        }
+        Ok(())
+    }
+
+    /// The free clusters count is not tracked, so it is marked as unknown before the first allocation change
+    fn invalidate_fs_info(&mut self) -> Result<(), FsError> {
+        if self.fs_info_invalidated {
+            return Ok(());
+        }
+        self.fs_info_invalidated = true;
+        if let Some(fs_info_sector) = self.fs_info_sector {
+            let mut sector = self.read_sectors(fs_info_sector, 1)?;
+            write_u32(&mut sector, FS_INFO_FREE_COUNT_OFFSET, FS_INFO_UNKNOWN);
+            write_u32(&mut sector, FS_INFO_NEXT_FREE_OFFSET, FS_INFO_UNKNOWN);
+            self.write_sectors(fs_info_sector, &sector)?;
+        }
+        Ok(())
+    }
+}
+
+// File allocation table
+impl FatFileSystem {
+    fn load_fat_sector(&mut self, sector_index: u64) -> Result<&mut Vec<u8>, FsError> {
+        if self.fat_cache.as_ref().map(|(index, _)| *index) != Some(sector_index) {
+            let sector = self.read_sectors(self.fat_start + sector_index, 1)?;
+            self.fat_cache = Some((sector_index, sector));
+        }
+        Ok(&mut self.fat_cache.as_mut().unwrap().1)
+    }
+
+    fn read_fat_byte(&mut self, offset: usize) -> Result<u8, FsError> {
+        let sector_size = self.sector_size;
+        let sector = self.load_fat_sector((offset / sector_size) as u64)?;
+        Ok(sector[offset % sector_size])
+    }
+
+    fn write_fat_byte(&mut self, offset: usize, value: u8) -> Result<(), FsError> {
+        let sector_size = self.sector_size;
+        let sector_index = (offset / sector_size) as u64;
+        let sector = self.load_fat_sector(sector_index)?;
+        sector[offset % sector_size] = value;
+        let sector = sector.clone();
+        for fat_index in 0..self.fats_count {
+            self.write_sectors(self.fat_start + fat_index * self.sectors_per_fat + sector_index, &sector)?;
+        }
+        Ok(())
+    }
+
+    fn read_fat_bytes<const N: usize>(&mut self, offset: usize) -> Result<[u8; N], FsError> {
+        let mut bytes = [0u8; N];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.read_fat_byte(offset + index)?;
+        }
+        Ok(bytes)
+    }
+
+    fn read_fat_entry(&mut self, cluster: u32) -> Result<u32, FsError> {
+        let cluster = cluster as usize;
+        Ok(match self.fat_type {
+            FatType::Fat12 => {
+                let value = u16::from_le_bytes(self.read_fat_bytes(cluster + cluster / 2)?);
+                if cluster & 1 == 1 { (value >> 4) as u32 } else { (value & 0xFFF) as u32 }
+            }
+            FatType::Fat16 => u16::from_le_bytes(self.read_fat_bytes(cluster * 2)?) as u32,
+            FatType::Fat32 => u32::from_le_bytes(self.read_fat_bytes(cluster * 4)?) & 0x0FFF_FFFF,
+        })
+    }
+
+    fn write_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let cluster = cluster as usize;
+        let (offset, bytes): (usize, Vec<u8>) = match self.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                let current = u16::from_le_bytes(self.read_fat_bytes(offset)?);
+                let value = value as u16 & 0xFFF;
+                let updated = if cluster & 1 == 1 {
+                    (current & 0x000F) | (value << 4)
+                } else {
+                    (current & 0xF000) | value
+                };
+                (offset, updated.to_le_bytes().into())
+            }
+            FatType::Fat16 => (cluster * 2, (value as u16).to_le_bytes().into()),
+            FatType::Fat32 => {
+                let offset = cluster * 4;
+                let current = u32::from_le_bytes(self.read_fat_bytes(offset)?);
+                (offset, ((current & 0xF000_0000) | (value & 0x0FFF_FFFF)).to_le_bytes().into())
+            }
+        };
+        for (index, byte) in bytes.into_iter().enumerate() {
+            self.write_fat_byte(offset + index, byte)?;
+        }
+        Ok(())
+    }
+
+    fn cluster_chain(&mut self, first_cluster: u32) -> Result<Vec<u32>, FsError> {
+        let mut chain = Vec::new();
+        let mut cluster = first_cluster;
+        while self.is_valid_cluster(cluster) {
+            if chain.len() > self.clusters_count as usize {
+                return Err(FsError::Corrupted("cluster chain loop"));
+            }
+            chain.push(cluster);
+            cluster = self.read_fat_entry(cluster)?;
+        }
+        if !chain.is_empty() && !self.fat_type.is_end_of_chain(cluster) {
+            return Err(FsError::Corrupted("invalid cluster in chain"));
+        }
+        Ok(chain)
+    }
+
+    fn allocate_cluster(&mut self, previous: Option<u32>) -> Result<u32, FsError> {
+        self.invalidate_fs_info()?;
+        for step in 0..self.clusters_count {
+            let cluster = 2 + (self.next_free_cluster - 2 + step) % self.clusters_count;
+            if self.read_fat_entry(cluster)? == 0 {
+                self.write_fat_entry(cluster, self.fat_type.end_of_chain())?;
+                if let Some(previous) = previous {
+                    self.write_fat_entry(previous, cluster)?;
+                }
+                self.next_free_cluster = if cluster + 1 < self.clusters_count + 2 { cluster + 1 } else { 2 };
+                return Ok(cluster);
+            }
+        }
+        Err(FsError::NoSpace)
+    }
+
+    fn allocate_chain(&mut self, clusters_count: usize) -> Result<Vec<u32>, FsError> {
+        let mut chain: Vec<u32> = Vec::with_capacity(clusters_count);
+        for _ in 0..clusters_count {
+            match self.allocate_cluster(chain.last().copied()) {
+                Ok(cluster) => chain.push(cluster),
+                Err(error) => {
+                    if let Some(&first_cluster) = chain.first() {
+                        self.free_chain(first_cluster)?;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(chain)
+    }
+
+    fn free_chain(&mut self, first_cluster: u32) -> Result<(), FsError> {
+        self.invalidate_fs_info()?;
+        for cluster in self.cluster_chain(first_cluster)? {
+            self.write_fat_entry(cluster, 0)?;
+        }
+        Ok(())
+    }
+
+    fn write_clusters(&mut self, chain: &[u32], data: &[u8]) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size();
+        let mut buffer = vec![0u8; cluster_size];
+        for (index, &cluster) in chain.iter().enumerate() {
+            let start = (index * cluster_size).min(data.len());
+            let end = (start + cluster_size).min(data.len());
+            buffer[..end - start].copy_from_slice(&data[start..end]);
+            buffer[end - start..].fill(0);
+            self.write_sectors(self.cluster_lba(cluster), &buffer)?;
+        }
+        Ok(())
+    }
+}
+
+// Directories
+impl FatFileSystem {
+    fn root_directory(&self) -> Directory {
+        match self.fat_type {
+            FatType::Fat32 => Directory::Clusters(self.root_cluster),
+            _ => Directory::FixedRoot,
+        }
+    }
+
+    fn directory_of(&self, node: &Node) -> Result<Directory, FsError> {
+        match node {
+            Node::Root => Ok(self.root_directory()),
+            Node::Entry(entry) if !entry.is_directory() => Err(FsError::NotADirectory),
+            Node::Entry(entry) if entry.first_cluster == 0 => Ok(self.root_directory()),
+            Node::Entry(entry) => Ok(Directory::Clusters(entry.first_cluster)),
+        }
+    }
+
+    /// The cluster stored in `..` entries, where zero means the root directory
+    fn directory_cluster(&self, directory: Directory) -> u32 {
+        match directory {
+            Directory::Clusters(cluster) if cluster != self.root_cluster => cluster,
+            _ => 0,
+        }
+    }
+
+    fn load_directory(&mut self, directory: Directory) -> Result<DirectoryData, FsError> {
+        let sectors: Vec<u64> = match directory {
+            Directory::FixedRoot => (0..self.root_directory_sectors)
+                .map(|index| self.root_directory_start + index)
+                .collect(),
+            Directory::Clusters(first_cluster) => {
+                let mut sectors = Vec::new();
+                for cluster in self.cluster_chain(first_cluster)? {
+                    let lba = self.cluster_lba(cluster);
+                    sectors.extend((0..self.sectors_per_cluster).map(|index| lba + index));
+                }
+                sectors
+            }
+        };
+
+        let mut data = Vec::with_capacity(sectors.len() * self.sector_size);
+        for &lba in &sectors {
+            data.extend(self.read_sectors(lba, 1)?);
+        }
+        Ok(DirectoryData { sectors, data })
+    }
+
+    fn store_slots(&mut self, directory: &DirectoryData, first_slot: usize, last_slot: usize) -> Result<(), FsError> {
+        let first_sector = first_slot * DIRECTORY_ENTRY_SIZE / self.sector_size;
+        let last_sector = last_slot * DIRECTORY_ENTRY_SIZE / self.sector_size;
+        for index in first_sector..=last_sector {
+            let data = &directory.data[index * self.sector_size..(index + 1) * self.sector_size];
+            self.write_sectors(directory.sectors[index], data)?;
+        }
+        Ok(())
+    }
+
+    fn parse_entries(directory: &DirectoryData) -> Vec<RawEntry> {
+        let mut entries = Vec::new();
+        let mut long_name: Option<(Vec<u16>, u8, usize)> = None;
+
+        for index in 0..directory.slots_count() {
+            let slot = directory.slot(index);
+            match slot[0] {
+                END_OF_DIRECTORY => break,
+                DELETED_ENTRY => {
+                    long_name = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let attributes = slot[11];
+            if attributes & ATTRIBUTE_LONG_NAME == ATTRIBUTE_LONG_NAME {
+                let ordinal = slot[0] & LFN_ORDINAL_MASK;
+                if ordinal == 0 {
+                    long_name = None;
+                    continue;
+                }
+                if slot[0] & LFN_LAST_ENTRY != 0 {
+                    let characters = vec![0xFFFF; ordinal as usize * LFN_CHARACTERS_PER_ENTRY];
+                    long_name = Some((characters, slot[13], index));
+                }
+                if let Some((characters, checksum, _)) = &mut long_name {
+                    let position = (ordinal as usize - 1) * LFN_CHARACTERS_PER_ENTRY;
+                    if *checksum != slot[13] || position >= characters.len() {
+                        long_name = None;
+                        continue;
+                    }
+                    for (offset_index, &offset) in LFN_CHARACTER_OFFSETS.iter().enumerate() {
+                        characters[position + offset_index] = read_u16(slot, offset);
+                    }
+                }
+                continue;
+            }
+
+            if attributes & ATTRIBUTE_VOLUME_ID != 0 {
+                long_name = None;
+                continue;
+            }
+
+            let mut short_name = [0u8; 11];
+            short_name.copy_from_slice(&slot[0..11]);
+            if short_name[0] == ESCAPED_E5 {
+                short_name[0] = DELETED_ENTRY;
+            }
+
+            let (name, first_slot) = match long_name.take() {
+                Some((characters, checksum, first_slot)) if checksum == short_name_checksum(&slot[0..11]) => {
+                    let length = characters.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or(characters.len());
+                    (String::from_utf16_lossy(&characters[..length]), first_slot)
+                }
+                _ => (format_short_name(&short_name, slot[12]), index),
+            };
+
+            entries.push(RawEntry {
+                name,
+                short_name,
+                attributes,
+                first_cluster: (read_u16(slot, 20) as u32) << 16 | read_u16(slot, 26) as u32,
+                size: read_u32(slot, 28),
+                first_slot,
+                slot: index,
+            });
+        }
+        entries
+    }
+
+    fn find_entry(&mut self, directory: Directory, name: &str) -> Result<Option<RawEntry>, FsError> {
+        let data = self.load_directory(directory)?;
+        Ok(Self::parse_entries(&data).into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name)))
+    }
+
+    fn lookup(&mut self, path: &str) -> Result<Node, FsError> {
+        let mut node = Node::Root;
+        for component in path::components(path) {
+            let directory = self.directory_of(&node)?;
+            node = Node::Entry(self.find_entry(directory, component)?.ok_or(FsError::NotFound)?);
+        }
+        Ok(node)
+    }
+
+    fn lookup_parent<'a>(&mut self, path: &'a str) -> Result<(Directory, &'a str), FsError> {
+        let (parent, name) = path::split_parent(path);
+        if name.is_empty() {
+            return Err(FsError::InvalidName);
+        }
+        let parent = self.lookup(parent)?;
+        Ok((self.directory_of(&parent)?, name))
+    }
+
+    fn find_free_slots(&mut self, directory: Directory, count: usize) -> Result<(DirectoryData, usize), FsError> {
+        loop {
+            let data = self.load_directory(directory)?;
+            let mut run_start = 0;
+            let mut run_length = 0;
+            for index in 0..data.slots_count() {
+                let marker = data.slot(index)[0];
+                if marker == END_OF_DIRECTORY || marker == DELETED_ENTRY {
+                    if run_length == 0 {
+                        run_start = index;
+                    }
+                    run_length += 1;
+                    if run_length == count {
+                        return Ok((data, run_start));
+                    }
+                } else {
+                    run_length = 0;
+                }
+            }
+
+            match directory {
+                Directory::FixedRoot => return Err(FsError::NoSpace),
+                Directory::Clusters(first_cluster) => {
+                    let last_cluster = *self.cluster_chain(first_cluster)?.last().unwrap();
+                    let cluster = self.allocate_cluster(Some(last_cluster))?;
+                    self.write_clusters(&[cluster], &[])?;
+                }
+            }
+        }
+    }
+
+    fn insert_entry(&mut self, directory: Directory, name: &str, attributes: u8, first_cluster: u32, size: u32) -> Result<(), FsError> {
+        let existing = Self::parse_entries(&self.load_directory(directory)?);
+        let (short_name, nt_flags, needs_long_name) = match short_name_for(name) {
+            Some((short_name, nt_flags)) => (short_name, nt_flags, false),
+            None => (generate_short_name(name, &existing)?, 0, true),
+        };
+
+        let characters: Vec<u16> = name.encode_utf16().collect();
+        let long_entries_count = if needs_long_name {
+            characters.len().div_ceil(LFN_CHARACTERS_PER_ENTRY)
+        } else {
+            0
+        };
+
+        let (mut data, first_slot) = self.find_free_slots(directory, long_entries_count + 1)?;
+        let checksum = short_name_checksum(&short_name);
+        for index in 0..long_entries_count {
+            let ordinal = (long_entries_count - index) as u8;
+            let slot = data.slot_mut(first_slot + index);
+            slot.fill(0);
+            slot[0] = if index == 0 { ordinal | LFN_LAST_ENTRY } else { ordinal };
+            slot[11] = ATTRIBUTE_LONG_NAME;
+            slot[13] = checksum;
+            let position = (ordinal as usize - 1) * LFN_CHARACTERS_PER_ENTRY;
+            for (offset_index, &offset) in LFN_CHARACTER_OFFSETS.iter().enumerate() {
+                let character = match characters.get(position + offset_index) {
+                    Some(&character) => character,
+                    None if position + offset_index == characters.len() => 0x0000,
+                    None => 0xFFFF,
+                };
+                write_u16(slot, offset, character);
+            }
+        }
+
+        let short_slot = first_slot + long_entries_count;
+        let slot = data.slot_mut(short_slot);
+        slot.fill(0);
+        slot[0..11].copy_from_slice(&short_name);
+        if slot[0] == DELETED_ENTRY {
+            slot[0] = ESCAPED_E5;
+        }
+        slot[11] = attributes;
+        slot[12] = nt_flags;
+        write_u16(slot, 16, DEFAULT_DATE);
+        write_u16(slot, 18, DEFAULT_DATE);
+        write_u16(slot, 24, DEFAULT_DATE);
+        write_u16(slot, 20, (first_cluster >> 16) as u16);
+        write_u16(slot, 26, first_cluster as u16);
+        write_u32(slot, 28, size);
+
+        self.store_slots(&data, first_slot, short_slot)
+    }
+
+    fn update_entry(&mut self, directory: Directory, entry: &RawEntry, first_cluster: u32, size: u32) -> Result<(), FsError> {
+        let mut data = self.load_directory(directory)?;
+        let slot = data.slot_mut(entry.slot);
+        write_u16(slot, 20, (first_cluster >> 16) as u16);
+        write_u16(slot, 26, first_cluster as u16);
+        write_u32(slot, 28, size);
+        self.store_slots(&data, entry.slot, entry.slot)
+    }
+
+    fn remove_entry(&mut self, directory: Directory, entry: &RawEntry) -> Result<(), FsError> {
+        let mut data = self.load_directory(directory)?;
+        for index in entry.first_slot..=entry.slot {
+            data.slot_mut(index)[0] = DELETED_ENTRY;
+        }
+        self.store_slots(&data, entry.first_slot, entry.slot)
+    }
+}
+
+impl FileSystem for FatFileSystem {
+    fn get_type_name(&self) -> &str {
+        match self.fat_type {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn get_label(&self) -> Option<String> {
+        self.label.clone()
+    }
+
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, FsError> {
+        let node = self.lookup(path)?;
+        let directory = self.directory_of(&node)?;
+        let data = self.load_directory(directory)?;
+        Ok(Self::parse_entries(&data).iter()
+            .filter(|entry| !entry.is_dot_entry())
+            .map(|entry| entry.to_directory_entry())
+            .collect())
+    }
+
+    fn stat(&mut self, path: &str) -> Result<DirectoryEntry, FsError> {
+        match self.lookup(path)? {
+            Node::Root => Ok(DirectoryEntry {
+                name: "/".into(),
+                kind: FileKind::Directory,
+                size: 0,
+            }),
+            Node::Entry(entry) => Ok(entry.to_directory_entry()),
+        }
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FsError> {
+        let entry = match self.lookup(path)? {
+            Node::Entry(entry) if !entry.is_directory() => entry,
+            _ => return Err(FsError::IsADirectory),
+        };
+
+        let chain = self.cluster_chain(entry.first_cluster)?;
+        let size = entry.size as usize;
+        if chain.len() * self.cluster_size() < size {
+            return Err(FsError::Corrupted("cluster chain shorter than file size"));
+        }
+
+        let mut content = Vec::with_capacity(chain.len() * self.cluster_size());
+        for cluster in chain {
+            content.extend(self.read_sectors(self.cluster_lba(cluster), self.sectors_per_cluster as usize)?);
+            if content.len() >= size {
+                break;
+            }
+        }
+        content.truncate(size);
+        Ok(content)
+    }
+
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), FsError> {
+        let size: u32 = data.len().try_into().map_err(|_| FsError::NoSpace)?;
+        let (directory, name) = self.lookup_parent(path)?;
+        let existing = self.find_entry(directory, name)?;
+        if existing.as_ref().is_some_and(|entry| entry.is_directory()) {
+            return Err(FsError::IsADirectory);
+        }
+
+        // The old content is freed only after the entry points at the new one, so it stays readable on errors
+        let clusters_count = data.len().div_ceil(self.cluster_size());
+        let chain = self.allocate_chain(clusters_count)?;
+        let first_cluster = chain.first().copied().unwrap_or(0);
+        let result = self.write_clusters(&chain, data).and_then(|_| match &existing {
+            Some(entry) => self.update_entry(directory, entry, first_cluster, size),
+            None => self.insert_entry(directory, name, ATTRIBUTE_ARCHIVE, first_cluster, size),
+        });
+        if let Err(error) = result {
+            if first_cluster != 0 {
+                self.free_chain(first_cluster)?;
+            }
+            return Err(error);
+        }
+
+        match existing {
+            Some(entry) if entry.first_cluster != 0 => self.free_chain(entry.first_cluster),
+            _ => Ok(()),
+        }
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = self.lookup_parent(path)?;
+        if self.find_entry(parent, name)?.is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let cluster = self.allocate_cluster(None)?;
+        let mut content = vec![0u8; self.cluster_size()];
+        let dot_entries = [(b".          ", cluster), (b"..         ", self.directory_cluster(parent))];
+        for (index, (dot_name, dot_cluster)) in dot_entries.iter().enumerate() {
+            let slot = &mut content[index * DIRECTORY_ENTRY_SIZE..(index + 1) * DIRECTORY_ENTRY_SIZE];
+            slot[0..11].copy_from_slice(*dot_name);
+            slot[11] = ATTRIBUTE_DIRECTORY;
+            write_u16(slot, 16, DEFAULT_DATE);
+            write_u16(slot, 18, DEFAULT_DATE);
+            write_u16(slot, 24, DEFAULT_DATE);
+            write_u16(slot, 20, (dot_cluster >> 16) as u16);
+            write_u16(slot, 26, *dot_cluster as u16);
+        }
+        self.write_clusters(&[cluster], &content)?;
+
+        let result = self.insert_entry(parent, name, ATTRIBUTE_DIRECTORY, cluster, 0);
+        if result.is_err() {
+            self.free_chain(cluster)?;
+        }
+        result
+    }
+
+    fn remove(&mut self, path: &str) -> Result<(), FsError> {
+        let (parent, name) = self.lookup_parent(path)?;
+        let entry = self.find_entry(parent, name)?.ok_or(FsError::NotFound)?;
+        if entry.is_dot_entry() {
+            return Err(FsError::InvalidName);
+        }
+        if entry.is_directory() {
+            let data = self.load_directory(Directory::Clusters(entry.first_cluster))?;
+            if Self::parse_entries(&data).iter().any(|child| !child.is_dot_entry()) {
+                return Err(FsError::DirectoryNotEmpty);
+            }
+        }
+
+        self.remove_entry(parent, &entry)?;
+        if entry.first_cluster != 0 {
+            self.free_chain(entry.first_cluster)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_valid_long_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "." && name != ".."
+        && name.encode_utf16().count() <= MAX_NAME_LENGTH
+        && !name.chars().any(|c| c < ' ' || "\\/:*?\"<>|".contains(c))
+}
+
+fn is_short_name_character(character: u8) -> bool {
+    character.is_ascii_uppercase() || character.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&character) || character >= 0x80
+}
+
+/// Returns the 8.3 form with its lowercase flags if the name can be stored without a long name
+fn short_name_for(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, extension) = match name.rfind('.') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 || !name.is_ascii() {
+        return None;
+    }
+    if name.find('.') != name.rfind('.') || name.ends_with('.') {
+        return None;
+    }
+
+    let mut nt_flags = 0;
+    for (part, flag) in [(base, NT_LOWERCASE_BASE), (extension, NT_LOWERCASE_EXTENSION)] {
+        let has_lowercase = part.bytes().any(|c| c.is_ascii_lowercase());
+        let has_uppercase = part.bytes().any(|c| c.is_ascii_uppercase());
+        match (has_lowercase, has_uppercase) {
+            (true, true) => return None,
+            (true, false) => nt_flags |= flag,
+            _ => {}
+        }
+    }
+
+    let mut short_name = [b' '; 11];
+    for (index, character) in base.bytes().enumerate() {
+        short_name[index] = character.to_ascii_uppercase();
+    }
+    for (index, character) in extension.bytes().enumerate() {
+        short_name[8 + index] = character.to_ascii_uppercase();
+    }
+    if short_name.iter().all(|&c| c == b' ' || is_short_name_character(c)) {
+        Some((short_name, nt_flags))
+    } else {
+        None
+    }
+}
+
+/// Generates a unique `BASIS~N.EXT` alias for a name that needs a long name entry
+fn generate_short_name(name: &str, existing: &[RawEntry]) -> Result<[u8; 11], FsError> {
+    if !is_valid_long_name(name) {
+        return Err(FsError::InvalidName);
+    }
+
+    let sanitize = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+                if c.is_ascii() && is_short_name_character(c as u8) { c as u8 } else { b'_' }
+            })
+            .collect()
+    };
+    let trimmed = name.trim_start_matches('.');
+    let (base, extension) = match trimmed.rfind('.') {
+        Some(index) => (sanitize(&trimmed[..index]), sanitize(&trimmed[index + 1..])),
+        None => (sanitize(trimmed), Vec::new()),
+    };
+
+    for number in 1..1_000_000 {
+        let tail = format!("~{}", number);
+        let base_length = base.len().min(8 - tail.len());
+        let mut short_name = [b' '; 11];
+        short_name[..base_length].copy_from_slice(&base[..base_length]);
+        short_name[base_length..base_length + tail.len()].copy_from_slice(tail.as_bytes());
+        for (index, &character) in extension.iter().take(3).enumerate() {
+            short_name[8 + index] = character;
+        }
+        if !existing.iter().any(|entry| entry.short_name == short_name) {
+            return Ok(short_name);
+        }
+    }
+    Err(FsError::AlreadyExists)
+}
+
+fn format_short_name(short_name: &[u8; 11], nt_flags: u8) -> String {
+    let convert = |part: &[u8], lowercase: bool| -> String {
+        part.iter()
+            .map(|&c| if lowercase { c.to_ascii_lowercase() } else { c } as char)
+            .collect::<String>()
+            .trim_end()
+            .into()
+    };
+    let base = convert(&short_name[0..8], nt_flags & NT_LOWERCASE_BASE != 0);
+    let extension = convert(&short_name[8..11], nt_flags & NT_LOWERCASE_EXTENSION != 0);
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+fn short_name_checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Builds a small FAT12 volume laid out the way `mkfs.vfat -F 12 -s 1 -r 64` would
+#[cfg(test)]
+fn make_fat12_volume() -> FatFileSystem {
+    use alloc::sync::Arc;
+    use spin::Mutex;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    const SECTORS_COUNT: u16 = 400;
+    const SECTORS_PER_FAT: u16 = 2;
+
+    let mut device = MockBlockDevice::new(SECTORS_COUNT as usize);
+    let image = device.get_data_mut();
+    image[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    image[3..11].copy_from_slice(b"mkfs.fat");
+    write_u16(image, 11, 512);
+    image[13] = 1;
+    write_u16(image, 14, 1);
+    image[16] = 2;
+    write_u16(image, 17, 64);
+    write_u16(image, 19, SECTORS_COUNT);
+    image[21] = 0xF8;
+    write_u16(image, 22, SECTORS_PER_FAT);
+    image[38] = 0x29;
+    image[43..54].copy_from_slice(b"TESTVOLUME ");
+    image[54..62].copy_from_slice(b"FAT12   ");
+    image[510..512].copy_from_slice(&BOOT_SIGNATURE);
+    for fat_index in 0..2 {
+        let offset = (1 + fat_index * SECTORS_PER_FAT as usize) * 512;
+        image[offset..offset + 3].copy_from_slice(&[0xF8, 0xFF, 0xFF]);
+    }
+
+    assert!(FatFileSystem::probe(&mut device));
+    FatFileSystem::new(Arc::new(Mutex::new(device))).unwrap()
+}
+
+#[cfg(test)]
+fn count_free_clusters(file_system: &mut FatFileSystem) -> usize {
+    (2..file_system.clusters_count + 2)
+        .filter(|&cluster| file_system.read_fat_entry(cluster).unwrap() == 0)
+        .count()
+}
+
+#[test_case]
+fn test_fat_write_and_read_files() {
+    let mut file_system = make_fat12_volume();
+    assert_eq!(file_system.get_type_name(), "fat12");
+    assert_eq!(file_system.get_label().as_deref(), Some("TESTVOLUME"));
+
+    let content: Vec<u8> = (0..1500u32).map(|i| (i % 251) as u8).collect();
+    file_system.write_file("/hello.txt", b"Hello, world!").unwrap();
+    file_system.write_file("/A long file name.text", &content).unwrap();
+
+    assert_eq!(file_system.read_file("/hello.txt").unwrap(), b"Hello, world!");
+    assert_eq!(file_system.read_file("/HELLO.TXT").unwrap(), b"Hello, world!");
+    assert_eq!(file_system.read_file("/a long file name.text").unwrap(), content);
+
+    let names: Vec<String> = file_system.read_dir("/").unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, ["hello.txt", "A long file name.text"]);
+
+    let data = file_system.load_directory(Directory::FixedRoot).unwrap();
+    let entries = FatFileSystem::parse_entries(&data);
+    assert_eq!(&entries[1].short_name, b"ALONGF~1TEX");
+    assert_eq!(entries[1].first_slot, 1);
+    assert_eq!(entries[1].slot, 3);
+}
+
+#[test_case]
+fn test_fat_overwrite_frees_clusters() {
+    let mut file_system = make_fat12_volume();
+    let free_clusters = count_free_clusters(&mut file_system);
+
+    file_system.write_file("/data.bin", &[0xAA; 2000]).unwrap();
+    assert_eq!(count_free_clusters(&mut file_system), free_clusters - 4);
+
+    file_system.write_file("/data.bin", &[0x55; 100]).unwrap();
+    assert_eq!(count_free_clusters(&mut file_system), free_clusters - 1);
+    assert_eq!(file_system.read_file("/data.bin").unwrap(), [0x55; 100]);
+
+    file_system.remove("/data.bin").unwrap();
+    assert_eq!(count_free_clusters(&mut file_system), free_clusters);
+    assert!(matches!(file_system.read_file("/data.bin"), Err(FsError::NotFound)));
+}
+
+#[test_case]
+fn test_fat_write_on_full_volume_keeps_old_file() {
+    let mut file_system = make_fat12_volume();
+    file_system.write_file("/old.txt", b"Old content").unwrap();
+    let free_clusters = count_free_clusters(&mut file_system);
+    file_system.write_file("/filler.bin", &vec![0xAA; (free_clusters - 1) * 512]).unwrap();
+
+    assert!(matches!(file_system.write_file("/old.txt", &[0x55; 1024]), Err(FsError::NoSpace)));
+    assert_eq!(file_system.read_file("/old.txt").unwrap(), b"Old content");
+    assert_eq!(count_free_clusters(&mut file_system), 1);
+}
+
+#[cfg(test)]
+fn open_test_image(image: &[u8]) -> FatFileSystem {
+    use alloc::sync::Arc;
+    use spin::Mutex;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut device = MockBlockDevice::from_image(image);
+    assert!(FatFileSystem::probe(&mut device));
+    FatFileSystem::new(Arc::new(Mutex::new(device))).unwrap()
+}
+
+#[cfg(test)]
+fn check_test_image(file_system: &mut FatFileSystem) {
+    let names: Vec<String> = file_system.read_dir("/").unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, ["docs", "hello.txt"]);
+    let mut names: Vec<String> = file_system.read_dir("/docs").unwrap().into_iter().map(|entry| entry.name).collect();
+    names.sort();
+    assert_eq!(names, ["A long file name.text", "big.bin", "readme.md"]);
+
+    assert_eq!(file_system.read_file("/hello.txt").unwrap(), b"Hello from FAT!\n");
+    assert_eq!(file_system.read_file("/docs/readme.md").unwrap(), b"# Documentation\n");
+    assert_eq!(file_system.read_file("/docs/A long file name.text").unwrap(), b"Long names are kept\n");
+    let big = file_system.read_file("/docs/big.bin").unwrap();
+    assert_eq!(big.len(), 20000);
+    assert!(big.iter().enumerate().all(|(index, &byte)| byte == (index % 251) as u8));
+
+    file_system.write_file("/docs/readme.md", b"# Changed").unwrap();
+    file_system.create_dir("/new").unwrap();
+    file_system.write_file("/new/file.txt", &[0x42; 3000]).unwrap();
+    assert_eq!(file_system.read_file("/docs/readme.md").unwrap(), b"# Changed");
+    assert_eq!(file_system.read_file("/new/file.txt").unwrap(), [0x42; 3000]);
+}
+
+#[test_case]
+fn test_fat16_image() {
+    let mut file_system = open_test_image(include_bytes!("fixtures/fat16.img"));
+    assert_eq!(file_system.get_type_name(), "fat16");
+    assert_eq!(file_system.get_label().as_deref(), Some("JUST-OS16"));
+    check_test_image(&mut file_system);
+}
+
+#[test_case]
+fn test_fat32_image() {
+    let mut file_system = open_test_image(include_bytes!("fixtures/fat32.img"));
+    assert_eq!(file_system.get_type_name(), "fat32");
+    assert_eq!(file_system.get_label().as_deref(), Some("JUST-OS32"));
+    check_test_image(&mut file_system);
+}
+
+#[test_case]
+fn test_fat_directories() {
+    let mut file_system = make_fat12_volume();
+    file_system.create_dir("/docs").unwrap();
+    file_system.create_dir("/docs/nested directory").unwrap();
+    file_system.write_file("/docs/nested directory/readme.md", b"# Readme").unwrap();
+
+    assert!(matches!(file_system.create_dir("/docs"), Err(FsError::AlreadyExists)));
+    assert!(matches!(file_system.remove("/docs"), Err(FsError::DirectoryNotEmpty)));
+    assert_eq!(file_system.read_file("/docs/nested directory/readme.md").unwrap(), b"# Readme");
+    assert_eq!(file_system.stat("/docs").unwrap().kind, FileKind::Directory);
+
+    for index in 0..40 {
+        file_system.write_file(&format!("/docs/file number {}.txt", index), b"x").unwrap();
+    }
+    assert_eq!(file_system.read_dir("/docs").unwrap().len(), 41);
+    assert_eq!(file_system.read_file("/docs/file number 39.txt").unwrap(), b"x");
+
+    for index in 0..40 {
+        file_system.remove(&format!("/docs/file number {}.txt", index)).unwrap();
+    }
+    file_system.remove("/docs/nested directory/readme.md").unwrap();
+    file_system.remove("/docs/nested directory").unwrap();
+    file_system.remove("/docs").unwrap();
+    assert!(file_system.read_dir("/").unwrap().is_empty());
+}
@@ -0,0 +1,89 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub kind: FileKind,
+    pub size: u64,
+}
+
+/// Paths passed to the file system are normalized and relative to its mount point, e.g. `/dir/file.txt`
+pub trait FileSystem: Send {
+    fn get_type_name(&self) -> &str;
+
+    fn get_label(&self) -> Option<String> {
+        None
+    }
+
+    fn read_dir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, FsError>;
+
+    fn stat(&mut self, path: &str) -> Result<DirectoryEntry, FsError>;
+
+    fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FsError>;
+
+    fn read_link(&mut self, _path: &str) -> Result<String, FsError> {
+        Err(FsError::Unsupported)
+    }
+
+    /// Creates the file or replaces its content
+    fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), FsError>;
+
+    fn create_dir(&mut self, path: &str) -> Result<(), FsError>;
+
+    fn remove(&mut self, path: &str) -> Result<(), FsError>;
+}
+
+#[derive(Debug)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    NoSpace,
+    InvalidName,
+    SymlinkLoop,
+    ReadOnly,
+    Unsupported,
+    Corrupted(&'static str),
+    Device(Box<dyn Error>),
+}
+
+impl Display for FsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FsError::NotFound => write!(f, "No such file or directory"),
+            FsError::NotADirectory => write!(f, "Not a directory"),
+            FsError::IsADirectory => write!(f, "Is a directory"),
+            FsError::AlreadyExists => write!(f, "File exists"),
+            FsError::DirectoryNotEmpty => write!(f, "Directory not empty"),
+            FsError::NoSpace => write!(f, "No space left on device"),
+            FsError::InvalidName => write!(f, "Invalid file name"),
+            FsError::SymlinkLoop => write!(f, "Too many levels of symbolic links"),
+            FsError::ReadOnly => write!(f, "Read-only file system"),
+            FsError::Unsupported => write!(f, "Operation not supported"),
+            FsError::Corrupted(reason) => write!(f, "File system corrupted: {}", reason),
+            FsError::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for FsError {}
+
+impl From<Box<dyn Error>> for FsError {
+    fn from(error: Box<dyn Error>) -> Self {
+        FsError::Device(error)
+    }
+}
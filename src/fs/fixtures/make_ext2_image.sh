@@ -0,0 +1,21 @@
+#!/bin/sh
+# Regenerates ext2.img used by the ext2 driver tests
+set -e
+
+cd "$(dirname "$0")"
+root=$(mktemp -d)
+trap 'rm -rf "$root"' EXIT
+
+mkdir "$root/docs"
+printf 'Hello from ext2!\n' > "$root/hello.txt"
+printf '# Documentation\n' > "$root/docs/readme.md"
+python3 -c "import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(20000)))" > "$root/docs/big.bin"
+ln -s docs/readme.md "$root/readme"
+ln -s /hello.txt "$root/docs/hello"
+ln -s loop "$root/loop"
+
+rm -f ext2.img
+truncate -s 64K ext2.img
+E2FSPROGS_FAKE_TIME=0 mke2fs -q -t ext2 -b 1024 -N 32 -L just-os \
+    -U 6a757374-2d6f-7300-0000-000000000000 -E hash_seed=6a757374-2d6f-7300-0000-000000000000 \
+    -d "$root" ext2.img
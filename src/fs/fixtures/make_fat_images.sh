@@ -0,0 +1,22 @@
+#!/bin/sh
+# Regenerates fat16.img and fat32.img used by the FAT driver tests (needs dosfstools and mtools)
+set -e
+
+cd "$(dirname "$0")"
+root=$(mktemp -d)
+trap 'rm -rf "$root"' EXIT
+
+mkdir "$root/docs"
+printf 'Hello from FAT!\n' > "$root/hello.txt"
+printf '# Documentation\n' > "$root/docs/readme.md"
+printf 'Long names are kept\n' > "$root/docs/A long file name.text"
+python3 -c "import sys; sys.stdout.buffer.write(bytes(i % 251 for i in range(20000)))" > "$root/docs/big.bin"
+
+# FAT16 needs at least 4085 clusters, mkfs.vfat formats also smaller volumes as FAT32 when asked for it
+make_image() {
+    rm -f "$1"
+    mkfs.vfat -C --invariant -F "$2" -s 1 -n "$3" "$1" "$4" > /dev/null
+    mcopy -s -i "$1" "$root/hello.txt" "$root/docs" ::/
+}
+make_image fat16.img 16 JUST-OS16 2200
+make_image fat32.img 32 JUST-OS32 1024
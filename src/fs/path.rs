@@ -0,0 +1,74 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Returns the absolute path without `.`, `..` and repeated slashes, relative paths are resolved from the root
+pub fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut normalized = String::new();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+pub fn components(path: &str) -> impl DoubleEndedIterator<Item=&str> {
+    path.split('/').filter(|component| !component.is_empty())
+}
+
+/// Splits the normalized path into the parent directory and the last component
+pub fn split_parent(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(index) => (&path[..index], &path[index + 1..]),
+        None => ("/", path),
+    }
+}
+
+/// Returns the remainder of the path inside the mount point if the path lies under it
+pub fn strip_mount_point<'a>(path: &'a str, mount_point: &str) -> Option<&'a str> {
+    if mount_point == "/" {
+        return Some(path);
+    }
+    let remainder = path.strip_prefix(mount_point)?;
+    if remainder.is_empty() {
+        Some("/")
+    } else if remainder.starts_with('/') {
+        Some(remainder)
+    } else {
+        None
+    }
+}
+
+#[test_case]
+fn test_normalize_path() {
+    assert_eq!(normalize(""), "/");
+    assert_eq!(normalize("/"), "/");
+    assert_eq!(normalize("mnt//disk/"), "/mnt/disk");
+    assert_eq!(normalize("/mnt/./disk/../other"), "/mnt/other");
+    assert_eq!(normalize("/.."), "/");
+}
+
+#[test_case]
+fn test_split_parent_and_strip_mount_point() {
+    assert_eq!(split_parent("/file.txt"), ("/", "file.txt"));
+    assert_eq!(split_parent("/mnt/disk/file.txt"), ("/mnt/disk", "file.txt"));
+
+    assert_eq!(strip_mount_point("/mnt/disk/a", "/mnt/disk"), Some("/a"));
+    assert_eq!(strip_mount_point("/mnt/disk", "/mnt/disk"), Some("/"));
+    assert_eq!(strip_mount_point("/mnt/disk2", "/mnt/disk"), None);
+    assert_eq!(strip_mount_point("/a", "/"), Some("/a"));
+}
@@ -0,0 +1,366 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::gdb::packet::{MAX_PACKET_SIZE, PacketWriter};
+use crate::interrupts::exception_frame::ExceptionFrame;
+use crate::memory;
+use crate::serial::{self, SerialChannel};
+use crate::serial::uart::Uart;
+
+pub mod packet;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const INT3_OPCODE: u8 = 0xCC;
+const MAX_BREAKPOINTS: usize = 32;
+const PAGE_SIZE: u64 = 4096;
+/// RAX-R15 and RIP, followed by 32-bit EFLAGS, CS, SS, DS, ES, FS and GS
+const GENERAL_REGISTERS_COUNT: usize = 17;
+const REGISTERS_SIZE: usize = GENERAL_REGISTERS_COUNT * 8 + 7 * 4;
+
+/// GDB sent a packet since the last detach, so it expects the stop replies
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+static STEPPING: AtomicBool = AtomicBool::new(false);
+static BREAK_REQUESTED: AtomicBool = AtomicBool::new(false);
+static BREAKPOINTS: Mutex<[Option<Breakpoint>; MAX_BREAKPOINTS]> = Mutex::new([None; MAX_BREAKPOINTS]);
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    original_byte: u8,
+}
+
+enum Action {
+    Reply,
+    Resume,
+    ReplyAndResume,
+}
+
+/// Asks to stop in the stub on the next debug exception, used when GDB sends data to the running kernel
+pub(crate) fn request_break() {
+    BREAK_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Stops in the stub, returns false if there is no port bound to the GDB channel
+pub(crate) fn handle_breakpoint(frame: &mut ExceptionFrame) -> bool {
+    let port = match serial::get_raw_port(SerialChannel::Gdb) {
+        Some(port) => port,
+        None => return false,
+    };
+    // The instruction pointer is past int3, GDB expects the address of its breakpoint
+    let address = frame.rip.wrapping_sub(1);
+    if BREAKPOINTS.lock().iter().flatten().any(|breakpoint| breakpoint.address == address) {
+        frame.rip = address;
+    }
+    Session { port, frame }.run(SIGTRAP);
+    true
+}
+
+/// Stops in the stub after a single step or a break request, returns false for other debug exceptions
+pub(crate) fn handle_debug(frame: &mut ExceptionFrame) -> bool {
+    let stepping = STEPPING.swap(false, Ordering::Relaxed);
+    let break_requested = BREAK_REQUESTED.swap(false, Ordering::Relaxed);
+    if !stepping && !break_requested {
+        return false;
+    }
+    frame.rflags &= !RFlags::TRAP_FLAG.bits();
+    if let Some(port) = serial::get_raw_port(SerialChannel::Gdb) {
+        Session { port, frame }.run(if stepping { SIGTRAP } else { SIGINT });
+    }
+    true
+}
+
+/// Conversation with GDB while the kernel is stopped, runs with interrupts disabled
+struct Session<'a> {
+    port: Uart,
+    frame: &'a mut ExceptionFrame,
+}
+
+impl Session<'_> {
+    fn run(&mut self, signal: u8) {
+        let mut input = [0; MAX_PACKET_SIZE];
+        let mut response = PacketWriter::new();
+        if ATTACHED.load(Ordering::Relaxed) {
+            response.push(b'S');
+            response.push_hex(&[signal]);
+            self.send_packet(response.as_bytes());
+        }
+
+        loop {
+            let length = self.receive_packet(&mut input);
+            ATTACHED.store(true, Ordering::Relaxed);
+            response.clear();
+            match self.handle_packet(&input[..length], signal, &mut response) {
+                Action::Reply => self.send_packet(response.as_bytes()),
+                Action::Resume => return,
+                Action::ReplyAndResume => {
+                    self.send_packet(response.as_bytes());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Unsupported packets get an empty response
+    fn handle_packet(&mut self, packet: &[u8], signal: u8, response: &mut PacketWriter) -> Action {
+        let (command, arguments) = match packet.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Action::Reply,
+        };
+        match command {
+            b'?' => {
+                response.push(b'S');
+                response.push_hex(&[signal]);
+            }
+            b'g' => {
+                for register in get_general_registers(self.frame) {
+                    response.push_hex(&register.to_le_bytes());
+                }
+                for register in [self.frame.rflags, self.frame.cs, self.frame.ss, 0, 0, 0, 0] {
+                    response.push_hex(&(register as u32).to_le_bytes());
+                }
+            }
+            b'G' => {
+                let mut registers = [0; REGISTERS_SIZE];
+                match packet::decode_hex(arguments, &mut registers) {
+                    Some(REGISTERS_SIZE) => {
+                        self.set_registers(&registers);
+                        response.push_str("OK");
+                    }
+                    _ => response.push_str("E01"),
+                }
+            }
+            b'm' => {
+                let mut memory = [0; MAX_PACKET_SIZE / 2];
+                match packet::parse_address_length(arguments) {
+                    Some((address, length)) => {
+                        let memory = &mut memory[..length.min(MAX_PACKET_SIZE / 2)];
+                        if read_memory(address, memory) {
+                            response.push_hex(memory);
+                        } else {
+                            response.push_str("E14");
+                        }
+                    }
+                    None => response.push_str("E01"),
+                }
+            }
+            b'M' => {
+                let mut memory = [0; MAX_PACKET_SIZE / 2];
+                let separator = arguments.iter().position(|byte| *byte == b':').unwrap_or(arguments.len());
+                let header = packet::parse_address_length(&arguments[..separator]);
+                let data = arguments.get(separator + 1..).and_then(|data| packet::decode_hex(data, &mut memory));
+                match (header, data) {
+                    (Some((address, length)), Some(decoded)) if length == decoded => {
+                        if write_memory(address, &memory[..length]) {
+                            response.push_str("OK");
+                        } else {
+                            response.push_str("E14");
+                        }
+                    }
+                    _ => response.push_str("E01"),
+                }
+            }
+            b'c' | b's' => {
+                if let Some(address) = packet::parse_hex(arguments) {
+                    self.frame.rip = address;
+                }
+                if command == b's' {
+                    STEPPING.store(true, Ordering::Relaxed);
+                    self.frame.rflags |= RFlags::TRAP_FLAG.bits();
+                }
+                return Action::Resume;
+            }
+            b'Z' | b'z' => {
+                // Only software breakpoints, "0,address,kind"
+                let address = match arguments.strip_prefix(b"0,") {
+                    Some(arguments) => packet::parse_address_length(arguments).map(|(address, _)| address),
+                    None => return Action::Reply,
+                };
+                let done = match address {
+                    Some(address) if command == b'Z' => insert_breakpoint(address),
+                    Some(address) => remove_breakpoint(address),
+                    None => false,
+                };
+                response.push_str(if done { "OK" } else { "E01" });
+            }
+            b'D' | b'k' => {
+                remove_all_breakpoints();
+                ATTACHED.store(false, Ordering::Relaxed);
+                if command == b'k' {
+                    return Action::Resume;
+                }
+                response.push_str("OK");
+                return Action::ReplyAndResume;
+            }
+            b'H' => response.push_str("OK"),
+            b'q' if arguments.starts_with(b"Supported") => {
+                response.push_str("PacketSize=");
+                response.push_hex(&(MAX_PACKET_SIZE as u16).to_be_bytes());
+            }
+            b'q' if arguments == b"Attached" => response.push(b'1'),
+            _ => {}
+        }
+        Action::Reply
+    }
+
+    fn set_registers(&mut self, registers: &[u8; REGISTERS_SIZE]) {
+        let values = registers.chunks_exact(8)
+            .map(|value| u64::from_le_bytes(value.try_into().unwrap()));
+        for (register, value) in get_general_registers(self.frame).into_iter().zip(values) {
+            *register = value;
+        }
+        let offset = GENERAL_REGISTERS_COUNT * 8;
+        let eflags = u32::from_le_bytes(registers[offset..offset + 4].try_into().unwrap());
+        self.frame.rflags = (self.frame.rflags & !0xFFFF_FFFF) | eflags as u64;
+    }
+
+    fn receive_byte(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.port.try_receive() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Waits for a packet with a valid checksum and acknowledges it, returns the length of its data
+    fn receive_packet(&mut self, buffer: &mut [u8; MAX_PACKET_SIZE]) -> usize {
+        loop {
+            while self.receive_byte() != b'$' {}
+
+            let mut length = 0;
+            let mut is_overflow = false;
+            loop {
+                match self.receive_byte() {
+                    b'#' => break,
+                    _ if length == MAX_PACKET_SIZE => is_overflow = true,
+                    byte => {
+                        buffer[length] = byte;
+                        length += 1;
+                    }
+                }
+            }
+            let checksum = [self.receive_byte(), self.receive_byte()];
+
+            if !is_overflow && packet::parse_hex(&checksum) == Some(packet::checksum(&buffer[..length]) as u64) {
+                self.port.send(b'+');
+                return length;
+            }
+            self.port.send(b'-');
+        }
+    }
+
+    /// Sends the packet until GDB acknowledges it
+    fn send_packet(&mut self, data: &[u8]) {
+        let mut checksum = PacketWriter::new();
+        checksum.push_hex(&[packet::checksum(data)]);
+        loop {
+            self.port.send(b'$');
+            data.iter().for_each(|byte| self.port.send(*byte));
+            self.port.send(b'#');
+            checksum.as_bytes().iter().for_each(|byte| self.port.send(*byte));
+            loop {
+                match self.receive_byte() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Registers in the order of the GDB x86-64 register set
+fn get_general_registers(frame: &mut ExceptionFrame) -> [&mut u64; GENERAL_REGISTERS_COUNT] {
+    [
+        &mut frame.rax, &mut frame.rbx, &mut frame.rcx, &mut frame.rdx,
+        &mut frame.rsi, &mut frame.rdi, &mut frame.rbp, &mut frame.rsp,
+        &mut frame.r8, &mut frame.r9, &mut frame.r10, &mut frame.r11,
+        &mut frame.r12, &mut frame.r13, &mut frame.r14, &mut frame.r15,
+        &mut frame.rip,
+    ]
+}
+
+/// Checks every page of the range, so the stub doesn't cause page faults
+fn is_accessible(address: u64, length: usize) -> bool {
+    let last_address = match address.checked_add(length.saturating_sub(1) as u64) {
+        Some(last_address) => last_address,
+        None => return false,
+    };
+    let mut page = address & !(PAGE_SIZE - 1);
+    loop {
+        match VirtAddr::try_new(page) {
+            Ok(page) if memory::is_mapped(page) => {}
+            _ => return false,
+        }
+        if page >= last_address & !(PAGE_SIZE - 1) {
+            return true;
+        }
+        page += PAGE_SIZE;
+    }
+}
+
+fn read_memory(address: u64, buffer: &mut [u8]) -> bool {
+    if !is_accessible(address, buffer.len()) {
+        return false;
+    }
+    for (offset, byte) in buffer.iter_mut().enumerate() {
+        *byte = unsafe { core::ptr::read_volatile((address as usize + offset) as *const u8) };
+    }
+    true
+}
+
+/// The kernel code is mapped read-only, so the write protection is lifted while writing
+fn write_memory(address: u64, data: &[u8]) -> bool {
+    if !is_accessible(address, data.len()) {
+        return false;
+    }
+    unsafe {
+        let cr0 = Cr0::read();
+        Cr0::write(cr0 & !Cr0Flags::WRITE_PROTECT);
+        for (offset, byte) in data.iter().enumerate() {
+            core::ptr::write_volatile((address as usize + offset) as *mut u8, *byte);
+        }
+        Cr0::write(cr0);
+    }
+    true
+}
+
+fn insert_breakpoint(address: u64) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().flatten().any(|breakpoint| breakpoint.address == address) {
+        return true;
+    }
+    let slot = match breakpoints.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => slot,
+        None => return false,
+    };
+    let mut original_byte = [0];
+    if !read_memory(address, &mut original_byte) || !write_memory(address, &[INT3_OPCODE]) {
+        return false;
+    }
+    *slot = Some(Breakpoint { address, original_byte: original_byte[0] });
+    true
+}
+
+fn remove_breakpoint(address: u64) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let slot = breakpoints.iter_mut()
+        .find(|slot| matches!(slot, Some(breakpoint) if breakpoint.address == address));
+    match slot.and_then(|slot| slot.take()) {
+        Some(breakpoint) => write_memory(breakpoint.address, &[breakpoint.original_byte]),
+        None => false,
+    }
+}
+
+fn remove_all_breakpoints() {
+    for slot in BREAKPOINTS.lock().iter_mut() {
+        if let Some(breakpoint) = slot.take() {
+            write_memory(breakpoint.address, &[breakpoint.original_byte]);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+/// Size of the packet data buffers, announced to GDB as `PacketSize`
+pub const MAX_PACKET_SIZE: usize = 1024;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+pub fn parse_hex(text: &[u8]) -> Option<u64> {
+    if text.is_empty() || text.len() > 16 {
+        return None;
+    }
+    text.iter().try_fold(0u64, |value, digit| Some(value << 4 | parse_hex_digit(*digit)? as u64))
+}
+
+/// Decodes pairs of hex digits into the output, returns the number of decoded bytes
+pub fn decode_hex(text: &[u8], output: &mut [u8]) -> Option<usize> {
+    if !text.len().is_multiple_of(2) || text.len() / 2 > output.len() {
+        return None;
+    }
+    for (byte, digits) in output.iter_mut().zip(text.chunks_exact(2)) {
+        *byte = parse_hex_digit(digits[0])? << 4 | parse_hex_digit(digits[1])?;
+    }
+    Some(text.len() / 2)
+}
+
+/// Parses "address,length" used by the memory packets
+pub fn parse_address_length(text: &[u8]) -> Option<(u64, usize)> {
+    let separator = text.iter().position(|byte| *byte == b',')?;
+    let address = parse_hex(&text[..separator])?;
+    let length = parse_hex(&text[separator + 1..])?;
+    Some((address, length as usize))
+}
+
+/// Builds the response in a fixed buffer, the stub doesn't allocate,
+/// because the stopped code may hold the lock of the heap
+pub struct PacketWriter {
+    buffer: [u8; MAX_PACKET_SIZE],
+    length: usize,
+}
+
+impl PacketWriter {
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; MAX_PACKET_SIZE],
+            length: 0,
+        }
+    }
+}
+
+impl PacketWriter {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+
+    pub fn clear(&mut self) {
+        self.length = 0;
+    }
+
+    /// Bytes over the size of the buffer are dropped
+    pub fn push(&mut self, byte: u8) {
+        if self.length < MAX_PACKET_SIZE {
+            self.buffer[self.length] = byte;
+            self.length += 1;
+        }
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        text.bytes().for_each(|byte| self.push(byte));
+    }
+
+    pub fn push_hex(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.push(HEX_DIGITS[(byte >> 4) as usize]);
+            self.push(HEX_DIGITS[(byte & 0xF) as usize]);
+        }
+    }
+}
+
+#[test_case]
+fn test_packet_encoding() {
+    assert_eq!(checksum(b"OK"), 0x9A);
+    assert_eq!(parse_address_length(b"ffff8000,10"), Some((0xFFFF_8000, 16)));
+    assert_eq!(parse_address_length(b"ffff8000"), None);
+
+    let mut writer = PacketWriter::new();
+    writer.push(b'S');
+    writer.push_hex(&[0x05, 0xAB]);
+    assert_eq!(writer.as_bytes(), b"S05ab");
+
+    let mut output = [0; 2];
+    assert_eq!(decode_hex(b"05aB", &mut output), Some(2));
+    assert_eq!(output, [0x05, 0xAB]);
+    assert_eq!(decode_hex(b"05a", &mut output), None);
+    assert_eq!(decode_hex(b"zz", &mut output), None);
+}
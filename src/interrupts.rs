@@ -1,22 +1,36 @@
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin::Mutex;
 use x86_64::{
     instructions::{self, port::Port},
+    registers::rflags::RFlags,
     structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
 };
 
-use crate::{gdt, log_debug, log_error};
+use crate::{gdt, log_debug};
+use crate::interrupts::exception_frame::ExceptionFrame;
+use crate::storage::ata::AtaChannelKind;
+
+pub mod exception_frame;
+pub mod exception_context;
 
 const PIC_1_OFFSET: u8 = 32;
 const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+type PciIrqHandler = Box<dyn Fn() + Send>;
+
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        // Both go through the entries saving all registers, so the GDB stub can access them
+        unsafe {
+            idt.debug.set_handler_addr(exception_frame::get_debug_entry());
+            idt.breakpoint.set_handler_addr(exception_frame::get_breakpoint_entry());
+        }
         unsafe {
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
@@ -25,10 +39,20 @@ lazy_static! {
         idt.page_fault.set_handler_fn(page_fault_handler);
         idt[ExternalInterrupt::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[ExternalInterrupt::Keyboard.as_usize()].set_handler_fn(keyboard_handler);
+        idt[ExternalInterrupt::Serial1.as_usize()].set_handler_fn(serial_1_handler);
+        idt[ExternalInterrupt::Serial2.as_usize()].set_handler_fn(serial_2_handler);
+        idt[ExternalInterrupt::PrimaryAta.as_usize()].set_handler_fn(primary_ata_handler);
+        idt[ExternalInterrupt::SecondaryAta.as_usize()].set_handler_fn(secondary_ata_handler);
+        idt[ExternalInterrupt::PciIrq5.as_usize()].set_handler_fn(pci_irq_5_handler);
+        idt[ExternalInterrupt::PciIrq9.as_usize()].set_handler_fn(pci_irq_9_handler);
+        idt[ExternalInterrupt::PciIrq10.as_usize()].set_handler_fn(pci_irq_10_handler);
+        idt[ExternalInterrupt::PciIrq11.as_usize()].set_handler_fn(pci_irq_11_handler);
         idt
     };
     static ref PICS: Mutex<ChainedPics> =
         Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+    static ref PCI_IRQ_HANDLERS: Mutex<BTreeMap<u8, Vec<PciIrqHandler>>> =
+        Mutex::new(BTreeMap::new());
 }
 
 static mut TIMER_HANDLER: Option<Box<dyn Fn()>> = None;
@@ -36,6 +60,12 @@ static mut TIMER_HANDLER: Option<Box<dyn Fn()>> = None;
 pub fn init() {
     IDT.load();
     unsafe { PICS.lock().initialize() };
+
+    unmask(ExternalInterrupt::Cascade);
+    unmask(ExternalInterrupt::Serial1);
+    unmask(ExternalInterrupt::Serial2);
+    unmask(ExternalInterrupt::PrimaryAta);
+    unmask(ExternalInterrupt::SecondaryAta);
 }
 
 pub fn enable() {
@@ -58,11 +88,40 @@ pub fn set_timer_handler(handler: Box<dyn Fn()>) {
     }
 }
 
+/// Adds a handler of the PCI interrupt line, lines can be shared by many devices,
+/// so the handler should check itself if the device raised the interrupt.
+/// Returns false if the line isn't supported.
+pub fn add_pci_irq_handler(irq_line: u8, handler: Box<dyn Fn() + Send>) -> bool {
+    let interrupt = match ExternalInterrupt::pci_irq(irq_line) {
+        Some(interrupt) => interrupt,
+        None => return false,
+    };
+    instructions::interrupts::without_interrupts(|| {
+        PCI_IRQ_HANDLERS.lock()
+            .entry(irq_line)
+            .or_default()
+            .push(handler);
+    });
+    unmask(interrupt);
+    true
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 enum ExternalInterrupt {
     Timer = PIC_1_OFFSET,
     Keyboard = PIC_1_OFFSET + 1,
+    Cascade = PIC_1_OFFSET + 2,
+    /// COM2 and COM4
+    Serial2 = PIC_1_OFFSET + 3,
+    /// COM1 and COM3
+    Serial1 = PIC_1_OFFSET + 4,
+    PrimaryAta = PIC_2_OFFSET + 6,
+    SecondaryAta = PIC_2_OFFSET + 7,
+    PciIrq5 = PIC_1_OFFSET + 5,
+    PciIrq9 = PIC_2_OFFSET + 1,
+    PciIrq10 = PIC_2_OFFSET + 2,
+    PciIrq11 = PIC_2_OFFSET + 3,
 }
 
 impl ExternalInterrupt {
@@ -73,17 +132,53 @@ impl ExternalInterrupt {
     fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
+
+    fn irq_line(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
+    }
+
+    fn pci_irq(irq_line: u8) -> Option<Self> {
+        match irq_line {
+            5 => Some(ExternalInterrupt::PciIrq5),
+            9 => Some(ExternalInterrupt::PciIrq9),
+            10 => Some(ExternalInterrupt::PciIrq10),
+            11 => Some(ExternalInterrupt::PciIrq11),
+            _ => None,
+        }
+    }
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    log_debug!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+fn unmask(interrupt: ExternalInterrupt) {
+    let irq_line = interrupt.irq_line();
+    let (mut data_port, bit): (Port<u8>, u8) = if irq_line < 8 {
+        (Port::new(0x21), irq_line)
+    } else {
+        (Port::new(0xA1), irq_line - 8)
+    };
+    unsafe {
+        let mask = data_port.read();
+        data_port.write(mask & !(1 << bit));
+    }
+}
+
+fn breakpoint_handler(frame: &mut ExceptionFrame) {
+    if !crate::gdb::handle_breakpoint(frame) {
+        log_debug!("EXCEPTION: BREAKPOINT\n{:#?}", frame);
+    }
+}
+
+fn debug_handler(frame: &mut ExceptionFrame) {
+    if !crate::gdb::handle_debug(frame) {
+        log_debug!("EXCEPTION: DEBUG\n{:#?}", frame);
+    }
 }
 
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     _error_code: u64,
 ) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+    exception_context::set_exception_context(&stack_frame);
+    panic!("EXCEPTION: DOUBLE FAULT");
 }
 
 extern "x86-interrupt" fn page_fault_handler(
@@ -92,15 +187,12 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
-    log_error!("EXCEPTION: PAGE FAULT");
-    log_error!("Accessed Address: {:?}", Cr2::read());
-    log_error!("Error Code: {:?}", error_code);
-    log_error!("{:#?}", stack_frame);
-    
-    loop {}
+    exception_context::set_exception_context(&stack_frame);
+    panic!("EXCEPTION: PAGE FAULT\nAccessed Address: {:?}\nError Code: {:?}", Cr2::read(), error_code);
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::task::timer::tick();
     unsafe {
         if let Some(handler) = &TIMER_HANDLER {
             handler();
@@ -123,3 +215,81 @@ extern "x86-interrupt" fn keyboard_handler(_stack_frame: InterruptStackFrame) {
             .notify_end_of_interrupt(ExternalInterrupt::Keyboard.as_u8())
     }
 }
+
+extern "x86-interrupt" fn serial_1_handler(mut stack_frame: InterruptStackFrame) {
+    if crate::serial::handle_interrupt(ExternalInterrupt::Serial1.irq_line()) {
+        request_debugger_break(&mut stack_frame);
+    }
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(ExternalInterrupt::Serial1.as_u8())
+    }
+}
+
+extern "x86-interrupt" fn serial_2_handler(mut stack_frame: InterruptStackFrame) {
+    if crate::serial::handle_interrupt(ExternalInterrupt::Serial2.irq_line()) {
+        request_debugger_break(&mut stack_frame);
+    }
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(ExternalInterrupt::Serial2.as_u8())
+    }
+}
+
+/// Sets the trap flag of the interrupted code, so the debug exception raised
+/// after the next instruction stops it in the GDB stub
+fn request_debugger_break(stack_frame: &mut InterruptStackFrame) {
+    crate::gdb::request_break();
+    unsafe {
+        stack_frame.as_mut().update(|frame| frame.cpu_flags |= RFlags::TRAP_FLAG.bits());
+    }
+}
+
+extern "x86-interrupt" fn primary_ata_handler(_stack_frame: InterruptStackFrame) {
+    crate::storage::ata::handle_interrupt(AtaChannelKind::Primary);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(ExternalInterrupt::PrimaryAta.as_u8())
+    }
+}
+
+extern "x86-interrupt" fn secondary_ata_handler(_stack_frame: InterruptStackFrame) {
+    crate::storage::ata::handle_interrupt(AtaChannelKind::Secondary);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(ExternalInterrupt::SecondaryAta.as_u8())
+    }
+}
+
+extern "x86-interrupt" fn pci_irq_5_handler(_stack_frame: InterruptStackFrame) {
+    handle_pci_irq(ExternalInterrupt::PciIrq5);
+}
+
+extern "x86-interrupt" fn pci_irq_9_handler(_stack_frame: InterruptStackFrame) {
+    handle_pci_irq(ExternalInterrupt::PciIrq9);
+}
+
+extern "x86-interrupt" fn pci_irq_10_handler(_stack_frame: InterruptStackFrame) {
+    handle_pci_irq(ExternalInterrupt::PciIrq10);
+}
+
+extern "x86-interrupt" fn pci_irq_11_handler(_stack_frame: InterruptStackFrame) {
+    handle_pci_irq(ExternalInterrupt::PciIrq11);
+}
+
+fn handle_pci_irq(interrupt: ExternalInterrupt) {
+    if let Some(handlers) = PCI_IRQ_HANDLERS.lock().get(&interrupt.irq_line()) {
+        for handler in handlers {
+            handler();
+        }
+    }
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(interrupt.as_u8())
+    }
+}
@@ -0,0 +1,67 @@
+use core::fmt::{Display, Formatter};
+
+use spin::Mutex;
+use x86_64::registers::control::{Cr0, Cr2, Cr3};
+use x86_64::structures::idt::{InterruptStackFrame, InterruptStackFrameValue};
+
+use crate::backtrace::Backtrace;
+
+/// Context of the exception, which is about to panic, used by the panic handler instead of its own
+static EXCEPTION_CONTEXT: Mutex<Option<ExceptionContext>> = Mutex::new(None);
+
+#[derive(Clone)]
+pub struct ExceptionContext {
+    pub backtrace: Backtrace,
+    pub stack_frame: InterruptStackFrameValue,
+}
+
+/// Registers shown after a panic, the ones from the stack frame are known only for exceptions
+pub struct Registers {
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    stack_frame: Option<InterruptStackFrameValue>,
+}
+
+/// Remembers the context of the exception handler before it panics.
+/// Called directly in the handler, so the backtrace starts at the interrupted instruction.
+#[inline(always)]
+pub fn set_exception_context(stack_frame: &InterruptStackFrame) {
+    let context = ExceptionContext {
+        backtrace: Backtrace::capture_exception(stack_frame.instruction_pointer),
+        stack_frame: **stack_frame,
+    };
+    if let Some(mut exception_context) = EXCEPTION_CONTEXT.try_lock() {
+        *exception_context = Some(context);
+    }
+}
+
+pub fn take_exception_context() -> Option<ExceptionContext> {
+    EXCEPTION_CONTEXT.try_lock()?.take()
+}
+
+impl Registers {
+    pub fn read(stack_frame: Option<InterruptStackFrameValue>) -> Self {
+        Self {
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read().as_u64(),
+            cr3: Cr3::read().0.start_address().as_u64(),
+            stack_frame,
+        }
+    }
+}
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "CR0={:#018x} CR2={:#018x} CR3={:#018x}", self.cr0, self.cr2, self.cr3)?;
+        match &self.stack_frame {
+            Some(stack_frame) => write!(
+                f, "RIP={:#018x} RSP={:#018x} RFLAGS={:#018x}",
+                stack_frame.instruction_pointer.as_u64(),
+                stack_frame.stack_pointer.as_u64(),
+                stack_frame.cpu_flags,
+            ),
+            None => write!(f, "RIP, RSP and RFLAGS are known only for exceptions"),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use core::arch::global_asm;
+
+use x86_64::VirtAddr;
+
+const DEBUG_VECTOR: u64 = 1;
+const BREAKPOINT_VECTOR: u64 = 3;
+
+/// All general purpose registers of the interrupted code followed by the frame pushed by the CPU.
+/// Unlike `InterruptStackFrame`, the registers can be inspected and modified by the handler.
+#[derive(Debug)]
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub vector: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+// The CPU aligns the stack to 16 bytes before pushing its 5 quadwords, after the vector
+// and 15 registers one more quadword is needed to keep the alignment required by the call.
+global_asm!(
+    ".global debug_exception_entry",
+    "debug_exception_entry:",
+    "    push {debug_vector}",
+    "    jmp exception_entry",
+    ".global breakpoint_exception_entry",
+    "breakpoint_exception_entry:",
+    "    push {breakpoint_vector}",
+    "    jmp exception_entry",
+    "exception_entry:",
+    "    push r15",
+    "    push r14",
+    "    push r13",
+    "    push r12",
+    "    push r11",
+    "    push r10",
+    "    push r9",
+    "    push r8",
+    "    push rbp",
+    "    push rdi",
+    "    push rsi",
+    "    push rdx",
+    "    push rcx",
+    "    push rbx",
+    "    push rax",
+    "    mov rdi, rsp",
+    "    cld",
+    "    sub rsp, 8",
+    "    call {dispatch}",
+    "    add rsp, 8",
+    "    pop rax",
+    "    pop rbx",
+    "    pop rcx",
+    "    pop rdx",
+    "    pop rsi",
+    "    pop rdi",
+    "    pop rbp",
+    "    pop r8",
+    "    pop r9",
+    "    pop r10",
+    "    pop r11",
+    "    pop r12",
+    "    pop r13",
+    "    pop r14",
+    "    pop r15",
+    "    add rsp, 8",
+    "    iretq",
+    debug_vector = const DEBUG_VECTOR,
+    breakpoint_vector = const BREAKPOINT_VECTOR,
+    dispatch = sym dispatch,
+);
+
+extern "C" {
+    fn debug_exception_entry();
+    fn breakpoint_exception_entry();
+}
+
+pub fn get_debug_entry() -> VirtAddr {
+    VirtAddr::new(debug_exception_entry as unsafe extern "C" fn() as usize as u64)
+}
+
+pub fn get_breakpoint_entry() -> VirtAddr {
+    VirtAddr::new(breakpoint_exception_entry as unsafe extern "C" fn() as usize as u64)
+}
+
+extern "C" fn dispatch(frame: &mut ExceptionFrame) {
+    match frame.vector {
+        DEBUG_VECTOR => super::debug_handler(frame),
+        BREAKPOINT_VECTOR => super::breakpoint_handler(frame),
+        _ => {}
+    }
+}
@@ -6,6 +6,7 @@ use core::fmt::Write;
 use crate::serial_print;
 
 static mut STANDARD_OUTPUT_WRITER: Option<Rc<RefCell<dyn Write>>> = None;
+static mut REDIRECTED_OUTPUT_WRITER: Option<Rc<RefCell<dyn Write>>> = None;
 
 pub fn set_standard_output_writer(writer: Rc<RefCell<dyn Write>>) {
     unsafe {
@@ -13,6 +14,35 @@ pub fn set_standard_output_writer(writer: Rc<RefCell<dyn Write>>) {
     }
 }
 
+/// Runs the function with the standard output going only to the writer, e.g. for a command of a console or the serial session
+pub fn with_standard_output_writer<R>(writer: Rc<RefCell<dyn Write>>, function: impl FnOnce() -> R) -> R {
+    let previous_writer = unsafe { REDIRECTED_OUTPUT_WRITER.replace(writer) };
+    let result = function();
+    unsafe {
+        REDIRECTED_OUTPUT_WRITER = previous_writer;
+    }
+    result
+}
+
+/// Standard output of a command, captured by the tasks it spawns to keep writing where the command did
+pub struct CapturedOutput(Option<Rc<RefCell<dyn Write>>>);
+
+// The commands and the tasks all run on the same CPU
+unsafe impl Send for CapturedOutput {}
+
+impl CapturedOutput {
+    pub fn capture() -> Self {
+        CapturedOutput(unsafe { REDIRECTED_OUTPUT_WRITER.clone() })
+    }
+
+    pub fn with<R>(&self, function: impl FnOnce() -> R) -> R {
+        match &self.0 {
+            Some(writer) => with_standard_output_writer(writer.clone(), function),
+            None => function(),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => (
@@ -33,6 +63,10 @@ macro_rules! println {
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     unsafe {
+        if let Some(writer) = &REDIRECTED_OUTPUT_WRITER {
+            writer.borrow_mut().write_fmt(args).unwrap();
+            return;
+        }
         if let Some(writer) = &STANDARD_OUTPUT_WRITER {
             writer.borrow_mut().write_fmt(args).unwrap();
         }
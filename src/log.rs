@@ -7,8 +7,20 @@ use core::fmt::{Display, Formatter};
 
 use lazy_static::lazy_static;
 use spin::Mutex;
+use x86::cpuid::CpuId;
 
-#[derive(Debug, Copy, Clone)]
+use crate::log::log_buffer::LogBuffer;
+use crate::task::{self, timer};
+
+pub mod log_buffer;
+pub mod log_facade;
+
+/// Number of the latest logs kept in the history
+pub const LOG_HISTORY_SIZE: usize = 512;
+
+pub static LOG_BUFFER: LogBuffer<LOG_HISTORY_SIZE> = LogBuffer::new();
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Level {
     DEBUG,
     INFO,
@@ -16,96 +28,261 @@ pub enum Level {
     ERROR,
 }
 
+impl Level {
+    pub const NAMES: [&'static str; 4] = ["debug", "info", "warning", "error"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::DEBUG),
+            "info" => Some(Level::INFO),
+            "warning" => Some(Level::WARNING),
+            "error" => Some(Level::ERROR),
+            _ => None,
+        }
+    }
+}
+
+/// Place in the code which emitted the log, captured by the `log_*!` macros
+#[derive(Debug, Copy, Clone)]
+pub struct Source {
+    pub module_path: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+}
+
+impl Source {
+    /// Returns the module path without the name of this crate, e.g. "net::dhcp".
+    /// Paths in other crates, which log through the `log` facade, are returned whole.
+    pub fn get_module(&self) -> &'static str {
+        strip_crate_name(self.module_path)
+    }
+}
+
+/// Strips the name of this crate from the module path, the module levels are set without it
+pub fn strip_crate_name(module_path: &str) -> &str {
+    match module_path.strip_prefix(env!("CARGO_CRATE_NAME")) {
+        Some("") => "",
+        Some(module) if module.starts_with("::") => &module[2..],
+        _ => module_path,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Log {
     pub level: Level,
     pub message: String,
+    /// Milliseconds since the boot
+    pub timestamp: u64,
+    pub source: Source,
+    pub cpu_id: u8,
+    pub task_id: Option<usize>,
+}
+
+struct Listener {
+    name: String,
+    minimum_level: Level,
+    callback: Box<dyn FnMut(Log)>,
 }
 
 pub struct Logger {
-    logs: Vec<Log>,
-    listeners: Vec<Box<dyn FnMut(Log)>>,
+    listeners: Vec<Listener>,
+    /// Minimum levels of the modules and their submodules, overriding the levels of the listeners
+    module_levels: Vec<(String, Level)>,
 }
 
 unsafe impl Send for Logger {}
 
 impl Logger {
-    pub fn new(capability: usize) -> Self {
+    pub fn new() -> Self {
         Logger {
-            logs: Vec::with_capacity(capability),
             listeners: Vec::new(),
+            module_levels: Vec::new(),
         }
     }
 
-    pub fn register_listener(&mut self, listener: Box<dyn FnMut(Log)>) {
-        self.listeners.push(listener);
+    pub fn register_listener(&mut self, name: &str, minimum_level: Level, listener: Box<dyn FnMut(Log)>) {
+        self.listeners.push(Listener {
+            name: name.to_string(),
+            minimum_level,
+            callback: listener,
+        });
+    }
+}
+
+impl Logger {
+    pub fn get_listeners(&self) -> impl Iterator<Item=(&str, Level)> {
+        self.listeners.iter().map(|listener| (listener.name.as_str(), listener.minimum_level))
+    }
+
+    /// Returns false if there is no listener with the name
+    pub fn set_listener_level(&mut self, name: &str, minimum_level: Level) -> bool {
+        match self.listeners.iter_mut().find(|listener| listener.name == name) {
+            Some(listener) => {
+                listener.minimum_level = minimum_level;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_module_levels(&self) -> &[(String, Level)] {
+        &self.module_levels
+    }
+
+    /// Sets the minimum level of the module, `None` removes the override
+    pub fn set_module_level(&mut self, module: &str, minimum_level: Option<Level>) {
+        self.module_levels.retain(|(other, _)| other != module);
+        if let Some(minimum_level) = minimum_level {
+            self.module_levels.push((module.to_string(), minimum_level));
+        }
     }
 }
 
 impl Logger {
-    fn log(&mut self, level: Level, message: &str) {
+    fn log(&mut self, level: Level, source: Source, message: &str) {
         let log = Log {
             level,
             message: message.to_string(),
+            timestamp: timer::get_uptime_ms(),
+            source,
+            cpu_id: get_cpu_id(),
+            task_id: task::get_current_task_id(),
         };
 
+        let module_level = self.get_module_level(source.get_module());
         for listener in &mut self.listeners {
-            listener(log.clone());
+            if level >= module_level.unwrap_or(listener.minimum_level) {
+                (listener.callback)(log.clone());
+            }
         }
 
-        self.logs.push(log);
+        LOG_BUFFER.push(&log);
+    }
+}
+
+impl Logger {
+    pub fn get_module_level(&self, module: &str) -> Option<Level> {
+        find_module_level(&self.module_levels, module)
     }
 }
 
+/// Finds the override of the module, the most specific one wins
+fn find_module_level(module_levels: &[(String, Level)], module: &str) -> Option<Level> {
+    module_levels.iter()
+        .filter(|(prefix, _)| match module.strip_prefix(prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, level)| *level)
+}
+
+fn get_cpu_id() -> u8 {
+    CpuId::new().get_feature_info()
+        .map_or(0, |feature_info| feature_info.initial_local_apic_id())
+}
+
 impl Display for Log {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "[{:?}] {}", self.level, self.message)
+        format_log(f, self.level, self.timestamp, &self.source, self.cpu_id, self.task_id, &self.message)
+    }
+}
+
+fn format_log(
+    f: &mut Formatter<'_>,
+    level: Level,
+    timestamp: u64,
+    source: &Source,
+    cpu_id: u8,
+    task_id: Option<usize>,
+    message: &str,
+) -> core::fmt::Result {
+    write!(f, "[{:>5}.{:03}] [{:?}] ", timestamp / 1000, timestamp % 1000, level)?;
+    match task_id {
+        Some(task_id) => write!(f, "cpu{}/task{} ", cpu_id, task_id)?,
+        None => write!(f, "cpu{} ", cpu_id)?,
+    }
+    write!(f, "{}", source.get_module())?;
+    // The location helps finding the cause of problems, for other levels the module is enough
+    if level >= Level::WARNING {
+        write!(f, " ({}:{})", source.file, source.line)?;
     }
+    write!(f, ": {}", message)
 }
 
 lazy_static! {
     pub static ref KERNEL_LOGGER: Mutex<Logger> =
-        Mutex::new(Logger::new(512));
+        Mutex::new(Logger::new());
 }
 
 #[doc(hidden)]
-pub fn log(level: Level, message: &str) {
+pub fn log(level: Level, source: Source, message: &str) {
     use x86_64::instructions::interrupts;
 
     interrupts::without_interrupts(|| {
         let mut logger = KERNEL_LOGGER.lock();
-        logger.log(level, message);
+        logger.log(level, source, message);
     });
 }
 
+#[doc(hidden)]
 #[macro_export]
-macro_rules! log_debug {
-    ($($args:tt)*) => {{
+macro_rules! log_with_level {
+    ($level:expr, $($args:tt)*) => {{
         use alloc::format;
-        $crate::log::log($crate::log::Level::DEBUG, format!($($args)*).as_str());
+        let source = $crate::log::Source {
+            module_path: module_path!(),
+            file: file!(),
+            line: line!(),
+        };
+        $crate::log::log($level, source, format!($($args)*).as_str());
     }};
 }
 
+#[macro_export]
+macro_rules! log_debug {
+    ($($args:tt)*) => {
+        $crate::log_with_level!($crate::log::Level::DEBUG, $($args)*)
+    };
+}
+
 #[macro_export]
 macro_rules! log_info {
-    ($($args:tt)*) => {{
-        use alloc::format;
-        $crate::log::log($crate::log::Level::INFO, format!($($args)*).as_str());
-    }};
+    ($($args:tt)*) => {
+        $crate::log_with_level!($crate::log::Level::INFO, $($args)*)
+    };
 }
 
 #[macro_export]
 macro_rules! log_warning {
-    ($($args:tt)*) => {{
-        use alloc::format;
-        $crate::log::log($crate::log::Level::WARNING, format!($($args)*).as_str());
-    }};
+    ($($args:tt)*) => {
+        $crate::log_with_level!($crate::log::Level::WARNING, $($args)*)
+    };
 }
 
 #[macro_export]
 macro_rules! log_error {
-    ($($args:tt)*) => {{
-        use alloc::format;
-        $crate::log::log($crate::log::Level::ERROR, format!($($args)*).as_str());
-    }};
+    ($($args:tt)*) => {
+        $crate::log_with_level!($crate::log::Level::ERROR, $($args)*)
+    };
+}
+
+#[test_case]
+fn test_find_module_level() {
+    let module_levels = [
+        (String::from("net"), Level::WARNING),
+        (String::from("net::dhcp"), Level::DEBUG),
+    ];
+    assert_eq!(find_module_level(&module_levels, "net::tcp"), Some(Level::WARNING));
+    assert_eq!(find_module_level(&module_levels, "net::dhcp"), Some(Level::DEBUG));
+    assert_eq!(find_module_level(&module_levels, "network"), None);
+    assert_eq!(find_module_level(&module_levels, "storage"), None);
+}
+
+#[test_case]
+fn test_strip_crate_name() {
+    let crate_name = env!("CARGO_CRATE_NAME");
+    assert_eq!(strip_crate_name(&alloc::format!("{}::net::dhcp", crate_name)), "net::dhcp");
+    assert_eq!(strip_crate_name(crate_name), "");
+    assert_eq!(strip_crate_name("virtio_drivers::device::blk"), "virtio_drivers::device::blk");
 }
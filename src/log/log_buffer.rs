@@ -0,0 +1,182 @@
+use alloc::string::String;
+use core::cell::UnsafeCell;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicU64, Ordering, fence};
+
+use futures_util::task::AtomicWaker;
+
+use crate::log::{self, Level, Log, Source};
+
+/// Longer messages are truncated in the history, the listeners still get them whole
+const MAX_MESSAGE_LENGTH: usize = 160;
+
+/// Copy of a log kept in the history, reading it doesn't allocate
+#[derive(Clone, Copy)]
+pub struct LogRecord {
+    level: Level,
+    timestamp: u64,
+    source: Source,
+    cpu_id: u8,
+    task_id: Option<usize>,
+    message: [u8; MAX_MESSAGE_LENGTH],
+    message_length: usize,
+}
+
+impl LogRecord {
+    pub fn get_level(&self) -> Level {
+        self.level
+    }
+
+    pub fn get_message(&self) -> &str {
+        // Truncated at a char boundary when pushed
+        core::str::from_utf8(&self.message[..self.message_length]).unwrap_or("")
+    }
+}
+
+impl Display for LogRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        log::format_log(f, self.level, self.timestamp, &self.source, self.cpu_id, self.task_id, self.get_message())
+    }
+}
+
+/// Holds one record guarded by a sequence lock, the sequence is odd while the record is written,
+/// otherwise it's `2 * (index + 1)` of the record, where the index counts all logs since the boot
+struct Slot {
+    sequence: AtomicU64,
+    record: UnsafeCell<Option<LogRecord>>,
+}
+
+/// Fixed-size history of the logs, the newest entries overwrite the oldest ones.
+/// Reading never blocks and never waits for the writers, so it's also safe in the panic handler.
+pub struct LogBuffer<const CAPACITY: usize> {
+    slots: [Slot; CAPACITY],
+    next_index: AtomicU64,
+    /// Records before this index were cleared
+    first_index: AtomicU64,
+    waker: AtomicWaker,
+}
+
+unsafe impl<const CAPACITY: usize> Sync for LogBuffer<CAPACITY> {}
+
+impl<const CAPACITY: usize> LogBuffer<CAPACITY> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { Slot { sequence: AtomicU64::new(0), record: UnsafeCell::new(None) } }; CAPACITY],
+            next_index: AtomicU64::new(0),
+            first_index: AtomicU64::new(0),
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+impl<const CAPACITY: usize> LogBuffer<CAPACITY> {
+    pub fn push(&self, log: &Log) {
+        let mut message = [0; MAX_MESSAGE_LENGTH];
+        let mut message_length = log.message.len().min(MAX_MESSAGE_LENGTH);
+        while !log.message.is_char_boundary(message_length) {
+            message_length -= 1;
+        }
+        message[..message_length].copy_from_slice(&log.message.as_bytes()[..message_length]);
+        let record = LogRecord {
+            level: log.level,
+            timestamp: log.timestamp,
+            source: log.source,
+            cpu_id: log.cpu_id,
+            task_id: log.task_id,
+            message,
+            message_length,
+        };
+
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[index as usize % CAPACITY];
+        slot.sequence.store(2 * index + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe { slot.record.get().write_volatile(Some(record)) };
+        slot.sequence.store(2 * (index + 1), Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// Returns the log with the index, or `None` if it was overwritten, cleared or isn't written yet
+    pub fn get(&self, index: u64) -> Option<Log> {
+        let record = self.get_record(index)?;
+        Some(Log {
+            level: record.level,
+            message: String::from(record.get_message()),
+            timestamp: record.timestamp,
+            source: record.source,
+            cpu_id: record.cpu_id,
+            task_id: record.task_id,
+        })
+    }
+
+    /// Like `get`, but without allocating the message
+    pub fn get_record(&self, index: u64) -> Option<LogRecord> {
+        if index < self.get_first_index() {
+            return None;
+        }
+        let slot = &self.slots[index as usize % CAPACITY];
+        let sequence = slot.sequence.load(Ordering::Acquire);
+        if sequence != 2 * (index + 1) {
+            return None;
+        }
+        let record = unsafe { slot.record.get().read_volatile() };
+        fence(Ordering::Acquire);
+        if slot.sequence.load(Ordering::Relaxed) != sequence {
+            return None;
+        }
+
+        record
+    }
+
+    /// Index of the oldest log still in the history
+    pub fn get_first_index(&self) -> u64 {
+        let next_index = self.get_next_index();
+        self.first_index.load(Ordering::Relaxed)
+            .max(next_index.saturating_sub(CAPACITY as u64))
+    }
+
+    /// Index the next pushed log will get
+    pub fn get_next_index(&self) -> u64 {
+        self.next_index.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        self.first_index.store(self.get_next_index(), Ordering::Relaxed);
+    }
+
+    /// Registers the waker woken by the next pushed log
+    pub fn register_waker(&self, waker: &core::task::Waker) {
+        self.waker.register(waker);
+    }
+
+    /// Wakes the registered waker without a new log, e.g. to let a follower notice it should stop
+    pub fn wake(&self) {
+        self.waker.wake();
+    }
+}
+
+#[test_case]
+fn test_log_buffer_overwrites_oldest_logs() {
+    let buffer = LogBuffer::<4>::new();
+    let source = Source { module_path: "just_os::log", file: "src/log.rs", line: 1 };
+    for number in 0..6 {
+        buffer.push(&Log {
+            level: Level::INFO,
+            message: alloc::format!("log {}", number),
+            timestamp: number,
+            source,
+            cpu_id: 0,
+            task_id: None,
+        });
+    }
+    assert_eq!(buffer.get_first_index(), 2);
+    assert_eq!(buffer.get_next_index(), 6);
+    assert!(buffer.get(1).is_none());
+    assert_eq!(buffer.get(2).unwrap().message, "log 2");
+    assert_eq!(buffer.get(5).unwrap().message, "log 5");
+    assert!(buffer.get(6).is_none());
+
+    buffer.clear();
+    assert!(buffer.get(5).is_none());
+    assert_eq!(buffer.get_first_index(), 6);
+}
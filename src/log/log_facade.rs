@@ -0,0 +1,70 @@
+use alloc::format;
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::log::{KERNEL_LOGGER, Level, Source, strip_crate_name};
+
+/// Passes the records of the `log` crate, used by third-party crates, to `KERNEL_LOGGER`.
+/// The targets are filtered by the module levels of the logger, e.g. `log module virtio_drivers warning`.
+pub struct LogFacade;
+
+static LOG_FACADE: LogFacade = LogFacade;
+
+pub fn init() {
+    ::log::set_logger(&LOG_FACADE).expect("the logger of the log facade already set");
+    ::log::set_max_level(::log::LevelFilter::Trace);
+}
+
+impl From<::log::Level> for Level {
+    /// There is no trace level in the kernel, such records are logged as debug
+    fn from(level: ::log::Level) -> Self {
+        match level {
+            ::log::Level::Error => Level::ERROR,
+            ::log::Level::Warn => Level::WARNING,
+            ::log::Level::Info => Level::INFO,
+            ::log::Level::Debug | ::log::Level::Trace => Level::DEBUG,
+        }
+    }
+}
+
+impl From<Level> for ::log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => ::log::Level::Error,
+            Level::WARNING => ::log::Level::Warn,
+            Level::INFO => ::log::Level::Info,
+            Level::DEBUG => ::log::Level::Debug,
+        }
+    }
+}
+
+impl ::log::Log for LogFacade {
+    fn enabled(&self, metadata: &::log::Metadata) -> bool {
+        let level = Level::from(metadata.level());
+        let module = strip_crate_name(metadata.target());
+        without_interrupts(|| KERNEL_LOGGER.lock().get_module_level(module))
+            .is_none_or(|minimum_level| level >= minimum_level)
+    }
+
+    fn log(&self, record: &::log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let source = Source {
+            module_path: record.module_path_static().unwrap_or(""),
+            file: record.file_static().unwrap_or(""),
+            line: record.line().unwrap_or(0),
+        };
+        crate::log::log(record.level().into(), source, format!("{}", record.args()).as_str());
+    }
+
+    fn flush(&self) {}
+}
+
+#[test_case]
+fn test_level_mapping() {
+    assert_eq!(Level::from(::log::Level::Warn), Level::WARNING);
+    assert_eq!(Level::from(::log::Level::Trace), Level::DEBUG);
+    assert_eq!(::log::Level::from(Level::ERROR), ::log::Level::Error);
+    assert_eq!(::log::Level::from(Level::from(::log::Level::Info)), ::log::Level::Info);
+}
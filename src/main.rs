@@ -20,20 +20,45 @@ use core::panic::PanicInfo;
 
 use bootloader::{BootInfo, entry_point};
 use spin::Mutex;
-use x86_64::VirtAddr;
+use x86_64::{PhysAddr, VirtAddr};
 
 #[cfg(test)]
 use qemu_exit::{ExitCode, qemu_exit};
 
+use crate::backtrace::Backtrace;
 use crate::command::command_register::CommandRegister;
 use crate::command::cpuid_command::cpuid_command;
+use crate::command::completion::complete_path_argument;
+use crate::command::dmesg_command::{dmesg_command, dmesg_completion};
+use crate::command::gdb_command::gdb_command;
+use crate::command::log_command::{log_command, log_completion};
+use crate::command::lsblk_command::lsblk_command;
+use crate::command::mount_command::{mount_command, mount_completion, umount_command, umount_completion};
+use crate::command::ls_command::ls_command;
+use crate::command::cat_command::cat_command;
+use crate::command::write_command::{write_command, write_completion};
+use crate::command::mkdir_command::mkdir_command;
+use crate::command::rm_command::rm_command;
+use crate::command::cache_command::cache_command;
+use crate::command::ifconfig_command::ifconfig_command;
+use crate::command::netsend_command::netsend_command;
+use crate::command::ping_command::ping_command;
 use crate::command::ping_pong_command::ping_pong_command;
-use crate::log::KERNEL_LOGGER;
+use crate::command::serial_command::{serial_command, serial_completion};
+use crate::command::tcpsend_command::tcpsend_command;
+use crate::command::tcplisten_command::tcplisten_command;
+use crate::command::udpsend_command::udpsend_command;
+use crate::interrupts::exception_context::{self, Registers};
+use crate::log::{KERNEL_LOGGER, Level, log_facade};
 use crate::rtc::RTC;
+use crate::serial::SerialChannel;
 use crate::task::executor::Executor;
-use crate::task::keyboard;
+use crate::task::{keyboard, serial_shell, timer};
 use crate::tui::panic_screen::PanicScreen;
-use crate::tui::terminal_screen::{Header, TerminalScreen};
+use crate::tui::header_bar::Header;
+use crate::tui::log_screen::LogScreen;
+use crate::tui::terminal_screen::TerminalScreen;
+use crate::tui::virtual_consoles::{ConsoleScreen, VirtualConsoles};
 use crate::vga_video::{VGA_FRAME_BUFFER};
 use crate::vga_video::cursor::VgaCursor;
 
@@ -51,73 +76,178 @@ mod serial;
 mod error;
 mod command;
 mod io;
+mod storage;
+mod pci;
+mod virtio;
+mod crc32;
+mod fs;
+mod net;
+mod gdb;
+mod backtrace;
+mod crash_log;
 
 #[cfg(test)]
 mod qemu_exit;
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Lines of each console kept after scrolling out of the screen. A full-width line takes about 190 bytes
+/// of the heap (2 bytes per character and the bookkeeping), so all the consoles take up to about 2.2 MiB.
+const SCROLLBACK_SIZE: usize = 2000;
+/// Consoles with a shell on Alt+F1..F5, the kernel log is on the next one
+const TERMINAL_CONSOLES_COUNT: usize = 5;
 
 entry_point!(kernel_main);
 
 #[no_mangle]
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    serial::init();
+    let crash_log_address: Option<PhysAddr>;
     unsafe {
         serial_println!("Physical memory offset: {:#x}", boot_info.physical_memory_offset);
         let mut mapper = memory::init(VirtAddr::new(boot_info.physical_memory_offset));
         let mut frame_allocator = memory::BootInfoFrameAllocator::new(&boot_info.memory_map);
+        crash_log_address = frame_allocator.reserve_last_frames(crash_log::CRASH_LOG_FRAMES);
 
         allocator::init(&mut mapper, &mut frame_allocator)
             .expect("heap allocator initialization failed");
+        memory::MemoryManager::init(mapper, frame_allocator);
     }
 
-    KERNEL_LOGGER.lock().register_listener(Box::new(move |log| {
-        serial_println!("LOG: {}", &log);
+    KERNEL_LOGGER.lock().register_listener("serial", Level::DEBUG, Box::new(move |log| {
+        serial::print_to(SerialChannel::Log, format_args!("LOG: {}\n", log));
     }));
 
+    log_facade::init();
+
     log_info!("{} (ver. {})", PKG_NAME, PKG_VERSION);
 
+    match crash_log_address {
+        Some(address) => crash_log::init(VirtAddr::new(boot_info.physical_memory_offset + address.as_u64())),
+        None => log_warning!("No memory for the crash log"),
+    }
+
+    for (index, com_port) in serial::COM_PORTS.iter().enumerate() {
+        if let Some(config) = serial::get_port_config(index) {
+            log_info!("Serial port {} at {:#x}, {}", com_port.name, com_port.base, config);
+        }
+    }
+
     interrupts::init();
     log_info!("Interrupts initialized");
 
+    timer::init();
+    log_info!("Timer initialized ({} Hz)", timer::TICKS_PER_SECOND);
+
     gdt::init();
     log_info!("GDT initialized");
 
     interrupts::enable();
     log_info!("Interrupts enabled");
 
+    storage::init();
+    log_info!("Storage initialized");
+
+    net::init();
+    log_info!("Network initialized");
+
     let mut command_register = CommandRegister::new();
-    command_register.register("ping", Box::new(ping_pong_command));
+    command_register.register("pingpong", Box::new(ping_pong_command));
     command_register.register("cpuid", Box::new(cpuid_command));
+    command_register.register("lsblk", Box::new(lsblk_command));
+    command_register.register_with_completer("mount", Box::new(mount_command), Box::new(mount_completion));
+    command_register.register_with_completer("umount", Box::new(umount_command), Box::new(umount_completion));
+    command_register.register_with_completer("ls", Box::new(ls_command), Box::new(complete_path_argument));
+    command_register.register_with_completer("cat", Box::new(cat_command), Box::new(complete_path_argument));
+    command_register.register_with_completer("write", Box::new(write_command), Box::new(write_completion));
+    command_register.register_with_completer("mkdir", Box::new(mkdir_command), Box::new(complete_path_argument));
+    command_register.register_with_completer("rm", Box::new(rm_command), Box::new(complete_path_argument));
+    command_register.register("cache", Box::new(cache_command));
+    command_register.register("ifconfig", Box::new(ifconfig_command));
+    command_register.register("netsend", Box::new(netsend_command));
+    command_register.register("ping", Box::new(ping_command));
+    command_register.register("tcpsend", Box::new(tcpsend_command));
+    command_register.register("tcplisten", Box::new(tcplisten_command));
+    command_register.register("udpsend", Box::new(udpsend_command));
+    command_register.register_with_completer("serial", Box::new(serial_command), Box::new(serial_completion));
+    command_register.register("gdb", Box::new(gdb_command));
+    command_register.register_with_completer("log", Box::new(log_command), Box::new(log_completion));
+    command_register.register_with_completer("dmesg", Box::new(dmesg_command), Box::new(dmesg_completion));
+
+    let command_register = Rc::new(command_register);
+    let serial_command_register = command_register.clone();
 
     let rtc = Rc::new(Mutex::new(RTC::new()));
-    let cursor = Rc::new(Mutex::new(VgaCursor::new()));
-    let mut terminal_screen = TerminalScreen::new(
+    let mut virtual_consoles = VirtualConsoles::new(
         unsafe { &VGA_FRAME_BUFFER },
-        Header::new(String::from(PKG_NAME), String::from(PKG_VERSION)),
-        rtc.clone(),
-        String::from("> "),
-        cursor,
-        Box::new(move |command| command_register.perform(command)),
+        Rc::new(Mutex::new(VgaCursor::new())),
     );
-    terminal_screen.begin();
-
-    io::set_standard_output_writer(terminal_screen.get_standard_output());
+    // Output written outside of any command goes to the first terminal
+    let mut standard_output = None;
+    for number in 1..=TERMINAL_CONSOLES_COUNT {
+        let command_register = command_register.clone();
+        let completion_command_register = command_register.clone();
+        virtual_consoles.add_console(|frame_buffer, cursor| {
+            let mut terminal_screen = TerminalScreen::new(
+                frame_buffer,
+                Header::new(String::from(PKG_NAME), String::from(PKG_VERSION), format!("tty{}", number)),
+                rtc.clone(),
+                format!("tty{}> ", number),
+                cursor,
+                Box::new(move |command| command_register.perform(command)),
+                Box::new(move |line| completion_command_register.complete(line)),
+            );
+            terminal_screen.enable_scrollback(SCROLLBACK_SIZE);
+            terminal_screen.begin();
+            standard_output.get_or_insert_with(|| terminal_screen.get_standard_output());
+            ConsoleScreen::Terminal(Rc::new(terminal_screen))
+        });
+    }
+    let mut log_screen = None;
+    virtual_consoles.add_console(|frame_buffer, cursor| {
+        let screen = Rc::new(LogScreen::new(
+            frame_buffer,
+            Header::new(String::from(PKG_NAME), String::from(PKG_VERSION), String::from("Kernel log")),
+            rtc.clone(),
+            cursor,
+        ));
+        screen.enable_scrollback(SCROLLBACK_SIZE);
+        screen.begin();
+        log_screen = Some(screen.clone());
+        ConsoleScreen::Log(screen)
+    });
+    virtual_consoles.switch_to(0);
+    if let Some(standard_output) = standard_output {
+        io::set_standard_output_writer(standard_output);
+    }
 
-    let terminal_screen = Rc::new(terminal_screen);
-    let terminal_screen_2 = terminal_screen.clone();
+    let virtual_consoles = Rc::new(virtual_consoles);
+    let virtual_consoles_2 = virtual_consoles.clone();
     interrupts::set_timer_handler(Box::new(move || {
-        terminal_screen_2.refresh_header();
+        if timer::get_ticks().is_multiple_of(timer::TICKS_PER_SECOND / 10) {
+            virtual_consoles_2.refresh_headers();
+        }
     }));
 
     #[cfg(test)]
     test_main();
 
     let mut executor = Executor::new();
-    let terminal_screen_3 = terminal_screen.clone();
-    executor.spawn(keyboard::keyboard_decoding_task(Box::new(move |key| {
-        terminal_screen_3.handle_keypress(key);
+    executor.spawn(keyboard::keyboard_decoding_task(Box::new(move |key, modifiers| {
+        virtual_consoles.handle_keypress(key, modifiers);
+    })));
+    if let Some(log_screen) = log_screen {
+        executor.spawn(log_screen.follow());
+    }
+    executor.spawn(serial_shell::serial_shell_task(String::from("> "), Box::new(move |command| {
+        serial_command_register.perform(command);
     })));
+    executor.spawn(storage::block_cache::write_back_task());
+    for (name, device) in net::NETWORK_DEVICES.lock().iter() {
+        executor.spawn(net::receive_task(name.clone(), device.clone()));
+        executor.spawn(net::dhcp::dhcp_task(name.clone()));
+    }
+    executor.spawn(net::timer_task());
     executor.run();
 }
 
@@ -134,11 +264,27 @@ unsafe fn panic(info: &PanicInfo) -> ! {
     if !PANIC_LOOP_GUARD {
         PANIC_LOOP_GUARD = true;
 
+        let exception_context = exception_context::take_exception_context();
+        let backtrace = match &exception_context {
+            Some(exception_context) => exception_context.backtrace.clone(),
+            None => Backtrace::capture(),
+        };
+        let registers = Registers::read(exception_context.map(|exception_context| exception_context.stack_frame));
+        crash_log::write(info, backtrace.clone());
+
         serial_println!("[PANIC!]");
         serial_println!("{:#?}", info);
+        serial_println!("{}", registers);
+        serial_println!("Stack trace:");
+        for (index, frame) in backtrace.clone().enumerate() {
+            serial_println!("  #{} {}", index, frame);
+        }
+
+        let mut panic_screen = PanicScreen::new(&VGA_FRAME_BUFFER, info, backtrace, registers);
+        panic_screen.display();
 
-        let mut panic_screen = PanicScreen::new(&VGA_FRAME_BUFFER);
-        panic_screen.display(info);
+        #[cfg(not(test))]
+        panic_screen.handle_keys();
     }
 
     #[cfg(test)]
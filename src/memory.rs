@@ -1,5 +1,9 @@
+use core::ops::Range;
+
+use conquer_once::spin::OnceCell;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
-use x86_64::{PhysAddr, structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB}, VirtAddr};
+use spin::Mutex;
+use x86_64::{PhysAddr, structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate}, VirtAddr};
 
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
     let level_4_table = active_level_4_table(physical_memory_offset);
@@ -21,17 +25,37 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    /// Physical addresses never handed out by the allocator
+    reserved: Range<u64>,
 }
 
 impl BootInfoFrameAllocator {
     pub unsafe fn new(memory_map: &'static MemoryMap) -> Self {
         BootInfoFrameAllocator {
             memory_map,
-            next: 0
+            next: 0,
+            reserved: 0..0,
         }
     }
 }
 
+impl BootInfoFrameAllocator {
+    /// Reserves frames at the end of the highest usable region, they are at the same place on every boot
+    /// with the same memory, so the content can be preserved across warm reboots.
+    /// Must be called before any frame is allocated.
+    pub fn reserve_last_frames(&mut self, count: usize) -> Option<PhysAddr> {
+        assert_eq!(self.next, 0, "frames reserved after the allocation");
+        let size = (count * Self::FRAME_SIZE) as u64;
+        let region = self.memory_map.iter()
+            .filter(|region| region.region_type == MemoryRegionType::Usable)
+            .filter(|region| region.range.end_addr() - region.range.start_addr() >= size)
+            .max_by_key(|region| region.range.end_addr())?;
+        let end = region.range.end_addr() & !(Self::FRAME_SIZE as u64 - 1);
+        self.reserved = (end - size)..end;
+        Some(PhysAddr::new(end - size))
+    }
+}
+
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
         let frame = self.usable_frames().nth(self.next);
@@ -43,6 +67,25 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
 impl BootInfoFrameAllocator {
     const FRAME_SIZE: usize = 4096;
 
+    fn allocate_contiguous_frames(&mut self, count: usize) -> Option<PhysFrame> {
+        let mut first_frame: Option<PhysFrame> = None;
+        let mut found = 0;
+        for (index, frame) in self.usable_frames().enumerate().skip(self.next) {
+            match first_frame {
+                Some(first) if frame == first + found as u64 => found += 1,
+                _ => {
+                    first_frame = Some(frame);
+                    found = 1;
+                }
+            }
+            if found == count {
+                self.next = index + 1;
+                return first_frame;
+            }
+        }
+        None
+    }
+
     fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
         
         let regions = self.memory_map.iter();
@@ -52,12 +95,113 @@ impl BootInfoFrameAllocator {
         let addresses_ranges = usable_regions.map(
             |region| region.range.start_addr()..region.range.end_addr()
         );
+        let reserved = self.reserved.clone();
         let frames_addresses = addresses_ranges.flat_map(
             |range| range.step_by(Self::FRAME_SIZE)
-        );
+        ).filter(move |frame_address| !reserved.contains(frame_address));
         frames_addresses.map(
             |frame_address| PhysFrame::containing_address(PhysAddr::new(frame_address))
         )
     }
 }
 
+const MMIO_START: u64 = 0x_5555_0000_0000;
+
+static MEMORY_MANAGER: OnceCell<Mutex<MemoryManager>> = OnceCell::uninit();
+
+/// Owns the page table mapper and the frame allocator after the heap is set up,
+/// so drivers can allocate DMA memory and map device registers
+pub struct MemoryManager {
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BootInfoFrameAllocator,
+    next_mmio_address: VirtAddr,
+}
+
+impl MemoryManager {
+    pub fn init(mapper: OffsetPageTable<'static>, frame_allocator: BootInfoFrameAllocator) {
+        MEMORY_MANAGER
+            .try_init_once(|| Mutex::new(MemoryManager {
+                mapper,
+                frame_allocator,
+                next_mmio_address: VirtAddr::new(MMIO_START),
+            }))
+            .expect("MemoryManager::init should only be called once");
+    }
+
+    fn get() -> &'static Mutex<MemoryManager> {
+        MEMORY_MANAGER
+            .try_get()
+            .expect("memory manager uninitialized")
+    }
+}
+
+/// Physically contiguous memory which can be handed to devices for DMA.
+/// The region is never freed, drivers are expected to allocate it once.
+#[derive(Debug)]
+pub struct DmaRegion {
+    pub physical_address: PhysAddr,
+    pub virtual_address: VirtAddr,
+    pub size: usize,
+}
+
+impl DmaRegion {
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.virtual_address.as_mut_ptr()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.size) }
+    }
+}
+
+unsafe impl Send for DmaRegion {}
+
+pub fn allocate_dma(size: usize) -> Option<DmaRegion> {
+    let frames_count = size.div_ceil(BootInfoFrameAllocator::FRAME_SIZE);
+    let mut memory_manager = MemoryManager::get().lock();
+    let first_frame = memory_manager.frame_allocator.allocate_contiguous_frames(frames_count)?;
+
+    let physical_address = first_frame.start_address();
+    let virtual_address = memory_manager.mapper.phys_offset() + physical_address.as_u64();
+    let size = frames_count * BootInfoFrameAllocator::FRAME_SIZE;
+    unsafe {
+        core::ptr::write_bytes(virtual_address.as_mut_ptr::<u8>(), 0, size);
+    }
+    Some(DmaRegion { physical_address, virtual_address, size })
+}
+
+/// Maps device registers as uncached memory and returns the address of the first byte
+pub fn map_mmio(physical_address: PhysAddr, size: usize) -> VirtAddr {
+    let mut memory_manager = MemoryManager::get().lock();
+    let MemoryManager { mapper, frame_allocator, next_mmio_address } = &mut *memory_manager;
+
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(physical_address);
+    let last_frame = PhysFrame::<Size4KiB>::containing_address(physical_address + (size - 1));
+    let first_page = Page::<Size4KiB>::containing_address(*next_mmio_address);
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH;
+    for (index, frame) in PhysFrame::range_inclusive(first_frame, last_frame).enumerate() {
+        let page = first_page + index as u64;
+        unsafe {
+            mapper.map_to(page, frame, flags, frame_allocator)
+                .expect("mapping MMIO failed")
+                .flush();
+        }
+        *next_mmio_address = page.start_address() + Page::<Size4KiB>::SIZE;
+    }
+
+    first_page.start_address() + (physical_address - first_frame.start_address())
+}
+
+/// Checks if the address is mapped without waiting for the lock, so it can be used by exception handlers.
+/// Returns false also if the memory manager is uninitialized or busy.
+pub fn is_mapped(address: VirtAddr) -> bool {
+    MEMORY_MANAGER.try_get().ok()
+        .and_then(|memory_manager| memory_manager.try_lock())
+        .is_some_and(|memory_manager| memory_manager.mapper.translate_addr(address).is_some())
+}
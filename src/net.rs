@@ -0,0 +1,75 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::log_info;
+use crate::net::network_device::{FrameStream, NetworkDevice, SharedNetworkDevice};
+use crate::net::network_device_register::NetworkDeviceRegister;
+use crate::net::stack::NetworkStack;
+use crate::task::timer;
+
+pub mod arp;
+pub mod dhcp;
+pub mod e1000;
+pub mod ethernet;
+pub mod icmp;
+pub mod ipv4;
+pub mod network_device;
+pub mod network_device_register;
+pub mod stack;
+pub mod tcp;
+pub mod udp;
+pub mod virtio_net;
+
+const TIMER_INTERVAL_MS: u64 = 100;
+
+lazy_static! {
+    pub static ref NETWORK_DEVICES: Mutex<NetworkDeviceRegister> =
+        Mutex::new(NetworkDeviceRegister::new());
+
+    pub static ref NETWORK_STACK: Mutex<NetworkStack> =
+        Mutex::new(NetworkStack::new());
+}
+
+pub fn init() {
+    let devices = virtio_net::detect_devices();
+    for device in devices {
+        register_device(&format!("VirtIO at {}", device.get_pci_address()), device);
+    }
+    let devices = e1000::detect_devices();
+    for device in devices {
+        register_device(&format!("e1000 at {}", device.get_pci_address()), device);
+    }
+}
+
+fn register_device(location: &str, device: impl NetworkDevice + 'static) {
+    let mut devices = NETWORK_DEVICES.lock();
+    let name = format!("eth{}", devices.iter().count());
+    log_info!("{}: {}, MAC {}, link {}", name, location, device.get_mac_address(),
+        if device.is_link_up() { "up" } else { "down" });
+    let device: SharedNetworkDevice = Arc::new(Mutex::new(device));
+    devices.register(&name, device.clone());
+    NETWORK_STACK.lock().add_interface(&name, device);
+}
+
+/// Passes frames of the device to the network stack as they arrive
+pub async fn receive_task(name: String, device: SharedNetworkDevice) {
+    let interface_index = NETWORK_STACK.lock().find_interface(&name)
+        .expect("registered device has an interface");
+    let mut frames = FrameStream::new(device);
+    while let Some(frame) = frames.next().await {
+        NETWORK_STACK.lock().handle_frame(interface_index, &frame);
+    }
+}
+
+/// Drives retransmissions and expiration in the network stack
+pub async fn timer_task() {
+    loop {
+        timer::sleep(TIMER_INTERVAL_MS).await;
+        NETWORK_STACK.lock().poll_timers();
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::net::ethernet::{ETHER_TYPE_ARP, ETHER_TYPE_IPV4};
+use crate::net::ipv4::Ipv4Address;
+use crate::net::network_device::MacAddress;
+use crate::net::stack::NetworkStack;
+use crate::task::timer;
+
+const HARDWARE_TYPE_ETHERNET: u16 = 1;
+const ARP_PACKET_SIZE: usize = 28;
+const OPERATION_REQUEST: u16 = 1;
+const OPERATION_REPLY: u16 = 2;
+
+const ENTRY_LIFETIME_MS: u64 = 300_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: u16,
+    pub sender_mac: MacAddress,
+    pub sender_address: Ipv4Address,
+    pub target_mac: MacAddress,
+    pub target_address: Ipv4Address,
+}
+
+impl ArpPacket {
+    /// Parses the packet, only Ethernet hardware and IPv4 protocol addresses are supported
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < ARP_PACKET_SIZE
+            || u16::from_be_bytes([packet[0], packet[1]]) != HARDWARE_TYPE_ETHERNET
+            || u16::from_be_bytes([packet[2], packet[3]]) != ETHER_TYPE_IPV4
+            || packet[4] != 6 || packet[5] != 4 {
+            return None;
+        }
+        Some(Self {
+            operation: u16::from_be_bytes([packet[6], packet[7]]),
+            sender_mac: MacAddress::from_bytes(&packet[8..14]),
+            sender_address: Ipv4Address::from_bytes(&packet[14..18]),
+            target_mac: MacAddress::from_bytes(&packet[18..24]),
+            target_address: Ipv4Address::from_bytes(&packet[24..28]),
+        })
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(ARP_PACKET_SIZE);
+        packet.extend_from_slice(&HARDWARE_TYPE_ETHERNET.to_be_bytes());
+        packet.extend_from_slice(&ETHER_TYPE_IPV4.to_be_bytes());
+        packet.extend_from_slice(&[6, 4]);
+        packet.extend_from_slice(&self.operation.to_be_bytes());
+        packet.extend_from_slice(&self.sender_mac.0);
+        packet.extend_from_slice(&self.sender_address.0);
+        packet.extend_from_slice(&self.target_mac.0);
+        packet.extend_from_slice(&self.target_address.0);
+        packet
+    }
+}
+
+pub struct ArpCache {
+    /// Hardware address with the tick the entry expires at
+    entries: BTreeMap<Ipv4Address, (MacAddress, u64)>,
+}
+
+impl ArpCache {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, address: Ipv4Address) -> Option<MacAddress> {
+        self.entries.get(&address)
+            .filter(|(_, expires)| *expires > timer::get_ticks())
+            .map(|(mac_address, _)| *mac_address)
+    }
+
+    pub fn contains(&self, address: Ipv4Address) -> bool {
+        self.entries.contains_key(&address)
+    }
+
+    pub fn insert(&mut self, address: Ipv4Address, mac_address: MacAddress) {
+        let expires = timer::get_ticks() + timer::ms_to_ticks(ENTRY_LIFETIME_MS);
+        self.entries.insert(address, (mac_address, expires));
+    }
+
+    pub fn remove_expired(&mut self) {
+        let ticks = timer::get_ticks();
+        self.entries.retain(|_, (_, expires)| *expires > ticks);
+    }
+}
+
+impl NetworkStack {
+    pub(super) fn handle_arp(&mut self, interface_index: usize, payload: &[u8]) {
+        let packet = match ArpPacket::parse(payload) {
+            Some(packet) => packet,
+            None => return,
+        };
+        let interface = &self.interfaces[interface_index];
+        let own_address = match &interface.config {
+            Some(config) => config.address,
+            None => return,
+        };
+
+        // Merges the sender like in RFC 826, existing entries are refreshed even if the request isn't for us
+        let for_us = packet.target_address == own_address;
+        if for_us || self.arp_cache.contains(packet.sender_address) {
+            self.arp_cache.insert(packet.sender_address, packet.sender_mac);
+            self.flush_pending_packets(packet.sender_address, packet.sender_mac);
+        }
+
+        if for_us && packet.operation == OPERATION_REQUEST {
+            let reply = ArpPacket {
+                operation: OPERATION_REPLY,
+                sender_mac: self.interfaces[interface_index].mac_address,
+                sender_address: own_address,
+                target_mac: packet.sender_mac,
+                target_address: packet.sender_address,
+            };
+            let _ = self.send_frame(interface_index, packet.sender_mac, ETHER_TYPE_ARP, &reply.to_bytes());
+        }
+    }
+
+    pub(super) fn send_arp_request(&mut self, interface_index: usize, target_address: Ipv4Address) {
+        let interface = &self.interfaces[interface_index];
+        let request = ArpPacket {
+            operation: OPERATION_REQUEST,
+            sender_mac: interface.mac_address,
+            sender_address: interface.config.as_ref().map_or(Ipv4Address::UNSPECIFIED, |config| config.address),
+            target_mac: MacAddress::default(),
+            target_address,
+        };
+        let _ = self.send_frame(interface_index, MacAddress::BROADCAST, ETHER_TYPE_ARP, &request.to_bytes());
+    }
+}
+
+#[test_case]
+fn test_arp_packet_round_trip() {
+    let packet = ArpPacket {
+        operation: OPERATION_REQUEST,
+        sender_mac: MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+        sender_address: Ipv4Address([10, 0, 2, 15]),
+        target_mac: MacAddress::default(),
+        target_address: Ipv4Address([10, 0, 2, 2]),
+    };
+    let bytes = packet.to_bytes();
+    assert_eq!(bytes.len(), ARP_PACKET_SIZE);
+    assert_eq!(&bytes[..8], &[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+    assert_eq!(ArpPacket::parse(&bytes), Some(packet));
+    assert_eq!(ArpPacket::parse(&bytes[..27]), None);
+}
@@ -0,0 +1,434 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::task::{Poll, Waker};
+
+use futures_util::future::poll_fn;
+
+use crate::net::NETWORK_STACK;
+use crate::net::ipv4::{self, Ipv4Address};
+use crate::net::network_device::MacAddress;
+use crate::net::stack::{Ipv4Config, NetError, NetworkStack, Route};
+use crate::net::udp;
+use crate::task::timer;
+use crate::{log_info, log_warning};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+const OPERATION_REQUEST: u8 = 1;
+const OPERATION_REPLY: u8 = 2;
+const HARDWARE_TYPE_ETHERNET: u8 = 1;
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Size of the fixed part of the message, up to and including the magic cookie
+const FIXED_PART_SIZE: usize = 240;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVER: u8 = 6;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_IDENTIFIER: u8 = 54;
+const OPTION_PARAMETER_REQUEST_LIST: u8 = 55;
+const OPTION_RENEWAL_TIME: u8 = 58;
+const OPTION_REBINDING_TIME: u8 = 59;
+const OPTION_END: u8 = 255;
+
+pub const MESSAGE_DISCOVER: u8 = 1;
+pub const MESSAGE_OFFER: u8 = 2;
+pub const MESSAGE_REQUEST: u8 = 3;
+pub const MESSAGE_ACK: u8 = 5;
+pub const MESSAGE_NAK: u8 = 6;
+
+const MAX_QUEUED_REPLIES: usize = 8;
+const REPLY_TIMEOUT_MS: u64 = 4000;
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_DELAY_MS: u64 = 10_000;
+/// Interval between renewal requests until the server answers
+const RENEWAL_RETRY_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhcpOptions {
+    pub message_type: Option<u8>,
+    pub subnet_mask: Option<Ipv4Address>,
+    pub routers: Vec<Ipv4Address>,
+    pub dns_servers: Vec<Ipv4Address>,
+    pub requested_address: Option<Ipv4Address>,
+    pub server_identifier: Option<Ipv4Address>,
+    /// Times in seconds
+    pub lease_time: Option<u32>,
+    pub renewal_time: Option<u32>,
+    pub rebinding_time: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpMessage {
+    pub operation: u8,
+    pub transaction_id: u32,
+    pub flags: u16,
+    pub client_address: Ipv4Address,
+    pub your_address: Ipv4Address,
+    pub client_mac: MacAddress,
+    pub options: DhcpOptions,
+}
+
+impl DhcpMessage {
+    pub fn new_request(transaction_id: u32, client_mac: MacAddress, message_type: u8) -> Self {
+        Self {
+            operation: OPERATION_REQUEST,
+            transaction_id,
+            flags: 0,
+            client_address: Ipv4Address::UNSPECIFIED,
+            your_address: Ipv4Address::UNSPECIFIED,
+            client_mac,
+            options: DhcpOptions {
+                message_type: Some(message_type),
+                ..DhcpOptions::default()
+            },
+        }
+    }
+
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        if message.len() < FIXED_PART_SIZE || message[1] != HARDWARE_TYPE_ETHERNET || message[2] != 6
+            || message[236..240] != MAGIC_COOKIE {
+            return None;
+        }
+
+        let mut options = DhcpOptions::default();
+        let mut index = FIXED_PART_SIZE;
+        while index < message.len() {
+            let code = message[index];
+            if code == OPTION_END {
+                break;
+            }
+            if code == OPTION_PAD {
+                index += 1;
+                continue;
+            }
+            let length = *message.get(index + 1)? as usize;
+            let value = message.get(index + 2..index + 2 + length)?;
+            let address = || (length >= 4).then(|| Ipv4Address::from_bytes(value));
+            let seconds = || (length >= 4).then(|| u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+            match code {
+                OPTION_MESSAGE_TYPE if length >= 1 => options.message_type = Some(value[0]),
+                OPTION_SUBNET_MASK => options.subnet_mask = address(),
+                OPTION_ROUTER => options.routers = value.chunks_exact(4).map(Ipv4Address::from_bytes).collect(),
+                OPTION_DNS_SERVER => options.dns_servers = value.chunks_exact(4).map(Ipv4Address::from_bytes).collect(),
+                OPTION_REQUESTED_ADDRESS => options.requested_address = address(),
+                OPTION_SERVER_IDENTIFIER => options.server_identifier = address(),
+                OPTION_LEASE_TIME => options.lease_time = seconds(),
+                OPTION_RENEWAL_TIME => options.renewal_time = seconds(),
+                OPTION_REBINDING_TIME => options.rebinding_time = seconds(),
+                _ => {}
+            }
+            index += 2 + length;
+        }
+
+        Some(Self {
+            operation: message[0],
+            transaction_id: u32::from_be_bytes([message[4], message[5], message[6], message[7]]),
+            flags: u16::from_be_bytes([message[10], message[11]]),
+            client_address: Ipv4Address::from_bytes(&message[12..16]),
+            your_address: Ipv4Address::from_bytes(&message[16..20]),
+            client_mac: MacAddress::from_bytes(&message[28..34]),
+            options,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(FIXED_PART_SIZE + 64);
+        message.extend_from_slice(&[self.operation, HARDWARE_TYPE_ETHERNET, 6, 0]);
+        message.extend_from_slice(&self.transaction_id.to_be_bytes());
+        message.extend_from_slice(&[0, 0]);
+        message.extend_from_slice(&self.flags.to_be_bytes());
+        message.extend_from_slice(&self.client_address.0);
+        message.extend_from_slice(&self.your_address.0);
+        message.extend_from_slice(&[0; 8]);
+        message.extend_from_slice(&self.client_mac.0);
+        message.resize(236, 0);
+        message.extend_from_slice(&MAGIC_COOKIE);
+
+        let options = &self.options;
+        if let Some(message_type) = options.message_type {
+            message.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type]);
+        }
+        let mut push_addresses = |code: u8, addresses: &[Ipv4Address]| {
+            if !addresses.is_empty() {
+                message.extend_from_slice(&[code, (addresses.len() * 4) as u8]);
+                addresses.iter().for_each(|address| message.extend_from_slice(&address.0));
+            }
+        };
+        push_addresses(OPTION_SUBNET_MASK, options.subnet_mask.as_slice());
+        push_addresses(OPTION_ROUTER, &options.routers);
+        push_addresses(OPTION_DNS_SERVER, &options.dns_servers);
+        push_addresses(OPTION_REQUESTED_ADDRESS, options.requested_address.as_slice());
+        push_addresses(OPTION_SERVER_IDENTIFIER, options.server_identifier.as_slice());
+        for (code, seconds) in [
+            (OPTION_LEASE_TIME, options.lease_time),
+            (OPTION_RENEWAL_TIME, options.renewal_time),
+            (OPTION_REBINDING_TIME, options.rebinding_time),
+        ] {
+            if let Some(seconds) = seconds {
+                message.extend_from_slice(&[code, 4]);
+                message.extend_from_slice(&seconds.to_be_bytes());
+            }
+        }
+        if self.operation == OPERATION_REQUEST {
+            message.extend_from_slice(&[OPTION_PARAMETER_REQUEST_LIST, 6,
+                OPTION_SUBNET_MASK, OPTION_ROUTER, OPTION_DNS_SERVER,
+                OPTION_LEASE_TIME, OPTION_RENEWAL_TIME, OPTION_REBINDING_TIME]);
+        }
+        message.push(OPTION_END);
+        message
+    }
+}
+
+/// Replies addressed to the client of an interface
+pub(super) struct DhcpClientState {
+    replies: VecDeque<DhcpMessage>,
+    waker: Option<Waker>,
+}
+
+impl NetworkStack {
+    /// Takes a datagram for the client port, returns false if the interface has no DHCP client
+    pub(super) fn handle_dhcp(&mut self, interface_index: usize, data: &[u8]) -> bool {
+        let mac_address = self.interfaces[interface_index].mac_address;
+        let client = match self.dhcp_clients.get_mut(&interface_index) {
+            Some(client) => client,
+            None => return false,
+        };
+        if let Some(message) = DhcpMessage::parse(data) {
+            if message.operation == OPERATION_REPLY && message.client_mac == mac_address
+                && client.replies.len() < MAX_QUEUED_REPLIES {
+                client.replies.push_back(message);
+                if let Some(waker) = client.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        true
+    }
+
+    /// Sends the message to the server, or broadcasts it when the server isn't known
+    fn send_dhcp(&mut self, interface_index: usize, message: &DhcpMessage, server: Option<Ipv4Address>) -> Result<(), NetError> {
+        let (route, destination) = match server {
+            Some(server) => (self.route(server)?, server),
+            None => {
+                let route = Route {
+                    interface_index,
+                    source: message.client_address,
+                    next_hop: Ipv4Address::BROADCAST,
+                };
+                (route, Ipv4Address::BROADCAST)
+            }
+        };
+        let datagram = udp::build_datagram(route.source, destination, DHCP_CLIENT_PORT, DHCP_SERVER_PORT, &message.to_bytes());
+        self.send_ipv4(route, destination, ipv4::PROTOCOL_UDP, &datagram)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Lease {
+    config: Ipv4Config,
+    server: Ipv4Address,
+    /// Uptime of the acknowledgment and times relative to it, in milliseconds
+    obtained_ms: u64,
+    renewal_ms: u64,
+    rebinding_ms: u64,
+    lease_ms: u64,
+}
+
+impl Lease {
+    fn from_ack(ack: &DhcpMessage, server: Ipv4Address) -> Self {
+        let options = &ack.options;
+        let lease_seconds = options.lease_time.unwrap_or(u32::MAX) as u64;
+        let lease_ms = lease_seconds * 1000;
+        Self {
+            config: Ipv4Config {
+                address: ack.your_address,
+                netmask: options.subnet_mask.unwrap_or_else(|| Ipv4Address::netmask(24)),
+                gateway: options.routers.first().copied(),
+                dns_servers: options.dns_servers.clone(),
+            },
+            server: options.server_identifier.unwrap_or(server),
+            obtained_ms: timer::get_uptime_ms(),
+            renewal_ms: options.renewal_time.map_or(lease_ms / 2, |seconds| seconds as u64 * 1000),
+            rebinding_ms: options.rebinding_time.map_or(lease_ms * 7 / 8, |seconds| seconds as u64 * 1000),
+            lease_ms,
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        self.lease_ms == u32::MAX as u64 * 1000
+    }
+}
+
+/// Configures the interface with a lease from the DHCP server and keeps renewing it
+pub async fn dhcp_task(name: String) {
+    let (interface_index, mac_address) = {
+        let mut stack = NETWORK_STACK.lock();
+        let interface_index = stack.find_interface(&name).expect("registered device has an interface");
+        stack.dhcp_clients.insert(interface_index, DhcpClientState {
+            replies: VecDeque::new(),
+            waker: None,
+        });
+        (interface_index, stack.get_interfaces()[interface_index].mac_address)
+    };
+
+    loop {
+        let mut lease = match acquire_lease(interface_index, mac_address).await {
+            Some(lease) => lease,
+            None => {
+                log_warning!("{}: no answer from a DHCP server, retrying", name);
+                timer::sleep(RETRY_DELAY_MS).await;
+                continue;
+            }
+        };
+        configure(&name, interface_index, &lease);
+
+        while let Some(renewed) = renew_lease(interface_index, mac_address, &lease).await {
+            if renewed.config != lease.config {
+                configure(&name, interface_index, &renewed);
+            } else {
+                log_info!("{}: DHCP lease {} renewed for {} s", name, renewed.config.address, renewed.lease_ms / 1000);
+            }
+            lease = renewed;
+        }
+        log_warning!("{}: DHCP lease of {} lost", name, lease.config.address);
+        NETWORK_STACK.lock().set_config(interface_index, None);
+    }
+}
+
+fn configure(name: &str, interface_index: usize, lease: &Lease) {
+    let config = &lease.config;
+    log_info!("{}: DHCP lease {}/{} from {}, gateway {}, DNS {:?}, valid for {} s",
+        name, config.address, config.netmask.get_prefix_length(), lease.server,
+        config.gateway.unwrap_or(Ipv4Address::UNSPECIFIED), config.dns_servers, lease.lease_ms / 1000);
+    NETWORK_STACK.lock().set_config(interface_index, Some(config.clone()));
+}
+
+/// Performs DISCOVER, OFFER, REQUEST and ACK
+async fn acquire_lease(interface_index: usize, mac_address: MacAddress) -> Option<Lease> {
+    let transaction_id = timer::get_timestamp() as u32;
+    let mut discover = DhcpMessage::new_request(transaction_id, mac_address, MESSAGE_DISCOVER);
+    discover.flags = FLAG_BROADCAST;
+    let offer = exchange(interface_index, &discover, None, &[MESSAGE_OFFER]).await?;
+    let server = offer.options.server_identifier?;
+
+    let mut request = DhcpMessage::new_request(transaction_id, mac_address, MESSAGE_REQUEST);
+    request.flags = FLAG_BROADCAST;
+    request.options.requested_address = Some(offer.your_address);
+    request.options.server_identifier = Some(server);
+    let ack = exchange(interface_index, &request, None, &[MESSAGE_ACK, MESSAGE_NAK]).await?;
+    if ack.options.message_type != Some(MESSAGE_ACK) {
+        return None;
+    }
+    Some(Lease::from_ack(&ack, server))
+}
+
+/// Waits until the renewal time and renews the lease, first with its server and then with any server.
+/// Returns `None` when the lease expires or the server refuses it.
+async fn renew_lease(interface_index: usize, mac_address: MacAddress, lease: &Lease) -> Option<Lease> {
+    if lease.is_infinite() {
+        futures_util::future::pending::<()>().await;
+    }
+
+    loop {
+        let elapsed_ms = timer::get_uptime_ms() - lease.obtained_ms;
+        if elapsed_ms >= lease.lease_ms {
+            return None;
+        }
+        if elapsed_ms < lease.renewal_ms {
+            timer::sleep(lease.renewal_ms - elapsed_ms).await;
+            continue;
+        }
+
+        // Renewing is unicast to the leasing server, rebinding is broadcast to any server.
+        // Retransmissions stop when the lease expires, so a new one is discovered right away.
+        let server = (elapsed_ms < lease.rebinding_ms).then_some(lease.server);
+        let mut request = DhcpMessage::new_request(timer::get_timestamp() as u32, mac_address, MESSAGE_REQUEST);
+        request.client_address = lease.config.address;
+        let reply = exchange(interface_index, &request, server, &[MESSAGE_ACK, MESSAGE_NAK]);
+        if let Some(ack) = timer::with_timeout(lease.lease_ms - elapsed_ms, reply).await.flatten() {
+            if ack.options.message_type != Some(MESSAGE_ACK) {
+                return None;
+            }
+            return Some(Lease::from_ack(&ack, lease.server));
+        }
+        let remaining_ms = lease.lease_ms - (timer::get_uptime_ms() - lease.obtained_ms).min(lease.lease_ms);
+        timer::sleep(RENEWAL_RETRY_MS.min(remaining_ms)).await;
+    }
+}
+
+/// Sends the message, retransmitting it with a growing timeout, until a reply of the expected type arrives
+async fn exchange(interface_index: usize, message: &DhcpMessage, server: Option<Ipv4Address>, expected_types: &[u8]) -> Option<DhcpMessage> {
+    let mut timeout_ms = REPLY_TIMEOUT_MS;
+    for _ in 0..MAX_ATTEMPTS {
+        clear_replies(interface_index);
+        if let Err(error) = NETWORK_STACK.lock().send_dhcp(interface_index, message, server) {
+            log_warning!("DHCP: {}", error);
+        }
+        let reply = timer::with_timeout(timeout_ms, receive_reply(interface_index, message.transaction_id, expected_types)).await;
+        if reply.is_some() {
+            return reply;
+        }
+        timeout_ms *= 2;
+    }
+    None
+}
+
+fn clear_replies(interface_index: usize) {
+    if let Some(client) = NETWORK_STACK.lock().dhcp_clients.get_mut(&interface_index) {
+        client.replies.clear();
+    }
+}
+
+async fn receive_reply(interface_index: usize, transaction_id: u32, expected_types: &[u8]) -> DhcpMessage {
+    poll_fn(|context| {
+        let mut stack = NETWORK_STACK.lock();
+        let client = stack.dhcp_clients.get_mut(&interface_index).expect("interface has a DHCP client");
+        while let Some(reply) = client.replies.pop_front() {
+            let expected = reply.options.message_type.is_some_and(|message_type| expected_types.contains(&message_type));
+            if reply.transaction_id == transaction_id && expected {
+                return Poll::Ready(reply);
+            }
+        }
+        client.waker = Some(context.waker().clone());
+        Poll::Pending
+    }).await
+}
+
+#[test_case]
+fn test_build_and_parse_dhcp_message() {
+    let mut request = DhcpMessage::new_request(0x12345678, MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]), MESSAGE_REQUEST);
+    request.flags = FLAG_BROADCAST;
+    request.options.requested_address = Some(Ipv4Address([10, 0, 2, 15]));
+    request.options.server_identifier = Some(Ipv4Address([10, 0, 2, 2]));
+    let bytes = request.to_bytes();
+    assert_eq!(&bytes[236..240], &MAGIC_COOKIE);
+    assert_eq!(DhcpMessage::parse(&bytes), Some(request));
+}
+
+#[test_case]
+fn test_parse_dhcp_ack() {
+    let mut ack = DhcpMessage::new_request(1, MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]), MESSAGE_ACK);
+    ack.operation = OPERATION_REPLY;
+    ack.your_address = Ipv4Address([10, 0, 2, 15]);
+    ack.options.subnet_mask = Some(Ipv4Address([255, 255, 255, 0]));
+    ack.options.routers = alloc::vec![Ipv4Address([10, 0, 2, 2])];
+    ack.options.dns_servers = alloc::vec![Ipv4Address([10, 0, 2, 3])];
+    ack.options.server_identifier = Some(Ipv4Address([10, 0, 2, 2]));
+    ack.options.lease_time = Some(86400);
+    let parsed = DhcpMessage::parse(&ack.to_bytes()).unwrap();
+    assert_eq!(parsed, ack);
+
+    let lease = Lease::from_ack(&parsed, Ipv4Address::UNSPECIFIED);
+    assert_eq!(lease.config.address, Ipv4Address([10, 0, 2, 15]));
+    assert_eq!(lease.config.gateway, Some(Ipv4Address([10, 0, 2, 2])));
+    assert_eq!(lease.server, Ipv4Address([10, 0, 2, 2]));
+    assert_eq!(lease.renewal_ms, 43_200_000);
+    assert_eq!(lease.rebinding_ms, 75_600_000);
+}
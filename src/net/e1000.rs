@@ -0,0 +1,369 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::task::Waker;
+
+use futures_util::task::AtomicWaker;
+use x86_64::VirtAddr;
+
+use crate::error::Error;
+use crate::interrupts;
+use crate::log_warning;
+use crate::memory::{self, DmaRegion};
+use crate::net::network_device::{ETHERNET_MAX_FRAME_SIZE, MacAddress, NetworkDevice};
+use crate::pci::{self, Bar, PciCommand};
+
+const INTEL_VENDOR_ID: u16 = 0x8086;
+/// 82540EM (QEMU default) and 82545EM, older models have no EERD register to read the MAC address with
+const DEVICE_IDS: [u16; 2] = [0x100E, 0x100F];
+
+const REG_CTRL: usize = 0x0000;
+const REG_STATUS: usize = 0x0008;
+const REG_EERD: usize = 0x0014;
+const REG_ICR: usize = 0x00C0;
+const REG_IMS: usize = 0x00D0;
+const REG_IMC: usize = 0x00D8;
+const REG_RCTL: usize = 0x0100;
+const REG_TCTL: usize = 0x0400;
+const REG_TIPG: usize = 0x0410;
+const REG_RDBAL: usize = 0x2800;
+const REG_RDBAH: usize = 0x2804;
+const REG_RDLEN: usize = 0x2808;
+const REG_RDH: usize = 0x2810;
+const REG_RDT: usize = 0x2818;
+const REG_TDBAL: usize = 0x3800;
+const REG_TDBAH: usize = 0x3804;
+const REG_TDLEN: usize = 0x3808;
+const REG_TDH: usize = 0x3810;
+const REG_TDT: usize = 0x3818;
+const REG_MTA: usize = 0x5200;
+const REG_RAL0: usize = 0x5400;
+const REG_RAH0: usize = 0x5404;
+
+const MTA_ENTRIES: usize = 128;
+
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+const STATUS_LU: u32 = 1 << 1;
+const EERD_START: u32 = 1 << 0;
+const EERD_DONE: u32 = 1 << 4;
+const RAH_AV: u32 = 1 << 31;
+
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_SECRC: u32 = 1 << 26;
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT: u32 = 0x10 << 4;
+const TCTL_COLD: u32 = 0x40 << 12;
+/// Recommended inter packet gap for the IEEE 802.3 standard
+const TIPG_DEFAULT: u32 = 10 | 8 << 10 | 6 << 20;
+
+const INTERRUPT_LSC: u32 = 1 << 2;
+const INTERRUPT_RXDMT0: u32 = 1 << 4;
+const INTERRUPT_RXO: u32 = 1 << 6;
+const INTERRUPT_RXT0: u32 = 1 << 7;
+
+const DESCRIPTOR_SIZE: usize = 16;
+const RECEIVE_DESCRIPTORS: usize = 32;
+const TRANSMIT_DESCRIPTORS: usize = 16;
+const BUFFER_SIZE: usize = 2048;
+
+const DESCRIPTOR_STATUS_DD: u8 = 1 << 0;
+const DESCRIPTOR_STATUS_EOP: u8 = 1 << 1;
+const TRANSMIT_COMMAND_EOP: u8 = 1 << 0;
+const TRANSMIT_COMMAND_IFCS: u8 = 1 << 1;
+const TRANSMIT_COMMAND_RS: u8 = 1 << 3;
+
+#[derive(Debug)]
+pub enum E1000Error {
+    UnsupportedBar,
+    NoMemory,
+    ResetTimeout,
+    EepromTimeout,
+    FrameTooLarge(usize),
+    TransmitQueueFull,
+}
+
+impl Display for E1000Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            E1000Error::UnsupportedBar => write!(f, "Registers in I/O space BAR are not supported"),
+            E1000Error::NoMemory => write!(f, "Not enough memory for descriptor rings"),
+            E1000Error::ResetTimeout => write!(f, "Device reset timed out"),
+            E1000Error::EepromTimeout => write!(f, "EEPROM read timed out"),
+            E1000Error::FrameTooLarge(length) => write!(f, "Frame of {} bytes is too large", length),
+            E1000Error::TransmitQueueFull => write!(f, "Transmit ring is full"),
+        }
+    }
+}
+
+impl Error for E1000Error {}
+
+#[derive(Clone, Copy)]
+struct Registers {
+    base: VirtAddr,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+}
+
+/// Descriptor ring with a packet buffer of `BUFFER_SIZE` bytes for every descriptor
+struct DescriptorRing {
+    descriptors: DmaRegion,
+    buffers: DmaRegion,
+}
+
+impl DescriptorRing {
+    fn new(count: usize) -> Result<Self, E1000Error> {
+        let descriptors = memory::allocate_dma(count * DESCRIPTOR_SIZE).ok_or(E1000Error::NoMemory)?;
+        let buffers = memory::allocate_dma(count * BUFFER_SIZE).ok_or(E1000Error::NoMemory)?;
+        let mut ring = Self { descriptors, buffers };
+        for index in 0..count {
+            let address = ring.buffers.physical_address.as_u64() + (index * BUFFER_SIZE) as u64;
+            ring.descriptor(index)[0..8].copy_from_slice(&address.to_le_bytes());
+        }
+        Ok(ring)
+    }
+
+    fn descriptor(&mut self, index: usize) -> &mut [u8] {
+        &mut self.descriptors.as_mut_slice()[index * DESCRIPTOR_SIZE..(index + 1) * DESCRIPTOR_SIZE]
+    }
+
+    fn buffer(&mut self, index: usize) -> &mut [u8] {
+        &mut self.buffers.as_mut_slice()[index * BUFFER_SIZE..(index + 1) * BUFFER_SIZE]
+    }
+
+    fn status(&mut self, index: usize) -> u8 {
+        unsafe { core::ptr::read_volatile(&self.descriptor(index)[12]) }
+    }
+}
+
+pub struct E1000 {
+    pci_address: pci::PciAddress,
+    registers: Registers,
+    receive_ring: DescriptorRing,
+    transmit_ring: DescriptorRing,
+    next_receive: usize,
+    next_transmit: usize,
+    mac_address: MacAddress,
+    interrupt_driven: bool,
+    receive_waker: Arc<AtomicWaker>,
+}
+
+impl E1000 {
+    fn new(pci_device: &pci::PciDevice) -> Result<Self, E1000Error> {
+        let (address, size) = match pci_device.read_bar(0) {
+            Some(Bar::Memory { address, size, .. }) => (address, size),
+            _ => return Err(E1000Error::UnsupportedBar),
+        };
+        pci_device.enable(PciCommand::MEMORY_SPACE | PciCommand::BUS_MASTER);
+        pci_device.disable(PciCommand::INTERRUPT_DISABLE);
+        let registers = Registers { base: memory::map_mmio(address, size) };
+
+        registers.write(REG_IMC, u32::MAX);
+        registers.write(REG_CTRL, registers.read(REG_CTRL) | CTRL_RST);
+        Self::wait_for_reset(registers)?;
+        registers.write(REG_IMC, u32::MAX);
+        registers.read(REG_ICR);
+        registers.write(REG_CTRL, registers.read(REG_CTRL) | CTRL_SLU | CTRL_ASDE);
+
+        let mac_address = Self::read_mac_address(registers)?;
+        registers.write(REG_RAL0, u32::from_le_bytes([mac_address.0[0], mac_address.0[1], mac_address.0[2], mac_address.0[3]]));
+        registers.write(REG_RAH0, u16::from_le_bytes([mac_address.0[4], mac_address.0[5]]) as u32 | RAH_AV);
+        for index in 0..MTA_ENTRIES {
+            registers.write(REG_MTA + index * 4, 0);
+        }
+
+        let mut device = Self {
+            pci_address: pci_device.address,
+            registers,
+            receive_ring: DescriptorRing::new(RECEIVE_DESCRIPTORS)?,
+            transmit_ring: DescriptorRing::new(TRANSMIT_DESCRIPTORS)?,
+            next_receive: 0,
+            next_transmit: 0,
+            mac_address,
+            interrupt_driven: false,
+            receive_waker: Arc::new(AtomicWaker::new()),
+        };
+        device.setup_receive();
+        device.setup_transmit();
+
+        let waker = device.receive_waker.clone();
+        device.interrupt_driven = interrupts::add_pci_irq_handler(pci_device.interrupt_line, Box::new(move || {
+            // Reading the cause register acknowledges the interrupt
+            if registers.read(REG_ICR) != 0 {
+                waker.wake();
+            }
+        }));
+        if device.interrupt_driven {
+            registers.write(REG_IMS, INTERRUPT_RXT0 | INTERRUPT_RXO | INTERRUPT_RXDMT0 | INTERRUPT_LSC);
+        } else {
+            log_warning!("e1000 {}: unsupported IRQ {}, falling back to polling",
+                pci_device.address, pci_device.interrupt_line);
+        }
+        Ok(device)
+    }
+
+    fn wait_for_reset(registers: Registers) -> Result<(), E1000Error> {
+        for _ in 0..1_000_000 {
+            if registers.read(REG_CTRL) & CTRL_RST == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(E1000Error::ResetTimeout)
+    }
+
+    /// Takes the address loaded by the device to the receive address registers, or reads it from the EEPROM
+    fn read_mac_address(registers: Registers) -> Result<MacAddress, E1000Error> {
+        let high = registers.read(REG_RAH0);
+        if high & RAH_AV != 0 {
+            let low = registers.read(REG_RAL0).to_le_bytes();
+            let high = (high as u16).to_le_bytes();
+            return Ok(MacAddress([low[0], low[1], low[2], low[3], high[0], high[1]]));
+        }
+
+        let mut address = [0u8; 6];
+        for word in 0..3 {
+            let value = Self::read_eeprom(registers, word as u32)?;
+            address[word * 2..word * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(MacAddress(address))
+    }
+
+    fn read_eeprom(registers: Registers, address: u32) -> Result<u16, E1000Error> {
+        registers.write(REG_EERD, EERD_START | address << 8);
+        for _ in 0..1_000_000 {
+            let value = registers.read(REG_EERD);
+            if value & EERD_DONE != 0 {
+                return Ok((value >> 16) as u16);
+            }
+            core::hint::spin_loop();
+        }
+        Err(E1000Error::EepromTimeout)
+    }
+
+    fn setup_receive(&mut self) {
+        let ring_address = self.receive_ring.descriptors.physical_address.as_u64();
+        self.registers.write(REG_RDBAL, ring_address as u32);
+        self.registers.write(REG_RDBAH, (ring_address >> 32) as u32);
+        self.registers.write(REG_RDLEN, (RECEIVE_DESCRIPTORS * DESCRIPTOR_SIZE) as u32);
+        self.registers.write(REG_RDH, 0);
+        self.registers.write(REG_RDT, (RECEIVE_DESCRIPTORS - 1) as u32);
+        // Buffer size of 2048 bytes is selected by BSIZE = 0 without BSEX
+        self.registers.write(REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+    }
+
+    fn setup_transmit(&mut self) {
+        // Free descriptors are marked as done, so that the ring looks like after completed transmissions
+        for index in 0..TRANSMIT_DESCRIPTORS {
+            self.transmit_ring.descriptor(index)[12] = DESCRIPTOR_STATUS_DD;
+        }
+        let ring_address = self.transmit_ring.descriptors.physical_address.as_u64();
+        self.registers.write(REG_TDBAL, ring_address as u32);
+        self.registers.write(REG_TDBAH, (ring_address >> 32) as u32);
+        self.registers.write(REG_TDLEN, (TRANSMIT_DESCRIPTORS * DESCRIPTOR_SIZE) as u32);
+        self.registers.write(REG_TDH, 0);
+        self.registers.write(REG_TDT, 0);
+        self.registers.write(REG_TIPG, TIPG_DEFAULT);
+        self.registers.write(REG_TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+    }
+
+    pub fn get_pci_address(&self) -> pci::PciAddress {
+        self.pci_address
+    }
+}
+
+impl NetworkDevice for E1000 {
+    fn get_mac_address(&self) -> MacAddress {
+        self.mac_address
+    }
+
+    fn is_link_up(&self) -> bool {
+        self.registers.read(REG_STATUS) & STATUS_LU != 0
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        if frame.len() > ETHERNET_MAX_FRAME_SIZE {
+            return Err(E1000Error::FrameTooLarge(frame.len()).into());
+        }
+
+        let index = self.next_transmit;
+        if self.transmit_ring.status(index) & DESCRIPTOR_STATUS_DD == 0 {
+            return Err(E1000Error::TransmitQueueFull.into());
+        }
+        self.transmit_ring.buffer(index)[..frame.len()].copy_from_slice(frame);
+
+        let descriptor = self.transmit_ring.descriptor(index);
+        descriptor[8..10].copy_from_slice(&(frame.len() as u16).to_le_bytes());
+        descriptor[10] = 0;
+        descriptor[11] = TRANSMIT_COMMAND_EOP | TRANSMIT_COMMAND_IFCS | TRANSMIT_COMMAND_RS;
+        descriptor[12] = 0;
+
+        self.next_transmit = (index + 1) % TRANSMIT_DESCRIPTORS;
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        self.registers.write(REG_TDT, self.next_transmit as u32);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let index = self.next_receive;
+            let status = self.receive_ring.status(index);
+            if status & DESCRIPTOR_STATUS_DD == 0 {
+                return None;
+            }
+
+            let descriptor = self.receive_ring.descriptor(index);
+            let length = (u16::from_le_bytes([descriptor[8], descriptor[9]]) as usize).min(BUFFER_SIZE);
+            let errors = descriptor[13];
+            let frame = if status & DESCRIPTOR_STATUS_EOP != 0 && errors == 0 {
+                Some(self.receive_ring.buffer(index)[..length].to_vec())
+            } else {
+                None
+            };
+
+            // Hands the descriptor back to the device, the tail points to the last one it may fill
+            self.receive_ring.descriptor(index)[12] = 0;
+            self.next_receive = (index + 1) % RECEIVE_DESCRIPTORS;
+            core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+            self.registers.write(REG_RDT, index as u32);
+
+            if frame.is_some() {
+                return frame;
+            }
+        }
+    }
+
+    fn register_receive_waker(&mut self, waker: &Waker) {
+        self.receive_waker.register(waker);
+        if !self.interrupt_driven {
+            waker.wake_by_ref();
+        }
+    }
+
+    fn get_description(&self) -> &str {
+        "Intel 8254x (e1000) network device"
+    }
+}
+
+pub fn detect_devices() -> Vec<E1000> {
+    let mut devices = Vec::new();
+    for pci_device in pci::find_devices_by_id(INTEL_VENDOR_ID, &DEVICE_IDS) {
+        match E1000::new(pci_device) {
+            Ok(device) => devices.push(device),
+            Err(error) => log_warning!("e1000 {}: {}", pci_device.address, error),
+        }
+    }
+    devices
+}
@@ -0,0 +1,41 @@
+use alloc::vec::Vec;
+
+use crate::net::network_device::{ETHERNET_HEADER_SIZE, ETHERNET_MIN_FRAME_SIZE, MacAddress};
+
+pub const ETHER_TYPE_IPV4: u16 = 0x0800;
+pub const ETHER_TYPE_ARP: u16 = 0x0806;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EthernetHeader {
+    pub destination: MacAddress,
+    pub source: MacAddress,
+    pub ether_type: u16,
+}
+
+impl EthernetHeader {
+    /// Parses the header of a frame, returns it with the payload
+    pub fn parse(frame: &[u8]) -> Option<(Self, &[u8])> {
+        if frame.len() < ETHERNET_HEADER_SIZE {
+            return None;
+        }
+        let header = Self {
+            destination: MacAddress::from_bytes(&frame[0..6]),
+            source: MacAddress::from_bytes(&frame[6..12]),
+            ether_type: u16::from_be_bytes([frame[12], frame[13]]),
+        };
+        Some((header, &frame[ETHERNET_HEADER_SIZE..]))
+    }
+
+    /// Builds the frame with the payload, padded to the minimal frame size
+    pub fn build_frame(&self, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity((ETHERNET_HEADER_SIZE + payload.len()).max(ETHERNET_MIN_FRAME_SIZE));
+        frame.extend_from_slice(&self.destination.0);
+        frame.extend_from_slice(&self.source.0);
+        frame.extend_from_slice(&self.ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        if frame.len() < ETHERNET_MIN_FRAME_SIZE {
+            frame.resize(ETHERNET_MIN_FRAME_SIZE, 0);
+        }
+        frame
+    }
+}
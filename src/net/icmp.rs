@@ -0,0 +1,103 @@
+use alloc::vec::Vec;
+use core::task::{Poll, Waker};
+
+use futures_util::future::poll_fn;
+
+use crate::net::NETWORK_STACK;
+use crate::net::ipv4::{self, Ipv4Address, Ipv4Header};
+use crate::net::stack::{NetError, NetworkStack};
+use crate::task::timer;
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_ECHO_REQUEST: u8 = 8;
+const ICMP_HEADER_SIZE: usize = 8;
+
+/// Outstanding echo request, keyed by its identifier and sequence number
+pub(super) struct EchoRequest {
+    replied: bool,
+    waker: Option<Waker>,
+}
+
+fn build_echo(message_type: u8, identifier: u16, sequence: u16, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(ICMP_HEADER_SIZE + data.len());
+    message.extend_from_slice(&[message_type, 0, 0, 0]);
+    message.extend_from_slice(&identifier.to_be_bytes());
+    message.extend_from_slice(&sequence.to_be_bytes());
+    message.extend_from_slice(data);
+    let checksum = ipv4::checksum(&[&message]);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+    message
+}
+
+impl NetworkStack {
+    pub(super) fn handle_icmp(&mut self, header: &Ipv4Header, message: &[u8]) {
+        if message.len() < ICMP_HEADER_SIZE || ipv4::checksum(&[message]) != 0 {
+            return;
+        }
+        let identifier = u16::from_be_bytes([message[4], message[5]]);
+        let sequence = u16::from_be_bytes([message[6], message[7]]);
+
+        match message[0] {
+            TYPE_ECHO_REQUEST => {
+                if header.destination.is_broadcast() {
+                    return;
+                }
+                let reply = build_echo(TYPE_ECHO_REPLY, identifier, sequence, &message[ICMP_HEADER_SIZE..]);
+                if let Ok(route) = self.route(header.source) {
+                    let _ = self.send_ipv4(route, header.source, ipv4::PROTOCOL_ICMP, &reply);
+                }
+            }
+            TYPE_ECHO_REPLY => {
+                if let Some(request) = self.echo_requests.get_mut(&(identifier, sequence)) {
+                    request.replied = true;
+                    if let Some(waker) = request.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_echo_request(&mut self, destination: Ipv4Address, identifier: u16, sequence: u16, data: &[u8]) -> Result<(), NetError> {
+        let route = self.route(destination)?;
+        let request = build_echo(TYPE_ECHO_REQUEST, identifier, sequence, data);
+        self.echo_requests.insert((identifier, sequence), EchoRequest { replied: false, waker: None });
+        self.send_ipv4(route, destination, ipv4::PROTOCOL_ICMP, &request)
+    }
+}
+
+/// Sends an echo request and waits for the reply, returns the round-trip time in milliseconds
+pub async fn ping(destination: Ipv4Address, identifier: u16, sequence: u16, data: &[u8], timeout_ms: u64) -> Result<u64, NetError> {
+    let key = (identifier, sequence);
+    let start_ms = timer::get_uptime_ms();
+    NETWORK_STACK.lock().send_echo_request(destination, identifier, sequence, data)?;
+
+    let reply = poll_fn(|context| {
+        let mut stack = NETWORK_STACK.lock();
+        match stack.echo_requests.get_mut(&key) {
+            Some(request) if !request.replied => {
+                request.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+            _ => Poll::Ready(()),
+        }
+    });
+    let replied = timer::with_timeout(timeout_ms, reply).await.is_some();
+    NETWORK_STACK.lock().echo_requests.remove(&key);
+
+    if replied {
+        Ok(timer::get_uptime_ms() - start_ms)
+    } else {
+        Err(NetError::TimedOut)
+    }
+}
+
+#[test_case]
+fn test_build_echo_request() {
+    let request = build_echo(TYPE_ECHO_REQUEST, 0x1234, 1, b"abcd");
+    assert_eq!(request.len(), ICMP_HEADER_SIZE + 4);
+    assert_eq!(&request[..2], &[TYPE_ECHO_REQUEST, 0]);
+    assert_eq!(&request[4..8], &[0x12, 0x34, 0x00, 0x01]);
+    assert_eq!(ipv4::checksum(&[&request]), 0);
+}
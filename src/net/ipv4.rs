@@ -0,0 +1,197 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+pub const IPV4_HEADER_SIZE: usize = 20;
+pub const IPV4_MTU: usize = 1500;
+pub const DEFAULT_TTL: u8 = 64;
+
+const FLAG_DONT_FRAGMENT: u16 = 0x4000;
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+const FRAGMENT_OFFSET_MASK: u16 = 0x1FFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Ipv4Address(pub [u8; 4]);
+
+impl Ipv4Address {
+    pub const UNSPECIFIED: Ipv4Address = Ipv4Address([0; 4]);
+    pub const BROADCAST: Ipv4Address = Ipv4Address([0xFF; 4]);
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut address = [0; 4];
+        address.copy_from_slice(&bytes[..4]);
+        Ipv4Address(address)
+    }
+
+    /// Parses `10.0.2.15`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut address = [0; 4];
+        let mut parts = text.split('.');
+        for byte in address.iter_mut() {
+            *byte = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Address(address))
+    }
+
+    /// Returns the netmask with the given number of leading ones, e.g. 255.255.255.0 for 24
+    pub fn netmask(prefix_length: u8) -> Self {
+        let bits = u32::MAX.checked_shl(32 - prefix_length.min(32) as u32).unwrap_or(0);
+        Self::from_u32(bits)
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        Ipv4Address(value.to_be_bytes())
+    }
+
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn get_prefix_length(&self) -> u8 {
+        self.to_u32().leading_ones() as u8
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    pub fn is_in_subnet(&self, network: Ipv4Address, netmask: Ipv4Address) -> bool {
+        self.to_u32() & netmask.to_u32() == network.to_u32() & netmask.to_u32()
+    }
+
+    pub fn get_subnet_broadcast(&self, netmask: Ipv4Address) -> Ipv4Address {
+        Self::from_u32(self.to_u32() | !netmask.to_u32())
+    }
+}
+
+impl Display for Ipv4Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Header {
+    pub source: Ipv4Address,
+    pub destination: Ipv4Address,
+    pub protocol: u8,
+    pub ttl: u8,
+}
+
+impl Ipv4Header {
+    /// Parses the header of a packet, returns it with the payload. Fragmented packets aren't supported.
+    pub fn parse(packet: &[u8]) -> Option<(Self, &[u8])> {
+        if packet.len() < IPV4_HEADER_SIZE || packet[0] >> 4 != 4 {
+            return None;
+        }
+        let header_length = (packet[0] & 0x0F) as usize * 4;
+        let total_length = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+        if header_length < IPV4_HEADER_SIZE || total_length < header_length || total_length > packet.len() {
+            return None;
+        }
+        if checksum(&[&packet[..header_length]]) != 0 {
+            return None;
+        }
+        let flags = u16::from_be_bytes([packet[6], packet[7]]);
+        if flags & FLAG_MORE_FRAGMENTS != 0 || flags & FRAGMENT_OFFSET_MASK != 0 {
+            return None;
+        }
+
+        let header = Self {
+            source: Ipv4Address::from_bytes(&packet[12..16]),
+            destination: Ipv4Address::from_bytes(&packet[16..20]),
+            protocol: packet[9],
+            ttl: packet[8],
+        };
+        Some((header, &packet[header_length..total_length]))
+    }
+
+    pub fn build_packet(&self, identification: u16, payload: &[u8]) -> Vec<u8> {
+        let total_length = (IPV4_HEADER_SIZE + payload.len()) as u16;
+        let mut packet = Vec::with_capacity(total_length as usize);
+        packet.push(0x45);
+        packet.push(0);
+        packet.extend_from_slice(&total_length.to_be_bytes());
+        packet.extend_from_slice(&identification.to_be_bytes());
+        packet.extend_from_slice(&FLAG_DONT_FRAGMENT.to_be_bytes());
+        packet.push(self.ttl);
+        packet.push(self.protocol);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&self.source.0);
+        packet.extend_from_slice(&self.destination.0);
+        let header_checksum = checksum(&[&packet]);
+        packet[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+}
+
+/// Internet checksum (RFC 1071) of the concatenated chunks
+pub fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum: u64 = 0;
+    for (index, byte) in chunks.iter().flat_map(|chunk| chunk.iter()).enumerate() {
+        sum += if index % 2 == 0 { (*byte as u64) << 8 } else { *byte as u64 };
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Pseudo-header covered by the UDP and TCP checksums
+pub fn pseudo_header(source: Ipv4Address, destination: Ipv4Address, protocol: u8, length: usize) -> [u8; 12] {
+    let mut header = [0; 12];
+    header[0..4].copy_from_slice(&source.0);
+    header[4..8].copy_from_slice(&destination.0);
+    header[9] = protocol;
+    header[10..12].copy_from_slice(&(length as u16).to_be_bytes());
+    header
+}
+
+#[test_case]
+fn test_parse_ipv4_address_and_netmask() {
+    use alloc::string::ToString;
+
+    let address = Ipv4Address::parse("10.0.2.15").unwrap();
+    assert_eq!(address, Ipv4Address([10, 0, 2, 15]));
+    assert_eq!(address.to_string(), "10.0.2.15");
+    assert_eq!(Ipv4Address::parse("10.0.2"), None);
+    assert_eq!(Ipv4Address::parse("10.0.2.256"), None);
+
+    let netmask = Ipv4Address::netmask(24);
+    assert_eq!(netmask, Ipv4Address([255, 255, 255, 0]));
+    assert_eq!(netmask.get_prefix_length(), 24);
+    assert_eq!(Ipv4Address::netmask(0), Ipv4Address::UNSPECIFIED);
+    assert!(Ipv4Address([10, 0, 2, 2]).is_in_subnet(address, netmask));
+    assert!(!Ipv4Address([10, 0, 3, 2]).is_in_subnet(address, netmask));
+    assert_eq!(address.get_subnet_broadcast(netmask), Ipv4Address([10, 0, 2, 255]));
+}
+
+#[test_case]
+fn test_build_and_parse_ipv4_packet() {
+    let header = Ipv4Header {
+        source: Ipv4Address([192, 168, 0, 1]),
+        destination: Ipv4Address([192, 168, 0, 199]),
+        protocol: PROTOCOL_UDP,
+        ttl: DEFAULT_TTL,
+    };
+    let mut packet = header.build_packet(0, b"payload");
+    assert_eq!(packet.len(), IPV4_HEADER_SIZE + 7);
+    assert_eq!(checksum(&[&packet[..IPV4_HEADER_SIZE]]), 0);
+
+    // Padding after the packet, as in short Ethernet frames, isn't a part of the payload
+    packet.extend_from_slice(&[0; 8]);
+    let (parsed, payload) = Ipv4Header::parse(&packet).unwrap();
+    assert_eq!(parsed, header);
+    assert_eq!(payload, b"payload");
+
+    packet[8] -= 1;
+    assert!(Ipv4Header::parse(&packet).is_none());
+}
@@ -0,0 +1,117 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use futures_util::Stream;
+use spin::Mutex;
+
+use crate::error::Error;
+
+pub type SharedNetworkDevice = Arc<Mutex<dyn NetworkDevice>>;
+
+pub const ETHERNET_HEADER_SIZE: usize = 14;
+pub const ETHERNET_MIN_FRAME_SIZE: usize = 60;
+pub const ETHERNET_MAX_FRAME_SIZE: usize = 1514;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    pub const BROADCAST: MacAddress = MacAddress([0xFF; 6]);
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut address = [0; 6];
+        address.copy_from_slice(&bytes[..6]);
+        MacAddress(address)
+    }
+
+    /// Parses `52:54:00:12:34:56`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut address = [0; 6];
+        let mut parts = text.split(':');
+        for byte in address.iter_mut() {
+            *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(MacAddress(address))
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 1 != 0
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+/// Ethernet controller sending and receiving whole frames, without the frame check sequence
+pub trait NetworkDevice: Send {
+    fn get_mac_address(&self) -> MacAddress;
+
+    fn is_link_up(&self) -> bool {
+        true
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the next received frame, if there is any
+    fn receive(&mut self) -> Option<Vec<u8>>;
+
+    /// Registers the waker woken by the device interrupt when a frame arrives
+    fn register_receive_waker(&mut self, waker: &Waker);
+
+    fn get_description(&self) -> &str {
+        ""
+    }
+}
+
+/// Stream of frames received by the device
+pub struct FrameStream {
+    device: SharedNetworkDevice,
+}
+
+impl FrameStream {
+    pub fn new(device: SharedNetworkDevice) -> Self {
+        Self { device }
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut device = self.device.lock();
+        if let Some(frame) = device.receive() {
+            return Poll::Ready(Some(frame));
+        }
+
+        device.register_receive_waker(context.waker());
+        match device.receive() {
+            Some(frame) => Poll::Ready(Some(frame)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test_case]
+fn test_parse_and_format_mac_address() {
+    use alloc::string::ToString;
+
+    let address = MacAddress::parse("52:54:00:12:34:5f").unwrap();
+    assert_eq!(address, MacAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x5F]));
+    assert_eq!(address.to_string(), "52:54:00:12:34:5f");
+    assert!(!address.is_multicast());
+    assert!(MacAddress::BROADCAST.is_multicast());
+
+    assert_eq!(MacAddress::parse("52:54:00:12:34"), None);
+    assert_eq!(MacAddress::parse("52:54:00:12:34:56:78"), None);
+}
@@ -0,0 +1,28 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use crate::net::network_device::SharedNetworkDevice;
+
+pub struct NetworkDeviceRegister {
+    devices: BTreeMap<String, SharedNetworkDevice>,
+}
+
+impl NetworkDeviceRegister {
+    pub fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, device: SharedNetworkDevice) {
+        self.devices.insert(name.into(), device);
+    }
+
+    pub fn get(&self, name: &str) -> Option<SharedNetworkDevice> {
+        self.devices.get(name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &SharedNetworkDevice)> {
+        self.devices.iter()
+    }
+}
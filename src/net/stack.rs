@@ -0,0 +1,293 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use crate::error::Error;
+use crate::log_debug;
+use crate::net::arp::ArpCache;
+use crate::net::dhcp::DhcpClientState;
+use crate::net::ethernet::{ETHER_TYPE_ARP, ETHER_TYPE_IPV4, EthernetHeader};
+use crate::net::icmp::EchoRequest;
+use crate::net::ipv4::{self, DEFAULT_TTL, IPV4_HEADER_SIZE, IPV4_MTU, Ipv4Address, Ipv4Header};
+use crate::net::network_device::{MacAddress, SharedNetworkDevice};
+use crate::net::tcp::{ConnectionKey, TcpConnection, TcpListenerState};
+use crate::net::udp::UdpSocketState;
+use crate::task::timer;
+
+const PENDING_PACKET_TIMEOUT_MS: u64 = 3000;
+const MAX_PENDING_PACKETS: usize = 32;
+const EPHEMERAL_PORTS_START: u16 = 49152;
+
+#[derive(Debug)]
+pub enum NetError {
+    NotConfigured,
+    NoRoute(Ipv4Address),
+    AddressInUse(u16),
+    NoFreePort,
+    PacketTooLarge(usize),
+    ConnectionRefused,
+    ConnectionReset,
+    NotConnected,
+    TimedOut,
+    Device(Box<dyn Error>),
+}
+
+impl Display for NetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NetError::NotConfigured => write!(f, "Network is not configured"),
+            NetError::NoRoute(address) => write!(f, "No route to host {}", address),
+            NetError::AddressInUse(port) => write!(f, "Port {} already in use", port),
+            NetError::NoFreePort => write!(f, "No free port"),
+            NetError::PacketTooLarge(length) => write!(f, "Packet of {} bytes is too large", length),
+            NetError::ConnectionRefused => write!(f, "Connection refused"),
+            NetError::ConnectionReset => write!(f, "Connection reset by peer"),
+            NetError::NotConnected => write!(f, "Not connected"),
+            NetError::TimedOut => write!(f, "Timed out"),
+            NetError::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for NetError {}
+
+impl From<Box<dyn Error>> for NetError {
+    fn from(error: Box<dyn Error>) -> Self {
+        NetError::Device(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv4Config {
+    pub address: Ipv4Address,
+    pub netmask: Ipv4Address,
+    pub gateway: Option<Ipv4Address>,
+    pub dns_servers: Vec<Ipv4Address>,
+}
+
+impl Ipv4Config {
+    pub fn get_broadcast_address(&self) -> Ipv4Address {
+        self.address.get_subnet_broadcast(self.netmask)
+    }
+}
+
+pub struct NetworkInterface {
+    pub name: String,
+    pub device: SharedNetworkDevice,
+    pub mac_address: MacAddress,
+    pub config: Option<Ipv4Config>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Route {
+    pub interface_index: usize,
+    pub source: Ipv4Address,
+    pub next_hop: Ipv4Address,
+}
+
+/// IPv4 packet waiting for the hardware address of the next hop
+struct PendingPacket {
+    interface_index: usize,
+    next_hop: Ipv4Address,
+    packet: Vec<u8>,
+    deadline: u64,
+}
+
+pub struct NetworkStack {
+    pub(super) interfaces: Vec<NetworkInterface>,
+    pub(super) arp_cache: ArpCache,
+    pending_packets: Vec<PendingPacket>,
+    next_identification: u16,
+    next_ephemeral_port: u16,
+    pub(super) echo_requests: BTreeMap<(u16, u16), EchoRequest>,
+    pub(super) udp_sockets: BTreeMap<u16, UdpSocketState>,
+    pub(super) tcp_connections: BTreeMap<ConnectionKey, TcpConnection>,
+    pub(super) tcp_listeners: BTreeMap<u16, TcpListenerState>,
+    pub(super) dhcp_clients: BTreeMap<usize, DhcpClientState>,
+}
+
+impl NetworkStack {
+    pub fn new() -> Self {
+        Self {
+            interfaces: Vec::new(),
+            arp_cache: ArpCache::new(),
+            pending_packets: Vec::new(),
+            next_identification: 0,
+            next_ephemeral_port: EPHEMERAL_PORTS_START,
+            echo_requests: BTreeMap::new(),
+            udp_sockets: BTreeMap::new(),
+            tcp_connections: BTreeMap::new(),
+            tcp_listeners: BTreeMap::new(),
+            dhcp_clients: BTreeMap::new(),
+        }
+    }
+}
+
+impl NetworkStack {
+    pub fn add_interface(&mut self, name: &str, device: SharedNetworkDevice) -> usize {
+        let mac_address = device.lock().get_mac_address();
+        self.interfaces.push(NetworkInterface {
+            name: name.into(),
+            device,
+            mac_address,
+            config: None,
+        });
+        self.interfaces.len() - 1
+    }
+
+    pub fn find_interface(&self, name: &str) -> Option<usize> {
+        self.interfaces.iter().position(|interface| interface.name == name)
+    }
+
+    pub fn get_interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    pub fn set_config(&mut self, index: usize, config: Option<Ipv4Config>) {
+        self.interfaces[index].config = config;
+    }
+
+    pub(super) fn next_ephemeral_port(&mut self, is_used: impl Fn(&Self, u16) -> bool) -> Result<u16, NetError> {
+        for _ in EPHEMERAL_PORTS_START..=u16::MAX {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORTS_START);
+            if !is_used(self, port) {
+                return Ok(port);
+            }
+        }
+        Err(NetError::NoFreePort)
+    }
+}
+
+impl NetworkStack {
+    pub fn handle_frame(&mut self, interface_index: usize, frame: &[u8]) {
+        let (header, payload) = match EthernetHeader::parse(frame) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        let interface = &self.interfaces[interface_index];
+        if header.destination != interface.mac_address && !header.destination.is_multicast() {
+            return;
+        }
+
+        match header.ether_type {
+            ETHER_TYPE_ARP => self.handle_arp(interface_index, payload),
+            ETHER_TYPE_IPV4 => self.handle_ipv4(interface_index, payload),
+            ether_type => log_debug!("{}: received {} bytes from {}, type {:#06x}",
+                interface.name, frame.len(), header.source, ether_type),
+        }
+    }
+
+    fn handle_ipv4(&mut self, interface_index: usize, packet: &[u8]) {
+        let (header, payload) = match Ipv4Header::parse(packet) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        // Unconfigured interfaces accept everything, so the configuration itself can be received
+        let accepted = match &self.interfaces[interface_index].config {
+            Some(config) => header.destination == config.address
+                || header.destination.is_broadcast()
+                || header.destination == config.get_broadcast_address(),
+            None => true,
+        };
+        if !accepted {
+            return;
+        }
+
+        match header.protocol {
+            ipv4::PROTOCOL_ICMP => self.handle_icmp(&header, payload),
+            ipv4::PROTOCOL_UDP => self.handle_udp(interface_index, &header, payload),
+            ipv4::PROTOCOL_TCP => self.handle_tcp(&header, payload),
+            _ => {}
+        }
+    }
+
+    /// Chooses the interface for the destination, directly connected networks take precedence over gateways
+    pub(super) fn route(&self, destination: Ipv4Address) -> Result<Route, NetError> {
+        let configs = self.interfaces.iter().enumerate()
+            .filter_map(|(index, interface)| interface.config.as_ref().map(|config| (index, config)));
+
+        let mut default_route = None;
+        for (interface_index, config) in configs {
+            if destination.is_broadcast() || destination.is_in_subnet(config.address, config.netmask) {
+                return Ok(Route { interface_index, source: config.address, next_hop: destination });
+            }
+            if let (None, Some(gateway)) = (&default_route, config.gateway) {
+                default_route = Some(Route { interface_index, source: config.address, next_hop: gateway });
+            }
+        }
+
+        match default_route {
+            Some(route) => Ok(route),
+            None if self.interfaces.iter().all(|interface| interface.config.is_none()) => Err(NetError::NotConfigured),
+            None => Err(NetError::NoRoute(destination)),
+        }
+    }
+
+    pub(super) fn send_ipv4(&mut self, route: Route, destination: Ipv4Address, protocol: u8, payload: &[u8]) -> Result<(), NetError> {
+        if IPV4_HEADER_SIZE + payload.len() > IPV4_MTU {
+            return Err(NetError::PacketTooLarge(payload.len()));
+        }
+        let header = Ipv4Header {
+            source: route.source,
+            destination,
+            protocol,
+            ttl: DEFAULT_TTL,
+        };
+        let packet = header.build_packet(self.next_identification, payload);
+        self.next_identification = self.next_identification.wrapping_add(1);
+
+        let is_broadcast = destination.is_broadcast() || self.interfaces[route.interface_index].config.as_ref()
+            .is_some_and(|config| destination == config.get_broadcast_address());
+        if is_broadcast {
+            return self.send_frame(route.interface_index, MacAddress::BROADCAST, ETHER_TYPE_IPV4, &packet);
+        }
+        if let Some(mac_address) = self.arp_cache.get(route.next_hop) {
+            return self.send_frame(route.interface_index, mac_address, ETHER_TYPE_IPV4, &packet);
+        }
+
+        let already_requested = self.pending_packets.iter().any(|pending| pending.next_hop == route.next_hop);
+        if self.pending_packets.len() < MAX_PENDING_PACKETS {
+            self.pending_packets.push(PendingPacket {
+                interface_index: route.interface_index,
+                next_hop: route.next_hop,
+                packet,
+                deadline: timer::get_ticks() + timer::ms_to_ticks(PENDING_PACKET_TIMEOUT_MS),
+            });
+        }
+        if !already_requested {
+            self.send_arp_request(route.interface_index, route.next_hop);
+        }
+        Ok(())
+    }
+
+    pub(super) fn flush_pending_packets(&mut self, address: Ipv4Address, mac_address: MacAddress) {
+        let (resolved, pending) = core::mem::take(&mut self.pending_packets).into_iter()
+            .partition(|pending| pending.next_hop == address);
+        self.pending_packets = pending;
+        for pending in resolved {
+            let _ = self.send_frame(pending.interface_index, mac_address, ETHER_TYPE_IPV4, &pending.packet);
+        }
+    }
+
+    pub(super) fn send_frame(&mut self, interface_index: usize, destination: MacAddress, ether_type: u16, payload: &[u8]) -> Result<(), NetError> {
+        let interface = &self.interfaces[interface_index];
+        let header = EthernetHeader {
+            destination,
+            source: interface.mac_address,
+            ether_type,
+        };
+        let frame = header.build_frame(payload);
+        interface.device.lock().send(&frame)?;
+        Ok(())
+    }
+
+    pub fn poll_timers(&mut self) {
+        let ticks = timer::get_ticks();
+        self.pending_packets.retain(|pending| pending.deadline > ticks);
+        self.arp_cache.remove_expired();
+        self.poll_tcp_timers(ticks);
+    }
+}
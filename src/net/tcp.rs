@@ -0,0 +1,924 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::task::{Poll, Waker};
+
+use futures_util::future::poll_fn;
+
+use crate::net::NETWORK_STACK;
+use crate::net::ipv4::{self, IPV4_HEADER_SIZE, IPV4_MTU, Ipv4Address, Ipv4Header};
+use crate::net::stack::{NetError, NetworkStack};
+use crate::task::timer;
+
+const TCP_HEADER_SIZE: usize = 20;
+
+const FLAG_FIN: u8 = 0x01;
+const FLAG_SYN: u8 = 0x02;
+const FLAG_RST: u8 = 0x04;
+const FLAG_PSH: u8 = 0x08;
+const FLAG_ACK: u8 = 0x10;
+
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_MAXIMUM_SEGMENT_SIZE: u8 = 2;
+
+/// Maximum segment size assumed when the peer doesn't announce one
+const DEFAULT_MAXIMUM_SEGMENT_SIZE: usize = 536;
+const LOCAL_MAXIMUM_SEGMENT_SIZE: usize = IPV4_MTU - IPV4_HEADER_SIZE - TCP_HEADER_SIZE;
+const RECEIVE_BUFFER_SIZE: usize = 8192;
+const SEND_BUFFER_SIZE: usize = 8192;
+const RETRANSMISSION_TIMEOUT_MS: u64 = 1000;
+const MAX_RETRANSMISSION_TIMEOUT_MS: u64 = 16_000;
+const MAX_RETRANSMISSIONS: u32 = 5;
+/// Shortened 2*MSL, long enough for the last ACK to be retransmitted on a local network
+const TIME_WAIT_MS: u64 = 2000;
+/// Time for the peer to send its FIN once ours is acknowledged, the connection is dropped after it
+const FIN_WAIT_2_TIMEOUT_MS: u64 = 60_000;
+const LISTEN_BACKLOG: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ConnectionError {
+    Refused,
+    Reset,
+    TimedOut,
+}
+
+impl ConnectionError {
+    fn to_net_error(self) -> NetError {
+        match self {
+            ConnectionError::Refused => NetError::ConnectionRefused,
+            ConnectionError::Reset => NetError::ConnectionReset,
+            ConnectionError::TimedOut => NetError::TimedOut,
+        }
+    }
+}
+
+fn sequence_less(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn sequence_less_or_equal(a: u32, b: u32) -> bool {
+    !sequence_less(b, a)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment<'a> {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub sequence: u32,
+    pub acknowledgment: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub maximum_segment_size: Option<u16>,
+    pub payload: &'a [u8],
+}
+
+impl<'a> TcpSegment<'a> {
+    pub fn parse(header: &Ipv4Header, segment: &'a [u8]) -> Option<Self> {
+        if segment.len() < TCP_HEADER_SIZE {
+            return None;
+        }
+        let data_offset = (segment[12] >> 4) as usize * 4;
+        if data_offset < TCP_HEADER_SIZE || data_offset > segment.len() {
+            return None;
+        }
+        let pseudo_header = ipv4::pseudo_header(header.source, header.destination, ipv4::PROTOCOL_TCP, segment.len());
+        if ipv4::checksum(&[&pseudo_header, segment]) != 0 {
+            return None;
+        }
+
+        let mut maximum_segment_size = None;
+        let options = &segment[TCP_HEADER_SIZE..data_offset];
+        let mut index = 0;
+        while index < options.len() {
+            match options[index] {
+                OPTION_END => break,
+                OPTION_NOP => index += 1,
+                kind => {
+                    let length = *options.get(index + 1)? as usize;
+                    if length < 2 || index + length > options.len() {
+                        return None;
+                    }
+                    if kind == OPTION_MAXIMUM_SEGMENT_SIZE && length == 4 {
+                        maximum_segment_size = Some(u16::from_be_bytes([options[index + 2], options[index + 3]]));
+                    }
+                    index += length;
+                }
+            }
+        }
+
+        Some(Self {
+            source_port: u16::from_be_bytes([segment[0], segment[1]]),
+            destination_port: u16::from_be_bytes([segment[2], segment[3]]),
+            sequence: u32::from_be_bytes([segment[4], segment[5], segment[6], segment[7]]),
+            acknowledgment: u32::from_be_bytes([segment[8], segment[9], segment[10], segment[11]]),
+            flags: segment[13],
+            window: u16::from_be_bytes([segment[14], segment[15]]),
+            maximum_segment_size,
+            payload: &segment[data_offset..],
+        })
+    }
+
+    pub fn build(&self, source: Ipv4Address, destination: Ipv4Address) -> Vec<u8> {
+        let options_length = if self.maximum_segment_size.is_some() { 4 } else { 0 };
+        let header_length = TCP_HEADER_SIZE + options_length;
+        let mut segment = Vec::with_capacity(header_length + self.payload.len());
+        segment.extend_from_slice(&self.source_port.to_be_bytes());
+        segment.extend_from_slice(&self.destination_port.to_be_bytes());
+        segment.extend_from_slice(&self.sequence.to_be_bytes());
+        segment.extend_from_slice(&self.acknowledgment.to_be_bytes());
+        segment.push(((header_length / 4) as u8) << 4);
+        segment.push(self.flags);
+        segment.extend_from_slice(&self.window.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        if let Some(maximum_segment_size) = self.maximum_segment_size {
+            segment.extend_from_slice(&[OPTION_MAXIMUM_SEGMENT_SIZE, 4]);
+            segment.extend_from_slice(&maximum_segment_size.to_be_bytes());
+        }
+        segment.extend_from_slice(self.payload);
+
+        let pseudo_header = ipv4::pseudo_header(source, destination, ipv4::PROTOCOL_TCP, segment.len());
+        let checksum = ipv4::checksum(&[&pseudo_header, &segment]);
+        segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+        segment
+    }
+
+    /// Length in the sequence space, SYN and FIN occupy one number each
+    fn get_sequence_length(&self) -> u32 {
+        self.payload.len() as u32
+            + (self.flags & FLAG_SYN != 0) as u32
+            + (self.flags & FLAG_FIN != 0) as u32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct ConnectionKey {
+    local_port: u16,
+    remote_address: Ipv4Address,
+    remote_port: u16,
+}
+
+pub(super) struct TcpConnection {
+    key: ConnectionKey,
+    local_address: Ipv4Address,
+    state: TcpState,
+    initial_sequence: u32,
+    send_unacknowledged: u32,
+    send_next: u32,
+    send_window: u32,
+    /// Sequence number of the first byte in the send buffer, which holds unacknowledged and unsent data
+    send_buffer_sequence: u32,
+    send_buffer: VecDeque<u8>,
+    close_requested: bool,
+    receive_next: u32,
+    receive_buffer: VecDeque<u8>,
+    remote_closed: bool,
+    maximum_segment_size: usize,
+    retransmission_deadline: Option<u64>,
+    retransmissions: u32,
+    /// Ends the TIME-WAIT state, or the FIN-WAIT-2 state of a peer that never closes its side
+    close_deadline: Option<u64>,
+    error: Option<ConnectionError>,
+    ack_needed: bool,
+    /// Connection created by a listener and not accepted yet
+    from_listener: bool,
+    /// No stream refers to the connection, it's removed once closed
+    released: bool,
+    reader_waker: Option<Waker>,
+    writer_waker: Option<Waker>,
+}
+
+impl TcpConnection {
+    fn new(key: ConnectionKey, local_address: Ipv4Address, state: TcpState) -> Self {
+        let initial_sequence = timer::get_timestamp() as u32;
+        Self {
+            key,
+            local_address,
+            state,
+            initial_sequence,
+            send_unacknowledged: initial_sequence,
+            send_next: initial_sequence,
+            send_window: 0,
+            send_buffer_sequence: initial_sequence.wrapping_add(1),
+            send_buffer: VecDeque::new(),
+            close_requested: false,
+            receive_next: 0,
+            receive_buffer: VecDeque::new(),
+            remote_closed: false,
+            maximum_segment_size: DEFAULT_MAXIMUM_SEGMENT_SIZE,
+            retransmission_deadline: None,
+            retransmissions: 0,
+            close_deadline: None,
+            error: None,
+            ack_needed: false,
+            from_listener: false,
+            released: false,
+            reader_waker: None,
+            writer_waker: None,
+        }
+    }
+}
+
+impl TcpConnection {
+    fn get_receive_window(&self) -> usize {
+        RECEIVE_BUFFER_SIZE - self.receive_buffer.len()
+    }
+
+    fn get_data_end(&self) -> u32 {
+        self.send_buffer_sequence.wrapping_add(self.send_buffer.len() as u32)
+    }
+
+    fn is_fin_acknowledged(&self) -> bool {
+        self.close_requested && self.send_unacknowledged == self.get_data_end().wrapping_add(1)
+    }
+
+    /// Closed connection is removed once no stream refers to it, also when a listener has never handed it out
+    fn is_removable(&self) -> bool {
+        self.state == TcpState::Closed && (self.released || self.from_listener)
+    }
+
+    fn get_error(&self) -> Option<NetError> {
+        self.error.map(ConnectionError::to_net_error)
+    }
+
+    fn build_segment(&self, sequence: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let segment = TcpSegment {
+            source_port: self.key.local_port,
+            destination_port: self.key.remote_port,
+            sequence,
+            acknowledgment: if flags & FLAG_ACK != 0 { self.receive_next } else { 0 },
+            flags,
+            window: self.get_receive_window().min(u16::MAX as usize) as u16,
+            maximum_segment_size: if flags & FLAG_SYN != 0 { Some(LOCAL_MAXIMUM_SEGMENT_SIZE as u16) } else { None },
+            payload,
+        };
+        segment.build(self.local_address, self.key.remote_address)
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.reader_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.writer_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn fail(&mut self, error: ConnectionError) {
+        self.state = TcpState::Closed;
+        self.error = Some(error);
+        self.retransmission_deadline = None;
+        self.wake();
+    }
+
+    fn arm_retransmission(&mut self) {
+        if self.retransmission_deadline.is_none() {
+            let timeout_ms = (RETRANSMISSION_TIMEOUT_MS << self.retransmissions).min(MAX_RETRANSMISSION_TIMEOUT_MS);
+            self.retransmission_deadline = Some(timer::get_ticks() + timer::ms_to_ticks(timeout_ms));
+        }
+    }
+
+    fn enter_fin_wait_2(&mut self) {
+        self.state = TcpState::FinWait2;
+        self.close_deadline = Some(timer::get_ticks() + timer::ms_to_ticks(FIN_WAIT_2_TIMEOUT_MS));
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.retransmission_deadline = None;
+        self.close_deadline = Some(timer::get_ticks() + timer::ms_to_ticks(TIME_WAIT_MS));
+        self.wake();
+    }
+
+    /// Starts closing, FIN is sent after all the queued data
+    fn close(&mut self) {
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived => {
+                self.state = TcpState::Closed;
+                self.retransmission_deadline = None;
+            }
+            _ => self.close_requested = true,
+        }
+    }
+
+    /// Returns segments for the unsent data that fits in the window, FIN and a pending acknowledgment
+    fn output(&mut self) -> Vec<Vec<u8>> {
+        let mut segments = Vec::new();
+        match self.state {
+            TcpState::SynSent | TcpState::SynReceived => {
+                if self.send_next == self.initial_sequence {
+                    let flags = if self.state == TcpState::SynSent { FLAG_SYN } else { FLAG_SYN | FLAG_ACK };
+                    segments.push(self.build_segment(self.initial_sequence, flags, &[]));
+                    self.send_next = self.initial_sequence.wrapping_add(1);
+                    self.arm_retransmission();
+                }
+                return segments;
+            }
+            TcpState::Closed => return segments,
+            _ => {}
+        }
+
+        loop {
+            let offset = self.send_next.wrapping_sub(self.send_buffer_sequence) as usize;
+            if offset >= self.send_buffer.len() {
+                break;
+            }
+            // A zero window is probed with a single byte
+            let window = (self.send_window as usize).max(1);
+            let in_flight = self.send_next.wrapping_sub(self.send_unacknowledged) as usize;
+            if in_flight >= window {
+                break;
+            }
+            let length = (self.send_buffer.len() - offset)
+                .min(window - in_flight)
+                .min(self.maximum_segment_size);
+            let payload: Vec<u8> = self.send_buffer.range(offset..offset + length).copied().collect();
+            segments.push(self.build_segment(self.send_next, FLAG_ACK | FLAG_PSH, &payload));
+            self.send_next = self.send_next.wrapping_add(length as u32);
+            self.arm_retransmission();
+        }
+
+        let data_end = self.get_data_end();
+        if self.close_requested && self.send_next == data_end {
+            segments.push(self.build_segment(data_end, FLAG_FIN | FLAG_ACK, &[]));
+            self.send_next = data_end.wrapping_add(1);
+            self.arm_retransmission();
+            self.state = match self.state {
+                TcpState::Established => TcpState::FinWait1,
+                TcpState::CloseWait => TcpState::LastAck,
+                state => state,
+            };
+        }
+
+        if self.ack_needed && segments.is_empty() {
+            segments.push(self.build_segment(self.send_next, FLAG_ACK, &[]));
+        }
+        self.ack_needed = false;
+        segments
+    }
+
+    /// Returns the oldest unacknowledged segment
+    fn retransmit(&self) -> Option<Vec<u8>> {
+        match self.state {
+            TcpState::SynSent => return Some(self.build_segment(self.initial_sequence, FLAG_SYN, &[])),
+            TcpState::SynReceived => return Some(self.build_segment(self.initial_sequence, FLAG_SYN | FLAG_ACK, &[])),
+            _ => {}
+        }
+
+        let in_flight = self.send_next.wrapping_sub(self.send_unacknowledged) as usize;
+        let length = in_flight.min(self.send_buffer.len()).min(self.maximum_segment_size);
+        if length > 0 {
+            let payload: Vec<u8> = self.send_buffer.range(..length).copied().collect();
+            Some(self.build_segment(self.send_unacknowledged, FLAG_ACK | FLAG_PSH, &payload))
+        } else if self.close_requested && in_flight > 0 {
+            Some(self.build_segment(self.send_unacknowledged, FLAG_FIN | FLAG_ACK, &[]))
+        } else {
+            None
+        }
+    }
+
+    fn handle_segment(&mut self, segment: &TcpSegment) {
+        let has_flag = |flag: u8| segment.flags & flag != 0;
+        if self.state == TcpState::SynSent {
+            self.handle_syn_sent_segment(segment);
+            return;
+        }
+
+        if has_flag(FLAG_RST) {
+            if segment.sequence == self.receive_next {
+                let error = if self.state == TcpState::SynReceived { ConnectionError::Refused } else { ConnectionError::Reset };
+                self.fail(error);
+            }
+            return;
+        }
+        if has_flag(FLAG_SYN) {
+            if self.state == TcpState::SynReceived && segment.sequence.wrapping_add(1) == self.receive_next {
+                // The peer hasn't got our SYN-ACK yet
+                self.send_next = self.initial_sequence;
+            } else {
+                self.ack_needed = true;
+            }
+            return;
+        }
+        if !has_flag(FLAG_ACK) || self.state == TcpState::Closed {
+            return;
+        }
+
+        if self.state == TcpState::SynReceived {
+            if segment.acknowledgment != self.initial_sequence.wrapping_add(1) {
+                return;
+            }
+            self.state = TcpState::Established;
+        }
+
+        let acknowledgment = segment.acknowledgment;
+        if sequence_less(self.send_unacknowledged, acknowledgment) && sequence_less_or_equal(acknowledgment, self.send_next) {
+            let acknowledged_data = (acknowledgment.wrapping_sub(self.send_buffer_sequence) as usize).min(self.send_buffer.len());
+            self.send_buffer.drain(..acknowledged_data);
+            self.send_buffer_sequence = self.send_buffer_sequence.wrapping_add(acknowledged_data as u32);
+            self.send_unacknowledged = acknowledgment;
+            self.retransmissions = 0;
+            self.retransmission_deadline = None;
+            if self.send_unacknowledged != self.send_next {
+                self.arm_retransmission();
+            }
+            if let Some(waker) = self.writer_waker.take() {
+                waker.wake();
+            }
+        } else if sequence_less(self.send_next, acknowledgment) {
+            self.ack_needed = true;
+            return;
+        }
+        self.send_window = segment.window as u32;
+
+        if self.is_fin_acknowledged() {
+            match self.state {
+                TcpState::FinWait1 => self.enter_fin_wait_2(),
+                TcpState::Closing => self.enter_time_wait(),
+                TcpState::LastAck => {
+                    self.state = TcpState::Closed;
+                    self.retransmission_deadline = None;
+                    self.wake();
+                }
+                _ => {}
+            }
+        }
+
+        let receiving = matches!(self.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2);
+        if !segment.payload.is_empty() {
+            // Retransmitted segments may overlap with the already received data
+            let offset = self.receive_next.wrapping_sub(segment.sequence) as usize;
+            if receiving && sequence_less_or_equal(segment.sequence, self.receive_next) && offset < segment.payload.len() {
+                let data = &segment.payload[offset..];
+                let accepted = data.len().min(self.get_receive_window());
+                self.receive_buffer.extend(&data[..accepted]);
+                self.receive_next = self.receive_next.wrapping_add(accepted as u32);
+                if let Some(waker) = self.reader_waker.take() {
+                    waker.wake();
+                }
+            }
+            self.ack_needed = true;
+        }
+
+        if has_flag(FLAG_FIN) {
+            let fin_sequence = segment.sequence.wrapping_add(segment.payload.len() as u32);
+            if receiving && fin_sequence == self.receive_next {
+                self.receive_next = self.receive_next.wrapping_add(1);
+                self.remote_closed = true;
+                match self.state {
+                    TcpState::Established => self.state = TcpState::CloseWait,
+                    TcpState::FinWait1 => self.state = TcpState::Closing,
+                    TcpState::FinWait2 => self.enter_time_wait(),
+                    _ => {}
+                }
+                if let Some(waker) = self.reader_waker.take() {
+                    waker.wake();
+                }
+            }
+            self.ack_needed = true;
+        }
+    }
+
+    fn handle_syn_sent_segment(&mut self, segment: &TcpSegment) {
+        let has_flag = |flag: u8| segment.flags & flag != 0;
+        if has_flag(FLAG_ACK) && segment.acknowledgment != self.initial_sequence.wrapping_add(1) {
+            return;
+        }
+        if has_flag(FLAG_RST) {
+            if has_flag(FLAG_ACK) {
+                self.fail(ConnectionError::Refused);
+            }
+            return;
+        }
+        if !has_flag(FLAG_SYN) || !has_flag(FLAG_ACK) {
+            return;
+        }
+
+        self.receive_next = segment.sequence.wrapping_add(1);
+        self.maximum_segment_size = segment.maximum_segment_size
+            .map_or(DEFAULT_MAXIMUM_SEGMENT_SIZE, |size| size as usize)
+            .min(LOCAL_MAXIMUM_SEGMENT_SIZE);
+        self.send_unacknowledged = segment.acknowledgment;
+        self.send_window = segment.window as u32;
+        self.state = TcpState::Established;
+        self.retransmissions = 0;
+        self.retransmission_deadline = None;
+        self.ack_needed = true;
+        self.wake();
+    }
+}
+
+pub(super) struct TcpListenerState {
+    backlog: VecDeque<ConnectionKey>,
+    waker: Option<Waker>,
+}
+
+impl NetworkStack {
+    pub(super) fn handle_tcp(&mut self, header: &Ipv4Header, data: &[u8]) {
+        let segment = match TcpSegment::parse(header, data) {
+            Some(segment) => segment,
+            None => return,
+        };
+        let key = ConnectionKey {
+            local_port: segment.destination_port,
+            remote_address: header.source,
+            remote_port: segment.source_port,
+        };
+
+        if let Some(connection) = self.tcp_connections.get_mut(&key) {
+            let was_synchronizing = connection.state == TcpState::SynReceived;
+            connection.handle_segment(&segment);
+            if was_synchronizing && connection.from_listener && connection.state == TcpState::Established {
+                if let Some(listener) = self.tcp_listeners.get_mut(&key.local_port) {
+                    listener.backlog.push_back(key);
+                    if let Some(waker) = listener.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            self.flush_tcp(key);
+            return;
+        }
+
+        let is_connection_request = segment.flags & (FLAG_SYN | FLAG_ACK | FLAG_RST) == FLAG_SYN;
+        if is_connection_request && self.tcp_listeners.contains_key(&key.local_port) {
+            let pending = self.tcp_connections.values()
+                .filter(|connection| connection.from_listener && connection.key.local_port == key.local_port)
+                .count();
+            if pending < LISTEN_BACKLOG {
+                let mut connection = TcpConnection::new(key, header.destination, TcpState::SynReceived);
+                connection.receive_next = segment.sequence.wrapping_add(1);
+                connection.send_window = segment.window as u32;
+                connection.maximum_segment_size = segment.maximum_segment_size
+                    .map_or(DEFAULT_MAXIMUM_SEGMENT_SIZE, |size| size as usize)
+                    .min(LOCAL_MAXIMUM_SEGMENT_SIZE);
+                connection.from_listener = true;
+                self.tcp_connections.insert(key, connection);
+                self.flush_tcp(key);
+            }
+            return;
+        }
+
+        if segment.flags & FLAG_RST == 0 {
+            self.send_tcp_reset(header, &segment);
+        }
+    }
+
+    fn send_tcp_reset(&mut self, header: &Ipv4Header, segment: &TcpSegment) {
+        let (sequence, acknowledgment, flags) = if segment.flags & FLAG_ACK != 0 {
+            (segment.acknowledgment, 0, FLAG_RST)
+        } else {
+            (0, segment.sequence.wrapping_add(segment.get_sequence_length()), FLAG_RST | FLAG_ACK)
+        };
+        let reset = TcpSegment {
+            source_port: segment.destination_port,
+            destination_port: segment.source_port,
+            sequence,
+            acknowledgment,
+            flags,
+            window: 0,
+            maximum_segment_size: None,
+            payload: &[],
+        };
+        self.send_tcp_segments(header.source, &[reset.build(header.destination, header.source)]);
+    }
+
+    fn send_tcp_segments(&mut self, destination: Ipv4Address, segments: &[Vec<u8>]) {
+        if let Ok(route) = self.route(destination) {
+            for segment in segments {
+                let _ = self.send_ipv4(route, destination, ipv4::PROTOCOL_TCP, segment);
+            }
+        }
+    }
+
+    /// Sends whatever the connection has to send and removes it if it's closed and released
+    fn flush_tcp(&mut self, key: ConnectionKey) {
+        let connection = match self.tcp_connections.get_mut(&key) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let segments = connection.output();
+        if connection.is_removable() {
+            self.remove_tcp_connection(key);
+        }
+        self.send_tcp_segments(key.remote_address, &segments);
+    }
+
+    /// Removes the connection also from the backlog of its listener, so it doesn't count against the limit
+    fn remove_tcp_connection(&mut self, key: ConnectionKey) {
+        self.tcp_connections.remove(&key);
+        if let Some(listener) = self.tcp_listeners.get_mut(&key.local_port) {
+            listener.backlog.retain(|&pending_key| pending_key != key);
+        }
+    }
+
+    pub(super) fn poll_tcp_timers(&mut self, ticks: u64) {
+        let keys: Vec<ConnectionKey> = self.tcp_connections.keys().copied().collect();
+        for key in keys {
+            let connection = self.tcp_connections.get_mut(&key).expect("connection exists");
+            if connection.close_deadline.is_some_and(|deadline| deadline <= ticks) {
+                connection.close_deadline = None;
+                if connection.state == TcpState::FinWait2 {
+                    connection.fail(ConnectionError::TimedOut);
+                } else {
+                    connection.state = TcpState::Closed;
+                }
+            }
+
+            let mut retransmitted = None;
+            if connection.retransmission_deadline.is_some_and(|deadline| deadline <= ticks) {
+                connection.retransmission_deadline = None;
+                connection.retransmissions += 1;
+                if connection.retransmissions > MAX_RETRANSMISSIONS {
+                    connection.fail(ConnectionError::TimedOut);
+                } else {
+                    retransmitted = connection.retransmit();
+                    connection.arm_retransmission();
+                }
+            }
+
+            if connection.is_removable() {
+                self.remove_tcp_connection(key);
+            }
+            if let Some(segment) = retransmitted {
+                self.send_tcp_segments(key.remote_address, &[segment]);
+            }
+        }
+    }
+
+    fn get_tcp_connection(&mut self, key: ConnectionKey) -> &mut TcpConnection {
+        self.tcp_connections.get_mut(&key).expect("stream refers to an existing connection")
+    }
+
+    fn is_tcp_port_used(&self, port: u16) -> bool {
+        self.tcp_listeners.contains_key(&port) || self.tcp_connections.keys().any(|key| key.local_port == port)
+    }
+}
+
+pub struct TcpStream {
+    key: ConnectionKey,
+}
+
+impl TcpStream {
+    pub async fn connect(address: Ipv4Address, port: u16) -> Result<Self, NetError> {
+        let stream = {
+            let mut stack = NETWORK_STACK.lock();
+            let route = stack.route(address)?;
+            let local_port = stack.next_ephemeral_port(|stack, port| stack.is_tcp_port_used(port))?;
+            let key = ConnectionKey { local_port, remote_address: address, remote_port: port };
+            stack.tcp_connections.insert(key, TcpConnection::new(key, route.source, TcpState::SynSent));
+            stack.flush_tcp(key);
+            Self { key }
+        };
+
+        poll_fn(|context| {
+            let mut stack = NETWORK_STACK.lock();
+            let connection = stack.get_tcp_connection(stream.key);
+            if connection.state == TcpState::SynSent {
+                connection.writer_waker = Some(context.waker().clone());
+                return Poll::Pending;
+            }
+            Poll::Ready(match connection.get_error() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            })
+        }).await?;
+        Ok(stream)
+    }
+}
+
+impl TcpStream {
+    pub fn get_remote_endpoint(&self) -> (Ipv4Address, u16) {
+        (self.key.remote_address, self.key.remote_port)
+    }
+
+    /// Reads the received data into the buffer, returns 0 once the peer has closed the connection
+    pub async fn read(&self, buffer: &mut [u8]) -> Result<usize, NetError> {
+        poll_fn(|context| {
+            let mut stack = NETWORK_STACK.lock();
+            let connection = stack.get_tcp_connection(self.key);
+            if !connection.receive_buffer.is_empty() {
+                let length = buffer.len().min(connection.receive_buffer.len());
+                for (target, byte) in buffer.iter_mut().zip(connection.receive_buffer.drain(..length)) {
+                    *target = byte;
+                }
+                // Announces the window opened after it was too small for a full segment
+                if connection.get_receive_window() - length < connection.maximum_segment_size {
+                    connection.ack_needed = true;
+                }
+                stack.flush_tcp(self.key);
+                return Poll::Ready(Ok(length));
+            }
+            if let Some(error) = connection.get_error() {
+                return Poll::Ready(Err(error));
+            }
+            if connection.remote_closed {
+                return Poll::Ready(Ok(0));
+            }
+            connection.reader_waker = Some(context.waker().clone());
+            Poll::Pending
+        }).await
+    }
+
+    /// Queues the data for sending, waits while the send buffer is full, returns the number of queued bytes
+    pub async fn write(&self, data: &[u8]) -> Result<usize, NetError> {
+        poll_fn(|context| {
+            let mut stack = NETWORK_STACK.lock();
+            let connection = stack.get_tcp_connection(self.key);
+            if let Some(error) = connection.get_error() {
+                return Poll::Ready(Err(error));
+            }
+            if connection.close_requested || !matches!(connection.state, TcpState::Established | TcpState::CloseWait) {
+                return Poll::Ready(Err(NetError::NotConnected));
+            }
+            let free = SEND_BUFFER_SIZE - connection.send_buffer.len();
+            if free == 0 {
+                connection.writer_waker = Some(context.waker().clone());
+                return Poll::Pending;
+            }
+            let length = data.len().min(free);
+            connection.send_buffer.extend(&data[..length]);
+            stack.flush_tcp(self.key);
+            Poll::Ready(Ok(length))
+        }).await
+    }
+
+    pub async fn write_all(&self, mut data: &[u8]) -> Result<(), NetError> {
+        while !data.is_empty() {
+            let written = self.write(data).await?;
+            data = &data[written..];
+        }
+        Ok(())
+    }
+
+    /// Sends FIN after the queued data, the stream can still read until the peer closes as well
+    pub fn close(&self) {
+        let mut stack = NETWORK_STACK.lock();
+        stack.get_tcp_connection(self.key).close();
+        stack.flush_tcp(self.key);
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let mut stack = NETWORK_STACK.lock();
+        let connection = stack.get_tcp_connection(self.key);
+        connection.released = true;
+        connection.close();
+        stack.flush_tcp(self.key);
+    }
+}
+
+pub struct TcpListener {
+    port: u16,
+}
+
+impl TcpListener {
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let mut stack = NETWORK_STACK.lock();
+        if stack.tcp_listeners.contains_key(&port) {
+            return Err(NetError::AddressInUse(port));
+        }
+        stack.tcp_listeners.insert(port, TcpListenerState {
+            backlog: VecDeque::new(),
+            waker: None,
+        });
+        Ok(Self { port })
+    }
+}
+
+impl TcpListener {
+    pub async fn accept(&self) -> TcpStream {
+        poll_fn(|context| {
+            let mut stack = NETWORK_STACK.lock();
+            let listener = stack.tcp_listeners.get_mut(&self.port).expect("bound listener has a state");
+            match listener.backlog.pop_front() {
+                Some(key) => {
+                    stack.get_tcp_connection(key).from_listener = false;
+                    Poll::Ready(TcpStream { key })
+                }
+                None => {
+                    listener.waker = Some(context.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }).await
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let mut stack = NETWORK_STACK.lock();
+        stack.tcp_listeners.remove(&self.port);
+
+        // Connections nobody has accepted are reset
+        let keys: Vec<ConnectionKey> = stack.tcp_connections.iter()
+            .filter(|(key, connection)| key.local_port == self.port && connection.from_listener)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let connection = stack.tcp_connections.remove(&key).expect("connection exists");
+            if connection.state != TcpState::SynReceived {
+                let reset = connection.build_segment(connection.send_next, FLAG_RST | FLAG_ACK, &[]);
+                stack.send_tcp_segments(key.remote_address, &[reset]);
+            }
+        }
+    }
+}
+
+#[test_case]
+fn test_build_and_parse_tcp_segment() {
+    let header = Ipv4Header {
+        source: Ipv4Address([10, 0, 2, 15]),
+        destination: Ipv4Address([10, 0, 2, 2]),
+        protocol: ipv4::PROTOCOL_TCP,
+        ttl: ipv4::DEFAULT_TTL,
+    };
+    let segment = TcpSegment {
+        source_port: 49152,
+        destination_port: 80,
+        sequence: 0xFFFF_FFF0,
+        acknowledgment: 0,
+        flags: FLAG_SYN,
+        window: 8192,
+        maximum_segment_size: Some(1460),
+        payload: &[],
+    };
+    let bytes = segment.build(header.source, header.destination);
+    assert_eq!(bytes.len(), TCP_HEADER_SIZE + 4);
+    assert_eq!(TcpSegment::parse(&header, &bytes), Some(segment.clone()));
+    assert_eq!(segment.get_sequence_length(), 1);
+
+    let mut corrupted = bytes.clone();
+    corrupted[4] ^= 1;
+    assert_eq!(TcpSegment::parse(&header, &corrupted), None);
+}
+
+#[test_case]
+fn test_sequence_numbers_wrap_around() {
+    assert!(sequence_less(0xFFFF_FFF0, 0x10));
+    assert!(!sequence_less(0x10, 0xFFFF_FFF0));
+    assert!(sequence_less_or_equal(5, 5));
+}
+
+#[test_case]
+fn test_reset_connection_request_leaves_listen_backlog() {
+    let mut stack = NetworkStack::new();
+    stack.tcp_listeners.insert(80, TcpListenerState { backlog: VecDeque::new(), waker: None });
+    let header = Ipv4Header {
+        source: Ipv4Address([10, 0, 2, 2]),
+        destination: Ipv4Address([10, 0, 2, 15]),
+        protocol: ipv4::PROTOCOL_TCP,
+        ttl: ipv4::DEFAULT_TTL,
+    };
+    let send = |stack: &mut NetworkStack, source_port: u16, sequence: u32, flags: u8| {
+        let segment = TcpSegment {
+            source_port,
+            destination_port: 80,
+            sequence,
+            acknowledgment: 0,
+            flags,
+            window: 8192,
+            maximum_segment_size: None,
+            payload: &[],
+        };
+        stack.handle_tcp(&header, &segment.build(header.source, header.destination));
+    };
+
+    for source_port in 50000..50000 + LISTEN_BACKLOG as u16 {
+        send(&mut stack, source_port, 100, FLAG_SYN);
+        send(&mut stack, source_port, 101, FLAG_RST);
+    }
+    assert!(stack.tcp_connections.is_empty());
+
+    send(&mut stack, 60000, 100, FLAG_SYN);
+    assert_eq!(stack.tcp_connections.values().next().map(|connection| connection.state), Some(TcpState::SynReceived));
+}
+
+#[test_case]
+fn test_fin_wait_2_times_out() {
+    let mut stack = NetworkStack::new();
+    let key = ConnectionKey { local_port: 50000, remote_address: Ipv4Address([10, 0, 2, 2]), remote_port: 80 };
+    let mut connection = TcpConnection::new(key, Ipv4Address([10, 0, 2, 15]), TcpState::FinWait1);
+    connection.released = true;
+    connection.enter_fin_wait_2();
+    stack.tcp_connections.insert(key, connection);
+
+    stack.poll_tcp_timers(0);
+    assert!(stack.tcp_connections.contains_key(&key));
+    stack.poll_tcp_timers(u64::MAX);
+    assert!(stack.tcp_connections.is_empty());
+}
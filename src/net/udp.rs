@@ -0,0 +1,158 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::task::{Poll, Waker};
+
+use futures_util::future::poll_fn;
+
+use crate::net::NETWORK_STACK;
+use crate::net::dhcp::DHCP_CLIENT_PORT;
+use crate::net::ipv4::{self, Ipv4Address, Ipv4Header};
+use crate::net::stack::{NetError, NetworkStack};
+
+const UDP_HEADER_SIZE: usize = 8;
+const MAX_QUEUED_DATAGRAMS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Datagram {
+    pub source: Ipv4Address,
+    pub source_port: u16,
+    pub data: Vec<u8>,
+}
+
+pub(super) struct UdpSocketState {
+    datagrams: VecDeque<Datagram>,
+    waker: Option<Waker>,
+}
+
+pub fn build_datagram(source: Ipv4Address, destination: Ipv4Address, source_port: u16, destination_port: u16, data: &[u8]) -> Vec<u8> {
+    let length = UDP_HEADER_SIZE + data.len();
+    let mut datagram = Vec::with_capacity(length);
+    datagram.extend_from_slice(&source_port.to_be_bytes());
+    datagram.extend_from_slice(&destination_port.to_be_bytes());
+    datagram.extend_from_slice(&(length as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(data);
+
+    let pseudo_header = ipv4::pseudo_header(source, destination, ipv4::PROTOCOL_UDP, length);
+    let checksum = match ipv4::checksum(&[&pseudo_header, &datagram]) {
+        0 => 0xFFFF,
+        checksum => checksum,
+    };
+    datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+    datagram
+}
+
+/// Parses the datagram, returns the source port, the destination port and the data
+pub fn parse_datagram<'a>(header: &Ipv4Header, datagram: &'a [u8]) -> Option<(u16, u16, &'a [u8])> {
+    if datagram.len() < UDP_HEADER_SIZE {
+        return None;
+    }
+    let length = u16::from_be_bytes([datagram[4], datagram[5]]) as usize;
+    if length < UDP_HEADER_SIZE || length > datagram.len() {
+        return None;
+    }
+    let datagram = &datagram[..length];
+    let checksum = u16::from_be_bytes([datagram[6], datagram[7]]);
+    let pseudo_header = ipv4::pseudo_header(header.source, header.destination, ipv4::PROTOCOL_UDP, length);
+    if checksum != 0 && ipv4::checksum(&[&pseudo_header, datagram]) != 0 {
+        return None;
+    }
+
+    let source_port = u16::from_be_bytes([datagram[0], datagram[1]]);
+    let destination_port = u16::from_be_bytes([datagram[2], datagram[3]]);
+    Some((source_port, destination_port, &datagram[UDP_HEADER_SIZE..]))
+}
+
+impl NetworkStack {
+    pub(super) fn handle_udp(&mut self, interface_index: usize, header: &Ipv4Header, datagram: &[u8]) {
+        let (source_port, destination_port, data) = match parse_datagram(header, datagram) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        if destination_port == DHCP_CLIENT_PORT && self.handle_dhcp(interface_index, data) {
+            return;
+        }
+        let socket = match self.udp_sockets.get_mut(&destination_port) {
+            Some(socket) => socket,
+            None => return,
+        };
+        if socket.datagrams.len() >= MAX_QUEUED_DATAGRAMS {
+            return;
+        }
+        socket.datagrams.push_back(Datagram {
+            source: header.source,
+            source_port,
+            data: data.to_vec(),
+        });
+        if let Some(waker) = socket.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct UdpSocket {
+    local_port: u16,
+}
+
+impl UdpSocket {
+    /// Binds the socket to the port, or to a free ephemeral port for 0
+    pub fn bind(port: u16) -> Result<Self, NetError> {
+        let mut stack = NETWORK_STACK.lock();
+        let local_port = match port {
+            0 => stack.next_ephemeral_port(|stack, port| stack.udp_sockets.contains_key(&port))?,
+            port if stack.udp_sockets.contains_key(&port) => return Err(NetError::AddressInUse(port)),
+            port => port,
+        };
+        stack.udp_sockets.insert(local_port, UdpSocketState {
+            datagrams: VecDeque::new(),
+            waker: None,
+        });
+        Ok(Self { local_port })
+    }
+}
+
+impl UdpSocket {
+    pub fn send_to(&self, data: &[u8], destination: Ipv4Address, port: u16) -> Result<(), NetError> {
+        let mut stack = NETWORK_STACK.lock();
+        let route = stack.route(destination)?;
+        let datagram = build_datagram(route.source, destination, self.local_port, port, data);
+        stack.send_ipv4(route, destination, ipv4::PROTOCOL_UDP, &datagram)
+    }
+
+    pub async fn receive_from(&self) -> Datagram {
+        poll_fn(|context| {
+            let mut stack = NETWORK_STACK.lock();
+            let socket = stack.udp_sockets.get_mut(&self.local_port)
+                .expect("bound socket has a state");
+            match socket.datagrams.pop_front() {
+                Some(datagram) => Poll::Ready(datagram),
+                None => {
+                    socket.waker = Some(context.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }).await
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        NETWORK_STACK.lock().udp_sockets.remove(&self.local_port);
+    }
+}
+
+#[test_case]
+fn test_build_and_parse_udp_datagram() {
+    let header = Ipv4Header {
+        source: Ipv4Address([10, 0, 2, 15]),
+        destination: Ipv4Address([10, 0, 2, 3]),
+        protocol: ipv4::PROTOCOL_UDP,
+        ttl: ipv4::DEFAULT_TTL,
+    };
+    let mut datagram = build_datagram(header.source, header.destination, 49152, 53, b"query");
+    assert_eq!(datagram.len(), UDP_HEADER_SIZE + 5);
+    assert_eq!(parse_datagram(&header, &datagram), Some((49152, 53, &b"query"[..])));
+
+    datagram[8] ^= 0xFF;
+    assert_eq!(parse_datagram(&header, &datagram), None);
+}
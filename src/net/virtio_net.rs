@@ -0,0 +1,259 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::task::Waker;
+
+use futures_util::task::AtomicWaker;
+
+use crate::error::Error;
+use crate::interrupts;
+use crate::log_warning;
+use crate::memory::{self, DmaRegion};
+use crate::net::network_device::{ETHERNET_MAX_FRAME_SIZE, MacAddress, NetworkDevice};
+use crate::pci;
+use crate::virtio::{VIRTIO_VENDOR_ID, VirtioError, VirtioPciTransport};
+use crate::virtio::virtqueue::{Virtqueue, VirtqueueBuffer};
+
+const DEVICE_IDS: [u16; 2] = [0x1000, 0x1041];
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+const MAX_QUEUE_SIZE: u16 = 64;
+const RECEIVE_BUFFERS: usize = 32;
+const TRANSMIT_BUFFERS: usize = 16;
+const BUFFER_SIZE: usize = 2048;
+/// Size of `virtio_net_hdr` including `num_buffers`, which is always present with VIRTIO_F_VERSION_1
+const NET_HEADER_SIZE: usize = 12;
+
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+const VIRTIO_NET_F_STATUS: u64 = 1 << 16;
+
+const CONFIG_MAC: usize = 0;
+const CONFIG_STATUS: usize = 6;
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
+
+#[derive(Debug)]
+pub enum VirtioNetError {
+    Transport(VirtioError),
+    MissingMacAddress,
+    FrameTooLarge(usize),
+    TransmitQueueFull,
+}
+
+impl Display for VirtioNetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VirtioNetError::Transport(error) => write!(f, "{}", error),
+            VirtioNetError::MissingMacAddress => write!(f, "Device doesn't provide a MAC address"),
+            VirtioNetError::FrameTooLarge(length) => write!(f, "Frame of {} bytes is too large", length),
+            VirtioNetError::TransmitQueueFull => write!(f, "Transmit queue is full"),
+        }
+    }
+}
+
+impl Error for VirtioNetError {}
+
+impl From<VirtioError> for VirtioNetError {
+    fn from(error: VirtioError) -> Self {
+        VirtioNetError::Transport(error)
+    }
+}
+
+/// Fixed-size packet buffers in one DMA region, each one prefixed with the virtio net header
+struct PacketBuffers {
+    memory: DmaRegion,
+    count: usize,
+}
+
+impl PacketBuffers {
+    fn new(count: usize) -> Result<Self, VirtioNetError> {
+        let memory = memory::allocate_dma(count * BUFFER_SIZE).ok_or(VirtioError::NoMemory)?;
+        Ok(Self { memory, count })
+    }
+
+    fn descriptor(&self, index: usize, length: usize, device_writable: bool) -> VirtqueueBuffer {
+        VirtqueueBuffer {
+            address: self.memory.physical_address + (index * BUFFER_SIZE) as u64,
+            length: length as u32,
+            device_writable,
+        }
+    }
+
+    fn buffer(&mut self, index: usize) -> &mut [u8] {
+        &mut self.memory.as_mut_slice()[index * BUFFER_SIZE..(index + 1) * BUFFER_SIZE]
+    }
+}
+
+pub struct VirtioNet {
+    transport: VirtioPciTransport,
+    receive_queue: Virtqueue,
+    transmit_queue: Virtqueue,
+    receive_buffers: PacketBuffers,
+    transmit_buffers: PacketBuffers,
+    /// Buffer index of the descriptor chain with the given head
+    receive_heads: Vec<Option<usize>>,
+    transmit_heads: Vec<Option<usize>>,
+    free_transmit_buffers: Vec<usize>,
+    mac_address: MacAddress,
+    status_supported: bool,
+    interrupt_driven: bool,
+    receive_waker: Arc<AtomicWaker>,
+}
+
+impl VirtioNet {
+    fn new(pci_device: &pci::PciDevice) -> Result<Self, VirtioNetError> {
+        let mut transport = VirtioPciTransport::new(pci_device)?;
+        let features = transport.negotiate_features(VIRTIO_NET_F_MAC | VIRTIO_NET_F_STATUS)?;
+        if features & VIRTIO_NET_F_MAC == 0 {
+            return Err(VirtioNetError::MissingMacAddress);
+        }
+
+        let receive_queue = transport.setup_queue(RECEIVE_QUEUE, MAX_QUEUE_SIZE)?;
+        let transmit_queue = transport.setup_queue(TRANSMIT_QUEUE, MAX_QUEUE_SIZE)?;
+        let receive_buffers = PacketBuffers::new(RECEIVE_BUFFERS.min(receive_queue.get_size() as usize))?;
+        let transmit_buffers = PacketBuffers::new(TRANSMIT_BUFFERS.min(transmit_queue.get_size() as usize))?;
+
+        let config = transport.get_device_config();
+        let mut mac_address = [0u8; 6];
+        for (index, byte) in mac_address.iter_mut().enumerate() {
+            *byte = config.read::<u8>(CONFIG_MAC + index);
+        }
+
+        let receive_waker = Arc::new(AtomicWaker::new());
+        let isr = transport.get_isr();
+        let waker = receive_waker.clone();
+        let interrupt_driven = interrupts::add_pci_irq_handler(pci_device.interrupt_line, Box::new(move || {
+            if isr.read::<u8>(0) != 0 {
+                waker.wake();
+            }
+        }));
+        if !interrupt_driven {
+            log_warning!("virtio-net {}: unsupported IRQ {}, falling back to polling",
+                pci_device.address, pci_device.interrupt_line);
+        }
+
+        transport.finish_initialization();
+
+        let mut device = Self {
+            transport,
+            receive_heads: vec![None; receive_queue.get_size() as usize],
+            transmit_heads: vec![None; transmit_queue.get_size() as usize],
+            free_transmit_buffers: (0..transmit_buffers.count).collect(),
+            receive_queue,
+            transmit_queue,
+            receive_buffers,
+            transmit_buffers,
+            mac_address: MacAddress(mac_address),
+            status_supported: features & VIRTIO_NET_F_STATUS != 0,
+            interrupt_driven,
+            receive_waker,
+        };
+        for index in 0..device.receive_buffers.count {
+            device.post_receive_buffer(index);
+        }
+        device.receive_queue.notify();
+        Ok(device)
+    }
+
+    pub fn get_pci_address(&self) -> pci::PciAddress {
+        self.transport.get_pci_device().address
+    }
+}
+
+impl VirtioNet {
+    fn post_receive_buffer(&mut self, index: usize) {
+        let buffer = self.receive_buffers.descriptor(index, BUFFER_SIZE, true);
+        let head = self.receive_queue.push(&[buffer])
+            .expect("receive queue has a descriptor for every buffer");
+        self.receive_heads[head as usize] = Some(index);
+    }
+
+    fn reclaim_transmitted(&mut self) {
+        while let Some((head, _)) = self.transmit_queue.pop_used() {
+            if let Some(index) = self.transmit_heads[head as usize].take() {
+                self.free_transmit_buffers.push(index);
+            }
+        }
+    }
+}
+
+impl NetworkDevice for VirtioNet {
+    fn get_mac_address(&self) -> MacAddress {
+        self.mac_address
+    }
+
+    fn is_link_up(&self) -> bool {
+        !self.status_supported
+            || self.transport.get_device_config().read::<u16>(CONFIG_STATUS) & VIRTIO_NET_S_LINK_UP != 0
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+        if frame.len() > ETHERNET_MAX_FRAME_SIZE {
+            return Err(VirtioNetError::FrameTooLarge(frame.len()).into());
+        }
+
+        self.reclaim_transmitted();
+        let index = self.free_transmit_buffers.pop().ok_or(VirtioNetError::TransmitQueueFull)?;
+        let buffer = self.transmit_buffers.buffer(index);
+        buffer[..NET_HEADER_SIZE].fill(0);
+        buffer[NET_HEADER_SIZE..NET_HEADER_SIZE + frame.len()].copy_from_slice(frame);
+
+        let descriptor = self.transmit_buffers.descriptor(index, NET_HEADER_SIZE + frame.len(), false);
+        let head = match self.transmit_queue.push(&[descriptor]) {
+            Some(head) => head,
+            None => {
+                self.free_transmit_buffers.push(index);
+                return Err(VirtioNetError::TransmitQueueFull.into());
+            }
+        };
+        self.transmit_heads[head as usize] = Some(index);
+        self.transmit_queue.notify();
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        while let Some((head, length)) = self.receive_queue.pop_used() {
+            let index = match self.receive_heads[head as usize].take() {
+                Some(index) => index,
+                None => continue,
+            };
+            let length = (length as usize).min(BUFFER_SIZE);
+            let frame = if length > NET_HEADER_SIZE {
+                Some(self.receive_buffers.buffer(index)[NET_HEADER_SIZE..length].to_vec())
+            } else {
+                None
+            };
+
+            self.post_receive_buffer(index);
+            self.receive_queue.notify();
+            if frame.is_some() {
+                return frame;
+            }
+        }
+        None
+    }
+
+    fn register_receive_waker(&mut self, waker: &Waker) {
+        self.receive_waker.register(waker);
+        if !self.interrupt_driven {
+            waker.wake_by_ref();
+        }
+    }
+
+    fn get_description(&self) -> &str {
+        "VirtIO network device"
+    }
+}
+
+pub fn detect_devices() -> Vec<VirtioNet> {
+    let mut devices = Vec::new();
+    for pci_device in pci::find_devices_by_id(VIRTIO_VENDOR_ID, &DEVICE_IDS) {
+        match VirtioNet::new(pci_device) {
+            Ok(device) => devices.push(device),
+            Err(error) => log_warning!("virtio-net {}: {}", pci_device.address, error),
+        }
+    }
+    devices
+}
@@ -0,0 +1,229 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+const CONFIG_ADDRESS_PORT: u16 = 0xCF8;
+const CONFIG_DATA_PORT: u16 = 0xCFC;
+
+const VENDOR_NONE: u16 = 0xFFFF;
+
+const COMMAND_OFFSET: u8 = 0x04;
+const CAPABILITIES_POINTER_OFFSET: u8 = 0x34;
+const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+const BAR0_OFFSET: u8 = 0x10;
+const INTERRUPT_LINE_OFFSET: u8 = 0x3C;
+
+bitflags! {
+    pub struct PciCommand: u16 {
+        const IO_SPACE =          0b0000_0000_0001;
+        const MEMORY_SPACE =      0b0000_0000_0010;
+        const BUS_MASTER =        0b0000_0000_0100;
+        const INTERRUPT_DISABLE = 0b0100_0000_0000;
+    }
+}
+
+lazy_static! {
+    static ref CONFIG_SPACE: Mutex<ConfigSpace> = Mutex::new(ConfigSpace {
+        address_port: Port::new(CONFIG_ADDRESS_PORT),
+        data_port: Port::new(CONFIG_DATA_PORT),
+    });
+
+    pub static ref PCI_DEVICES: Vec<PciDevice> = enumerate();
+}
+
+struct ConfigSpace {
+    address_port: Port<u32>,
+    data_port: Port<u32>,
+}
+
+impl ConfigSpace {
+    fn read(&mut self, address: PciAddress, offset: u8) -> u32 {
+        unsafe {
+            self.address_port.write(address.config_address(offset));
+            self.data_port.read()
+        }
+    }
+
+    fn write(&mut self, address: PciAddress, offset: u8, value: u32) {
+        unsafe {
+            self.address_port.write(address.config_address(offset));
+            self.data_port.write(value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    fn config_address(self, offset: u8) -> u32 {
+        1 << 31
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset & 0xFC) as u32
+    }
+}
+
+impl Display for PciAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Bar {
+    Memory { address: PhysAddr, size: usize },
+    /// I/O ports, not used by the drivers
+    Io,
+}
+
+#[derive(Debug, Clone)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub interrupt_line: u8,
+}
+
+impl PciDevice {
+    fn probe(address: PciAddress) -> Option<Self> {
+        let id = read_config(address, 0x00);
+        let vendor_id = id as u16;
+        if vendor_id == VENDOR_NONE {
+            return None;
+        }
+        let class = read_config(address, 0x08);
+        Some(Self {
+            address,
+            vendor_id,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            interrupt_line: read_config(address, INTERRUPT_LINE_OFFSET) as u8,
+        })
+    }
+}
+
+impl PciDevice {
+    pub fn read_config(&self, offset: u8) -> u32 {
+        read_config(self.address, offset)
+    }
+
+    pub fn write_config(&self, offset: u8, value: u32) {
+        write_config(self.address, offset, value)
+    }
+
+    pub fn enable(&self, flags: PciCommand) {
+        let value = self.read_config(COMMAND_OFFSET);
+        self.write_config(COMMAND_OFFSET, (value & 0xFFFF) | flags.bits() as u32);
+    }
+
+    pub fn disable(&self, flags: PciCommand) {
+        let value = self.read_config(COMMAND_OFFSET);
+        self.write_config(COMMAND_OFFSET, value & 0xFFFF & !(flags.bits() as u32));
+    }
+
+    /// Returns ID and offset in the config space of each capability
+    pub fn capabilities(&self) -> Vec<(u8, u8)> {
+        let mut capabilities = Vec::new();
+        let status = (self.read_config(COMMAND_OFFSET) >> 16) as u16;
+        if status & STATUS_CAPABILITIES_LIST == 0 {
+            return capabilities;
+        }
+
+        let mut offset = self.read_config(CAPABILITIES_POINTER_OFFSET) as u8 & 0xFC;
+        while offset != 0 && capabilities.len() < 48 {
+            let header = self.read_config(offset);
+            capabilities.push((header as u8, offset));
+            offset = (header >> 8) as u8 & 0xFC;
+        }
+        capabilities
+    }
+
+    pub fn read_bar(&self, index: u8) -> Option<Bar> {
+        let offset = BAR0_OFFSET + index * 4;
+        let value = self.read_config(offset);
+        if value & 0x1 != 0 {
+            return Some(Bar::Io);
+        }
+
+        let is_64bit = (value >> 1) & 0b11 == 0b10;
+        let mut address = (value & 0xFFFF_FFF0) as u64;
+        if is_64bit {
+            address |= (self.read_config(offset + 4) as u64) << 32;
+        }
+
+        // Size is determined by writing all ones and reading back the writable bits
+        self.write_config(offset, 0xFFFF_FFFF);
+        let size_mask = self.read_config(offset) & 0xFFFF_FFF0;
+        self.write_config(offset, value);
+
+        if address == 0 || size_mask == 0 {
+            return None;
+        }
+        Some(Bar::Memory {
+            address: PhysAddr::new(address),
+            size: (!size_mask).wrapping_add(1) as usize,
+        })
+    }
+}
+
+impl Display for PciDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} [{:04x}:{:04x}] class {:02x}.{:02x}.{:02x} IRQ {}",
+               self.address, self.vendor_id, self.device_id,
+               self.class, self.subclass, self.prog_if, self.interrupt_line)
+    }
+}
+
+pub fn find_devices(class: u8, subclass: u8) -> impl Iterator<Item=&'static PciDevice> {
+    PCI_DEVICES.iter()
+        .filter(move |device| device.class == class && device.subclass == subclass)
+}
+
+pub fn find_devices_by_id(vendor_id: u16, device_ids: &'static [u16]) -> impl Iterator<Item=&'static PciDevice> {
+    PCI_DEVICES.iter()
+        .filter(move |device| device.vendor_id == vendor_id && device_ids.contains(&device.device_id))
+}
+
+fn enumerate() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let address = PciAddress { bus, device, function: 0 };
+            if let Some(pci_device) = PciDevice::probe(address) {
+                let is_multifunction = (read_config(address, 0x0C) >> 16) & 0x80 != 0;
+                devices.push(pci_device);
+
+                if is_multifunction {
+                    for function in 1..8u8 {
+                        let address = PciAddress { bus, device, function };
+                        devices.extend(PciDevice::probe(address));
+                    }
+                }
+            }
+        }
+    }
+    devices
+}
+
+fn read_config(address: PciAddress, offset: u8) -> u32 {
+    CONFIG_SPACE.lock().read(address, offset)
+}
+
+fn write_config(address: PciAddress, offset: u8, value: u32) {
+    CONFIG_SPACE.lock().write(address, offset, value)
+}
@@ -1,17 +1,104 @@
 use alloc::fmt;
+use core::fmt::{Display, Formatter, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use lazy_static::lazy_static;
 use spin::mutex::Mutex;
-use uart_16550::SerialPort;
+use x86_64::instructions::interrupts::without_interrupts;
 
-lazy_static! {
-    pub static ref SERIAL_1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
+use crate::error::Error;
+use crate::serial::serial_config::SerialConfig;
+use crate::serial::uart::Uart;
+
+pub mod serial_config;
+pub mod uart;
+
+pub struct ComPort {
+    pub name: &'static str,
+    pub base: u16,
+    pub irq_line: u8,
+}
+
+pub const COM_PORTS: [ComPort; 4] = [
+    ComPort { name: "COM1", base: 0x3F8, irq_line: 4 },
+    ComPort { name: "COM2", base: 0x2F8, irq_line: 3 },
+    ComPort { name: "COM3", base: 0x3E8, irq_line: 4 },
+    ComPort { name: "COM4", base: 0x2E8, irq_line: 3 },
+];
+
+const NO_PORT: usize = usize::MAX;
+
+static SERIAL_PORTS: [Mutex<Option<Uart>>; 4] = [
+    Mutex::new(None),
+    Mutex::new(None),
+    Mutex::new(None),
+    Mutex::new(None),
+];
+/// Read by the interrupt handler, which can't take the locks of the ports
+static PORTS_PRESENT: [AtomicBool; 4] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+/// Index of the port bound to each channel, all channels except GDB use COM1 by default
+static CHANNEL_BINDINGS: [AtomicUsize; 4] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(NO_PORT),
+];
+
+/// Independent streams of the kernel output, each can be bound to a different port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialChannel {
+    /// Messages of the kernel logger
+    Log,
+    /// Interactive shell session
+    Shell,
+    /// Output of `serial_print!`, panics and tests
+    Debug,
+    /// GDB remote serial protocol, needs a port for itself
+    Gdb,
+}
+
+impl SerialChannel {
+    pub const ALL: [SerialChannel; 4] = [SerialChannel::Log, SerialChannel::Shell, SerialChannel::Debug, SerialChannel::Gdb];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|channel| channel.get_name() == name)
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            SerialChannel::Log => "log",
+            SerialChannel::Shell => "shell",
+            SerialChannel::Debug => "debug",
+            SerialChannel::Gdb => "gdb",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
 }
 
+#[derive(Debug)]
+pub enum SerialError {
+    PortNotPresent(&'static str),
+    PortInUse(&'static str),
+}
+
+impl Display for SerialError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SerialError::PortNotPresent(name) => write!(f, "{}: port not present", name),
+            SerialError::PortInUse(name) => write!(f, "{}: port in use by the GDB channel", name),
+        }
+    }
+}
+
+impl Error for SerialError {}
+
 #[macro_export]
 macro_rules! serial_print {
     ($($arg:tt)*) => (
@@ -29,11 +116,128 @@ macro_rules! serial_println {
     );
 }
 
+/// Probes COM1-COM4 and configures the present ports with the default settings.
+/// Doesn't allocate, so it can be called before the heap is initialized.
+pub fn init() {
+    for (index, com_port) in COM_PORTS.iter().enumerate() {
+        let mut uart = Uart::new(com_port.base);
+        if !uart.self_test() {
+            continue;
+        }
+        uart.configure(SerialConfig::default());
+        without_interrupts(|| *SERIAL_PORTS[index].lock() = Some(uart));
+        PORTS_PRESENT[index].store(true, Ordering::Release);
+    }
+}
+
+pub fn find_port(name: &str) -> Option<usize> {
+    COM_PORTS.iter().position(|com_port| com_port.name.eq_ignore_ascii_case(name))
+}
+
+/// Returns the configuration of the port, or `None` if the port is not present
+pub fn get_port_config(index: usize) -> Option<SerialConfig> {
+    with_port(index, |uart| uart.get_config())
+}
+
+pub fn configure_port(index: usize, config: SerialConfig) -> Result<(), SerialError> {
+    with_port(index, |uart| uart.configure(config))
+        .ok_or(SerialError::PortNotPresent(COM_PORTS[index].name))
+}
+
+pub fn get_binding(channel: SerialChannel) -> Option<usize> {
+    match CHANNEL_BINDINGS[channel.index()].load(Ordering::Relaxed) {
+        NO_PORT => None,
+        index => Some(index),
+    }
+}
+
+/// Binds the channel to the port, `None` disables the channel.
+/// The port of the GDB channel can't be shared, any other output would break the protocol.
+pub fn bind(channel: SerialChannel, port: Option<usize>) -> Result<(), SerialError> {
+    if let Some(index) = port {
+        if !PORTS_PRESENT[index].load(Ordering::Acquire) {
+            return Err(SerialError::PortNotPresent(COM_PORTS[index].name));
+        }
+        let is_shared = SerialChannel::ALL.iter()
+            .filter(|other| **other != channel && get_binding(**other) == Some(index))
+            .any(|other| channel == SerialChannel::Gdb || *other == SerialChannel::Gdb);
+        if is_shared {
+            return Err(SerialError::PortInUse(COM_PORTS[index].name));
+        }
+    }
+    CHANNEL_BINDINGS[channel.index()].store(port.unwrap_or(NO_PORT), Ordering::Relaxed);
+    Ok(())
+}
+
+fn with_port<R>(index: usize, f: impl FnOnce(&mut Uart) -> R) -> Option<R> {
+    without_interrupts(|| SERIAL_PORTS[index].lock().as_mut().map(f))
+}
+
+fn write_bytes(channel: SerialChannel, bytes: impl Iterator<Item=u8>) {
+    if let Some(index) = get_binding(channel) {
+        with_port(index, |uart| bytes.for_each(|byte| uart.send(byte)));
+    }
+}
+
+/// Returns the port of the channel for the use without its lock, by the code running
+/// with interrupts disabled, which could otherwise deadlock on the interrupted code.
+pub(crate) fn get_raw_port(channel: SerialChannel) -> Option<Uart> {
+    get_binding(channel).map(|index| Uart::new(COM_PORTS[index].base))
+}
+
+/// Moves the bytes received on the ports of the IRQ line to the serial shell input.
+/// The ports are read directly, because the interrupted code may hold their locks.
+/// Returns true if data arrived from GDB, the data is left for the GDB stub.
+pub(crate) fn handle_interrupt(irq_line: u8) -> bool {
+    let shell_port = get_binding(SerialChannel::Shell);
+    let gdb_port = get_binding(SerialChannel::Gdb);
+    let mut gdb_data_received = false;
+    for (index, com_port) in COM_PORTS.iter().enumerate() {
+        if com_port.irq_line != irq_line || !PORTS_PRESENT[index].load(Ordering::Acquire) {
+            continue;
+        }
+        let mut uart = Uart::new(com_port.base);
+        if gdb_port == Some(index) {
+            gdb_data_received |= uart.is_data_ready();
+            continue;
+        }
+        while let Some(byte) = uart.try_receive() {
+            if shell_port == Some(index) {
+                crate::task::serial_shell::add_byte(byte);
+            }
+        }
+    }
+    gdb_data_received
+}
+
+/// Writes to the port of the shell channel with line feeds translated to CR LF, as expected by terminals
+pub struct SerialWriter;
+
+impl Write for SerialWriter {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let bytes = text.bytes().flat_map(|byte| match byte {
+            b'\n' => [Some(b'\r'), Some(b'\n')],
+            byte => [Some(byte), None],
+        }).flatten();
+        write_bytes(SerialChannel::Shell, bytes);
+        Ok(())
+    }
+}
+
+struct ChannelWriter(SerialChannel);
+
+impl Write for ChannelWriter {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        write_bytes(self.0, text.bytes());
+        Ok(())
+    }
+}
+
+pub fn print_to(channel: SerialChannel, args: fmt::Arguments) {
+    let _ = ChannelWriter(channel).write_fmt(args);
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    SERIAL_1
-        .lock()
-        .write_fmt(args)
-        .expect("Printing to serial failed");
+    print_to(SerialChannel::Debug, args);
 }
@@ -0,0 +1,126 @@
+use core::fmt::{Display, Formatter};
+
+/// Frequency of the UART clock divided by 16, the highest supported baud rate
+pub const MAX_BAUD_RATE: u32 = 115200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    Mark,
+    Space,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl SerialConfig {
+    /// 115200 baud, 8 data bits, no parity and 1 stop bit
+    pub const DEFAULT: Self = Self {
+        baud_rate: MAX_BAUD_RATE,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+    };
+
+    /// Parses the baud rate and an optional frame format like "8N1"
+    pub fn parse(baud_rate: &str, frame_format: Option<&str>) -> Option<Self> {
+        let baud_rate = baud_rate.parse::<u32>().ok()?;
+        if baud_rate == 0 || !MAX_BAUD_RATE.is_multiple_of(baud_rate) {
+            return None;
+        }
+        let mut config = Self { baud_rate, ..Self::default() };
+        if let Some(frame_format) = frame_format {
+            let (data_bits, parity, stop_bits) = match frame_format.as_bytes() {
+                &[data_bits, parity, stop_bits] => (data_bits, parity, stop_bits),
+                _ => return None,
+            };
+            config.data_bits = match data_bits {
+                b'5'..=b'8' => data_bits - b'0',
+                _ => return None,
+            };
+            config.parity = match parity.to_ascii_uppercase() {
+                b'N' => Parity::None,
+                b'O' => Parity::Odd,
+                b'E' => Parity::Even,
+                b'M' => Parity::Mark,
+                b'S' => Parity::Space,
+                _ => return None,
+            };
+            config.stop_bits = match stop_bits {
+                b'1' => StopBits::One,
+                b'2' => StopBits::Two,
+                _ => return None,
+            };
+        }
+        Some(config)
+    }
+
+    pub fn get_divisor(&self) -> u16 {
+        (MAX_BAUD_RATE / self.baud_rate) as u16
+    }
+
+    /// Value of the line control register for the frame format
+    pub fn get_line_control(&self) -> u8 {
+        let data_bits = self.data_bits - 5;
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 0,
+            StopBits::Two => 1 << 2,
+        };
+        let parity = match self.parity {
+            Parity::None => 0b000 << 3,
+            Parity::Odd => 0b001 << 3,
+            Parity::Even => 0b011 << 3,
+            Parity::Mark => 0b101 << 3,
+            Parity::Space => 0b111 << 3,
+        };
+        data_bits | stop_bits | parity
+    }
+}
+
+impl Display for SerialConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+            Parity::Mark => 'M',
+            Parity::Space => 'S',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        write!(f, "{} {}{}{}", self.baud_rate, self.data_bits, parity, stop_bits)
+    }
+}
+
+#[test_case]
+fn test_parse_serial_config() {
+    let config = SerialConfig::parse("9600", Some("7e2")).unwrap();
+    assert_eq!(config.get_divisor(), 12);
+    assert_eq!(config.get_line_control(), 0b0001_1110);
+    assert_eq!(alloc::format!("{}", config), "9600 7E2");
+
+    assert_eq!(SerialConfig::parse("115200", None), Some(SerialConfig::default()));
+    assert_eq!(SerialConfig::parse("1000", None), None);
+    assert_eq!(SerialConfig::parse("9600", Some("9N1")), None);
+}
@@ -0,0 +1,107 @@
+use x86_64::instructions::port::Port;
+
+use crate::serial::serial_config::SerialConfig;
+
+const DATA_REGISTER: u16 = 0;
+const INTERRUPT_ENABLE_REGISTER: u16 = 1;
+const FIFO_CONTROL_REGISTER: u16 = 2;
+const LINE_CONTROL_REGISTER: u16 = 3;
+const MODEM_CONTROL_REGISTER: u16 = 4;
+const LINE_STATUS_REGISTER: u16 = 5;
+
+const INTERRUPT_RECEIVED_DATA: u8 = 1;
+/// Enabled, both FIFOs cleared, interrupt after 14 bytes
+const FIFO_CONTROL_ENABLE: u8 = 0xC7;
+const LINE_CONTROL_DIVISOR_LATCH: u8 = 0x80;
+/// DTR, RTS and OUT2, which connects the interrupt line
+const MODEM_CONTROL_NORMAL: u8 = 0x0B;
+/// RTS, OUT1, OUT2 and loopback
+const MODEM_CONTROL_LOOPBACK: u8 = 0x1E;
+const LINE_STATUS_DATA_READY: u8 = 1;
+const LINE_STATUS_TRANSMITTER_EMPTY: u8 = 1 << 5;
+
+const LOOPBACK_TEST_BYTE: u8 = 0xAE;
+/// Bounds waiting for the transmitter, so a disconnected port doesn't hang the kernel
+const MAX_TRANSMIT_WAIT: usize = 100_000;
+
+/// 16550 compatible UART
+#[derive(Debug, Clone, Copy)]
+pub struct Uart {
+    base: u16,
+    config: SerialConfig,
+}
+
+impl Uart {
+    pub const fn new(base: u16) -> Self {
+        Self {
+            base,
+            config: SerialConfig::DEFAULT,
+        }
+    }
+}
+
+impl Uart {
+    pub fn get_config(&self) -> SerialConfig {
+        self.config
+    }
+
+    /// Checks if the UART exists by sending a byte to itself in the loopback mode
+    pub fn self_test(&mut self) -> bool {
+        unsafe {
+            self.write_register(INTERRUPT_ENABLE_REGISTER, 0);
+            self.write_register(MODEM_CONTROL_REGISTER, MODEM_CONTROL_LOOPBACK);
+            self.write_register(DATA_REGISTER, LOOPBACK_TEST_BYTE);
+            let received = self.read_register(DATA_REGISTER);
+            self.write_register(MODEM_CONTROL_REGISTER, MODEM_CONTROL_NORMAL);
+            received == LOOPBACK_TEST_BYTE
+        }
+    }
+
+    /// Sets the line parameters and enables the receive interrupt
+    pub fn configure(&mut self, config: SerialConfig) {
+        let divisor = config.get_divisor();
+        unsafe {
+            self.write_register(INTERRUPT_ENABLE_REGISTER, 0);
+            self.write_register(LINE_CONTROL_REGISTER, LINE_CONTROL_DIVISOR_LATCH);
+            self.write_register(DATA_REGISTER, divisor as u8);
+            self.write_register(INTERRUPT_ENABLE_REGISTER, (divisor >> 8) as u8);
+            self.write_register(LINE_CONTROL_REGISTER, config.get_line_control());
+            self.write_register(FIFO_CONTROL_REGISTER, FIFO_CONTROL_ENABLE);
+            self.write_register(MODEM_CONTROL_REGISTER, MODEM_CONTROL_NORMAL);
+            self.write_register(INTERRUPT_ENABLE_REGISTER, INTERRUPT_RECEIVED_DATA);
+        }
+        self.config = config;
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        unsafe {
+            for _ in 0..MAX_TRANSMIT_WAIT {
+                if self.read_register(LINE_STATUS_REGISTER) & LINE_STATUS_TRANSMITTER_EMPTY != 0 {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            self.write_register(DATA_REGISTER, byte);
+        }
+    }
+
+    pub fn is_data_ready(&self) -> bool {
+        unsafe { self.read_register(LINE_STATUS_REGISTER) & LINE_STATUS_DATA_READY != 0 }
+    }
+
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if self.is_data_ready() {
+            Some(unsafe { self.read_register(DATA_REGISTER) })
+        } else {
+            None
+        }
+    }
+
+    unsafe fn read_register(&self, register: u16) -> u8 {
+        Port::<u8>::new(self.base + register).read()
+    }
+
+    unsafe fn write_register(&self, register: u16, value: u8) {
+        Port::<u8>::new(self.base + register).write(value)
+    }
+}
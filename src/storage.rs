@@ -0,0 +1,92 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::{log_info, log_warning};
+use crate::storage::block_device::{BlockDevice, SharedBlockDevice};
+use crate::storage::block_device_register::BlockDeviceRegister;
+use crate::storage::partition::{PartitionBlockDevice, PartitionError};
+
+pub mod block_device;
+pub mod block_device_register;
+pub mod ata;
+pub mod ahci;
+pub mod virtio_block;
+pub mod partition;
+pub mod block_cache;
+
+#[cfg(test)]
+pub mod mock_block_device;
+
+lazy_static! {
+    pub static ref BLOCK_DEVICES: Mutex<BlockDeviceRegister> =
+        Mutex::new(BlockDeviceRegister::new());
+}
+
+pub fn init() {
+    let drives = ata::detect_drives();
+    for (index, drive) in drives.into_iter().enumerate() {
+        let name = format!("ata{}", index);
+        let identity = drive.get_identity();
+        log_info!("{}: {:?} {:?}, \"{}\" (serial {}, firmware {}), {} sectors{}",
+            name, drive.get_channel_kind(), drive.get_position(),
+            identity.model, identity.serial_number, identity.firmware_revision, drive.get_sectors_count(),
+            if identity.lba48_supported { ", LBA48" } else { "" });
+        BLOCK_DEVICES.lock().register(&name, Arc::new(Mutex::new(drive)));
+    }
+
+    let ports = ahci::detect_ports();
+    for (index, port) in ports.into_iter().enumerate() {
+        let name = format!("sata{}", index);
+        let identity = port.get_identity();
+        log_info!("{}: AHCI port {}, \"{}\" (serial {}, firmware {}), {} sectors",
+            name, port.get_index(), identity.model, identity.serial_number, identity.firmware_revision,
+            port.get_sectors_count());
+        BLOCK_DEVICES.lock().register(&name, Arc::new(Mutex::new(port)));
+    }
+
+    let devices = virtio_block::detect_devices();
+    for (index, device) in devices.into_iter().enumerate() {
+        let name = format!("vd{}", (b'a' + index as u8) as char);
+        {
+            let device = device.lock();
+            log_info!("{}: VirtIO block device at {}, {} sectors{}",
+                name, device.get_pci_address(), device.get_sectors_count(),
+                if device.is_read_only() { ", read-only" } else { "" });
+        }
+        BLOCK_DEVICES.lock().register(&name, device);
+    }
+
+    let disks: Vec<(String, SharedBlockDevice)> = BLOCK_DEVICES.lock().iter()
+        .map(|(name, device)| (name.clone(), device.clone()))
+        .collect();
+    for (name, device) in disks {
+        register_partitions(&name, device);
+    }
+}
+
+fn register_partitions(disk_name: &str, disk: SharedBlockDevice) {
+    let table = partition::read_partition_table(&mut *disk.lock());
+    let table = match table {
+        Ok(table) => table,
+        Err(PartitionError::NoPartitionTable) => return,
+        Err(error) => {
+            log_warning!("{}: {}", disk_name, error);
+            return;
+        }
+    };
+
+    for info in table.partitions {
+        let name = format!("{}p{}", disk_name, info.number);
+        log_info!("{}: {:?} partition {}{}, sectors {}..{}",
+            name, table.kind, info.partition_type,
+            if info.name.is_empty() { String::new() } else { format!(" \"{}\"", info.name) },
+            info.first_lba, info.first_lba + info.sectors_count);
+        let partition = PartitionBlockDevice::new(disk.clone(), info);
+        BLOCK_DEVICES.lock().register(&name, Arc::new(Mutex::new(partition)));
+    }
+}
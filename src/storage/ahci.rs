@@ -0,0 +1,379 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use x86_64::VirtAddr;
+
+use crate::error::Error;
+use crate::log_warning;
+use crate::memory::{self, DmaRegion};
+use crate::pci::{self, Bar, PciCommand};
+use crate::storage::ata::AtaIdentity;
+use crate::storage::block_device::BlockDevice;
+use crate::task::timer;
+
+const PCI_CLASS_MASS_STORAGE: u8 = 0x01;
+const PCI_SUBCLASS_SATA: u8 = 0x06;
+const PCI_PROG_IF_AHCI: u8 = 0x01;
+const ABAR_INDEX: u8 = 5;
+
+const SECTOR_SIZE: usize = 512;
+const BOUNCE_BUFFER_SIZE: usize = 64 * 1024;
+const MAX_PORTS: usize = 32;
+/// Time for a command issued to the port to complete
+const COMMAND_TIMEOUT_MS: u64 = 10_000;
+/// Time for the port to stop running its command list before it is started
+const START_TIMEOUT_MS: u64 = 500;
+/// Bound of the polls when the ticks stand still, e.g. with interrupts disabled in the panic handler
+const MAX_COMMAND_POLLS: u32 = 10_000_000;
+
+const SATA_SIGNATURE_ATA: u32 = 0x0000_0101;
+
+const FIS_TYPE_REGISTER_H2D: u8 = 0x27;
+
+// HBA registers
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0C;
+const HBA_PORTS: usize = 0x100;
+const HBA_PORT_SIZE: usize = 0x80;
+
+// Port registers
+const PORT_CLB: usize = 0x00;
+const PORT_CLBU: usize = 0x04;
+const PORT_FB: usize = 0x08;
+const PORT_FBU: usize = 0x0C;
+const PORT_IS: usize = 0x10;
+const PORT_IE: usize = 0x14;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_CI: usize = 0x38;
+
+// Memory layout of the per port DMA region
+const COMMAND_LIST_OFFSET: usize = 0x000;
+const RECEIVED_FIS_OFFSET: usize = 0x400;
+const COMMAND_TABLE_OFFSET: usize = 0x500;
+const PRDT_OFFSET: usize = 0x80;
+
+bitflags! {
+    struct GlobalHostControl: u32 {
+        const HBA_RESET =        1 << 0;
+        const INTERRUPT_ENABLE = 1 << 1;
+        const AHCI_ENABLE =      1 << 31;
+    }
+
+    struct PortCommand: u32 {
+        const START =                1 << 0;
+        const FIS_RECEIVE_ENABLE =   1 << 4;
+        const FIS_RECEIVE_RUNNING =  1 << 14;
+        const COMMAND_LIST_RUNNING = 1 << 15;
+    }
+}
+
+const TFD_ERROR: u32 = 1 << 0;
+const TFD_DATA_REQUEST: u32 = 1 << 3;
+const TFD_BUSY: u32 = 1 << 7;
+const IS_TASK_FILE_ERROR: u32 = 1 << 30;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum AtaCommand {
+    ReadDmaExt = 0x25,
+    WriteDmaExt = 0x35,
+    FlushCacheExt = 0xEA,
+    Identify = 0xEC,
+}
+
+#[derive(Debug)]
+pub enum AhciError {
+    NoMemory,
+    PortHung,
+    TaskFileError(u32),
+    Timeout,
+}
+
+impl Display for AhciError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AhciError::NoMemory => write!(f, "Failed to allocate DMA memory"),
+            AhciError::PortHung => write!(f, "Port is hung"),
+            AhciError::TaskFileError(tfd) => write!(f, "Task file error (TFD: {:#010x})", tfd),
+            AhciError::Timeout => write!(f, "Command timed out"),
+        }
+    }
+}
+
+impl Error for AhciError {}
+
+/// Registers of the HBA memory space (ABAR)
+#[derive(Clone, Copy)]
+struct Registers {
+    base: VirtAddr,
+}
+
+impl Registers {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+
+    fn port(&self, index: usize) -> Registers {
+        Registers { base: self.base + HBA_PORTS + index * HBA_PORT_SIZE }
+    }
+}
+
+pub struct AhciPort {
+    index: usize,
+    registers: Registers,
+    memory: DmaRegion,
+    bounce_buffer: DmaRegion,
+    identity: AtaIdentity,
+}
+
+unsafe impl Send for AhciPort {}
+
+impl AhciPort {
+    fn new(index: usize, registers: Registers) -> Result<Self, AhciError> {
+        let memory = memory::allocate_dma(4096).ok_or(AhciError::NoMemory)?;
+        let bounce_buffer = memory::allocate_dma(BOUNCE_BUFFER_SIZE).ok_or(AhciError::NoMemory)?;
+        let mut port = Self {
+            index,
+            registers,
+            memory,
+            bounce_buffer,
+            identity: AtaIdentity::parse(&[0; 256]),
+        };
+
+        port.stop()?;
+        let base = port.memory.physical_address.as_u64();
+        port.registers.write(PORT_CLB, (base + COMMAND_LIST_OFFSET as u64) as u32);
+        port.registers.write(PORT_CLBU, ((base + COMMAND_LIST_OFFSET as u64) >> 32) as u32);
+        port.registers.write(PORT_FB, (base + RECEIVED_FIS_OFFSET as u64) as u32);
+        port.registers.write(PORT_FBU, ((base + RECEIVED_FIS_OFFSET as u64) >> 32) as u32);
+        port.registers.write(PORT_SERR, 0xFFFF_FFFF);
+        port.registers.write(PORT_IS, 0xFFFF_FFFF);
+        port.registers.write(PORT_IE, 0);
+        port.start()?;
+
+        let mut words = [0u16; 256];
+        port.execute(AtaCommand::Identify, 0, 1, false)?;
+        for (index, word) in words.iter_mut().enumerate() {
+            let bytes = &port.bounce_buffer.as_slice()[index * 2..index * 2 + 2];
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        port.identity = AtaIdentity::parse(&words);
+        Ok(port)
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_identity(&self) -> &AtaIdentity {
+        &self.identity
+    }
+}
+
+impl AhciPort {
+    fn stop(&mut self) -> Result<(), AhciError> {
+        let command = self.read_command() - PortCommand::START - PortCommand::FIS_RECEIVE_ENABLE;
+        self.registers.write(PORT_CMD, command.bits());
+
+        for _ in 0..1_000_000 {
+            let command = self.read_command();
+            if !command.intersects(PortCommand::COMMAND_LIST_RUNNING | PortCommand::FIS_RECEIVE_RUNNING) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(AhciError::PortHung)
+    }
+
+    fn start(&mut self) -> Result<(), AhciError> {
+        let deadline = timer::get_ticks() + timer::ms_to_ticks(START_TIMEOUT_MS);
+        let mut polls = 0;
+        while self.read_command().contains(PortCommand::COMMAND_LIST_RUNNING) {
+            polls += 1;
+            if timer::get_ticks() >= deadline || polls == MAX_COMMAND_POLLS {
+                return Err(AhciError::PortHung);
+            }
+            core::hint::spin_loop();
+        }
+        let command = self.read_command() | PortCommand::FIS_RECEIVE_ENABLE | PortCommand::START;
+        self.registers.write(PORT_CMD, command.bits());
+        Ok(())
+    }
+
+    fn read_command(&self) -> PortCommand {
+        PortCommand::from_bits_truncate(self.registers.read(PORT_CMD))
+    }
+
+    /// Issues a command in the slot 0 with the bounce buffer as the data buffer
+    fn execute(&mut self, command: AtaCommand, lba: u64, sectors_count: u16, write: bool) -> Result<(), AhciError> {
+        let byte_count = match command {
+            AtaCommand::Identify => SECTOR_SIZE,
+            _ => sectors_count as usize * SECTOR_SIZE,
+        };
+        self.prepare_command_slot(command, lba, sectors_count, write, byte_count);
+
+        for _ in 0..1_000_000 {
+            if self.registers.read(PORT_TFD) & (TFD_BUSY | TFD_DATA_REQUEST) == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+
+        self.registers.write(PORT_IS, 0xFFFF_FFFF);
+        self.registers.write(PORT_CI, 1);
+
+        let deadline = timer::get_ticks() + timer::ms_to_ticks(COMMAND_TIMEOUT_MS);
+        let mut polls = 0;
+        loop {
+            if self.registers.read(PORT_IS) & IS_TASK_FILE_ERROR != 0 {
+                return Err(AhciError::TaskFileError(self.registers.read(PORT_TFD)));
+            }
+            if self.registers.read(PORT_CI) & 1 == 0 {
+                break;
+            }
+            polls += 1;
+            if timer::get_ticks() >= deadline || polls == MAX_COMMAND_POLLS {
+                // Restarting the port clears the issued command
+                self.stop()?;
+                self.start()?;
+                return Err(AhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+
+        let tfd = self.registers.read(PORT_TFD);
+        if tfd & TFD_ERROR != 0 {
+            return Err(AhciError::TaskFileError(tfd));
+        }
+        Ok(())
+    }
+
+    fn prepare_command_slot(&mut self, command: AtaCommand, lba: u64, sectors_count: u16, write: bool, byte_count: usize) {
+        let command_table_address = self.memory.physical_address.as_u64() + COMMAND_TABLE_OFFSET as u64;
+        let bounce_buffer_address = self.bounce_buffer.physical_address.as_u64();
+        let memory = self.memory.as_mut_slice();
+
+        // Command header: FIS length in dwords, write flag and the number of PRDT entries
+        let header = &mut memory[COMMAND_LIST_OFFSET..COMMAND_LIST_OFFSET + 32];
+        header.fill(0);
+        let prdt_length = if byte_count > 0 { 1 } else { 0 };
+        let flags = 5 | if write { 1 << 6 } else { 0 } | prdt_length << 16;
+        header[0..4].copy_from_slice(&(flags as u32).to_le_bytes());
+        header[8..16].copy_from_slice(&command_table_address.to_le_bytes());
+
+        let table = &mut memory[COMMAND_TABLE_OFFSET..COMMAND_TABLE_OFFSET + PRDT_OFFSET + 16];
+        table.fill(0);
+
+        let lba = lba.to_le_bytes();
+        let count = sectors_count.to_le_bytes();
+        let fis = &mut table[0..20];
+        fis[0] = FIS_TYPE_REGISTER_H2D;
+        fis[1] = 1 << 7; // Command
+        fis[2] = command as u8;
+        fis[4..7].copy_from_slice(&lba[0..3]);
+        fis[7] = 1 << 6; // LBA mode
+        fis[8..11].copy_from_slice(&lba[3..6]);
+        fis[12..14].copy_from_slice(&count);
+
+        if byte_count == 0 {
+            return;
+        }
+        let prdt = &mut table[PRDT_OFFSET..PRDT_OFFSET + 16];
+        prdt[0..8].copy_from_slice(&bounce_buffer_address.to_le_bytes());
+        prdt[12..16].copy_from_slice(&((byte_count - 1) as u32).to_le_bytes());
+    }
+}
+
+impl BlockDevice for AhciPort {
+    fn get_sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        self.identity.sectors_count
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+
+        let mut lba = lba;
+        for chunk in buffer.chunks_mut(BOUNCE_BUFFER_SIZE) {
+            let sectors_count = chunk.len() / SECTOR_SIZE;
+            self.execute(AtaCommand::ReadDmaExt, lba, sectors_count as u16, false)?;
+            chunk.copy_from_slice(&self.bounce_buffer.as_slice()[..chunk.len()]);
+            lba += sectors_count as u64;
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+
+        let mut lba = lba;
+        for chunk in buffer.chunks(BOUNCE_BUFFER_SIZE) {
+            let sectors_count = chunk.len() / SECTOR_SIZE;
+            self.bounce_buffer.as_mut_slice()[..chunk.len()].copy_from_slice(chunk);
+            self.execute(AtaCommand::WriteDmaExt, lba, sectors_count as u16, true)?;
+            lba += sectors_count as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.execute(AtaCommand::FlushCacheExt, 0, 0, false)?;
+        Ok(())
+    }
+
+    fn get_description(&self) -> &str {
+        &self.identity.model
+    }
+}
+
+pub fn detect_ports() -> Vec<AhciPort> {
+    let mut ports = Vec::new();
+    let controllers = pci::find_devices(PCI_CLASS_MASS_STORAGE, PCI_SUBCLASS_SATA)
+        .filter(|device| device.prog_if == PCI_PROG_IF_AHCI);
+
+    for controller in controllers {
+        let (address, size) = match controller.read_bar(ABAR_INDEX) {
+            Some(Bar::Memory { address, size, .. }) => (address, size),
+            _ => continue,
+        };
+        controller.enable(PciCommand::MEMORY_SPACE | PciCommand::BUS_MASTER);
+
+        let registers = Registers { base: memory::map_mmio(address, size) };
+        let control = GlobalHostControl::from_bits_truncate(registers.read(HBA_GHC));
+        let control = (control | GlobalHostControl::AHCI_ENABLE) - GlobalHostControl::INTERRUPT_ENABLE;
+        registers.write(HBA_GHC, control.bits());
+
+        let ports_implemented = registers.read(HBA_PI);
+        for index in (0..MAX_PORTS).filter(|index| ports_implemented & (1 << index) != 0) {
+            let port_registers = registers.port(index);
+
+            // Device detected and the PHY communication established
+            let status = port_registers.read(PORT_SSTS);
+            if status & 0x0F != 3 || (status >> 8) & 0x0F != 1 {
+                continue;
+            }
+            if port_registers.read(PORT_SIG) != SATA_SIGNATURE_ATA {
+                continue;
+            }
+
+            match AhciPort::new(index, port_registers) {
+                Ok(port) => ports.push(port),
+                Err(error) => log_warning!("AHCI port {}: {}", index, error),
+            }
+        }
+    }
+    ports
+}
@@ -0,0 +1,457 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::{self, port::{Port, PortReadOnly, PortWriteOnly}};
+
+use crate::error::Error;
+use crate::storage::block_device::BlockDevice;
+use crate::task::timer;
+
+const SECTOR_SIZE: usize = 512;
+const MAX_SECTORS_PER_COMMAND: u64 = 256;
+const LBA28_LIMIT: u64 = 1 << 28;
+/// Time for the drive to finish a command, long enough to spin up
+const COMMAND_TIMEOUT_MS: u64 = 10_000;
+/// Limit of the status polls while interrupts are disabled and the timer doesn't tick
+const MAX_STATUS_POLLS: u32 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaChannelKind {
+    Primary,
+    Secondary,
+}
+
+impl AtaChannelKind {
+    fn io_base(self) -> u16 {
+        match self {
+            AtaChannelKind::Primary => 0x1F0,
+            AtaChannelKind::Secondary => 0x170,
+        }
+    }
+
+    fn control_base(self) -> u16 {
+        match self {
+            AtaChannelKind::Primary => 0x3F6,
+            AtaChannelKind::Secondary => 0x376,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            AtaChannelKind::Primary => 0,
+            AtaChannelKind::Secondary => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaDrivePosition {
+    Master,
+    Slave,
+}
+
+impl AtaDrivePosition {
+    fn select_bit(self) -> u8 {
+        match self {
+            AtaDrivePosition::Master => 0,
+            AtaDrivePosition::Slave => 1 << 4,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum AtaCommand {
+    ReadSectors = 0x20,
+    ReadSectorsExt = 0x24,
+    WriteSectors = 0x30,
+    WriteSectorsExt = 0x34,
+    CacheFlush = 0xE7,
+    CacheFlushExt = 0xEA,
+    Identify = 0xEC,
+}
+
+bitflags! {
+    struct Status: u8 {
+        const ERROR =         0b0000_0001;
+        const DATA_REQUEST =  0b0000_1000;
+        const SERVICE =       0b0001_0000;
+        const DRIVE_FAULT =   0b0010_0000;
+        const READY =         0b0100_0000;
+        const BUSY =          0b1000_0000;
+    }
+}
+
+static INTERRUPT_RECEIVED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+pub(crate) fn handle_interrupt(channel_kind: AtaChannelKind) {
+    // Reading the regular status register acknowledges the interrupt
+    let mut status_port: PortReadOnly<u8> = PortReadOnly::new(channel_kind.io_base() + 7);
+    unsafe { status_port.read() };
+
+    INTERRUPT_RECEIVED[channel_kind.index()].store(true, Ordering::Release);
+}
+
+#[derive(Debug)]
+pub enum AtaError {
+    NoDevice,
+    NotAta,
+    DriveFault,
+    CommandFailed(u8),
+    Timeout,
+}
+
+impl Display for AtaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AtaError::NoDevice => write!(f, "No device"),
+            AtaError::NotAta => write!(f, "Device is not an ATA drive"),
+            AtaError::DriveFault => write!(f, "Drive fault"),
+            AtaError::CommandFailed(error) => write!(f, "Command failed (error register: {:#04x})", error),
+            AtaError::Timeout => write!(f, "Drive did not respond in time"),
+        }
+    }
+}
+
+impl Error for AtaError {}
+
+pub struct AtaChannel {
+    kind: AtaChannelKind,
+    data_port: Port<u16>,
+    error_port: PortReadOnly<u8>,
+    sector_count_port: PortWriteOnly<u8>,
+    lba_low_port: Port<u8>,
+    lba_mid_port: Port<u8>,
+    lba_high_port: Port<u8>,
+    drive_select_port: PortWriteOnly<u8>,
+    status_port: PortReadOnly<u8>,
+    command_port: PortWriteOnly<u8>,
+    alternate_status_port: PortReadOnly<u8>,
+}
+
+impl AtaChannel {
+    pub fn new(kind: AtaChannelKind) -> Self {
+        let io_base = kind.io_base();
+        Self {
+            kind,
+            data_port: Port::new(io_base),
+            error_port: PortReadOnly::new(io_base + 1),
+            sector_count_port: PortWriteOnly::new(io_base + 2),
+            lba_low_port: Port::new(io_base + 3),
+            lba_mid_port: Port::new(io_base + 4),
+            lba_high_port: Port::new(io_base + 5),
+            drive_select_port: PortWriteOnly::new(io_base + 6),
+            status_port: PortReadOnly::new(io_base + 7),
+            command_port: PortWriteOnly::new(io_base + 7),
+            alternate_status_port: PortReadOnly::new(kind.control_base()),
+        }
+    }
+}
+
+impl AtaChannel {
+    fn identify(&mut self, position: AtaDrivePosition) -> Result<AtaIdentity, AtaError> {
+        // Floating bus, there are no drives on this channel
+        if self.read_status().bits() == 0xFF {
+            return Err(AtaError::NoDevice);
+        }
+
+        unsafe {
+            self.drive_select_port.write(0xA0 | position.select_bit());
+            self.delay();
+            self.sector_count_port.write(0);
+            self.lba_low_port.write(0);
+            self.lba_mid_port.write(0);
+            self.lba_high_port.write(0);
+        }
+        self.send_command(AtaCommand::Identify);
+
+        if self.read_status().is_empty() {
+            return Err(AtaError::NoDevice);
+        }
+        self.wait_for_status(|status| !status.contains(Status::BUSY))?;
+
+        // ATAPI and SATA devices report their signature in the LBA registers
+        let (lba_mid, lba_high) = unsafe { (self.lba_mid_port.read(), self.lba_high_port.read()) };
+        if lba_mid != 0 || lba_high != 0 {
+            return Err(AtaError::NotAta);
+        }
+
+        self.wait_for_data()?;
+        let mut words = [0u16; 256];
+        for word in words.iter_mut() {
+            *word = unsafe { self.data_port.read() };
+        }
+        Ok(AtaIdentity::parse(&words))
+    }
+
+    fn read_sectors(&mut self, position: AtaDrivePosition, lba48: bool, lba: u64, buffer: &mut [u8]) -> Result<(), AtaError> {
+        let sectors_count = (buffer.len() / SECTOR_SIZE) as u64;
+        let command = if lba48 { AtaCommand::ReadSectorsExt } else { AtaCommand::ReadSectors };
+        self.setup_transfer(position, lba48, lba, sectors_count);
+        self.send_command(command);
+
+        for sector in buffer.chunks_exact_mut(SECTOR_SIZE) {
+            self.wait_for_data()?;
+            for word in sector.chunks_exact_mut(2) {
+                let value = unsafe { self.data_port.read() };
+                word.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, position: AtaDrivePosition, lba48: bool, lba: u64, buffer: &[u8]) -> Result<(), AtaError> {
+        let sectors_count = (buffer.len() / SECTOR_SIZE) as u64;
+        let command = if lba48 { AtaCommand::WriteSectorsExt } else { AtaCommand::WriteSectors };
+        self.setup_transfer(position, lba48, lba, sectors_count);
+        self.send_command(command);
+
+        for sector in buffer.chunks_exact(SECTOR_SIZE) {
+            self.wait_for_data()?;
+            for word in sector.chunks_exact(2) {
+                let value = u16::from_le_bytes([word[0], word[1]]);
+                unsafe { self.data_port.write(value) };
+            }
+        }
+        self.flush_cache(position, lba48)
+    }
+
+    fn flush_cache(&mut self, position: AtaDrivePosition, lba48: bool) -> Result<(), AtaError> {
+        unsafe { self.drive_select_port.write(0xE0 | position.select_bit()) };
+        self.delay();
+        self.send_command(if lba48 { AtaCommand::CacheFlushExt } else { AtaCommand::CacheFlush });
+        self.wait_while_busy()
+    }
+
+    fn setup_transfer(&mut self, position: AtaDrivePosition, lba48: bool, lba: u64, sectors_count: u64) {
+        // In LBA28 mode the count of zero means 256 sectors, in LBA48 mode it means 65536
+        let count = if !lba48 && sectors_count == MAX_SECTORS_PER_COMMAND { 0 } else { sectors_count };
+        let lba_bytes = lba.to_le_bytes();
+        unsafe {
+            if lba48 {
+                self.drive_select_port.write(0x40 | position.select_bit());
+                self.delay();
+                self.sector_count_port.write((count >> 8) as u8);
+                self.lba_low_port.write(lba_bytes[3]);
+                self.lba_mid_port.write(lba_bytes[4]);
+                self.lba_high_port.write(lba_bytes[5]);
+            } else {
+                self.drive_select_port.write(0xE0 | position.select_bit() | (lba_bytes[3] & 0x0F));
+                self.delay();
+            }
+            self.sector_count_port.write(count as u8);
+            self.lba_low_port.write(lba_bytes[0]);
+            self.lba_mid_port.write(lba_bytes[1]);
+            self.lba_high_port.write(lba_bytes[2]);
+        }
+    }
+
+    fn send_command(&mut self, command: AtaCommand) {
+        INTERRUPT_RECEIVED[self.kind.index()].store(false, Ordering::Release);
+        unsafe { self.command_port.write(command as u8) };
+        self.delay();
+    }
+
+    fn wait_for_data(&mut self) -> Result<(), AtaError> {
+        let status = self.wait_for_status(|status| {
+            !status.contains(Status::BUSY) && status.intersects(Status::DATA_REQUEST | Status::ERROR | Status::DRIVE_FAULT)
+        })?;
+        self.check_errors(status)
+    }
+
+    fn wait_while_busy(&mut self) -> Result<(), AtaError> {
+        let status = self.wait_for_status(|status| !status.contains(Status::BUSY))?;
+        self.check_errors(status)
+    }
+
+    /// Waits until the status meets the condition, or the drive runs out of time
+    fn wait_for_status(&mut self, condition: impl Fn(Status) -> bool) -> Result<Status, AtaError> {
+        let deadline = timer::get_ticks() + timer::ms_to_ticks(COMMAND_TIMEOUT_MS);
+        for _ in 0..MAX_STATUS_POLLS {
+            let status = self.read_alternate_status();
+            if condition(status) {
+                return Ok(status);
+            }
+            if timer::get_ticks() >= deadline {
+                break;
+            }
+            self.wait_for_interrupt();
+        }
+        Err(AtaError::Timeout)
+    }
+
+    fn check_errors(&mut self, status: Status) -> Result<(), AtaError> {
+        if status.contains(Status::DRIVE_FAULT) {
+            Err(AtaError::DriveFault)
+        } else if status.contains(Status::ERROR) {
+            Err(AtaError::CommandFailed(unsafe { self.error_port.read() }))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleeps until the channel raises IRQ (or any other interrupt arrives).
+    /// With interrupts disabled, e.g. inside the panic handler, it falls back to busy polling.
+    fn wait_for_interrupt(&self) {
+        use instructions::interrupts;
+
+        if !interrupts::are_enabled() {
+            core::hint::spin_loop();
+            return;
+        }
+
+        interrupts::disable();
+        if INTERRUPT_RECEIVED[self.kind.index()].swap(false, Ordering::AcqRel) {
+            interrupts::enable();
+        } else {
+            interrupts::enable_and_hlt();
+        }
+    }
+
+    fn read_status(&mut self) -> Status {
+        Status::from_bits_truncate(unsafe { self.status_port.read() })
+    }
+
+    fn read_alternate_status(&mut self) -> Status {
+        Status::from_bits_truncate(unsafe { self.alternate_status_port.read() })
+    }
+
+    /// Waits about 400ns needed by the drive to update the status after drive select or command
+    fn delay(&mut self) {
+        for _ in 0..4 {
+            self.read_alternate_status();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AtaIdentity {
+    pub model: String,
+    pub serial_number: String,
+    pub firmware_revision: String,
+    pub lba48_supported: bool,
+    pub sectors_count: u64,
+}
+
+impl AtaIdentity {
+    pub(crate) fn parse(words: &[u16; 256]) -> Self {
+        let lba28_sectors = words[60] as u64 | (words[61] as u64) << 16;
+        let lba48_sectors = words[100] as u64
+            | (words[101] as u64) << 16
+            | (words[102] as u64) << 32
+            | (words[103] as u64) << 48;
+        let lba48_supported = words[83] & (1 << 10) != 0;
+
+        Self {
+            model: Self::parse_string(&words[27..47]),
+            serial_number: Self::parse_string(&words[10..20]),
+            firmware_revision: Self::parse_string(&words[23..27]),
+            lba48_supported,
+            sectors_count: if lba48_supported && lba48_sectors != 0 { lba48_sectors } else { lba28_sectors },
+        }
+    }
+
+    /// Strings in IDENTIFY data have swapped bytes in each word and are padded with spaces
+    fn parse_string(words: &[u16]) -> String {
+        let bytes: Vec<u8> = words.iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        String::from_utf8_lossy(&bytes).trim().into()
+    }
+}
+
+pub struct AtaDrive {
+    channel: Arc<Mutex<AtaChannel>>,
+    position: AtaDrivePosition,
+    identity: AtaIdentity,
+}
+
+impl AtaDrive {
+    pub fn get_channel_kind(&self) -> AtaChannelKind {
+        self.channel.lock().kind
+    }
+
+    pub fn get_position(&self) -> AtaDrivePosition {
+        self.position
+    }
+
+    pub fn get_identity(&self) -> &AtaIdentity {
+        &self.identity
+    }
+
+    fn use_lba48(&self, lba: u64, sectors_count: u64) -> bool {
+        self.identity.lba48_supported && lba + sectors_count > LBA28_LIMIT
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn get_sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        self.identity.sectors_count
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        let mut channel = self.channel.lock();
+
+        let mut lba = lba;
+        for chunk in buffer.chunks_mut(MAX_SECTORS_PER_COMMAND as usize * SECTOR_SIZE) {
+            let sectors_count = (chunk.len() / SECTOR_SIZE) as u64;
+            let lba48 = self.use_lba48(lba, sectors_count);
+            channel.read_sectors(self.position, lba48, lba, chunk)?;
+            lba += sectors_count;
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        let mut channel = self.channel.lock();
+
+        let mut lba = lba;
+        for chunk in buffer.chunks(MAX_SECTORS_PER_COMMAND as usize * SECTOR_SIZE) {
+            let sectors_count = (chunk.len() / SECTOR_SIZE) as u64;
+            let lba48 = self.use_lba48(lba, sectors_count);
+            channel.write_sectors(self.position, lba48, lba, chunk)?;
+            lba += sectors_count;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let lba48 = self.identity.lba48_supported;
+        self.channel.lock().flush_cache(self.position, lba48)?;
+        Ok(())
+    }
+
+    fn get_description(&self) -> &str {
+        &self.identity.model
+    }
+}
+
+pub fn detect_drives() -> Vec<AtaDrive> {
+    let mut drives = Vec::new();
+    for channel_kind in [AtaChannelKind::Primary, AtaChannelKind::Secondary] {
+        let channel = Arc::new(Mutex::new(AtaChannel::new(channel_kind)));
+        for position in [AtaDrivePosition::Master, AtaDrivePosition::Slave] {
+            let identity = channel.lock().identify(position);
+            if let Ok(identity) = identity {
+                drives.push(AtaDrive {
+                    channel: channel.clone(),
+                    position,
+                    identity,
+                });
+            }
+        }
+    }
+    drives
+}
@@ -0,0 +1,554 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::future::ready;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::error::Error;
+use crate::log_warning;
+use crate::storage::block_device::{BlockDevice, BlockIoFuture, SharedBlockDevice};
+use crate::task::{executor, timer};
+
+pub const DEFAULT_BUDGET: usize = 128 * 1024;
+const WRITE_BACK_INTERVAL_MS: u64 = 5000;
+
+lazy_static! {
+    pub static ref BLOCK_CACHE: Mutex<BlockCache> = Mutex::new(BlockCache::new(DEFAULT_BUDGET));
+}
+
+type DeviceId = usize;
+type BlockKey = (DeviceId, u64);
+
+struct CachedBlock {
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// Contiguous dirty blocks taken out of the cache to be written to the device
+pub struct WriteBack {
+    device_id: DeviceId,
+    device: SharedBlockDevice,
+    lba: u64,
+    data: Vec<u8>,
+}
+
+impl WriteBack {
+    pub fn write(self) -> Result<(), Box<dyn Error>> {
+        self.device.lock().write_sectors(self.lba, &self.data)?;
+        BLOCK_CACHE.lock().mark_clean(self.device_id, self.lba, &self.data);
+        Ok(())
+    }
+
+    async fn write_async(self) -> Result<(), Box<dyn Error>> {
+        let future = self.device.lock().write_async(self.lba, self.data);
+        let data = future.await?;
+        BLOCK_CACHE.lock().mark_clean(self.device_id, self.lba, &data);
+        Ok(())
+    }
+}
+
+pub struct BlockCacheStatistics {
+    pub blocks_count: usize,
+    pub dirty_count: usize,
+    pub used_bytes: usize,
+    pub budget: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub write_backs: u64,
+}
+
+/// Write-back cache of device sectors keyed by (device, sector) with LRU eviction under a memory budget
+pub struct BlockCache {
+    budget: usize,
+    used_bytes: usize,
+    clock: u64,
+    blocks: BTreeMap<BlockKey, CachedBlock>,
+    lru: BTreeMap<u64, BlockKey>,
+    devices: BTreeMap<DeviceId, SharedBlockDevice>,
+    hits: u64,
+    misses: u64,
+    write_backs: u64,
+}
+
+impl BlockCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used_bytes: 0,
+            clock: 0,
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            devices: BTreeMap::new(),
+            hits: 0,
+            misses: 0,
+            write_backs: 0,
+        }
+    }
+}
+
+impl BlockCache {
+    pub fn get_statistics(&self) -> BlockCacheStatistics {
+        BlockCacheStatistics {
+            blocks_count: self.blocks.len(),
+            dirty_count: self.blocks.values().filter(|block| block.dirty).count(),
+            used_bytes: self.used_bytes,
+            budget: self.budget,
+            hits: self.hits,
+            misses: self.misses,
+            write_backs: self.write_backs,
+        }
+    }
+
+    /// Changes the budget and returns the dirty blocks to write back to fit in it
+    pub fn set_budget(&mut self, budget: usize) -> Vec<WriteBack> {
+        self.budget = budget;
+        self.evict()
+    }
+
+    /// Copies the dirty blocks of the device, or of all devices, they stay dirty until they are written
+    pub fn take_dirty(&mut self, device_id: Option<DeviceId>) -> Vec<WriteBack> {
+        let mut write_backs: Vec<WriteBack> = Vec::new();
+        for (&(block_device_id, lba), block) in self.blocks.iter() {
+            if !block.dirty || device_id.is_some_and(|device_id| device_id != block_device_id) {
+                continue;
+            }
+
+            if let Some(last) = write_backs.last_mut() {
+                let sectors_count = (last.data.len() / block.data.len()) as u64;
+                if last.device_id == block_device_id && last.lba + sectors_count == lba {
+                    last.data.extend_from_slice(&block.data);
+                    continue;
+                }
+            }
+            write_backs.push(WriteBack {
+                device_id: block_device_id,
+                device: self.devices[&block_device_id].clone(),
+                lba,
+                data: block.data.clone(),
+            });
+        }
+        self.write_backs += write_backs.len() as u64;
+        write_backs
+    }
+
+    fn register_device(&mut self, device_id: DeviceId, device: SharedBlockDevice) {
+        self.devices.insert(device_id, device);
+    }
+
+    /// Drops all blocks of the device and returns how many of them were dirty
+    fn remove_device(&mut self, device_id: DeviceId) -> usize {
+        let keys: Vec<BlockKey> = self.blocks.range((device_id, 0)..=(device_id, u64::MAX))
+            .map(|(&key, _)| key)
+            .collect();
+        let dirty_count = keys.into_iter()
+            .filter_map(|key| self.remove(key))
+            .filter(|block| block.dirty)
+            .count();
+        self.devices.remove(&device_id);
+        dirty_count
+    }
+
+    fn remove(&mut self, key: BlockKey) -> Option<CachedBlock> {
+        let block = self.blocks.remove(&key)?;
+        self.lru.remove(&block.last_used);
+        self.used_bytes -= block.data.len();
+        Some(block)
+    }
+
+    fn touch(&mut self, key: BlockKey) {
+        self.clock += 1;
+        if let Some(block) = self.blocks.get_mut(&key) {
+            self.lru.remove(&block.last_used);
+            block.last_used = self.clock;
+            self.lru.insert(self.clock, key);
+        }
+    }
+
+    fn insert(&mut self, key: BlockKey, data: &[u8], dirty: bool) {
+        self.remove(key);
+        self.clock += 1;
+        self.used_bytes += data.len();
+        self.blocks.insert(key, CachedBlock { data: data.into(), dirty, last_used: self.clock });
+        self.lru.insert(self.clock, key);
+    }
+
+    /// Drops the least recently used blocks over the budget and returns write-backs of the dirty ones among them,
+    /// dirty blocks stay cached until their write-back succeeds
+    fn evict(&mut self) -> Vec<WriteBack> {
+        self.evict_blocks(true)
+    }
+
+    fn evict_blocks(&mut self, write_dirty: bool) -> Vec<WriteBack> {
+        let mut excess = self.used_bytes.saturating_sub(self.budget);
+        let mut clean_keys = Vec::new();
+        let mut dirty_keys = Vec::new();
+        for &key in self.lru.values() {
+            if excess == 0 {
+                break;
+            }
+            let block = &self.blocks[&key];
+            excess = excess.saturating_sub(block.data.len());
+            if block.dirty {
+                dirty_keys.push(key);
+            } else {
+                clean_keys.push(key);
+            }
+        }
+        for key in clean_keys {
+            self.remove(key);
+        }
+        if !write_dirty {
+            return Vec::new();
+        }
+
+        let write_backs: Vec<WriteBack> = dirty_keys.into_iter()
+            .map(|key| WriteBack {
+                device_id: key.0,
+                device: self.devices[&key.0].clone(),
+                lba: key.1,
+                data: self.blocks[&key].data.clone(),
+            })
+            .collect();
+        self.write_backs += write_backs.len() as u64;
+        write_backs
+    }
+
+    /// Marks the written blocks as clean, except the ones changed while they were being written,
+    /// and drops the ones kept over the budget only because they were dirty
+    fn mark_clean(&mut self, device_id: DeviceId, lba: u64, data: &[u8]) {
+        for (&(_, block_lba), block) in self.blocks.range_mut((device_id, lba)..=(device_id, u64::MAX)) {
+            let offset = (block_lba - lba) as usize * block.data.len();
+            match data.get(offset..offset + block.data.len()) {
+                Some(written) if written == &block.data[..] => block.dirty = false,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        self.evict_blocks(false);
+    }
+
+    /// Copies the sectors to the buffer if all of them are cached.
+    /// The range must be validated by the caller, e.g. with `BlockDevice::validate_request`.
+    fn read_cached(&mut self, device_id: DeviceId, lba: u64, buffer: &mut [u8], sector_size: usize) -> bool {
+        let sectors_count = (buffer.len() / sector_size) as u64;
+        if !(lba..lba + sectors_count).all(|sector| self.blocks.contains_key(&(device_id, sector))) {
+            self.misses += 1;
+            return false;
+        }
+
+        for (index, chunk) in buffer.chunks_mut(sector_size).enumerate() {
+            let key = (device_id, lba + index as u64);
+            chunk.copy_from_slice(&self.blocks[&key].data);
+            self.touch(key);
+        }
+        self.hits += 1;
+        true
+    }
+
+    /// Stores sectors read from the device, cached sectors take precedence as they may be newer
+    fn fill(&mut self, device_id: DeviceId, lba: u64, buffer: &mut [u8], sector_size: usize) -> Vec<WriteBack> {
+        for (index, chunk) in buffer.chunks_mut(sector_size).enumerate() {
+            let key = (device_id, lba + index as u64);
+            match self.blocks.get(&key) {
+                Some(block) => {
+                    chunk.copy_from_slice(&block.data);
+                    self.touch(key);
+                }
+                None => self.insert(key, chunk, false),
+            }
+        }
+        self.evict()
+    }
+
+    fn store(&mut self, device_id: DeviceId, lba: u64, buffer: &[u8], sector_size: usize) -> Vec<WriteBack> {
+        for (index, chunk) in buffer.chunks(sector_size).enumerate() {
+            self.insert((device_id, lba + index as u64), chunk, true);
+        }
+        self.evict()
+    }
+}
+
+/// Block device going through the global block cache, writes reach the device on flush, eviction or write-back
+pub struct CachedBlockDevice {
+    device: SharedBlockDevice,
+    device_id: DeviceId,
+    sector_size: usize,
+    sectors_count: u64,
+    description: String,
+}
+
+impl CachedBlockDevice {
+    pub fn new(device: SharedBlockDevice) -> Self {
+        static NEXT_DEVICE_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let device_id = NEXT_DEVICE_ID.fetch_add(1, Ordering::Relaxed);
+        let (sector_size, sectors_count, description) = {
+            let device = device.lock();
+            (device.get_sector_size(), device.get_sectors_count(), device.get_description().into())
+        };
+        BLOCK_CACHE.lock().register_device(device_id, device.clone());
+        Self { device, device_id, sector_size, sectors_count, description }
+    }
+}
+
+impl BlockDevice for CachedBlockDevice {
+    fn get_sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        self.sectors_count
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        let sectors_count = self.validate_request(lba, buffer.len())?;
+        let data = executor::block_on(self.read_async(lba, sectors_count))?;
+        buffer.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        let write_backs = BLOCK_CACHE.lock().store(self.device_id, lba, buffer, self.sector_size);
+        for write_back in write_backs {
+            if let Err(error) = write_back.write() {
+                log_warning!("Block cache write-back failed: {}", error);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let write_backs = BLOCK_CACHE.lock().take_dirty(Some(self.device_id));
+        for write_back in write_backs {
+            write_back.write()?;
+        }
+        self.device.lock().flush()
+    }
+
+    fn read_async(&mut self, lba: u64, sectors_count: u64) -> BlockIoFuture {
+        let mut buffer = alloc::vec![0; sectors_count as usize * self.sector_size];
+        if let Err(error) = self.validate_request(lba, buffer.len()) {
+            return Box::pin(ready(Err(error.into())));
+        }
+        if BLOCK_CACHE.lock().read_cached(self.device_id, lba, &mut buffer, self.sector_size) {
+            return Box::pin(ready(Ok(buffer)));
+        }
+
+        let device_id = self.device_id;
+        let sector_size = self.sector_size;
+        let future = self.device.lock().read_async(lba, sectors_count);
+        Box::pin(async move {
+            let mut data = future.await?;
+            let write_backs = BLOCK_CACHE.lock().fill(device_id, lba, &mut data, sector_size);
+            for write_back in write_backs {
+                if let Err(error) = write_back.write_async().await {
+                    log_warning!("Block cache write-back failed: {}", error);
+                }
+            }
+            Ok(data)
+        })
+    }
+
+    fn write_async(&mut self, lba: u64, data: Vec<u8>) -> BlockIoFuture {
+        if let Err(error) = self.validate_request(lba, data.len()) {
+            return Box::pin(ready(Err(error.into())));
+        }
+        let write_backs = BLOCK_CACHE.lock().store(self.device_id, lba, &data, self.sector_size);
+        Box::pin(async move {
+            for write_back in write_backs {
+                if let Err(error) = write_back.write_async().await {
+                    log_warning!("Block cache write-back failed: {}", error);
+                }
+            }
+            Ok(data)
+        })
+    }
+
+    fn flush_async(&mut self) -> BlockIoFuture {
+        let write_backs = BLOCK_CACHE.lock().take_dirty(Some(self.device_id));
+        let device = self.device.clone();
+        Box::pin(async move {
+            for write_back in write_backs {
+                write_back.write_async().await?;
+            }
+            let future = device.lock().flush_async();
+            future.await
+        })
+    }
+
+    fn get_description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Drop for CachedBlockDevice {
+    fn drop(&mut self) {
+        let dirty_count = BLOCK_CACHE.lock().remove_device(self.device_id);
+        if dirty_count > 0 {
+            log_warning!("Block cache: discarded {} unwritten blocks of a released device", dirty_count);
+        }
+    }
+}
+
+/// Periodically writes dirty blocks of all devices back to them and flushes the written devices
+pub async fn write_back_task() {
+    loop {
+        timer::sleep(WRITE_BACK_INTERVAL_MS).await;
+        let write_backs = BLOCK_CACHE.lock().take_dirty(None);
+        let mut written_devices = BTreeMap::new();
+        for write_back in write_backs {
+            let (device_id, device) = (write_back.device_id, write_back.device.clone());
+            match write_back.write_async().await {
+                Ok(()) => { written_devices.insert(device_id, device); }
+                Err(error) => log_warning!("Block cache write-back failed: {}", error),
+            }
+        }
+        for device in written_devices.into_values() {
+            let future = device.lock().flush_async();
+            if let Err(error) = future.await {
+                log_warning!("Block device flush failed: {}", error);
+            }
+        }
+    }
+}
+
+pub fn write_back_all() -> Result<(), Box<dyn Error>> {
+    let write_backs = BLOCK_CACHE.lock().take_dirty(None);
+    for write_back in write_backs {
+        write_back.write()?;
+    }
+    Ok(())
+}
+
+#[test_case]
+fn test_block_cache_defers_writes_until_flush() {
+    use alloc::sync::Arc;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mock = Arc::new(Mutex::new(MockBlockDevice::new(64)));
+    let mut device = CachedBlockDevice::new(mock.clone());
+
+    device.write_sectors(3, &[0xAB; 1024]).unwrap();
+    assert_eq!(mock.lock().get_data()[3 * 512], 0);
+
+    let mut buffer = [0u8; 512];
+    device.read_sectors(4, &mut buffer).unwrap();
+    assert_eq!(buffer, [0xAB; 512]);
+
+    device.flush().unwrap();
+    assert_eq!(&mock.lock().get_data()[3 * 512..5 * 512], &[0xAB; 1024]);
+}
+
+#[test_case]
+fn test_block_cache_reads_asynchronously() {
+    use alloc::sync::Arc;
+    use core::task::{Context, Poll, Waker};
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mock = Arc::new(Mutex::new(MockBlockDevice::new(64)));
+    mock.lock().get_data_mut()[5 * 512..7 * 512].fill(0x5A);
+    let mut device = CachedBlockDevice::new(mock.clone());
+
+    let mut context = Context::from_waker(Waker::noop());
+    for _ in 0..2 {
+        let result = device.read_async(5, 2).as_mut().poll(&mut context);
+        assert!(matches!(result, Poll::Ready(Ok(data)) if data == [0x5A; 1024]));
+        mock.lock().get_data_mut()[5 * 512..7 * 512].fill(0);
+    }
+    assert!(matches!(device.read_async(63, 2).as_mut().poll(&mut context), Poll::Ready(Err(_))));
+}
+
+#[test_case]
+fn test_block_cache_evicts_least_recently_used() {
+    use alloc::sync::Arc;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mock = Arc::new(Mutex::new(MockBlockDevice::new(64)));
+    mock.lock().get_data_mut()[..512].fill(0x11);
+    let mut device = CachedBlockDevice::new(mock.clone());
+    let previous_budget = BLOCK_CACHE.lock().get_statistics().budget;
+    assert!(BLOCK_CACHE.lock().set_budget(4 * 512).is_empty());
+
+    let mut buffer = [0u8; 512];
+    device.read_sectors(0, &mut buffer).unwrap();
+    for lba in 1..4 {
+        device.write_sectors(lba, &[lba as u8; 512]).unwrap();
+    }
+    device.read_sectors(0, &mut buffer).unwrap();
+    device.write_sectors(4, &[4; 512]).unwrap();
+
+    // Sector 1 was least recently used and got written back on eviction, sector 0 stayed cached
+    assert_eq!(mock.lock().get_data()[512], 1);
+    assert_eq!(mock.lock().get_data()[2 * 512], 0);
+    mock.lock().get_data_mut()[..512].fill(0x22);
+    device.read_sectors(0, &mut buffer).unwrap();
+    assert_eq!(buffer, [0x11; 512]);
+
+    let statistics = BLOCK_CACHE.lock().get_statistics();
+    assert!(statistics.used_bytes <= 4 * 512);
+    device.flush().unwrap();
+    drop(device);
+    BLOCK_CACHE.lock().set_budget(previous_budget);
+}
+
+#[test_case]
+fn test_block_cache_keeps_blocks_dirty_until_written() {
+    use alloc::sync::Arc;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mock = Arc::new(Mutex::new(MockBlockDevice::new(64)));
+    let mut device = CachedBlockDevice::new(mock.clone());
+
+    // Changed while being written, so written again on flush
+    device.write_sectors(2, &[1; 512]).unwrap();
+    let write_backs = BLOCK_CACHE.lock().take_dirty(Some(device.device_id));
+    device.write_sectors(2, &[2; 512]).unwrap();
+    for write_back in write_backs {
+        write_back.write().unwrap();
+    }
+    assert_eq!(mock.lock().get_data()[2 * 512], 1);
+    device.flush().unwrap();
+    assert_eq!(mock.lock().get_data()[2 * 512], 2);
+
+    // Evicted before its write-back finished, so written back on eviction as well
+    device.write_sectors(3, &[3; 512]).unwrap();
+    let write_backs = BLOCK_CACHE.lock().take_dirty(Some(device.device_id));
+    assert_eq!(write_backs.len(), 1);
+    let previous_budget = BLOCK_CACHE.lock().get_statistics().budget;
+    let evicted = BLOCK_CACHE.lock().set_budget(0);
+    BLOCK_CACHE.lock().set_budget(previous_budget);
+    assert!(evicted.iter().any(|write_back| write_back.device_id == device.device_id && write_back.lba == 3));
+}
+
+#[test_case]
+fn test_block_cache_keeps_dirty_blocks_until_write_back_succeeds() {
+    use alloc::sync::Arc;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mock = Arc::new(Mutex::new(MockBlockDevice::new(64)));
+    let mut device = CachedBlockDevice::new(mock.clone());
+    let previous_budget = BLOCK_CACHE.lock().get_statistics().budget;
+
+    // The failed write-back does not fail the write and the block stays cached over the budget
+    mock.lock().set_read_only(true);
+    device.write_sectors(7, &[7; 512]).unwrap();
+    assert_eq!(BLOCK_CACHE.lock().set_budget(0).len(), 1);
+    device.write_sectors(8, &[8; 512]).unwrap();
+    let mut buffer = [0u8; 512];
+    device.read_sectors(7, &mut buffer).unwrap();
+    assert_eq!(buffer, [7; 512]);
+
+    // Written and dropped once the device accepts it
+    mock.lock().set_read_only(false);
+    device.flush().unwrap();
+    assert_eq!(mock.lock().get_data()[7 * 512], 7);
+    assert_eq!(mock.lock().get_data()[8 * 512], 8);
+    assert_eq!(BLOCK_CACHE.lock().get_statistics().used_bytes, 0);
+    BLOCK_CACHE.lock().set_budget(previous_budget);
+}
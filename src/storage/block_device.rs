@@ -0,0 +1,91 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::future::{Future, ready};
+use core::pin::Pin;
+
+use spin::Mutex;
+
+use crate::error::Error;
+
+pub type SharedBlockDevice = Arc<Mutex<dyn BlockDevice>>;
+
+/// Resolves to the read data, or to the written buffer given back to the caller
+pub type BlockIoFuture = Pin<Box<dyn Future<Output=Result<Vec<u8>, Box<dyn Error>>>>>;
+
+pub trait BlockDevice: Send {
+    fn get_sector_size(&self) -> usize;
+
+    fn get_sectors_count(&self) -> u64;
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>>;
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Devices with queued I/O return a future resumed by their interrupt, others complete the request right away.
+    /// The returned future must not be polled while the device lock is held.
+    fn read_async(&mut self, lba: u64, sectors_count: u64) -> BlockIoFuture {
+        let mut buffer = vec![0; sectors_count as usize * self.get_sector_size()];
+        let result = self.read_sectors(lba, &mut buffer).map(|_| buffer);
+        Box::pin(ready(result))
+    }
+
+    fn write_async(&mut self, lba: u64, data: Vec<u8>) -> BlockIoFuture {
+        let result = self.write_sectors(lba, &data).map(|_| data);
+        Box::pin(ready(result))
+    }
+
+    fn flush_async(&mut self) -> BlockIoFuture {
+        let result = self.flush().map(|_| Vec::new());
+        Box::pin(ready(result))
+    }
+
+    fn get_size(&self) -> u64 {
+        self.get_sectors_count() * self.get_sector_size() as u64
+    }
+
+    fn get_description(&self) -> &str {
+        ""
+    }
+
+    /// Checks if the request fits in the device and returns the number of sectors it covers
+    fn validate_request(&self, lba: u64, buffer_length: usize) -> Result<u64, BlockDeviceError> {
+        let sector_size = self.get_sector_size();
+        if !buffer_length.is_multiple_of(sector_size) {
+            return Err(BlockDeviceError::UnalignedBuffer(buffer_length));
+        }
+        let sectors_count = (buffer_length / sector_size) as u64;
+        if lba.checked_add(sectors_count).is_none_or(|end| end > self.get_sectors_count()) {
+            return Err(BlockDeviceError::OutOfRange { lba, sectors_count });
+        }
+        Ok(sectors_count)
+    }
+}
+
+#[derive(Debug)]
+pub enum BlockDeviceError {
+    UnalignedBuffer(usize),
+    OutOfRange { lba: u64, sectors_count: u64 },
+    ReadOnly,
+}
+
+impl Display for BlockDeviceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BlockDeviceError::UnalignedBuffer(length) =>
+                write!(f, "Buffer length {} is not a multiple of the sector size", length),
+            BlockDeviceError::OutOfRange { lba, sectors_count } =>
+                write!(f, "Sectors {}..{} are out of the device range", lba, lba + sectors_count),
+            BlockDeviceError::ReadOnly =>
+                write!(f, "Device is read-only"),
+        }
+    }
+}
+
+impl Error for BlockDeviceError {}
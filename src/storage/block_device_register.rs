@@ -0,0 +1,28 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use crate::storage::block_device::SharedBlockDevice;
+
+pub struct BlockDeviceRegister {
+    devices: BTreeMap<String, SharedBlockDevice>,
+}
+
+impl BlockDeviceRegister {
+    pub fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, device: SharedBlockDevice) {
+        self.devices.insert(name.into(), device);
+    }
+
+    pub fn get(&self, name: &str) -> Option<SharedBlockDevice> {
+        self.devices.get(name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &SharedBlockDevice)> {
+        self.devices.iter()
+    }
+}
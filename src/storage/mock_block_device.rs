@@ -0,0 +1,69 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::Error;
+use crate::storage::block_device::{BlockDevice, BlockDeviceError};
+
+pub struct MockBlockDevice {
+    data: Vec<u8>,
+    sector_size: usize,
+    read_only: bool,
+}
+
+impl MockBlockDevice {
+    pub fn new(sectors_count: usize) -> Self {
+        Self {
+            data: vec![0; sectors_count * 512],
+            sector_size: 512,
+            read_only: false,
+        }
+    }
+
+    pub fn from_image(data: &[u8]) -> Self {
+        Self {
+            data: data.into(),
+            sector_size: 512,
+            read_only: false,
+        }
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+}
+
+impl BlockDevice for MockBlockDevice {
+    fn get_sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        (self.data.len() / self.sector_size) as u64
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        let offset = lba as usize * self.sector_size;
+        buffer.copy_from_slice(&self.data[offset..offset + buffer.len()]);
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        if self.read_only {
+            return Err(BlockDeviceError::ReadOnly.into());
+        }
+        let offset = lba as usize * self.sector_size;
+        self.data[offset..offset + buffer.len()].copy_from_slice(buffer);
+        Ok(())
+    }
+}
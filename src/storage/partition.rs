@@ -0,0 +1,488 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::future::ready;
+
+use crate::crc32::crc32;
+use crate::error::Error;
+use crate::storage::block_device::{BlockDevice, BlockIoFuture, SharedBlockDevice};
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_PARTITIONS_OFFSET: usize = 0x1BE;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_EXTENDED_CHS: u8 = 0x05;
+const MBR_TYPE_EXTENDED_LBA: u8 = 0x0F;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+const MAX_LOGICAL_PARTITIONS: usize = 64;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+const GPT_MAX_ENTRY_SIZE: usize = 4096;
+const GPT_MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    pub const fn zero() -> Self {
+        Guid([0; 16])
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&bytes[..16]);
+        Guid(guid)
+    }
+
+    /// Parses the canonical form, e.g. `C12A7328-F81F-11D2-BA4B-00A0C93EC93B`
+    pub const fn parse(text: &str) -> Self {
+        const fn hex(character: u8) -> u8 {
+            match character {
+                b'0'..=b'9' => character - b'0',
+                b'a'..=b'f' => character - b'a' + 10,
+                b'A'..=b'F' => character - b'A' + 10,
+                _ => panic!("invalid GUID"),
+            }
+        }
+
+        let text = text.as_bytes();
+        let mut canonical = [0u8; 16];
+        let mut index = 0;
+        let mut position = 0;
+        while index < 16 {
+            if text[position] == b'-' {
+                position += 1;
+            }
+            canonical[index] = hex(text[position]) << 4 | hex(text[position + 1]);
+            position += 2;
+            index += 1;
+        }
+
+        // The first three groups are stored as little-endian
+        let c = canonical;
+        Guid([c[3], c[2], c[1], c[0], c[5], c[4], c[7], c[6],
+            c[8], c[9], c[10], c[11], c[12], c[13], c[14], c[15]])
+    }
+}
+
+impl Display for Guid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+               b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9])?;
+        for byte in &b[10..16] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+const GPT_TYPES: [(Guid, &str); 6] = [
+    (Guid::parse("C12A7328-F81F-11D2-BA4B-00A0C93EC93B"), "EFI System"),
+    (Guid::parse("21686148-6449-6E6F-744E-656564454649"), "BIOS boot"),
+    (Guid::parse("EBD0A0A2-B9E5-4433-87C0-68B6B72699C7"), "Microsoft basic data"),
+    (Guid::parse("0FC63DAF-8483-4772-8E79-3D69D8477DE4"), "Linux filesystem"),
+    (Guid::parse("0657FD6D-A4AB-43C4-84E5-0933C84B4F4F"), "Linux swap"),
+    (Guid::parse("E6D6D379-F507-44C2-A23C-238F2A3DF928"), "Linux LVM"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionType {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+impl PartitionType {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            PartitionType::Mbr(0x01) => "FAT12",
+            PartitionType::Mbr(0x04 | 0x06 | 0x0E) => "FAT16",
+            PartitionType::Mbr(0x07) => "NTFS/exFAT",
+            PartitionType::Mbr(0x0B | 0x0C) => "FAT32",
+            PartitionType::Mbr(0x82) => "Linux swap",
+            PartitionType::Mbr(0x83) => "Linux",
+            PartitionType::Mbr(0x8E) => "Linux LVM",
+            PartitionType::Mbr(0xDA) => "Non-FS data",
+            PartitionType::Mbr(0xEF) => "EFI System",
+            PartitionType::Mbr(_) => "Unknown",
+            PartitionType::Gpt(guid) => GPT_TYPES.iter()
+                .find(|(type_guid, _)| type_guid == guid)
+                .map(|(_, name)| *name)
+                .unwrap_or("Unknown"),
+        }
+    }
+}
+
+impl Display for PartitionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PartitionType::Mbr(id) => write!(f, "{} ({:#04x})", self.get_name(), id),
+            PartitionType::Gpt(guid) => write!(f, "{} ({})", self.get_name(), guid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    pub number: usize,
+    pub first_lba: u64,
+    pub sectors_count: u64,
+    pub partition_type: PartitionType,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionTableKind {
+    Mbr,
+    Gpt,
+}
+
+#[derive(Debug)]
+pub struct PartitionTable {
+    pub kind: PartitionTableKind,
+    pub partitions: Vec<PartitionInfo>,
+}
+
+#[derive(Debug)]
+pub enum PartitionError {
+    NoPartitionTable,
+    InvalidGptHeader,
+    InvalidGptEntries,
+    Device(Box<dyn Error>),
+}
+
+impl Display for PartitionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PartitionError::NoPartitionTable => write!(f, "No partition table"),
+            PartitionError::InvalidGptHeader => write!(f, "Invalid GPT header"),
+            PartitionError::InvalidGptEntries => write!(f, "Invalid GPT partition entries"),
+            PartitionError::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for PartitionError {}
+
+impl From<Box<dyn Error>> for PartitionError {
+    fn from(error: Box<dyn Error>) -> Self {
+        PartitionError::Device(error)
+    }
+}
+
+pub fn read_partition_table(device: &mut dyn BlockDevice) -> Result<PartitionTable, PartitionError> {
+    let sector_size = device.get_sector_size();
+    let mut mbr = vec![0u8; sector_size];
+    device.read_sectors(0, &mut mbr)?;
+    if mbr[510..512] != BOOT_SIGNATURE {
+        return Err(PartitionError::NoPartitionTable);
+    }
+
+    let entries: Vec<(u8, u64, u64)> = (0..4)
+        .map(|index| parse_mbr_entry(&mbr, MBR_PARTITIONS_OFFSET + index * MBR_ENTRY_SIZE))
+        .collect();
+
+    if entries.iter().any(|(partition_type, _, _)| *partition_type == MBR_TYPE_GPT_PROTECTIVE) {
+        return read_gpt(device);
+    }
+
+    let mut partitions = Vec::new();
+    for (index, &(partition_type, first_lba, sectors_count)) in entries.iter().enumerate() {
+        match partition_type {
+            MBR_TYPE_EMPTY => {}
+            MBR_TYPE_EXTENDED_CHS | MBR_TYPE_EXTENDED_LBA => {
+                read_logical_partitions(device, first_lba, &mut partitions)?;
+            }
+            _ => partitions.push(PartitionInfo {
+                number: index + 1,
+                first_lba,
+                sectors_count,
+                partition_type: PartitionType::Mbr(partition_type),
+                name: String::new(),
+            }),
+        }
+    }
+    partitions.sort_by_key(|partition| partition.number);
+    Ok(PartitionTable { kind: PartitionTableKind::Mbr, partitions })
+}
+
+fn parse_mbr_entry(sector: &[u8], offset: usize) -> (u8, u64, u64) {
+    let entry = &sector[offset..offset + MBR_ENTRY_SIZE];
+    let partition_type = entry[4];
+    let first_lba = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+    let sectors_count = u32::from_le_bytes(entry[12..16].try_into().unwrap()) as u64;
+    (partition_type, first_lba, sectors_count)
+}
+
+/// Walks the chain of extended boot records, logical partitions are numbered from 5
+fn read_logical_partitions(device: &mut dyn BlockDevice, extended_lba: u64, partitions: &mut Vec<PartitionInfo>) -> Result<(), PartitionError> {
+    let mut ebr = vec![0u8; device.get_sector_size()];
+    let mut ebr_lba = extended_lba;
+    for number in 5..5 + MAX_LOGICAL_PARTITIONS {
+        device.read_sectors(ebr_lba, &mut ebr)?;
+        if ebr[510..512] != BOOT_SIGNATURE {
+            break;
+        }
+
+        let (partition_type, relative_lba, sectors_count) = parse_mbr_entry(&ebr, MBR_PARTITIONS_OFFSET);
+        if partition_type != MBR_TYPE_EMPTY {
+            partitions.push(PartitionInfo {
+                number,
+                first_lba: ebr_lba + relative_lba,
+                sectors_count,
+                partition_type: PartitionType::Mbr(partition_type),
+                name: String::new(),
+            });
+        }
+
+        let (next_type, next_relative_lba, _) = parse_mbr_entry(&ebr, MBR_PARTITIONS_OFFSET + MBR_ENTRY_SIZE);
+        if next_type == MBR_TYPE_EMPTY {
+            break;
+        }
+        ebr_lba = extended_lba + next_relative_lba;
+    }
+    Ok(())
+}
+
+fn read_gpt(device: &mut dyn BlockDevice) -> Result<PartitionTable, PartitionError> {
+    let last_lba = device.get_sectors_count().checked_sub(1).ok_or(PartitionError::NoPartitionTable)?;
+    let partitions = match read_gpt_at(device, 1) {
+        Ok(partitions) => partitions,
+        // Fallback to the backup header at the end of the disk
+        Err(_) => read_gpt_at(device, last_lba)?,
+    };
+    Ok(PartitionTable { kind: PartitionTableKind::Gpt, partitions })
+}
+
+fn read_gpt_at(device: &mut dyn BlockDevice, header_lba: u64) -> Result<Vec<PartitionInfo>, PartitionError> {
+    let sector_size = device.get_sector_size();
+    let mut header = vec![0u8; sector_size];
+    device.read_sectors(header_lba, &mut header)?;
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    let read_u64 = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+
+    if &header[0..8] != GPT_SIGNATURE {
+        return Err(PartitionError::InvalidGptHeader);
+    }
+    let header_size = read_u32(12) as usize;
+    if header_size < GPT_MIN_HEADER_SIZE || header_size > sector_size {
+        return Err(PartitionError::InvalidGptHeader);
+    }
+    let header_crc = read_u32(16);
+    let entries_lba = read_u64(72);
+    let entries_count = read_u32(80) as usize;
+    let entry_size = read_u32(84) as usize;
+    let entries_crc = read_u32(88);
+
+    let mut header_for_crc = header[..header_size].to_vec();
+    header_for_crc[16..20].fill(0);
+    if crc32(&header_for_crc) != header_crc {
+        return Err(PartitionError::InvalidGptHeader);
+    }
+    if !(GPT_MIN_ENTRY_SIZE..=GPT_MAX_ENTRY_SIZE).contains(&entry_size) || entries_count > GPT_MAX_ENTRIES {
+        return Err(PartitionError::InvalidGptEntries);
+    }
+    let entries_size = entries_count.checked_mul(entry_size).ok_or(PartitionError::InvalidGptEntries)?;
+
+    let entries_sectors = entries_size.div_ceil(sector_size);
+    let mut entries = vec![0u8; entries_sectors * sector_size];
+    device.read_sectors(entries_lba, &mut entries)?;
+    if crc32(&entries[..entries_size]) != entries_crc {
+        return Err(PartitionError::InvalidGptEntries);
+    }
+
+    let mut partitions = Vec::new();
+    for (index, entry) in entries.chunks_exact(entry_size).take(entries_count).enumerate() {
+        let type_guid = Guid::from_bytes(&entry[0..16]);
+        if type_guid == Guid::zero() {
+            continue;
+        }
+        let first_lba = u64::from_le_bytes(entry[32..40].try_into().unwrap());
+        let last_lba = u64::from_le_bytes(entry[40..48].try_into().unwrap());
+        if last_lba < first_lba {
+            continue;
+        }
+        let name: Vec<u16> = entry[56..128].chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .take_while(|&character| character != 0)
+            .collect();
+
+        partitions.push(PartitionInfo {
+            number: index + 1,
+            first_lba,
+            sectors_count: last_lba - first_lba + 1,
+            partition_type: PartitionType::Gpt(type_guid),
+            name: String::from_utf16_lossy(&name),
+        });
+    }
+    Ok(partitions)
+}
+
+/// Exposes a range of sectors of the parent device as a separate block device
+pub struct PartitionBlockDevice {
+    device: SharedBlockDevice,
+    info: PartitionInfo,
+}
+
+impl PartitionBlockDevice {
+    pub fn new(device: SharedBlockDevice, info: PartitionInfo) -> Self {
+        Self { device, info }
+    }
+}
+
+impl BlockDevice for PartitionBlockDevice {
+    fn get_sector_size(&self) -> usize {
+        self.device.lock().get_sector_size()
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        self.info.sectors_count
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        self.device.lock().read_sectors(self.info.first_lba + lba, buffer)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        self.device.lock().write_sectors(self.info.first_lba + lba, buffer)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.device.lock().flush()
+    }
+
+    fn read_async(&mut self, lba: u64, sectors_count: u64) -> BlockIoFuture {
+        if let Err(error) = self.validate_request(lba, sectors_count as usize * self.get_sector_size()) {
+            return Box::pin(ready(Err(error.into())));
+        }
+        self.device.lock().read_async(self.info.first_lba + lba, sectors_count)
+    }
+
+    fn write_async(&mut self, lba: u64, data: Vec<u8>) -> BlockIoFuture {
+        if let Err(error) = self.validate_request(lba, data.len()) {
+            return Box::pin(ready(Err(error.into())));
+        }
+        self.device.lock().write_async(self.info.first_lba + lba, data)
+    }
+
+    fn flush_async(&mut self) -> BlockIoFuture {
+        self.device.lock().flush_async()
+    }
+
+    fn get_description(&self) -> &str {
+        self.info.partition_type.get_name()
+    }
+}
+
+#[cfg(test)]
+fn write_mbr_entry(image: &mut [u8], index: usize, partition_type: u8, first_lba: u32, sectors_count: u32) {
+    let offset = MBR_PARTITIONS_OFFSET + index * MBR_ENTRY_SIZE;
+    image[offset + 4] = partition_type;
+    image[offset + 8..offset + 12].copy_from_slice(&first_lba.to_le_bytes());
+    image[offset + 12..offset + 16].copy_from_slice(&sectors_count.to_le_bytes());
+    image[510..512].copy_from_slice(&BOOT_SIGNATURE);
+}
+
+#[test_case]
+fn test_read_mbr_partitions() {
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut device = MockBlockDevice::new(1024);
+    write_mbr_entry(device.get_data_mut(), 0, 0x0C, 2048 / 512, 100);
+    write_mbr_entry(device.get_data_mut(), 1, 0x83, 200, 300);
+
+    let table = read_partition_table(&mut device).unwrap();
+
+    assert_eq!(table.kind, PartitionTableKind::Mbr);
+    assert_eq!(table.partitions.len(), 2);
+    assert_eq!(table.partitions[0].number, 1);
+    assert_eq!(table.partitions[0].first_lba, 4);
+    assert_eq!(table.partitions[0].sectors_count, 100);
+    assert_eq!(table.partitions[0].partition_type.get_name(), "FAT32");
+    assert_eq!(table.partitions[1].first_lba, 200);
+    assert_eq!(table.partitions[1].partition_type, PartitionType::Mbr(0x83));
+}
+
+#[test_case]
+fn test_read_gpt_partitions() {
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut device = MockBlockDevice::new(128);
+    let image = device.get_data_mut();
+    write_mbr_entry(image, 0, MBR_TYPE_GPT_PROTECTIVE, 1, 127);
+
+    let linux = Guid::parse("0FC63DAF-8483-4772-8E79-3D69D8477DE4");
+    let entries_offset = 2 * 512;
+    let entry = &mut image[entries_offset..entries_offset + 128];
+    entry[0..16].copy_from_slice(&linux.0);
+    entry[32..40].copy_from_slice(&34u64.to_le_bytes());
+    entry[40..48].copy_from_slice(&99u64.to_le_bytes());
+    for (index, character) in "root".encode_utf16().enumerate() {
+        entry[56 + index * 2..58 + index * 2].copy_from_slice(&character.to_le_bytes());
+    }
+    // Entry ending before it starts is skipped
+    let reversed_entry = &mut image[entries_offset + 128..entries_offset + 256];
+    reversed_entry[0..16].copy_from_slice(&linux.0);
+    reversed_entry[32..40].copy_from_slice(&100u64.to_le_bytes());
+    reversed_entry[40..48].copy_from_slice(&99u64.to_le_bytes());
+    let entries_crc = crc32(&image[entries_offset..entries_offset + 4 * 128]);
+
+    let header = &mut image[512..1024];
+    header[0..8].copy_from_slice(GPT_SIGNATURE);
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[24..32].copy_from_slice(&1u64.to_le_bytes());
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&4u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+    let header_crc = crc32(&header[..92]);
+    header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+    let table = read_partition_table(&mut device).unwrap();
+
+    assert_eq!(table.kind, PartitionTableKind::Gpt);
+    assert_eq!(table.partitions.len(), 1);
+    assert_eq!(table.partitions[0].first_lba, 34);
+    assert_eq!(table.partitions[0].sectors_count, 66);
+    assert_eq!(table.partitions[0].name, "root");
+    assert_eq!(table.partitions[0].partition_type.get_name(), "Linux filesystem");
+
+    // Corrupted header without a valid backup
+    device.get_data_mut()[512 + 40] ^= 0xFF;
+    assert!(read_partition_table(&mut device).is_err());
+}
+
+#[test_case]
+fn test_partition_block_device_offset() {
+    use alloc::sync::Arc;
+    use spin::Mutex;
+    use crate::storage::mock_block_device::MockBlockDevice;
+
+    let mut disk = MockBlockDevice::new(16);
+    disk.get_data_mut()[4 * 512] = 0xAB;
+    let disk = Arc::new(Mutex::new(disk));
+
+    let mut partition = PartitionBlockDevice::new(disk.clone(), PartitionInfo {
+        number: 1,
+        first_lba: 4,
+        sectors_count: 8,
+        partition_type: PartitionType::Mbr(0x83),
+        name: String::new(),
+    });
+
+    let mut sector = [0u8; 512];
+    partition.read_sectors(0, &mut sector).unwrap();
+    assert_eq!(sector[0], 0xAB);
+
+    partition.write_sectors(7, &[0xCD; 512]).unwrap();
+    assert_eq!(disk.lock().get_data()[11 * 512], 0xCD);
+
+    assert!(partition.read_sectors(8, &mut sector).is_err());
+}
@@ -0,0 +1,526 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions;
+
+use crate::error::Error;
+use crate::interrupts;
+use crate::log_warning;
+use crate::memory::{self, DmaRegion};
+use crate::pci;
+use crate::storage::block_device::{BlockDevice, BlockDeviceError, BlockIoFuture};
+use crate::virtio::{VIRTIO_VENDOR_ID, VirtioError, VirtioPciTransport};
+use crate::virtio::virtqueue::{Virtqueue, VirtqueueBuffer};
+
+const DEVICE_IDS: [u16; 2] = [0x1001, 0x1042];
+
+const SECTOR_SIZE: usize = 512;
+const MAX_QUEUE_SIZE: u16 = 128;
+const REQUEST_SLOTS: usize = 8;
+const SLOT_HEADER_SIZE: usize = 4096;
+const SLOT_DATA_SIZE: usize = 64 * 1024;
+pub const MAX_REQUEST_SECTORS: u64 = (SLOT_DATA_SIZE / SECTOR_SIZE) as u64;
+
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+const HEADER_SIZE: u32 = 16;
+const STATUS_OFFSET: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequestKind {
+    Read,
+    Write,
+    Flush,
+}
+
+#[derive(Debug)]
+pub enum VirtioBlockError {
+    Transport(VirtioError),
+    QueueFull,
+    TooLarge(u64),
+    IoError,
+    Unsupported,
+    Device(BlockDeviceError),
+}
+
+impl Display for VirtioBlockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VirtioBlockError::Transport(error) => write!(f, "{}", error),
+            VirtioBlockError::QueueFull => write!(f, "Virtqueue is full"),
+            VirtioBlockError::TooLarge(sectors_count) =>
+                write!(f, "Request of {} sectors exceeds the limit of {}", sectors_count, MAX_REQUEST_SECTORS),
+            VirtioBlockError::IoError => write!(f, "I/O error"),
+            VirtioBlockError::Unsupported => write!(f, "Request unsupported by device"),
+            VirtioBlockError::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for VirtioBlockError {}
+
+impl From<VirtioError> for VirtioBlockError {
+    fn from(error: VirtioError) -> Self {
+        VirtioBlockError::Transport(error)
+    }
+}
+
+impl From<BlockDeviceError> for VirtioBlockError {
+    fn from(error: BlockDeviceError) -> Self {
+        VirtioBlockError::Device(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Free,
+    Reserved,
+    Submitted { head: u16 },
+    Completed { status: u8 },
+    /// Future was dropped before the device finished, the slot is freed on completion
+    Abandoned { head: u16 },
+}
+
+/// DMA memory of a single in-flight request: header and status in the first page, data after it
+struct RequestSlot {
+    memory: DmaRegion,
+    state: SlotState,
+}
+
+impl RequestSlot {
+    fn data(&self, length: usize) -> &[u8] {
+        &self.memory.as_slice()[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + length]
+    }
+
+    fn data_mut(&mut self, length: usize) -> &mut [u8] {
+        &mut self.memory.as_mut_slice()[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + length]
+    }
+}
+
+pub struct VirtioBlock {
+    transport: VirtioPciTransport,
+    queue: Virtqueue,
+    slots: Vec<RequestSlot>,
+    capacity: u64,
+    read_only: bool,
+    flush_supported: bool,
+    interrupt_driven: bool,
+    slot_wakers: Arc<Vec<AtomicWaker>>,
+    free_slot_waiters: Vec<Waker>,
+    /// Set once the device is shared, lets the block device interface create request futures
+    this: Weak<Mutex<VirtioBlock>>,
+}
+
+impl VirtioBlock {
+    fn new(pci_device: &pci::PciDevice) -> Result<Self, VirtioBlockError> {
+        let mut transport = VirtioPciTransport::new(pci_device)?;
+        let features = transport.negotiate_features(VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH)?;
+        let queue = transport.setup_queue(0, MAX_QUEUE_SIZE)?;
+
+        let slots_count = REQUEST_SLOTS.min(queue.get_size() as usize / 3);
+        let mut slots = Vec::with_capacity(slots_count);
+        for _ in 0..slots_count {
+            let memory = memory::allocate_dma(SLOT_HEADER_SIZE + SLOT_DATA_SIZE)
+                .ok_or(VirtioError::NoMemory)?;
+            slots.push(RequestSlot { memory, state: SlotState::Free });
+        }
+
+        let slot_wakers: Arc<Vec<AtomicWaker>> = Arc::new((0..slots_count).map(|_| AtomicWaker::new()).collect());
+        let isr = transport.get_isr();
+        let wakers = slot_wakers.clone();
+        let interrupt_driven = interrupts::add_pci_irq_handler(pci_device.interrupt_line, Box::new(move || {
+            if isr.read::<u8>(0) != 0 {
+                for waker in wakers.iter() {
+                    waker.wake();
+                }
+            }
+        }));
+        if !interrupt_driven {
+            log_warning!("virtio-blk {}: unsupported IRQ {}, falling back to polling",
+                pci_device.address, pci_device.interrupt_line);
+        }
+
+        let capacity = transport.get_device_config().read::<u64>(0);
+        transport.finish_initialization();
+
+        Ok(Self {
+            transport,
+            queue,
+            slots,
+            capacity,
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+            flush_supported: features & VIRTIO_BLK_F_FLUSH != 0,
+            interrupt_driven,
+            slot_wakers,
+            free_slot_waiters: Vec::new(),
+            this: Weak::new(),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn get_pci_address(&self) -> pci::PciAddress {
+        self.transport.get_pci_device().address
+    }
+}
+
+impl VirtioBlock {
+    pub fn read(device: &Arc<Mutex<VirtioBlock>>, lba: u64, sectors_count: u64) -> VirtioBlockRequest {
+        VirtioBlockRequest::new(device.clone(), RequestKind::Read, lba, sectors_count, None)
+    }
+
+    pub fn write(device: &Arc<Mutex<VirtioBlock>>, lba: u64, data: Vec<u8>) -> VirtioBlockRequest {
+        let sectors_count = (data.len() / SECTOR_SIZE) as u64;
+        VirtioBlockRequest::new(device.clone(), RequestKind::Write, lba, sectors_count, Some(data))
+    }
+
+    pub fn flush_async(device: &Arc<Mutex<VirtioBlock>>) -> VirtioBlockRequest {
+        VirtioBlockRequest::new(device.clone(), RequestKind::Flush, 0, 0, None)
+    }
+}
+
+impl VirtioBlock {
+    fn allocate_slot(&mut self) -> Option<usize> {
+        let slot = self.slots.iter().position(|slot| slot.state == SlotState::Free)?;
+        self.slots[slot].state = SlotState::Reserved;
+        Some(slot)
+    }
+
+    fn release_slot(&mut self, slot: usize) {
+        self.slots[slot].state = SlotState::Free;
+        for waker in self.free_slot_waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn submit(&mut self, slot: usize, kind: RequestKind, lba: u64, sectors_count: u64) -> Result<(), VirtioBlockError> {
+        let request_type = match kind {
+            RequestKind::Read => VIRTIO_BLK_T_IN,
+            RequestKind::Write => VIRTIO_BLK_T_OUT,
+            RequestKind::Flush => VIRTIO_BLK_T_FLUSH,
+        };
+
+        let request_slot = &mut self.slots[slot];
+        let header = request_slot.memory.as_mut_slice();
+        header[0..4].copy_from_slice(&request_type.to_le_bytes());
+        header[4..8].fill(0);
+        header[8..16].copy_from_slice(&lba.to_le_bytes());
+        header[STATUS_OFFSET] = 0xFF;
+
+        let address = request_slot.memory.physical_address;
+        let header_buffer = VirtqueueBuffer { address, length: HEADER_SIZE, device_writable: false };
+        let status_buffer = VirtqueueBuffer { address: address + STATUS_OFFSET, length: 1, device_writable: true };
+        let data_buffer = VirtqueueBuffer {
+            address: address + SLOT_HEADER_SIZE,
+            length: (sectors_count as usize * SECTOR_SIZE) as u32,
+            device_writable: kind == RequestKind::Read,
+        };
+
+        let head = if kind == RequestKind::Flush {
+            self.queue.push(&[header_buffer, status_buffer])
+        } else {
+            self.queue.push(&[header_buffer, data_buffer, status_buffer])
+        }.ok_or(VirtioBlockError::QueueFull)?;
+
+        self.slots[slot].state = SlotState::Submitted { head };
+        self.queue.notify();
+        Ok(())
+    }
+
+    fn process_completions(&mut self) {
+        while let Some((head, _)) = self.queue.pop_used() {
+            let completed = self.slots.iter()
+                .position(|slot| match slot.state {
+                    SlotState::Submitted { head: slot_head } | SlotState::Abandoned { head: slot_head } => slot_head == head,
+                    _ => false,
+                });
+            if let Some(index) = completed {
+                let slot = &mut self.slots[index];
+                if let SlotState::Abandoned { .. } = slot.state {
+                    self.release_slot(index);
+                } else {
+                    let status = slot.memory.as_slice()[STATUS_OFFSET];
+                    slot.state = SlotState::Completed { status };
+                }
+            }
+        }
+    }
+
+    fn take_result(&mut self, slot: usize) -> Option<Result<(), VirtioBlockError>> {
+        match self.slots[slot].state {
+            SlotState::Completed { status: VIRTIO_BLK_S_OK } => Some(Ok(())),
+            SlotState::Completed { status: VIRTIO_BLK_S_UNSUPP } => Some(Err(VirtioBlockError::Unsupported)),
+            SlotState::Completed { .. } => Some(Err(VirtioBlockError::IoError)),
+            _ => None,
+        }
+    }
+
+    /// Performs the request synchronously, waiting for a free slot and for the completion
+    fn perform(&mut self, kind: RequestKind, lba: u64, input: Option<&[u8]>, output: Option<&mut [u8]>) -> Result<(), VirtioBlockError> {
+        let slot = loop {
+            if let Some(slot) = self.allocate_slot() {
+                break slot;
+            }
+            self.process_completions();
+            Self::wait();
+        };
+
+        let length = input.map(|data| data.len())
+            .or(output.as_ref().map(|data| data.len()))
+            .unwrap_or(0);
+        if let Some(input) = input {
+            self.slots[slot].data_mut(length).copy_from_slice(input);
+        }
+        if let Err(error) = self.submit(slot, kind, lba, (length / SECTOR_SIZE) as u64) {
+            self.release_slot(slot);
+            return Err(error);
+        }
+
+        let result = loop {
+            self.process_completions();
+            if let Some(result) = self.take_result(slot) {
+                break result;
+            }
+            Self::wait();
+        };
+        if let (Ok(()), Some(output)) = (&result, output) {
+            output.copy_from_slice(self.slots[slot].data(length));
+        }
+        self.release_slot(slot);
+        result
+    }
+
+    fn wait() {
+        if instructions::interrupts::are_enabled() {
+            instructions::hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlock {
+    fn get_sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn get_sectors_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_sectors(&mut self, lba: u64, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+
+        let mut lba = lba;
+        for chunk in buffer.chunks_mut(SLOT_DATA_SIZE) {
+            self.perform(RequestKind::Read, lba, None, Some(chunk))?;
+            lba += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.validate_request(lba, buffer.len())?;
+        if self.read_only {
+            return Err(BlockDeviceError::ReadOnly.into());
+        }
+
+        let mut lba = lba;
+        for chunk in buffer.chunks(SLOT_DATA_SIZE) {
+            self.perform(RequestKind::Write, lba, Some(chunk), None)?;
+            lba += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.flush_supported {
+            self.perform(RequestKind::Flush, 0, None, None)?;
+        }
+        Ok(())
+    }
+
+    fn read_async(&mut self, lba: u64, sectors_count: u64) -> BlockIoFuture {
+        let device = self.this.upgrade().expect("virtio-blk device is not shared");
+        Box::pin(async move {
+            let mut data = Vec::with_capacity(sectors_count as usize * SECTOR_SIZE);
+            let mut offset = 0;
+            while offset < sectors_count {
+                let count = (sectors_count - offset).min(MAX_REQUEST_SECTORS);
+                data.extend(VirtioBlock::read(&device, lba + offset, count).await?);
+                offset += count;
+            }
+            Ok(data)
+        })
+    }
+
+    fn write_async(&mut self, lba: u64, data: Vec<u8>) -> BlockIoFuture {
+        let device = self.this.upgrade().expect("virtio-blk device is not shared");
+        Box::pin(async move {
+            let mut lba = lba;
+            for chunk in data.chunks(SLOT_DATA_SIZE) {
+                VirtioBlock::write(&device, lba, chunk.to_vec()).await?;
+                lba += (chunk.len() / SECTOR_SIZE) as u64;
+            }
+            Ok(data)
+        })
+    }
+
+    fn flush_async(&mut self) -> BlockIoFuture {
+        let device = self.this.upgrade().expect("virtio-blk device is not shared");
+        Box::pin(async move {
+            Ok(VirtioBlock::flush_async(&device).await?)
+        })
+    }
+
+    fn get_description(&self) -> &str {
+        "VirtIO block device"
+    }
+}
+
+/// Future of an asynchronous request, completed by the device interrupt
+pub struct VirtioBlockRequest {
+    device: Arc<Mutex<VirtioBlock>>,
+    kind: RequestKind,
+    lba: u64,
+    sectors_count: u64,
+    data: Option<Vec<u8>>,
+    slot: Option<usize>,
+}
+
+impl VirtioBlockRequest {
+    fn new(device: Arc<Mutex<VirtioBlock>>, kind: RequestKind, lba: u64, sectors_count: u64, data: Option<Vec<u8>>) -> Self {
+        Self { device, kind, lba, sectors_count, data, slot: None }
+    }
+
+    fn validate(&self, device: &VirtioBlock) -> Result<(), VirtioBlockError> {
+        if self.sectors_count > MAX_REQUEST_SECTORS {
+            return Err(VirtioBlockError::TooLarge(self.sectors_count));
+        }
+        match self.kind {
+            RequestKind::Read => {
+                device.validate_request(self.lba, self.sectors_count as usize * SECTOR_SIZE)?;
+            }
+            RequestKind::Write => {
+                let length = self.data.as_ref().map(|data| data.len()).unwrap_or(0);
+                device.validate_request(self.lba, length)?;
+                if device.read_only {
+                    return Err(BlockDeviceError::ReadOnly.into());
+                }
+            }
+            RequestKind::Flush => {}
+        }
+        Ok(())
+    }
+}
+
+impl Future for VirtioBlockRequest {
+    /// Read data, or the written buffer given back to the caller
+    type Output = Result<Vec<u8>, VirtioBlockError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let request = self.get_mut();
+        let device = request.device.clone();
+        let mut device = device.lock();
+
+        let slot = match request.slot {
+            Some(slot) => slot,
+            None => {
+                if let Err(error) = request.validate(&device) {
+                    return Poll::Ready(Err(error));
+                }
+                if request.kind == RequestKind::Flush && !device.flush_supported {
+                    return Poll::Ready(Ok(Vec::new()));
+                }
+
+                let slot = match device.allocate_slot() {
+                    Some(slot) => slot,
+                    None => {
+                        device.free_slot_waiters.push(context.waker().clone());
+                        return Poll::Pending;
+                    }
+                };
+                if let Some(data) = &request.data {
+                    device.slots[slot].data_mut(data.len()).copy_from_slice(data);
+                }
+                if let Err(error) = device.submit(slot, request.kind, request.lba, request.sectors_count) {
+                    device.release_slot(slot);
+                    return Poll::Ready(Err(error));
+                }
+                request.slot = Some(slot);
+                slot
+            }
+        };
+
+        device.slot_wakers[slot].register(context.waker());
+        device.process_completions();
+        let result = match device.take_result(slot) {
+            Some(result) => result,
+            None => {
+                if !device.interrupt_driven {
+                    context.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+        };
+
+        let output = match (request.kind, result) {
+            (_, Err(error)) => Err(error),
+            (RequestKind::Read, Ok(())) => {
+                let length = request.sectors_count as usize * SECTOR_SIZE;
+                let mut data = vec![0; length];
+                data.copy_from_slice(device.slots[slot].data(length));
+                Ok(data)
+            }
+            (_, Ok(())) => Ok(request.data.take().unwrap_or_default()),
+        };
+        device.release_slot(slot);
+        request.slot = None;
+        Poll::Ready(output)
+    }
+}
+
+impl Drop for VirtioBlockRequest {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            let mut device = self.device.lock();
+            match device.slots[slot].state {
+                SlotState::Submitted { head } => device.slots[slot].state = SlotState::Abandoned { head },
+                _ => device.release_slot(slot),
+            }
+        }
+    }
+}
+
+pub fn detect_devices() -> Vec<Arc<Mutex<VirtioBlock>>> {
+    let mut devices = Vec::new();
+    for pci_device in pci::find_devices_by_id(VIRTIO_VENDOR_ID, &DEVICE_IDS) {
+        match VirtioBlock::new(pci_device) {
+            Ok(device) => {
+                let device = Arc::new(Mutex::new(device));
+                device.lock().this = Arc::downgrade(&device);
+                devices.push(device);
+            }
+            Err(error) => log_warning!("virtio-blk {}: {}", pci_device.address, error),
+        }
+    }
+    devices
+}
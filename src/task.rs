@@ -3,6 +3,20 @@ use core::{future::Future, pin::Pin, sync::atomic::{AtomicUsize, Ordering}, task
 
 pub mod executor;
 pub mod keyboard;
+pub mod serial_shell;
+pub mod timer;
+
+const NO_TASK: usize = usize::MAX;
+
+static CURRENT_TASK_ID: AtomicUsize = AtomicUsize::new(NO_TASK);
+
+/// Returns the ID of the task being polled by the executor, `None` outside of the tasks
+pub fn get_current_task_id() -> Option<usize> {
+    match CURRENT_TASK_ID.load(Ordering::Relaxed) {
+        NO_TASK => None,
+        task_id => Some(task_id),
+    }
+}
 
 pub struct Task {
     id: TaskId,
@@ -20,7 +34,10 @@ impl Task {
 
 impl Task {
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        CURRENT_TASK_ID.store(self.id.0, Ordering::Relaxed);
+        let poll = self.future.as_mut().poll(context);
+        CURRENT_TASK_ID.store(NO_TASK, Ordering::Relaxed);
+        poll
     }
 }
 
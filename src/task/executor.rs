@@ -1,17 +1,83 @@
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake};
+use core::fmt::{Display, Formatter};
+use core::pin::Pin;
 use core::task::{
     Context,
-    Poll::{Pending, Ready},
+    Poll::{self, Pending, Ready},
     Waker,
 };
 
 use crossbeam_queue::ArrayQueue;
 use futures_util::Future;
+use lazy_static::lazy_static;
+
+use crate::error::Error;
+use crate::io::CapturedOutput;
 
 use super::{Task, TaskId};
 
 const TASK_QUEUE_SIZE: usize = 255;
 
+type SpawnedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+lazy_static! {
+    static ref SPAWN_QUEUE: ArrayQueue<SpawnedFuture> = ArrayQueue::new(TASK_QUEUE_SIZE);
+}
+
+#[derive(Debug)]
+pub struct SpawnQueueFull;
+
+impl Display for SpawnQueueFull {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "too many tasks waiting to be spawned")
+    }
+}
+
+impl Error for SpawnQueueFull {}
+
+/// Spawns the task from anywhere, e.g. a command, the executor picks it up in its next iteration.
+/// The task prints to the standard output of the command spawning it.
+pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Result<(), SpawnQueueFull> {
+    let future = WithOutput {
+        future: Box::pin(future),
+        output: CapturedOutput::capture(),
+    };
+    SPAWN_QUEUE.push(Box::pin(future)).map_err(|_| SpawnQueueFull)
+}
+
+/// Runs the future to completion outside of the executor, e.g. to complete queued device I/O synchronously.
+/// The future must be resumed by an interrupt or complete on its own, as its waker is ignored.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    use x86_64::instructions::{self, interrupts};
+
+    let mut future = core::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        if interrupts::are_enabled() {
+            instructions::hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+struct WithOutput {
+    future: SpawnedFuture,
+    output: CapturedOutput,
+}
+
+impl Future for WithOutput {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let Self { future, output } = &mut *self;
+        output.with(|| future.as_mut().poll(context))
+    }
+}
+
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
     task_queue: Arc<ArrayQueue<TaskId>>,
@@ -42,16 +108,23 @@ impl Executor {
 impl Executor {
     pub fn run(&mut self) -> ! {
         loop {
+            self.spawn_queued_tasks();
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
+    fn spawn_queued_tasks(&mut self) {
+        while let Some(future) = SPAWN_QUEUE.pop() {
+            self.spawn(future);
+        }
+    }
+
     fn sleep_if_idle(&self) {
         use x86_64::instructions::interrupts;
 
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if self.task_queue.is_empty() && SPAWN_QUEUE.is_empty() {
             interrupts::enable_and_hlt();
         } else {
             interrupts::enable();
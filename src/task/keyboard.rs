@@ -7,7 +7,7 @@ use core::{
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
 use futures_util::{Stream, StreamExt, task::AtomicWaker};
-use pc_keyboard::{DecodedKey, HandleControl, Keyboard, layouts, ScancodeSet1};
+use pc_keyboard::{DecodedKey, HandleControl, Keyboard, KeyCode, KeyEvent, KeyState, layouts, ScancodeSet1};
 
 use crate::{log_warning};
 
@@ -61,14 +61,36 @@ impl Stream for ScanCodeStream {
     }
 }
 
-pub async fn keyboard_decoding_task(mut handler: Box<dyn FnMut(DecodedKey)>) {
+/// Modifier keys held while the key was pressed
+#[derive(Clone, Copy, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    fn update(&mut self, key_event: &KeyEvent) {
+        let is_down = key_event.state == KeyState::Down;
+        match key_event.code {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => self.shift = is_down,
+            KeyCode::ControlLeft | KeyCode::ControlRight => self.control = is_down,
+            KeyCode::AltLeft | KeyCode::AltRight => self.alt = is_down,
+            _ => {}
+        }
+    }
+}
+
+pub async fn keyboard_decoding_task(mut handler: Box<dyn FnMut(DecodedKey, Modifiers)>) {
     let mut scancodes = ScanCodeStream::new();
-    let mut keyboard = Keyboard::<layouts::Us104Key, ScancodeSet1>::new(HandleControl::Ignore);
+    let mut keyboard = Keyboard::<layouts::Us104Key, ScancodeSet1>::new(HandleControl::MapLettersToUnicode);
+    let mut modifiers = Modifiers::default();
 
     while let Some(scan_code) = scancodes.next().await {
         if let Ok(Some(key_event)) = keyboard.add_byte(scan_code) {
+            modifiers.update(&key_event);
             if let Some(key) = keyboard.process_keyevent(key_event) {
-                handler(key)
+                handler(key, modifiers)
             }
         }
     }
@@ -0,0 +1,120 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::{Stream, StreamExt, task::AtomicWaker};
+
+use crate::command::command::Command;
+use crate::io;
+use crate::serial::SerialWriter;
+
+static INPUT_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static INPUT_QUEUE_SIZE: usize = 255;
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+const ESCAPE: u8 = 0x1B;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+/// Input is dropped while there is no serial shell
+pub(crate) fn add_byte(byte: u8) {
+    if let Ok(queue) = INPUT_QUEUE.try_get() {
+        if queue.push(byte).is_ok() {
+            WAKER.wake();
+        }
+    }
+}
+
+pub struct SerialInputStream {}
+
+impl SerialInputStream {
+    pub fn new() -> Self {
+        INPUT_QUEUE
+            .try_init_once(|| ArrayQueue::new(INPUT_QUEUE_SIZE))
+            .expect("SerialInputStream::new should only be called once");
+        SerialInputStream {}
+    }
+}
+
+impl Stream for SerialInputStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let queue = INPUT_QUEUE
+            .try_get()
+            .expect("serial input queue uninitialized");
+
+        if let Some(byte) = queue.pop() {
+            return Poll::Ready(Some(byte));
+        }
+
+        WAKER.register(context.waker());
+        match queue.pop() {
+            Some(byte) => {
+                WAKER.take();
+                Poll::Ready(Some(byte))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputState {
+    Text,
+    /// After CR, a following LF ends the same line
+    CarriageReturn,
+    Escape,
+    ControlSequence,
+}
+
+/// Shell session on the port bound to the shell channel, runs the commands with their output written to the serial port
+pub async fn serial_shell_task(prompt: String, command_handler: Box<dyn Fn(Command)>) {
+    let mut input = SerialInputStream::new();
+    let mut writer = SerialWriter;
+    let output: Rc<RefCell<dyn Write>> = Rc::new(RefCell::new(SerialWriter));
+    let mut line = String::new();
+    let mut state = InputState::Text;
+
+    let _ = write!(writer, "{}", prompt);
+    while let Some(byte) = input.next().await {
+        let previous_state = state;
+        state = InputState::Text;
+        match (previous_state, byte) {
+            (InputState::CarriageReturn, b'\n') => {}
+            (InputState::Escape, b'[') => state = InputState::ControlSequence,
+            (InputState::Escape, _) => {}
+            (InputState::ControlSequence, 0x40..=0x7E) => {}
+            (InputState::ControlSequence, _) => state = InputState::ControlSequence,
+            (_, ESCAPE) => state = InputState::Escape,
+            (_, b'\r') | (_, b'\n') => {
+                if byte == b'\r' {
+                    state = InputState::CarriageReturn;
+                }
+                let _ = writer.write_str("\n");
+                if let Some(command) = Command::parse(core::mem::take(&mut line)) {
+                    io::with_standard_output_writer(output.clone(), || command_handler(command));
+                }
+                let _ = write!(writer, "{}", prompt);
+            }
+            (_, BACKSPACE) | (_, DELETE) => {
+                if line.pop().is_some() {
+                    let _ = writer.write_str("\x08 \x08");
+                }
+            }
+            (_, 0x20..=0x7E) => {
+                line.push(byte as char);
+                let _ = writer.write_char(byte as char);
+            }
+            _ => {}
+        }
+    }
+}
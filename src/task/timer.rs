@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use futures_util::future::{Either, select};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+pub const TICKS_PER_SECOND: u64 = 100;
+
+const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_CHANNEL_0_PORT: u16 = 0x40;
+const PIT_COMMAND_PORT: u16 = 0x43;
+/// Channel 0, low and high byte access, square wave generator
+const PIT_COMMAND_CHANNEL_0_SQUARE_WAVE: u8 = 0x36;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref SLEEPERS: Mutex<Vec<(u64, Waker)>> = Mutex::new(Vec::new());
+}
+
+pub fn init() {
+    let divisor = (PIT_FREQUENCY / TICKS_PER_SECOND) as u16;
+    unsafe {
+        Port::<u8>::new(PIT_COMMAND_PORT).write(PIT_COMMAND_CHANNEL_0_SQUARE_WAVE);
+        let mut channel = Port::<u8>::new(PIT_CHANNEL_0_PORT);
+        channel.write(divisor as u8);
+        channel.write((divisor >> 8) as u8);
+    }
+}
+
+pub(crate) fn tick() {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    // Sleepers registered while the lock is held are woken on one of the next ticks
+    if let Some(mut sleepers) = SLEEPERS.try_lock() {
+        sleepers.retain(|(deadline, waker)| {
+            if *deadline <= ticks {
+                waker.wake_by_ref();
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+pub fn get_ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Cycle counter of the processor, unrelated to the ticks, e.g. to seed numbers that should differ between boots
+pub fn get_timestamp() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+pub fn get_uptime_ms() -> u64 {
+    get_ticks() * 1000 / TICKS_PER_SECOND
+}
+
+pub fn ms_to_ticks(milliseconds: u64) -> u64 {
+    (milliseconds * TICKS_PER_SECOND).div_ceil(1000)
+}
+
+pub fn sleep(milliseconds: u64) -> Sleep {
+    Sleep {
+        deadline: get_ticks() + ms_to_ticks(milliseconds).max(1),
+        waker: None,
+    }
+}
+
+/// Runs the future until it completes or the time runs out, returns `None` in the latter case
+pub async fn with_timeout<F: Future>(milliseconds: u64, future: F) -> Option<F::Output> {
+    futures_util::pin_mut!(future);
+    match select(future, sleep(milliseconds)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+pub struct Sleep {
+    deadline: u64,
+    /// Waker registered in the sleepers, polling again registers only a different one
+    waker: Option<Waker>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if get_ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+        let sleep = self.get_mut();
+        if sleep.waker.as_ref().is_some_and(|waker| waker.will_wake(context.waker())) {
+            return Poll::Pending;
+        }
+        let waker = context.waker().clone();
+        without_interrupts(|| {
+            SLEEPERS.lock().push((sleep.deadline, waker.clone()));
+        });
+        sleep.waker = Some(waker);
+        Poll::Pending
+    }
+}
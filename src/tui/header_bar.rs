@@ -0,0 +1,70 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use spin::Mutex;
+
+use crate::geometry::position::Point;
+use crate::geometry::rect::Rect;
+use crate::geometry::size::Size;
+use crate::rtc::RTC;
+use crate::vga_video::CharacterColor;
+use crate::vga_video::frame_buffer::FrameBuffer;
+use crate::vga_video::screen_fragment_writer::ScreenFragmentWriter;
+
+pub struct Header {
+    name: String,
+    version: String,
+    /// Name of the virtual console, displayed on the left
+    console_name: String,
+}
+
+impl Header {
+    pub fn new(name: String, version: String, console_name: String) -> Self {
+        Header { name, version, console_name }
+    }
+}
+
+/// The top line of the screen with the system name, the console and the current time
+pub struct HeaderBar<'a> {
+    writer: RefCell<ScreenFragmentWriter<'a>>,
+    header: Header,
+    rtc: Rc<Mutex<RTC>>,
+}
+
+impl<'a> HeaderBar<'a> {
+    pub fn new(screen_buffer: &'a RefCell<dyn FrameBuffer>, header: Header, rtc: Rc<Mutex<RTC>>) -> Self {
+        let screen_size = screen_buffer.borrow().get_size();
+        let writer = ScreenFragmentWriter::new(
+            Rect::new(Point::new(0, 0), Size::new(screen_size.width, 1)),
+            CharacterColor::default(),
+            screen_buffer,
+        );
+        Self { writer: RefCell::new(writer), header, rtc }
+    }
+}
+
+impl HeaderBar<'_> {
+    pub fn refresh(&self) {
+        let now = self.rtc.lock().read_datetime();
+        self.display(
+            &self.header.console_name,
+            &format!("{} (ver. {})", self.header.name, self.header.version),
+            &now.to_string(),
+        )
+    }
+
+    fn display(&self, left: &str, center: &str, right: &str) {
+        let mut writer = self.writer.borrow_mut();
+
+        let total_width = writer.get_size().width;
+        let center_width = total_width - left.len() - right.len();
+        let header = format!("{:<}{:^width$}{:>}",
+                             left, center, right, width = center_width);
+
+        writer.reset_position();
+        writer.write_str(&header).unwrap();
+    }
+}
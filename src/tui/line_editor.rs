@@ -0,0 +1,232 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const HISTORY_SIZE: usize = 64;
+/// Longer lines wouldn't fit in the terminal body together with the prompt
+const MAX_LINE_LENGTH: usize = 255;
+
+/// Edited line of the terminal with the history of the entered lines, similar to readline
+pub struct LineEditor {
+    buffer: Vec<char>,
+    cursor: usize,
+    insert_mode: bool,
+    /// The oldest lines are at the front
+    history: VecDeque<String>,
+    /// Index of the history entry being browsed, `None` while editing a new line
+    history_index: Option<usize>,
+    /// The new line, kept while browsing the history
+    unsaved_line: Vec<char>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::with_capacity(MAX_LINE_LENGTH),
+            cursor: 0,
+            insert_mode: true,
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            history_index: None,
+            unsaved_line: Vec::new(),
+        }
+    }
+}
+
+impl LineEditor {
+    pub fn get_buffer(&self) -> &[char] {
+        &self.buffer
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_insert_mode(&self) -> bool {
+        self.insert_mode
+    }
+
+    pub fn toggle_insert_mode(&mut self) {
+        self.insert_mode = !self.insert_mode;
+    }
+
+    /// Inserts the character at the cursor, or replaces the one under it in the overwrite mode
+    pub fn put_char(&mut self, character: char) {
+        if !self.insert_mode && self.cursor < self.buffer.len() {
+            self.buffer[self.cursor] = character;
+        } else if self.buffer.len() < MAX_LINE_LENGTH {
+            self.buffer.insert(self.cursor, character);
+        } else {
+            return;
+        }
+        self.cursor += 1;
+    }
+
+    /// Removes the character before the cursor, returns `false` if there is none
+    pub fn backspace(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.cursor -= 1;
+        self.buffer.remove(self.cursor);
+        true
+    }
+
+    /// Removes the character under the cursor, returns `false` if there is none
+    pub fn delete(&mut self) -> bool {
+        if self.cursor == self.buffer.len() {
+            return false;
+        }
+        self.buffer.remove(self.cursor);
+        true
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.buffer.len());
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.buffer.len();
+    }
+
+    /// Replaces the text from the index to the cursor, e.g. a completed word, as much of it as fits in the line
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let start = start.min(self.cursor);
+        let available = MAX_LINE_LENGTH - (self.buffer.len() - (self.cursor - start));
+        let text: Vec<char> = text.chars().take(available).collect();
+        let length = text.len();
+        self.buffer.splice(start..self.cursor, text);
+        self.cursor = start + length;
+    }
+
+    /// Removes everything before the cursor (Ctrl-U)
+    pub fn delete_to_start(&mut self) {
+        self.buffer.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Removes the word before the cursor with the whitespaces after it (Ctrl-W)
+    pub fn delete_word(&mut self) {
+        let mut start = self.cursor;
+        while start > 0 && self.buffer[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.buffer[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        self.buffer.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Replaces the line with the previous entry of the history
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.unsaved_line = self.buffer.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.set_buffer(self.history[index].chars().collect());
+    }
+
+    /// Replaces the line with the next entry of the history, or the new line after the last one
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            None => {}
+            Some(index) if index + 1 < self.history.len() => {
+                self.history_index = Some(index + 1);
+                self.set_buffer(self.history[index + 1].chars().collect());
+            }
+            Some(_) => {
+                self.history_index = None;
+                let unsaved_line = core::mem::take(&mut self.unsaved_line);
+                self.set_buffer(unsaved_line);
+            }
+        }
+    }
+
+    /// Returns the entered line and starts a new one, the line is added to the history
+    pub fn take_line(&mut self) -> String {
+        let line: String = self.buffer.drain(..).collect();
+        self.cursor = 0;
+        self.history_index = None;
+        self.unsaved_line.clear();
+        if !line.trim().is_empty() && self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        line
+    }
+
+    fn set_buffer(&mut self, buffer: Vec<char>) {
+        self.buffer = buffer;
+        self.cursor = self.buffer.len();
+    }
+}
+
+#[test_case]
+fn test_line_editor_editing() {
+    let mut editor = LineEditor::new();
+    "ls /bin".chars().for_each(|character| editor.put_char(character));
+    editor.move_to_start();
+    editor.delete();
+    editor.put_char('L');
+    editor.move_to_end();
+    editor.move_left();
+    editor.toggle_insert_mode();
+    editor.put_char('N');
+    assert_eq!(editor.get_buffer().iter().collect::<String>(), "Ls /biN");
+
+    editor.delete_word();
+    assert_eq!(editor.get_buffer().iter().collect::<String>(), "Ls ");
+    editor.delete_to_start();
+    assert!(editor.get_buffer().is_empty());
+    assert!(!editor.backspace());
+}
+
+#[test_case]
+fn test_line_editor_history() {
+    let mut editor = LineEditor::new();
+    for line in ["first", "second", "second"] {
+        line.chars().for_each(|character| editor.put_char(character));
+        editor.take_line();
+    }
+    editor.put_char('x');
+
+    editor.history_previous();
+    assert_eq!(editor.get_buffer().iter().collect::<String>(), "second");
+    editor.history_previous();
+    editor.history_previous();
+    assert_eq!(editor.get_buffer().iter().collect::<String>(), "first");
+    editor.history_next();
+    editor.history_next();
+    assert_eq!(editor.get_buffer().iter().collect::<String>(), "x");
+    assert_eq!(editor.get_cursor(), 1);
+}
+
+#[test_case]
+fn test_line_editor_limits_line_length() {
+    let mut editor = LineEditor::new();
+    for _ in 0..MAX_LINE_LENGTH + 10 {
+        editor.put_char('a');
+    }
+    assert_eq!(editor.get_buffer().len(), MAX_LINE_LENGTH);
+
+    editor.move_to_start();
+    editor.replace_before_cursor(0, "cat ");
+    assert_eq!(editor.get_buffer().len(), MAX_LINE_LENGTH);
+    assert_eq!(editor.get_cursor(), 0);
+}
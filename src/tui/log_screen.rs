@@ -0,0 +1,113 @@
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+use core::fmt::Write;
+
+use pc_keyboard::{DecodedKey, KeyCode};
+use spin::Mutex;
+
+use crate::geometry::position::Point;
+use crate::geometry::rect::Rect;
+use crate::geometry::size::Size;
+use crate::log::{Level, LOG_BUFFER};
+use crate::rtc::RTC;
+use crate::task::keyboard::Modifiers;
+use crate::task::timer;
+use crate::tui::header_bar::{Header, HeaderBar};
+use crate::vga_video::CharacterColor;
+use crate::vga_video::cursor::Cursor;
+use crate::vga_video::frame_buffer::FrameBuffer;
+use crate::vga_video::screen_fragment_writer::ScreenFragmentWriter;
+
+const REFRESH_INTERVAL_MS: u64 = 100;
+
+/// Screen showing the kernel log as it's written, without a shell
+pub struct LogScreen<'a> {
+    header_bar: HeaderBar<'a>,
+    body_writer: RefCell<ScreenFragmentWriter<'a>>,
+    cursor: Rc<Mutex<dyn Cursor>>,
+    /// Index of the next log to display
+    next_index: Cell<u64>,
+}
+
+impl<'a> LogScreen<'a> {
+    pub fn new(
+        screen_buffer: &'a RefCell<dyn FrameBuffer>,
+        header: Header,
+        rtc: Rc<Mutex<RTC>>,
+        cursor: Rc<Mutex<dyn Cursor>>,
+    ) -> Self {
+        let screen_size = screen_buffer.borrow().get_size();
+        let body_writer = ScreenFragmentWriter::new(
+            Rect::new(Point::new(0, 1), Size::new(screen_size.width, screen_size.height - 1)),
+            CharacterColor::default(),
+            screen_buffer,
+        );
+
+        Self {
+            header_bar: HeaderBar::new(screen_buffer, header, rtc),
+            body_writer: RefCell::new(body_writer),
+            cursor,
+            next_index: Cell::new(0),
+        }
+    }
+
+    /// Keeps the lines scrolled out of the body, so they can be viewed with Shift+Page Up/Down
+    pub fn enable_scrollback(&self, capacity: usize) {
+        self.body_writer.borrow_mut().enable_scrollback(capacity);
+    }
+
+    pub fn begin(&self) {
+        self.refresh_header();
+
+        self.body_writer.borrow_mut().clear();
+        self.cursor.lock().disable();
+        self.display_new_logs();
+    }
+}
+
+impl LogScreen<'_> {
+    pub fn refresh_header(&self) {
+        self.header_bar.refresh();
+    }
+
+    /// Scrolls the log with Shift+Page Up/Down, other keys return to the newest logs
+    pub fn handle_keypress(&self, key: DecodedKey, modifiers: Modifiers) {
+        let mut body_writer = self.body_writer.borrow_mut();
+        let page_height = body_writer.get_size().height as isize - 1;
+        match key {
+            DecodedKey::RawKey(KeyCode::PageUp) if modifiers.shift => body_writer.scroll_view(page_height),
+            DecodedKey::RawKey(KeyCode::PageDown) if modifiers.shift => body_writer.scroll_view(-page_height),
+            _ => body_writer.reset_view(),
+        }
+    }
+
+    /// Displays the new logs until the end of the kernel, never returns
+    pub async fn follow(self: Rc<Self>) {
+        loop {
+            self.display_new_logs();
+            timer::sleep(REFRESH_INTERVAL_MS).await;
+        }
+    }
+
+    /// The logs are colored by the level. Nothing is displayed while viewing the scrollback,
+    /// the logs wait in the log buffer.
+    fn display_new_logs(&self) {
+        let mut body_writer = self.body_writer.borrow_mut();
+        if body_writer.is_viewing_scrollback() {
+            return;
+        }
+        let next_index = LOG_BUFFER.get_next_index();
+        for index in self.next_index.get().max(LOG_BUFFER.get_first_index())..next_index {
+            if let Some(record) = LOG_BUFFER.get_record(index) {
+                let color = match record.get_level() {
+                    Level::DEBUG => "\x1b[90m",
+                    Level::INFO => "",
+                    Level::WARNING => "\x1b[93m",
+                    Level::ERROR => "\x1b[91m",
+                };
+                writeln!(body_writer, "{}{}\x1b[0m", color, record).unwrap();
+            }
+        }
+        self.next_index.set(next_index);
+    }
+}
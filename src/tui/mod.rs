@@ -1,2 +1,6 @@
 pub mod panic_screen;
+pub mod line_editor;
 pub mod terminal_screen;
+pub mod header_bar;
+pub mod log_screen;
+pub mod virtual_consoles;
@@ -2,33 +2,202 @@ use core::cell::RefCell;
 use core::fmt::Write;
 use core::panic::PanicInfo;
 
+use x86_64::instructions::port::Port;
+
+use crate::backtrace::Backtrace;
 use crate::{geometry::position::Point, vga_video::{CharacterColor, Color}};
 use crate::geometry::rect::Rect;
+use crate::interrupts::exception_context::Registers;
+use crate::log::LOG_BUFFER;
 use crate::vga_video::frame_buffer::FrameBuffer;
 use crate::vga_video::screen_fragment_writer::ScreenFragmentWriter;
 
+const BAR_COLOR: CharacterColor = CharacterColor::new(Color::Blue, Color::Gray);
+const TEXT_COLOR: CharacterColor = CharacterColor::new(Color::White, Color::Blue);
+const HEADER_COLOR: CharacterColor = CharacterColor::new(Color::Yellow, Color::Blue);
+const RECENT_LOGS_COUNT: u64 = 64;
+
+/// Full screen report of the panic. Everything is formatted again on each scroll,
+/// so nothing is allocated, the heap may be the cause of the panic.
 pub struct PanicScreen<'a> {
     frame_buffer: &'a RefCell<dyn FrameBuffer>,
+    info: &'a PanicInfo<'a>,
+    backtrace: Backtrace,
+    registers: Registers,
+    first_line: usize,
+    lines_count: usize,
 }
 
 impl<'a> PanicScreen<'a> {
-    pub fn new(frame_buffer: &'a RefCell<dyn FrameBuffer>) -> Self {
-        PanicScreen { frame_buffer }
+    pub fn new(
+        frame_buffer: &'a RefCell<dyn FrameBuffer>,
+        info: &'a PanicInfo<'a>,
+        backtrace: Backtrace,
+        registers: Registers,
+    ) -> Self {
+        PanicScreen { frame_buffer, info, backtrace, registers, first_line: 0, lines_count: 0 }
     }
 }
 
 impl PanicScreen<'_> {
-    pub fn display(&mut self, info: &PanicInfo) {
-        self.frame_buffer.borrow_mut()
-            .clear().unwrap();
-
-        let mut writer = ScreenFragmentWriter::new(
-            Rect::new(Point::default(), self.frame_buffer.borrow().get_size()),
-            CharacterColor::new(Color::Red, Color::Black),
-            self.frame_buffer,
+    pub fn display(&mut self) {
+        let size = self.frame_buffer.borrow().get_size();
+        let body_rect = Rect::from(0, 1, size.width, size.height - 2);
+
+        let mut title_writer = ScreenFragmentWriter::new(Rect::from(0, 0, size.width, 1), BAR_COLOR, self.frame_buffer);
+        title_writer.clear();
+        // Writing into the last column would wrap the title out of the bar
+        let _ = write!(title_writer, "{:^width$}", "KERNEL PANIC", width = size.width - 1);
+
+        let mut body_writer = ScrolledWriter::new(body_rect.clone(), self.first_line, self.frame_buffer);
+        body_writer.clear();
+        self.write_details(&mut body_writer);
+        self.lines_count = body_writer.get_lines_count();
+
+        let last_line = self.lines_count.min(self.first_line + body_rect.size.height);
+        let mut status_writer = ScreenFragmentWriter::new(
+            Rect::from(0, size.height - 1, size.width, 1), BAR_COLOR, self.frame_buffer,
         );
+        status_writer.clear();
+        let _ = write!(
+            status_writer, " Lines {}-{} of {}, scroll with Up/Down, PgUp/PgDn, Home/End",
+            self.first_line + 1, last_line, self.lines_count,
+        );
+    }
+
+    /// Scrolls the details with the arrows, Page Up/Down, Home and End, never returns
+    pub fn handle_keys(&mut self) -> ! {
+        let page_height = self.frame_buffer.borrow().get_size().height - 2;
+        loop {
+            let max_first_line = self.lines_count.saturating_sub(page_height);
+            let first_line = match read_scancode() {
+                0x48 => self.first_line.saturating_sub(1), // Up
+                0x50 => self.first_line + 1, // Down
+                0x49 => self.first_line.saturating_sub(page_height), // Page Up
+                0x51 => self.first_line + page_height, // Page Down
+                0x47 => 0, // Home
+                0x4F => max_first_line, // End
+                _ => continue,
+            }.min(max_first_line);
+            if first_line != self.first_line {
+                self.first_line = first_line;
+                self.display();
+            }
+        }
+    }
+
+    fn write_details(&self, writer: &mut ScrolledWriter) {
+        let _ = writeln!(writer, "{}", self.info);
+
+        writer.color = HEADER_COLOR;
+        let _ = writeln!(writer, "\nRegisters:");
+        writer.color = TEXT_COLOR;
+        let _ = writeln!(writer, "{}", self.registers);
+
+        writer.color = HEADER_COLOR;
+        let _ = writeln!(writer, "\nStack trace:");
+        writer.color = TEXT_COLOR;
+        for (index, frame) in self.backtrace.clone().enumerate() {
+            let _ = writeln!(writer, "  #{} {}", index, frame);
+        }
+
+        writer.color = HEADER_COLOR;
+        let _ = writeln!(writer, "\nRecent logs:");
+        writer.color = TEXT_COLOR;
+        let next_index = LOG_BUFFER.get_next_index();
+        let first_index = LOG_BUFFER.get_first_index().max(next_index.saturating_sub(RECENT_LOGS_COUNT));
+        for index in first_index..next_index {
+            if let Some(record) = LOG_BUFFER.get_record(index) {
+                let _ = writeln!(writer, "{}", record);
+            }
+        }
+    }
+}
+
+/// Waits for a key press and returns its scan code (set 1), the extended prefix and key releases are skipped.
+/// Polls the keyboard controller, as the interrupts are disabled after the panic.
+fn read_scancode() -> u8 {
+    let mut status_port = Port::<u8>::new(0x64);
+    let mut data_port = Port::<u8>::new(0x60);
+    loop {
+        let status = unsafe { status_port.read() };
+        if status & 0x01 == 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+        let scancode = unsafe { data_port.read() };
+        // Data from the mouse
+        if status & 0x20 != 0 {
+            continue;
+        }
+        if scancode != 0xE0 && scancode & 0x80 == 0 {
+            return scancode;
+        }
+    }
+}
 
-        writeln!(writer, "[PANIC!]").unwrap();
-        writeln!(writer, "{:#?}", info).unwrap();
+/// Writes only the lines from `first_line` which fit in the rect, but counts all of them.
+/// Too long lines are wrapped.
+struct ScrolledWriter<'a> {
+    rect: Rect,
+    first_line: usize,
+    color: CharacterColor,
+    frame_buffer: &'a RefCell<dyn FrameBuffer>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> ScrolledWriter<'a> {
+    fn new(rect: Rect, first_line: usize, frame_buffer: &'a RefCell<dyn FrameBuffer>) -> Self {
+        Self { rect, first_line, color: TEXT_COLOR, frame_buffer, line: 0, column: 0 }
     }
-}
\ No newline at end of file
+
+    fn clear(&mut self) {
+        let mut frame_buffer = self.frame_buffer.borrow_mut();
+        for point in self.rect.points() {
+            let _ = frame_buffer.set_char(point, ' ', TEXT_COLOR);
+        }
+    }
+
+    fn get_lines_count(&self) -> usize {
+        if self.column > 0 { self.line + 1 } else { self.line }
+    }
+}
+
+impl Write for ScrolledWriter<'_> {
+    fn write_str(&mut self, string: &str) -> core::fmt::Result {
+        for character in string.chars() {
+            if character == '\n' {
+                self.line += 1;
+                self.column = 0;
+                continue;
+            }
+            if self.column == self.rect.size.width {
+                self.line += 1;
+                self.column = 0;
+            }
+            if self.line >= self.first_line && self.line < self.first_line + self.rect.size.height {
+                let position = Point::new(self.rect.min_x() + self.column, self.rect.min_y() + self.line - self.first_line);
+                let _ = self.frame_buffer.borrow_mut().set_char(position, character, self.color);
+            }
+            self.column += 1;
+        }
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_scrolled_writer_skips_lines_before_first_line() {
+    use crate::vga_video::mock_frame_buffer::MockFrameBuffer;
+
+    let frame_buffer = RefCell::new(MockFrameBuffer::new(80, 25));
+    let mut writer = ScrolledWriter::new(Rect::from(1, 1, 5, 2), 1, &frame_buffer);
+
+    writer.write_str("first\nsecond line\nthird\nlast").unwrap();
+
+    assert_eq!(writer.get_lines_count(), 6);
+    let frame_buffer = frame_buffer.borrow();
+    assert_eq!(frame_buffer.get_chars(0, 1, 7), ['\0', 's', 'e', 'c', 'o', 'n', '\0']);
+    assert_eq!(frame_buffer.get_chars(0, 2, 7), ['\0', 'd', ' ', 'l', 'i', 'n', '\0']);
+    assert_eq!(frame_buffer.get_chars(0, 3, 7), ['\0'; 7]);
+}
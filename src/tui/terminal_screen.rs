@@ -1,45 +1,41 @@
 use alloc::boxed::Box;
 use alloc::format;
 use alloc::rc::Rc;
-use alloc::string::{String, ToString};
-use alloc::vec::Vec;
-use core::cell::{RefCell};
+use alloc::string::String;
+use core::cell::{Cell, RefCell};
 use core::fmt::Write;
 
-use pc_keyboard::DecodedKey;
+use pc_keyboard::{DecodedKey, KeyCode};
 use spin::Mutex;
 
 use crate::command::command::Command;
+use crate::command::completion::{Completion, get_common_prefix};
 use crate::geometry::position::Point;
 use crate::geometry::rect::Rect;
 use crate::geometry::size::Size;
+use crate::io;
 use crate::rtc::RTC;
-use crate::serial_println;
+use crate::task::keyboard::Modifiers;
+use crate::tui::header_bar::{Header, HeaderBar};
+use crate::tui::line_editor::LineEditor;
 use crate::vga_video::CharacterColor;
 use crate::vga_video::cursor::{Cursor, CursorStyle};
 use crate::vga_video::frame_buffer::FrameBuffer;
 use crate::vga_video::screen_fragment_writer::ScreenFragmentWriter;
 
-pub struct Header {
-    name: String,
-    version: String,
-}
-
-impl Header {
-    pub fn new(name: String, version: String) -> Self {
-        Header { name, version }
-    }
-}
-
 pub struct TerminalScreen<'a> {
-    header_writer: RefCell<ScreenFragmentWriter<'a>>,
-    header: Header,
-    rtc: Rc<Mutex<RTC>>,
+    header_bar: HeaderBar<'a>,
     body_writer: Rc<RefCell<ScreenFragmentWriter<'a>>>,
     prompt: String,
     cursor: Rc<Mutex<dyn Cursor>>,
-    command_buffer: RefCell<Vec<char>>,
+    line_editor: Rc<RefCell<LineEditor>>,
+    /// Position of the edited line, just after the prompt
+    line_start: RefCell<Point>,
+    /// Length of the edited line on the screen
+    displayed_length: Cell<usize>,
     command_handler: Box<dyn Fn(Command)>,
+    /// Completes the line before the cursor
+    completion_handler: Box<dyn Fn(&str) -> Completion>,
 }
 
 impl<'a> TerminalScreen<'a> {
@@ -50,14 +46,9 @@ impl<'a> TerminalScreen<'a> {
         prompt: String,
         cursor: Rc<Mutex<dyn Cursor>>,
         command_handler: Box<dyn Fn(Command)>,
+        completion_handler: Box<dyn Fn(&str) -> Completion>,
     ) -> Self {
         let screen_size = screen_buffer.borrow().get_size();
-        let header_writer = ScreenFragmentWriter::new(
-            Rect::new(Point::new(0, 0), Size::new(screen_size.width, 1)),
-            CharacterColor::default(),
-            screen_buffer,
-        );
-
         let body_writer = ScreenFragmentWriter::new(
             Rect::new(Point::new(0, 1), Size::new(screen_size.width, screen_size.height - 1)),
             CharacterColor::default(),
@@ -65,17 +56,23 @@ impl<'a> TerminalScreen<'a> {
         );
 
         Self {
-            header_writer: RefCell::new(header_writer),
-            header,
-            rtc,
+            header_bar: HeaderBar::new(screen_buffer, header, rtc),
             body_writer: Rc::new(RefCell::new(body_writer)),
             prompt,
             cursor,
-            command_buffer: RefCell::new(Vec::with_capacity(255)),
+            line_editor: Rc::new(RefCell::new(LineEditor::new())),
+            line_start: RefCell::new(Point::default()),
+            displayed_length: Cell::new(0),
             command_handler,
+            completion_handler,
         }
     }
 
+    /// Keeps the lines scrolled out of the body, so they can be viewed with Shift+Page Up/Down
+    pub fn enable_scrollback(&self, capacity: usize) {
+        (*self.body_writer).borrow_mut().enable_scrollback(capacity);
+    }
+
     pub fn begin(&mut self) {
         self.refresh_header();
 
@@ -85,62 +82,170 @@ impl<'a> TerminalScreen<'a> {
     }
 
     pub fn get_standard_output(&self) -> Rc<RefCell<dyn Write + 'a>> {
-        return self.body_writer.clone();
+        Rc::new(RefCell::new(BodyOutput {
+            body_writer: self.body_writer.clone(),
+            cursor: self.cursor.clone(),
+            line_editor: self.line_editor.clone(),
+        }))
+    }
+}
+
+/// Writes to the body, showing the cursor again when the output returns the body from the scrollback to the live view
+struct BodyOutput<'a> {
+    body_writer: Rc<RefCell<ScreenFragmentWriter<'a>>>,
+    cursor: Rc<Mutex<dyn Cursor>>,
+    line_editor: Rc<RefCell<LineEditor>>,
+}
+
+impl Write for BodyOutput<'_> {
+    fn write_str(&mut self, string: &str) -> core::fmt::Result {
+        let mut body_writer = (*self.body_writer).borrow_mut();
+        if body_writer.is_viewing_scrollback() {
+            self.cursor.lock().enable(get_cursor_style(&self.line_editor.borrow()));
+        }
+        body_writer.write_str(string)
     }
 }
 
 impl TerminalScreen<'_> {
     pub fn refresh_header(&self) {
-        let now = self.rtc.lock().read_datetime();
-        self.display_header(
-            "",
-            &*format!("{} (ver. {})", self.header.name, self.header.version),
-            &*now.to_string(),
-        )
+        self.header_bar.refresh();
     }
+}
+
+const BACKSPACE: char = '\x08';
+const DELETE: char = '\x7F';
+const CTRL_L: char = '\x0C';
+const CTRL_U: char = '\x15';
+const CTRL_W: char = '\x17';
+
+/// The body is the standard output of the commands, which is kept in a static
+impl TerminalScreen<'static> {
+    pub fn handle_keypress(&self, key: DecodedKey, modifiers: Modifiers) {
+        match key {
+            DecodedKey::RawKey(KeyCode::PageUp) if modifiers.shift => {
+                self.scroll_body(1);
+                return;
+            }
+            DecodedKey::RawKey(KeyCode::PageDown) if modifiers.shift => {
+                self.scroll_body(-1);
+                return;
+            }
+            _ => self.reset_body_view(),
+        }
 
-    fn display_header(&self, left: &str, center: &str, right: &str) {
-        let mut header_writer = self.header_writer.borrow_mut();
+        match key {
+            DecodedKey::Unicode('\n') => {
+                let line = self.line_editor.borrow_mut().take_line();
+                self.write_body_char('\n');
+                self.process_command_text(line);
+                self.display_prompt();
+                return;
+            }
+            DecodedKey::Unicode('\t') => {
+                self.complete();
+                return;
+            }
+            DecodedKey::Unicode(CTRL_L) => {
+                let mut body_writer = (*self.body_writer).borrow_mut();
+                body_writer.clear();
+                body_writer.reset_position();
+                drop(body_writer);
+                self.display_prompt();
+                return;
+            }
+            _ => {}
+        }
 
-        let total_width = header_writer.get_size().width;
-        let center_width = total_width - left.len() - right.len();
-        let header = format!("{:<}{:^width$}{:>}",
-                             left, center, right, width = center_width);
+        let mut line_editor = self.line_editor.borrow_mut();
+        match key {
+            DecodedKey::Unicode(BACKSPACE) => {
+                line_editor.backspace();
+            }
+            DecodedKey::Unicode(DELETE) | DecodedKey::RawKey(KeyCode::Delete) => {
+                line_editor.delete();
+            }
+            DecodedKey::Unicode(CTRL_U) => line_editor.delete_to_start(),
+            DecodedKey::Unicode(CTRL_W) => line_editor.delete_word(),
+            DecodedKey::Unicode(character) if !character.is_control() => line_editor.put_char(character),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => line_editor.move_left(),
+            DecodedKey::RawKey(KeyCode::ArrowRight) => line_editor.move_right(),
+            DecodedKey::RawKey(KeyCode::Home) => line_editor.move_to_start(),
+            DecodedKey::RawKey(KeyCode::End) => line_editor.move_to_end(),
+            DecodedKey::RawKey(KeyCode::ArrowUp) => line_editor.history_previous(),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => line_editor.history_next(),
+            DecodedKey::RawKey(KeyCode::Insert) => {
+                line_editor.toggle_insert_mode();
+                self.cursor.lock().enable(get_cursor_style(&line_editor));
+            }
+            _ => return,
+        }
+        drop(line_editor);
+        self.refresh_line();
+    }
 
-        header_writer.reset_position();
-        header_writer.write_str(&*header).unwrap();
+    fn process_command_text(&self, command_text: String) {
+        let command = Command::parse(command_text);
+        if let Some(command) = command {
+            io::with_standard_output_writer(self.get_standard_output(), || (self.command_handler)(command));
+        }
     }
 }
 
 impl TerminalScreen<'_> {
-    pub fn handle_keypress(&self, key: DecodedKey) {
-        match key {
-            DecodedKey::Unicode(character) => match character {
-                '\x08' => { // Backspace
-                    if let Some(_) = self.command_buffer.borrow_mut().pop() {
-                        self.write_body_char(character);
-                    }
-                }
-                '\n' => { // Carriage Return
-                    self.write_body_char(character);
+    /// Scrolls the body back into the scrollback by the number of pages, or forward if negative.
+    /// The cursor is hidden until returning to the live view.
+    fn scroll_body(&self, pages: isize) {
+        let mut body_writer = (*self.body_writer).borrow_mut();
+        let page_height = body_writer.get_size().height as isize - 1;
+        body_writer.scroll_view(pages * page_height);
+        if body_writer.is_viewing_scrollback() {
+            self.cursor.lock().disable();
+        } else {
+            self.cursor.lock().enable(get_cursor_style(&self.line_editor.borrow()));
+        }
+    }
 
-                    self.process_command_text(self.command_buffer.borrow().iter().collect());
-                    self.command_buffer.borrow_mut().clear();
-                    self.display_prompt();
-                }
-                _ => {
-                    self.write_body_char(character);
-                    self.command_buffer.borrow_mut().push(character);
-                }
-            },
-            DecodedKey::RawKey(key) => {
-                serial_println!("KEYBOARD KEY_CODE={:?}", key);
-            }
+    fn reset_body_view(&self) {
+        let mut body_writer = (*self.body_writer).borrow_mut();
+        if body_writer.is_viewing_scrollback() {
+            body_writer.reset_view();
+            self.cursor.lock().enable(get_cursor_style(&self.line_editor.borrow()));
         }
     }
 
     pub fn display_prompt(&self) {
         self.write_body_str(&*self.prompt);
+        *self.line_start.borrow_mut() = (*self.body_writer).borrow().get_next_position();
+        self.displayed_length.set(0);
+        self.refresh_line();
+    }
+
+    /// Draws the edited line again after the prompt and moves the cursor into it
+    fn refresh_line(&self) {
+        let line_editor = self.line_editor.borrow();
+        let mut body_writer = (*self.body_writer).borrow_mut();
+        let buffer = line_editor.get_buffer();
+
+        body_writer.set_next_position(self.line_start.borrow().clone());
+        for &character in buffer {
+            body_writer.write_char(character).unwrap();
+        }
+        // Clears the rest of the previous, longer line
+        let padding = self.displayed_length.replace(buffer.len()).saturating_sub(buffer.len());
+        for _ in 0..padding {
+            body_writer.write_char(' ').unwrap();
+        }
+
+        // Writing may scroll the body, so the start of the line is found back from the end,
+        // the body spans the whole width of the screen
+        let width = body_writer.get_size().width;
+        let get_index = |position: Point| position.y * width + position.x;
+        let get_position = |index: usize| Point::new(index % width, index / width);
+        let start_index = get_index(body_writer.get_next_position()) - (buffer.len() + padding);
+        *self.line_start.borrow_mut() = get_position(start_index);
+        body_writer.set_next_position(get_position(start_index + buffer.len()));
+        self.cursor.lock().move_to(get_position(start_index + line_editor.get_cursor()));
     }
 
     fn write_body_str(&self, str: &str) {
@@ -161,10 +266,53 @@ impl TerminalScreen<'_> {
         self.cursor.lock().move_to(next_position);
     }
 
-    fn process_command_text(&self, command_text: String) {
-        let command = Command::parse(command_text);
-        if let Some(command) = command {
-            (self.command_handler)(command)
+    /// Completes the word before the cursor, or its common part and lists the candidates if there are more
+    fn complete(&self) {
+        let line: String = {
+            let line_editor = self.line_editor.borrow();
+            line_editor.get_buffer()[..line_editor.get_cursor()].iter().collect()
+        };
+        let completion = (self.completion_handler)(&line);
+        let word_length = line.chars().count() - completion.word_start;
+        match completion.candidates.as_slice() {
+            [] => return,
+            [candidate] => {
+                // Directories are completed further, other words are finished
+                let suffix = if candidate.ends_with('/') { "" } else { " " };
+                self.line_editor.borrow_mut()
+                    .replace_before_cursor(completion.word_start, &format!("{}{}", candidate, suffix));
+            }
+            candidates => {
+                let common_prefix = get_common_prefix(candidates);
+                if common_prefix.chars().count() > word_length {
+                    self.line_editor.borrow_mut().replace_before_cursor(completion.word_start, common_prefix);
+                } else {
+                    self.display_candidates(candidates);
+                    return;
+                }
+            }
         }
+        self.refresh_line();
     }
+
+    /// Lists the candidates in columns below the edited line and displays the prompt again
+    fn display_candidates(&self, candidates: &[String]) {
+        let mut body_writer = (*self.body_writer).borrow_mut();
+        let column_width = candidates.iter().map(|candidate| candidate.chars().count()).max().unwrap_or(0) + 2;
+        // A row filling the whole width would wrap before the new line
+        let columns = ((body_writer.get_size().width - 1) / column_width).max(1);
+        body_writer.write_char('\n').unwrap();
+        for row in candidates.chunks(columns) {
+            for candidate in row {
+                write!(body_writer, "{:<width$}", candidate, width = column_width).unwrap();
+            }
+            body_writer.write_char('\n').unwrap();
+        }
+        drop(body_writer);
+        self.display_prompt();
+    }
+}
+
+fn get_cursor_style(line_editor: &LineEditor) -> CursorStyle {
+    if line_editor.is_insert_mode() { CursorStyle::Underline } else { CursorStyle::Block }
 }
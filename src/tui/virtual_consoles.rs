@@ -0,0 +1,105 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+
+use pc_keyboard::{DecodedKey, KeyCode};
+use spin::Mutex;
+
+use crate::task::keyboard::Modifiers;
+use crate::tui::log_screen::LogScreen;
+use crate::tui::terminal_screen::TerminalScreen;
+use crate::vga_video::cursor::{Cursor, VirtualCursor};
+use crate::vga_video::frame_buffer::FrameBuffer;
+use crate::vga_video::virtual_frame_buffer::VirtualFrameBuffer;
+
+const FUNCTION_KEYS: [KeyCode; 6] = [KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6];
+
+pub enum ConsoleScreen {
+    Terminal(Rc<TerminalScreen<'static>>),
+    Log(Rc<LogScreen<'static>>),
+}
+
+struct VirtualConsole {
+    frame_buffer: &'static RefCell<VirtualFrameBuffer<'static>>,
+    cursor: Rc<Mutex<VirtualCursor>>,
+    screen: ConsoleScreen,
+}
+
+/// Consoles drawn in their own frame buffers, only the active one is displayed on the screen.
+/// Alt+F1, Alt+F2 and so on switch to the following consoles.
+pub struct VirtualConsoles {
+    screen_buffer: &'static RefCell<dyn FrameBuffer>,
+    screen_cursor: Rc<Mutex<dyn Cursor>>,
+    consoles: Vec<VirtualConsole>,
+    active_index: Cell<Option<usize>>,
+}
+
+impl VirtualConsoles {
+    pub fn new(screen_buffer: &'static RefCell<dyn FrameBuffer>, screen_cursor: Rc<Mutex<dyn Cursor>>) -> Self {
+        Self {
+            screen_buffer,
+            screen_cursor,
+            consoles: Vec::new(),
+            active_index: Cell::new(None),
+        }
+    }
+}
+
+impl VirtualConsoles {
+    /// Creates a console with the screen drawing in its frame buffer and with its cursor
+    pub fn add_console(
+        &mut self,
+        create_screen: impl FnOnce(&'static RefCell<dyn FrameBuffer>, Rc<Mutex<dyn Cursor>>) -> ConsoleScreen,
+    ) {
+        // The consoles exist until the end of the kernel
+        let frame_buffer = Box::leak(Box::new(RefCell::new(VirtualFrameBuffer::new(self.screen_buffer))));
+        let cursor = Rc::new(Mutex::new(VirtualCursor::new(self.screen_cursor.clone())));
+        let screen = create_screen(frame_buffer, cursor.clone());
+        self.consoles.push(VirtualConsole { frame_buffer, cursor, screen });
+    }
+
+    /// Displays the console
+    pub fn switch_to(&self, index: usize) {
+        let console = match self.consoles.get(index) {
+            Some(console) => console,
+            None => return,
+        };
+        if let Some(active_index) = self.active_index.replace(Some(index)) {
+            let active_console = &self.consoles[active_index];
+            active_console.frame_buffer.borrow_mut().deactivate();
+            active_console.cursor.lock().deactivate();
+        }
+        console.frame_buffer.borrow_mut().activate().unwrap();
+        console.cursor.lock().activate();
+    }
+
+    pub fn refresh_headers(&self) {
+        for console in &self.consoles {
+            match &console.screen {
+                ConsoleScreen::Terminal(terminal_screen) => terminal_screen.refresh_header(),
+                ConsoleScreen::Log(log_screen) => log_screen.refresh_header(),
+            }
+        }
+    }
+
+    /// Switches the console with Alt+Fn, other keys go to the active console
+    pub fn handle_keypress(&self, key: DecodedKey, modifiers: Modifiers) {
+        if let DecodedKey::RawKey(code) = key {
+            if modifiers.alt {
+                if let Some(index) = FUNCTION_KEYS.iter().position(|&function_key| function_key == code) {
+                    self.switch_to(index);
+                    return;
+                }
+            }
+        }
+        let active_console = match self.active_index.get() {
+            Some(index) => &self.consoles[index],
+            None => return,
+        };
+        match &active_console.screen {
+            ConsoleScreen::Terminal(terminal_screen) => terminal_screen.handle_keypress(key, modifiers),
+            ConsoleScreen::Log(log_screen) => log_screen.handle_keypress(key, modifiers),
+        }
+    }
+}
@@ -8,6 +8,9 @@ pub mod color;
 pub mod vga_frame_buffer;
 pub mod screen_fragment_writer;
 pub mod frame_buffer;
+pub mod scrollback;
+pub mod escape_sequence;
+pub mod virtual_frame_buffer;
 
 #[cfg(test)]
 pub mod mock_frame_buffer;
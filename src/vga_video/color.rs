@@ -20,8 +20,34 @@ pub enum Color {
     White = 0xF,
 }
 
+impl Color {
+    /// Color of the ANSI escape sequences, where 0 is black and 7 is white
+    pub fn from_ansi(index: u16, is_bright: bool) -> Option<Color> {
+        let color = match (index, is_bright) {
+            (0, false) => Color::Black,
+            (1, false) => Color::Red,
+            (2, false) => Color::Green,
+            (3, false) => Color::Brown,
+            (4, false) => Color::Blue,
+            (5, false) => Color::Purple,
+            (6, false) => Color::Cyan,
+            (7, false) => Color::Gray,
+            (0, true) => Color::DarkGray,
+            (1, true) => Color::LightRed,
+            (2, true) => Color::LightGreen,
+            (3, true) => Color::Yellow,
+            (4, true) => Color::LightBlue,
+            (5, true) => Color::LightPurple,
+            (6, true) => Color::LightCyan,
+            (7, true) => Color::White,
+            _ => return None,
+        };
+        Some(color)
+    }
+}
 
-#[derive(Debug, Clone, Copy)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct CharacterColor(u8);
 
@@ -34,6 +60,28 @@ impl CharacterColor {
     pub const fn zero() -> CharacterColor {
         CharacterColor(0)
     }
+
+    pub const fn with_foreground(self, foreground: Color) -> CharacterColor {
+        CharacterColor(self.0 & 0xF0 | foreground as u8)
+    }
+
+    pub const fn with_background(self, background: Color) -> CharacterColor {
+        CharacterColor((background as u8) << 4 | self.0 & 0x0F)
+    }
+
+    /// Takes the foreground from the other color, e.g. to restore the default one
+    pub const fn with_foreground_of(self, other: CharacterColor) -> CharacterColor {
+        CharacterColor(self.0 & 0xF0 | other.0 & 0x0F)
+    }
+
+    pub const fn with_background_of(self, other: CharacterColor) -> CharacterColor {
+        CharacterColor(other.0 & 0xF0 | self.0 & 0x0F)
+    }
+
+    /// The light variant of the foreground, e.g. yellow instead of brown
+    pub const fn with_bright_foreground(self) -> CharacterColor {
+        CharacterColor(self.0 | 0x08)
+    }
 }
 
 impl Default for CharacterColor {
@@ -1,11 +1,16 @@
+use alloc::rc::Rc;
+
+use spin::Mutex;
 use x86_64::instructions::port::Port;
 
 use crate::geometry::position::Point;
 use crate::geometry::size::Size;
 use crate::vga_video::vga_frame_buffer::VGA_SCREEN_SIZE;
 
+#[derive(Clone, Copy)]
 pub enum CursorStyle {
-    Underline
+    Underline,
+    Block,
 }
 
 pub trait Cursor {
@@ -54,6 +59,15 @@ impl Cursor for VgaCursor {
                 let high = self.data_port.read();
                 self.data_port.write(high & 0xE0 | 14);
             }
+            CursorStyle::Block => unsafe {
+                self.control_port.write(0x0A);
+                let low = self.data_port.read();
+                self.data_port.write(low & 0xC0);
+
+                self.control_port.write(0x0B);
+                let high = self.data_port.read();
+                self.data_port.write(high & 0xE0 | 15);
+            }
         }
     }
 
@@ -74,4 +88,61 @@ impl Cursor for VgaCursor {
             self.data_port.write(index_bytes[1]);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Cursor of a virtual console, the state is kept and applied to the target cursor while it's active
+pub struct VirtualCursor {
+    target: Rc<Mutex<dyn Cursor>>,
+    /// `None` if disabled
+    style: Option<CursorStyle>,
+    position: Point,
+    is_active: bool,
+}
+
+impl VirtualCursor {
+    pub fn new(target: Rc<Mutex<dyn Cursor>>) -> Self {
+        Self {
+            target,
+            style: None,
+            position: Point::default(),
+            is_active: false,
+        }
+    }
+
+    pub fn activate(&mut self) {
+        self.is_active = true;
+        let mut target = self.target.lock();
+        match self.style {
+            Some(style) => target.enable(style),
+            None => target.disable(),
+        }
+        target.move_to(self.position.clone());
+    }
+
+    pub fn deactivate(&mut self) {
+        self.is_active = false;
+    }
+}
+
+impl Cursor for VirtualCursor {
+    fn enable(&mut self, style: CursorStyle) {
+        self.style = Some(style);
+        if self.is_active {
+            self.target.lock().enable(style);
+        }
+    }
+
+    fn disable(&mut self) {
+        self.style = None;
+        if self.is_active {
+            self.target.lock().disable();
+        }
+    }
+
+    fn move_to(&mut self, position: Point) {
+        if self.is_active {
+            self.target.lock().move_to(position.clone());
+        }
+        self.position = position;
+    }
+}
@@ -0,0 +1,158 @@
+const ESCAPE: char = '\x1b';
+const MAX_PARAMETERS: usize = 16;
+
+/// Splits the text into the printed characters and the ANSI/VT100 escape sequences,
+/// the state is kept between the calls, so a sequence may be split across writes.
+pub struct EscapeSequenceParser {
+    state: State,
+    sequence: ControlSequence,
+}
+
+enum State {
+    Text,
+    Escape,
+    ControlSequence,
+}
+
+pub enum Action {
+    Print(char),
+    /// CSI sequence, e.g. `ESC [ 1 ; 31 m`
+    ControlSequence(ControlSequence),
+    /// `ESC 7`
+    SaveCursor,
+    /// `ESC 8`
+    RestoreCursor,
+    /// Part of a sequence, or an unsupported one
+    None,
+}
+
+#[derive(Clone, Copy)]
+pub struct ControlSequence {
+    parameters: [u16; MAX_PARAMETERS],
+    parameters_count: usize,
+    /// Marked with `?`, e.g. `ESC [ ? 25 h`
+    is_private: bool,
+    pub final_character: char,
+}
+
+impl EscapeSequenceParser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Text,
+            sequence: ControlSequence::new(),
+        }
+    }
+}
+
+impl EscapeSequenceParser {
+    pub fn advance(&mut self, character: char) -> Action {
+        match self.state {
+            State::Text if character == ESCAPE => {
+                self.state = State::Escape;
+                Action::None
+            }
+            State::Text => Action::Print(character),
+            State::Escape => {
+                self.state = State::Text;
+                match character {
+                    '[' => {
+                        self.state = State::ControlSequence;
+                        self.sequence = ControlSequence::new();
+                        Action::None
+                    }
+                    '7' => Action::SaveCursor,
+                    '8' => Action::RestoreCursor,
+                    _ => Action::None,
+                }
+            }
+            State::ControlSequence => match character {
+                '0'..='9' => {
+                    self.sequence.push_digit(character as u8 - b'0');
+                    Action::None
+                }
+                ';' => {
+                    self.sequence.next_parameter();
+                    Action::None
+                }
+                '<'..='?' => {
+                    self.sequence.is_private = true;
+                    Action::None
+                }
+                '@'..='~' => {
+                    self.state = State::Text;
+                    self.sequence.final_character = character;
+                    if self.sequence.parameters_count > 0 || self.sequence.parameters[0] != 0 {
+                        self.sequence.next_parameter();
+                    }
+                    Action::ControlSequence(self.sequence)
+                }
+                ' '..='/' => Action::None, // Intermediate characters
+                _ => {
+                    // Malformed sequence, the character is printed
+                    self.state = State::Text;
+                    self.advance(character)
+                }
+            },
+        }
+    }
+}
+
+impl ControlSequence {
+    const fn new() -> Self {
+        Self {
+            parameters: [0; MAX_PARAMETERS],
+            parameters_count: 0,
+            is_private: false,
+            final_character: '\0',
+        }
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if let Some(parameter) = self.parameters.get_mut(self.parameters_count) {
+            *parameter = parameter.saturating_mul(10).saturating_add(digit as u16);
+        }
+    }
+
+    fn next_parameter(&mut self) {
+        self.parameters_count = (self.parameters_count + 1).min(MAX_PARAMETERS);
+    }
+}
+
+impl ControlSequence {
+    /// Omitted parameters are 0
+    pub fn get_parameters(&self) -> &[u16] {
+        &self.parameters[..self.parameters_count]
+    }
+
+    /// Returns the parameter, or the default if it's omitted or 0
+    pub fn get_parameter(&self, index: usize, default: u16) -> u16 {
+        match self.get_parameters().get(index) {
+            Some(&parameter) if parameter != 0 => parameter,
+            _ => default,
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.is_private
+    }
+}
+
+#[test_case]
+fn test_escape_sequence_parser() {
+    let mut parser = EscapeSequenceParser::new();
+    let mut printed = alloc::string::String::new();
+    let mut sequences = alloc::vec::Vec::new();
+    for character in "a\x1b[1;31mb\x1b[Hc\x1b[;5H\x1b7".chars() {
+        match parser.advance(character) {
+            Action::Print(character) => printed.push(character),
+            Action::ControlSequence(sequence) => sequences.push(sequence),
+            Action::SaveCursor => printed.push('S'),
+            _ => {}
+        }
+    }
+    assert_eq!(printed, "abcS");
+    assert_eq!(sequences.len(), 3);
+    assert_eq!((sequences[0].final_character, sequences[0].get_parameters()), ('m', &[1, 31][..]));
+    assert!(sequences[1].get_parameters().is_empty());
+    assert_eq!((sequences[2].get_parameter(0, 1), sequences[2].get_parameter(1, 1)), (1, 5));
+}
@@ -15,6 +15,8 @@ pub trait FrameBuffer {
         color: CharacterColor,
     ) -> Result<(), Box<dyn Error>>;
 
+    fn get_char(&self, position: Point) -> Result<(char, CharacterColor), Box<dyn Error>>;
+
     fn copy_char(
         &mut self,
         source: Point,
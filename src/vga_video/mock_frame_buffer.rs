@@ -46,6 +46,10 @@ impl FrameBuffer for MockFrameBuffer {
         Ok(())
     }
 
+    fn get_char(&self, position: Point) -> Result<(char, CharacterColor), Box<dyn Error>> {
+        Ok(self.characters[self.get_index(position)])
+    }
+
     fn copy_char(
         &mut self,
         source: Point,
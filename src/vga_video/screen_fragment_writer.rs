@@ -1,4 +1,5 @@
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::fmt::Write;
 
@@ -6,14 +7,27 @@ use crate::error::Error;
 use crate::geometry::position::Point;
 use crate::geometry::rect::Rect;
 use crate::geometry::size::Size;
-use crate::vga_video::CharacterColor;
+use crate::vga_video::{CharacterColor, Color};
+use crate::vga_video::escape_sequence::{Action, ControlSequence, EscapeSequenceParser};
 use crate::vga_video::frame_buffer::FrameBuffer;
+use crate::vga_video::scrollback::Scrollback;
 
 pub struct ScreenFragmentWriter<'a> {
     rect: Rect,
     default_color: CharacterColor,
     frame_buffer: &'a RefCell<dyn FrameBuffer>,
     next_position: Point,
+    /// Color of the written text, changed by the SGR escape sequences
+    color: CharacterColor,
+    is_bold: bool,
+    saved_position: Point,
+    escape_sequence_parser: EscapeSequenceParser,
+    /// Lines scrolled out of the top, kept only if enabled
+    scrollback: Option<Scrollback>,
+    /// Number of lines the view is scrolled back, 0 is the live view
+    view_offset: usize,
+    /// The live content, saved while viewing the scrollback
+    live_rows: Vec<Vec<(char, CharacterColor)>>,
 }
 
 impl<'a> ScreenFragmentWriter<'a> {
@@ -23,10 +37,23 @@ impl<'a> ScreenFragmentWriter<'a> {
             default_color,
             frame_buffer,
             next_position: rect.corner_upper_left(),
+            color: default_color,
+            is_bold: false,
+            saved_position: rect.corner_upper_left(),
+            escape_sequence_parser: EscapeSequenceParser::new(),
+            scrollback: None,
+            view_offset: 0,
+            live_rows: Vec::new(),
         }
     }
 
+    /// Keeps up to `capacity` lines scrolled out of the fragment, so they can be viewed with `scroll_view`
+    pub fn enable_scrollback(&mut self, capacity: usize) {
+        self.scrollback = Some(Scrollback::new(capacity, self.default_color));
+    }
+
     pub fn clear(&mut self) {
+        self.reset_view();
         for point in self.rect.points() {
             self.frame_buffer.borrow_mut()
                 .set_char(point, char::default(), self.default_color)
@@ -42,37 +69,237 @@ impl<'a> ScreenFragmentWriter<'a> {
         self.next_position.clone()
     }
 
+    pub fn set_next_position(&mut self, position: Point) {
+        self.next_position = position;
+    }
+
     pub fn get_size(&self) -> Size {
         self.rect.size.clone()
     }
 }
 
+impl ScreenFragmentWriter<'_> {
+    /// Moves the view back into the scrollback by the number of lines, or forward if negative
+    pub fn scroll_view(&mut self, lines: isize) {
+        let scrollback_length = match &self.scrollback {
+            Some(scrollback) => scrollback.len(),
+            None => return,
+        };
+        let view_offset = (self.view_offset as isize + lines).clamp(0, scrollback_length as isize) as usize;
+        if view_offset == self.view_offset {
+            return;
+        }
+        if self.view_offset == 0 {
+            self.live_rows = self.read_rows();
+        }
+        self.view_offset = view_offset;
+        self.display_view();
+    }
+
+    /// Returns to the live view, e.g. before writing
+    pub fn reset_view(&mut self) {
+        if self.view_offset == 0 {
+            return;
+        }
+        self.view_offset = 0;
+        self.display_view();
+        self.live_rows.clear();
+    }
+
+    pub fn is_viewing_scrollback(&self) -> bool {
+        self.view_offset > 0
+    }
+
+    fn read_rows(&self) -> Vec<Vec<(char, CharacterColor)>> {
+        let frame_buffer = self.frame_buffer.borrow();
+        (self.rect.min_y()..=self.rect.max_y())
+            .map(|row| {
+                (self.rect.min_x()..=self.rect.max_x())
+                    .map(|column| frame_buffer.get_char(Point::new(column, row)).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Draws the lines of the scrollback and the live content visible at the view offset
+    fn display_view(&self) {
+        let scrollback = match &self.scrollback {
+            Some(scrollback) => scrollback,
+            None => return,
+        };
+        let mut frame_buffer = self.frame_buffer.borrow_mut();
+        let first_line = scrollback.len() - self.view_offset;
+        for (index, row) in (self.rect.min_y()..=self.rect.max_y()).enumerate() {
+            let line = first_line + index;
+            for (index, column) in (self.rect.min_x()..=self.rect.max_x()).enumerate() {
+                let (character, color) = match scrollback.get(line) {
+                    Some(characters) => characters.get(index)
+                        .map(|&(character, color)| (character as char, color))
+                        .unwrap_or((char::default(), self.default_color)),
+                    None => self.live_rows[line - scrollback.len()][index],
+                };
+                frame_buffer.set_char(Point::new(column, row), character, color).unwrap();
+            }
+        }
+    }
+}
+
 impl Write for ScreenFragmentWriter<'_> {
     fn write_str(&mut self, string: &str) -> core::fmt::Result {
+        self.reset_view();
         for char in string.chars() {
-            if self.needs_scroll() {
-                self.scroll_up().unwrap();
+            match self.escape_sequence_parser.advance(char) {
+                Action::Print(char) => self.write_printed_char(char),
+                Action::ControlSequence(sequence) => self.perform_control_sequence(&sequence),
+                Action::SaveCursor => self.saved_position = self.next_position.clone(),
+                Action::RestoreCursor => self.next_position = self.saved_position.clone(),
+                Action::None => {}
             }
+        }
+        Ok(())
+    }
+}
 
-            match char {
-                '\n' => {
-                    self.move_to_next_line();
-                }
-                '\x08' => { // Backspace
-                    self.move_to_previous_position();
-                    self.frame_buffer.borrow_mut()
-                        .set_char(self.next_position.clone(), char::default(), self.default_color)
-                        .unwrap();
+impl ScreenFragmentWriter<'_> {
+    fn write_printed_char(&mut self, char: char) {
+        if self.needs_scroll() {
+            self.scroll_up().unwrap();
+        }
+
+        match char {
+            '\n' => {
+                self.move_to_next_line();
+            }
+            '\x08' => { // Backspace
+                self.move_to_previous_position();
+                self.frame_buffer.borrow_mut()
+                    .set_char(self.next_position.clone(), char::default(), self.color)
+                    .unwrap();
+            }
+            _ => {
+                self.frame_buffer.borrow_mut()
+                    .set_char(self.next_position.clone(), char, self.color)
+                    .unwrap();
+                self.move_to_next_position();
+            }
+        }
+    }
+
+    fn perform_control_sequence(&mut self, sequence: &ControlSequence) {
+        if sequence.is_private() {
+            return;
+        }
+        let count = sequence.get_parameter(0, 1) as isize;
+        match sequence.final_character {
+            'm' => self.select_graphic_rendition(sequence.get_parameters()),
+            'H' | 'f' => self.move_cursor_to(
+                sequence.get_parameter(1, 1) as usize - 1,
+                sequence.get_parameter(0, 1) as usize - 1,
+            ),
+            'A' => self.move_cursor_by(0, -count),
+            'B' => self.move_cursor_by(0, count),
+            'C' => self.move_cursor_by(count, 0),
+            'D' => self.move_cursor_by(-count, 0),
+            'G' => {
+                let row = self.next_position.y.min(self.rect.max_y()) - self.rect.min_y();
+                self.move_cursor_to(count as usize - 1, row);
+            }
+            'J' => self.erase_display(sequence.get_parameter(0, 0)),
+            'K' => self.erase_line(sequence.get_parameter(0, 0)),
+            's' => self.saved_position = self.next_position.clone(),
+            'u' => self.next_position = self.saved_position.clone(),
+            _ => {}
+        }
+    }
+
+    /// Sets the color, the 256 and RGB colors are skipped
+    fn select_graphic_rendition(&mut self, parameters: &[u16]) {
+        if parameters.is_empty() {
+            self.select_graphic_rendition(&[0]);
+            return;
+        }
+        let mut index = 0;
+        while index < parameters.len() {
+            match parameters[index] {
+                0 => {
+                    self.color = self.default_color;
+                    self.is_bold = false;
                 }
-                _ => {
-                    self.frame_buffer.borrow_mut()
-                        .set_char(self.next_position.clone(), char, self.default_color)
-                        .unwrap();
-                    self.move_to_next_position();
+                1 => {
+                    self.color = self.color.with_bright_foreground();
+                    self.is_bold = true;
                 }
+                22 => self.is_bold = false,
+                parameter @ 30..=37 => self.set_foreground(Color::from_ansi(parameter - 30, self.is_bold)),
+                parameter @ 90..=97 => self.set_foreground(Color::from_ansi(parameter - 90, true)),
+                39 => self.color = self.color.with_foreground_of(self.default_color),
+                parameter @ 40..=47 => self.set_background(Color::from_ansi(parameter - 40, false)),
+                parameter @ 100..=107 => self.set_background(Color::from_ansi(parameter - 100, true)),
+                49 => self.color = self.color.with_background_of(self.default_color),
+                38 | 48 => match parameters.get(index + 1) {
+                    Some(5) => index += 2,
+                    Some(2) => index += 4,
+                    _ => {}
+                },
+                _ => {}
             }
+            index += 1;
+        }
+    }
+
+    fn set_foreground(&mut self, color: Option<Color>) {
+        if let Some(color) = color {
+            self.color = self.color.with_foreground(color);
+        }
+    }
+
+    fn set_background(&mut self, color: Option<Color>) {
+        if let Some(color) = color {
+            self.color = self.color.with_background(color);
+        }
+    }
+
+    /// Moves to the position in the fragment, counted from 0
+    fn move_cursor_to(&mut self, column: usize, row: usize) {
+        self.next_position = Point::new(
+            self.rect.min_x() + column.min(self.rect.size.width - 1),
+            self.rect.min_y() + row.min(self.rect.size.height - 1),
+        );
+    }
+
+    fn move_cursor_by(&mut self, columns: isize, rows: isize) {
+        let column = (self.next_position.x - self.rect.min_x()) as isize + columns;
+        let row = (self.next_position.y - self.rect.min_y()) as isize + rows;
+        self.move_cursor_to(column.max(0) as usize, row.max(0) as usize);
+    }
+
+    /// Erases from the cursor to the end of the fragment (0), from the start to the cursor (1) or everything (2)
+    fn erase_display(&mut self, mode: u16) {
+        let cursor = (self.next_position.y, self.next_position.x);
+        match mode {
+            0 => self.erase(|point| (point.y, point.x) >= cursor),
+            1 => self.erase(|point| (point.y, point.x) <= cursor),
+            2 | 3 => self.erase(|_| true),
+            _ => {}
+        }
+    }
+
+    /// Erases from the cursor to the end of the line (0), from the start to the cursor (1) or the whole line (2)
+    fn erase_line(&mut self, mode: u16) {
+        let cursor = self.next_position.clone();
+        match mode {
+            0 => self.erase(|point| point.y == cursor.y && point.x >= cursor.x),
+            1 => self.erase(|point| point.y == cursor.y && point.x <= cursor.x),
+            2 => self.erase(|point| point.y == cursor.y),
+            _ => {}
+        }
+    }
+
+    fn erase(&mut self, predicate: impl Fn(&Point) -> bool) {
+        let mut frame_buffer = self.frame_buffer.borrow_mut();
+        for point in self.rect.points().filter(predicate) {
+            frame_buffer.set_char(point, char::default(), self.color).unwrap();
         }
-        Ok(())
     }
 }
 
@@ -109,6 +336,13 @@ impl ScreenFragmentWriter<'_> {
         let columns = self.rect.min_x()..=self.rect.max_x();
         let mut frame_buffer = self.frame_buffer.borrow_mut();
 
+        if let Some(scrollback) = &mut self.scrollback {
+            let first_row = self.rect.min_y();
+            scrollback.push(columns.clone().map(|column| {
+                frame_buffer.get_char(Point::new(column, first_row)).unwrap()
+            }));
+        }
+
         for row in rows.skip(1) {
             let source_row = row;
             let destination_row = row - 1;
@@ -261,3 +495,82 @@ fn test_write_two_fragments() {
     assert_eq!(frame_buffer.get_chars(0, 3, 7), ['\0', 'i', 'p', 's', 'u', 'm', '\0']);
     assert_eq!(frame_buffer.get_chars(0, 4, 7), ['\0'; 7]);
 }
+
+#[test_case]
+fn test_write_text_with_colors() {
+    use crate::vga_video::mock_frame_buffer::MockFrameBuffer;
+    use crate::geometry::size::Size;
+
+    let frame_buffer = RefCell::new(
+        MockFrameBuffer::new(80, 25)
+    );
+    let mut writer = ScreenFragmentWriter::new(
+        Rect::new(Point::new(1, 1), Size::new(10, 3)),
+        CharacterColor::default(),
+        &frame_buffer,
+    );
+
+    writer.write_str("\x1b[31mA\x1b[1;44mB\x1b[0mC\x1b[").unwrap();
+    writer.write_str("92mD").unwrap();
+
+    let frame_buffer = frame_buffer.borrow();
+    assert_eq!(frame_buffer.get_chars(1, 1, 5), ['A', 'B', 'C', 'D', '\0']);
+    let get_color = |x| frame_buffer.get_char(Point::new(x, 1)).unwrap().1;
+    assert_eq!(get_color(1), CharacterColor::new(Color::Red, Color::Black));
+    assert_eq!(get_color(2), CharacterColor::new(Color::LightRed, Color::Blue));
+    assert_eq!(get_color(3), CharacterColor::default());
+    assert_eq!(get_color(4), CharacterColor::new(Color::LightGreen, Color::Black));
+}
+
+#[test_case]
+fn test_write_text_with_cursor_movement_and_erase() {
+    use crate::vga_video::mock_frame_buffer::MockFrameBuffer;
+    use crate::geometry::size::Size;
+
+    let frame_buffer = RefCell::new(
+        MockFrameBuffer::new(80, 25)
+    );
+    let mut writer = ScreenFragmentWriter::new(
+        Rect::new(Point::new(1, 1), Size::new(10, 3)),
+        CharacterColor::default(),
+        &frame_buffer,
+    );
+
+    writer.write_str("Lorem\nipsum\x1b[2;3HX\x1b[sdolor\x1b[u\x1b[K\x1b[1;2H\x1b[1K").unwrap();
+
+    let frame_buffer = frame_buffer.borrow();
+    assert_eq!(frame_buffer.get_chars(1, 1, 5), ['\0', '\0', 'r', 'e', 'm']);
+    assert_eq!(frame_buffer.get_chars(1, 2, 5), ['i', 'p', 'X', '\0', '\0']);
+    assert_eq!(writer.get_next_position(), Point::new(2, 1));
+}
+
+#[test_case]
+fn test_scroll_view_into_scrollback() {
+    use crate::vga_video::mock_frame_buffer::MockFrameBuffer;
+    use crate::geometry::size::Size;
+
+    let frame_buffer = RefCell::new(
+        MockFrameBuffer::new(80, 25)
+    );
+    let mut writer = ScreenFragmentWriter::new(
+        Rect::new(Point::new(1, 1), Size::new(5, 2)),
+        CharacterColor::default(),
+        &frame_buffer,
+    );
+    writer.enable_scrollback(10);
+
+    writer.write_str("one\ntwo\nsix\nfour").unwrap();
+    writer.scroll_view(1);
+    assert!(writer.is_viewing_scrollback());
+    assert_eq!(frame_buffer.borrow().get_chars(1, 1, 5), ['t', 'w', 'o', '\0', '\0']);
+    assert_eq!(frame_buffer.borrow().get_chars(1, 2, 5), ['s', 'i', 'x', '\0', '\0']);
+
+    writer.scroll_view(5);
+    assert_eq!(frame_buffer.borrow().get_chars(1, 1, 5), ['o', 'n', 'e', '\0', '\0']);
+    assert_eq!(frame_buffer.borrow().get_chars(1, 2, 5), ['t', 'w', 'o', '\0', '\0']);
+
+    writer.write_str("!").unwrap();
+    assert!(!writer.is_viewing_scrollback());
+    assert_eq!(frame_buffer.borrow().get_chars(1, 1, 5), ['s', 'i', 'x', '\0', '\0']);
+    assert_eq!(frame_buffer.borrow().get_chars(1, 2, 5), ['f', 'o', 'u', 'r', '!']);
+}
@@ -0,0 +1,72 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+
+use crate::vga_video::CharacterColor;
+
+/// Lines scrolled out of a screen fragment, the oldest ones are dropped when it's full.
+/// The characters are kept as bytes like in the VGA text mode, without the trailing blanks of the blank color.
+pub struct Scrollback {
+    lines: VecDeque<Box<[(u8, CharacterColor)]>>,
+    capacity: usize,
+    /// Color of the cells left out at the end of the lines, e.g. a colored background is kept
+    blank_color: CharacterColor,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize, blank_color: CharacterColor) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            capacity,
+            blank_color,
+        }
+    }
+}
+
+impl Scrollback {
+    pub fn push(&mut self, line: impl IntoIterator<Item=(char, CharacterColor)>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut line: alloc::vec::Vec<(u8, CharacterColor)> = line.into_iter()
+            .map(|(character, color)| (u8::try_from(character).unwrap_or(b'?'), color))
+            .collect();
+        while matches!(line.last(), Some(&(0 | b' ', color)) if color == self.blank_color) {
+            line.pop();
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.into_boxed_slice());
+    }
+
+    /// Returns the line, the oldest one has index 0
+    pub fn get(&self, index: usize) -> Option<&[(u8, CharacterColor)]> {
+        self.lines.get(index).map(|line| &**line)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+#[test_case]
+fn test_scrollback_drops_oldest_lines() {
+    let mut scrollback = Scrollback::new(2, CharacterColor::default());
+    for text in ["first", "second  ", "third"] {
+        scrollback.push(text.chars().map(|character| (character, CharacterColor::default())));
+    }
+    assert_eq!(scrollback.len(), 2);
+    let line: alloc::vec::Vec<u8> = scrollback.get(0).unwrap().iter().map(|(character, _)| *character).collect();
+    assert_eq!(line, b"second");
+    assert!(scrollback.get(2).is_none());
+}
+
+#[test_case]
+fn test_scrollback_keeps_colored_blanks() {
+    use crate::vga_video::Color;
+
+    let mut scrollback = Scrollback::new(1, CharacterColor::default());
+    let highlighted = CharacterColor::new(Color::Black, Color::Gray);
+    scrollback.push([('a', highlighted), (' ', highlighted), (' ', CharacterColor::default())]);
+    assert_eq!(scrollback.get(0).unwrap(), [(b'a', highlighted), (b' ', highlighted)]);
+}
@@ -50,6 +50,15 @@ impl FrameBuffer for VgaFrameBuffer {
         Ok(())
     }
 
+    fn get_char(&self, position: Point) -> Result<(char, CharacterColor), Box<dyn Error>> {
+        let index = self.get_index(position);
+        // Volatile is transparent, so the characters can be read directly from the memory
+        let screen_character = unsafe {
+            (self.characters as *const ScreenCharacter).add(index).read_volatile()
+        };
+        Ok((screen_character.character as char, screen_character.color))
+    }
+
     fn copy_char(&mut self, source: Point, destination: Point) -> Result<(), Box<dyn Error>> {
         let source_index = self.get_index(source);
         let destination_index = self.get_index(destination);
@@ -0,0 +1,97 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::error::Error;
+use crate::geometry::position::Point;
+use crate::geometry::size::Size;
+use crate::vga_video::CharacterColor;
+use crate::vga_video::frame_buffer::FrameBuffer;
+
+/// Off-screen frame buffer of a virtual console, the changes are also written to the target while it's active
+pub struct VirtualFrameBuffer<'a> {
+    characters: Vec<(char, CharacterColor)>,
+    size: Size,
+    target: &'a RefCell<dyn FrameBuffer>,
+    is_active: bool,
+}
+
+impl<'a> VirtualFrameBuffer<'a> {
+    pub fn new(target: &'a RefCell<dyn FrameBuffer>) -> Self {
+        let size = target.borrow().get_size();
+        Self {
+            characters: vec![(char::default(), CharacterColor::zero()); size.area()],
+            size,
+            target,
+            is_active: false,
+        }
+    }
+}
+
+impl VirtualFrameBuffer<'_> {
+    /// Copies the whole content to the target and keeps it updated
+    pub fn activate(&mut self) -> Result<(), Box<dyn Error>> {
+        self.is_active = true;
+        let mut target = self.target.borrow_mut();
+        for (index, &(character, color)) in self.characters.iter().enumerate() {
+            let position = Point::new(index % self.size.width, index / self.size.width);
+            target.set_char(position, character, color)?;
+        }
+        Ok(())
+    }
+
+    pub fn deactivate(&mut self) {
+        self.is_active = false;
+    }
+}
+
+impl FrameBuffer for VirtualFrameBuffer<'_> {
+    fn get_size(&self) -> Size {
+        self.size.clone()
+    }
+
+    fn set_char(&mut self, position: Point, character: char, color: CharacterColor) -> Result<(), Box<dyn Error>> {
+        let index = self.get_index(position.clone());
+        self.characters[index] = (character, color);
+        if self.is_active {
+            self.target.borrow_mut().set_char(position, character, color)?;
+        }
+        Ok(())
+    }
+
+    fn get_char(&self, position: Point) -> Result<(char, CharacterColor), Box<dyn Error>> {
+        Ok(self.characters[self.get_index(position)])
+    }
+
+    fn copy_char(&mut self, source: Point, destination: Point) -> Result<(), Box<dyn Error>> {
+        let source_index = self.get_index(source.clone());
+        let destination_index = self.get_index(destination.clone());
+        self.characters[destination_index] = self.characters[source_index];
+        if self.is_active {
+            self.target.borrow_mut().copy_char(source, destination)?;
+        }
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_virtual_frame_buffer_writes_target_only_while_active() {
+    use crate::vga_video::mock_frame_buffer::MockFrameBuffer;
+
+    let target = RefCell::new(MockFrameBuffer::new(4, 2));
+    let mut frame_buffer = VirtualFrameBuffer::new(&target);
+
+    frame_buffer.set_char(Point::new(0, 0), 'a', CharacterColor::default()).unwrap();
+    assert_eq!(target.borrow().get_chars(0, 0, 2), ['\0', '\0']);
+
+    frame_buffer.activate().unwrap();
+    assert_eq!(target.borrow().get_chars(0, 0, 2), ['a', '\0']);
+    frame_buffer.set_char(Point::new(1, 0), 'b', CharacterColor::default()).unwrap();
+    assert_eq!(target.borrow().get_chars(0, 0, 2), ['a', 'b']);
+
+    frame_buffer.deactivate();
+    frame_buffer.set_char(Point::new(0, 0), 'c', CharacterColor::default()).unwrap();
+    assert_eq!(target.borrow().get_chars(0, 0, 2), ['a', 'b']);
+    assert_eq!(frame_buffer.get_char(Point::new(0, 0)).unwrap().0, 'c');
+}
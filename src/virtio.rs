@@ -0,0 +1,242 @@
+use core::fmt::{Display, Formatter};
+
+use x86_64::VirtAddr;
+
+use crate::error::Error;
+use crate::memory;
+use crate::pci::{Bar, PciCommand, PciDevice};
+use crate::virtio::virtqueue::Virtqueue;
+
+pub mod virtqueue;
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const PCI_CAPABILITY_VENDOR_SPECIFIC: u8 = 0x09;
+
+const CFG_TYPE_COMMON: u8 = 1;
+const CFG_TYPE_NOTIFY: u8 = 2;
+const CFG_TYPE_ISR: u8 = 3;
+const CFG_TYPE_DEVICE: u8 = 4;
+
+// Common configuration structure
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_MSIX_VECTOR: usize = 0x1A;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+const NO_VECTOR: u16 = 0xFFFF;
+
+bitflags! {
+    struct DeviceStatus: u8 {
+        const ACKNOWLEDGE =        1;
+        const DRIVER =             2;
+        const DRIVER_OK =          4;
+        const FEATURES_OK =        8;
+        const DEVICE_NEEDS_RESET = 64;
+        const FAILED =             128;
+    }
+}
+
+#[derive(Debug)]
+pub enum VirtioError {
+    MissingCapability(u8),
+    UnsupportedBar,
+    FeaturesRejected,
+    QueueUnavailable(u16),
+    NoMemory,
+}
+
+impl Display for VirtioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VirtioError::MissingCapability(cfg_type) => write!(f, "Missing PCI capability of type {}", cfg_type),
+            VirtioError::UnsupportedBar => write!(f, "Configuration in I/O space BAR is not supported"),
+            VirtioError::FeaturesRejected => write!(f, "Device rejected features"),
+            VirtioError::QueueUnavailable(index) => write!(f, "Queue {} is unavailable", index),
+            VirtioError::NoMemory => write!(f, "Failed to allocate DMA memory"),
+        }
+    }
+}
+
+impl Error for VirtioError {}
+
+/// Memory mapped structure pointed by a virtio PCI capability
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigRegion {
+    base: VirtAddr,
+}
+
+impl ConfigRegion {
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { core::ptr::read_volatile((self.base + offset).as_ptr()) }
+    }
+
+    pub fn write<T: Copy>(&self, offset: usize, value: T) {
+        unsafe { core::ptr::write_volatile((self.base + offset).as_mut_ptr(), value) }
+    }
+}
+
+/// Modern (virtio 1.x) PCI transport
+pub struct VirtioPciTransport {
+    pci_device: PciDevice,
+    common: ConfigRegion,
+    notify: ConfigRegion,
+    notify_offset_multiplier: u32,
+    isr: ConfigRegion,
+    device: ConfigRegion,
+}
+
+unsafe impl Send for VirtioPciTransport {}
+
+impl VirtioPciTransport {
+    pub fn new(pci_device: &PciDevice) -> Result<Self, VirtioError> {
+        pci_device.enable(PciCommand::MEMORY_SPACE | PciCommand::BUS_MASTER);
+        pci_device.disable(PciCommand::INTERRUPT_DISABLE);
+
+        let mut mapped_bars: [Option<VirtAddr>; 6] = [None; 6];
+        let mut regions: [Option<ConfigRegion>; 5] = [None; 5];
+        let mut notify_offset_multiplier = 0;
+
+        let capabilities = pci_device.capabilities().into_iter()
+            .filter(|(id, _)| *id == PCI_CAPABILITY_VENDOR_SPECIFIC);
+        for (_, offset) in capabilities {
+            let header = pci_device.read_config(offset);
+            let cfg_type = (header >> 24) as u8;
+            let bar_index = pci_device.read_config(offset + 4) as u8;
+            let region_offset = pci_device.read_config(offset + 8) as usize;
+            if cfg_type == 0 || cfg_type > CFG_TYPE_DEVICE || bar_index > 5 || regions[cfg_type as usize].is_some() {
+                continue;
+            }
+
+            let bar_address = match mapped_bars[bar_index as usize] {
+                Some(address) => address,
+                None => match pci_device.read_bar(bar_index) {
+                    Some(Bar::Memory { address, size, .. }) => {
+                        let mapped = memory::map_mmio(address, size);
+                        mapped_bars[bar_index as usize] = Some(mapped);
+                        mapped
+                    }
+                    _ => return Err(VirtioError::UnsupportedBar),
+                }
+            };
+
+            if cfg_type == CFG_TYPE_NOTIFY {
+                notify_offset_multiplier = pci_device.read_config(offset + 16);
+            }
+            regions[cfg_type as usize] = Some(ConfigRegion { base: bar_address + region_offset });
+        }
+
+        let region = |cfg_type: u8| regions[cfg_type as usize].ok_or(VirtioError::MissingCapability(cfg_type));
+        Ok(Self {
+            pci_device: pci_device.clone(),
+            common: region(CFG_TYPE_COMMON)?,
+            notify: region(CFG_TYPE_NOTIFY)?,
+            notify_offset_multiplier,
+            isr: region(CFG_TYPE_ISR)?,
+            device: region(CFG_TYPE_DEVICE)?,
+        })
+    }
+}
+
+impl VirtioPciTransport {
+    /// Resets the device and negotiates features, returns the accepted features
+    pub fn negotiate_features(&mut self, driver_features: u64) -> Result<u64, VirtioError> {
+        self.write_status(DeviceStatus::empty());
+        while !self.read_status().is_empty() {
+            core::hint::spin_loop();
+        }
+        self.write_status(DeviceStatus::ACKNOWLEDGE);
+        self.write_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+
+        let device_features = self.read_device_features();
+        let features = device_features & (driver_features | VIRTIO_F_VERSION_1);
+        if features & VIRTIO_F_VERSION_1 == 0 {
+            self.fail();
+            return Err(VirtioError::FeaturesRejected);
+        }
+
+        self.common.write::<u32>(COMMON_DRIVER_FEATURE_SELECT, 0);
+        self.common.write::<u32>(COMMON_DRIVER_FEATURE, features as u32);
+        self.common.write::<u32>(COMMON_DRIVER_FEATURE_SELECT, 1);
+        self.common.write::<u32>(COMMON_DRIVER_FEATURE, (features >> 32) as u32);
+
+        let status = self.read_status() | DeviceStatus::FEATURES_OK;
+        self.write_status(status);
+        if !self.read_status().contains(DeviceStatus::FEATURES_OK) {
+            self.fail();
+            return Err(VirtioError::FeaturesRejected);
+        }
+        Ok(features)
+    }
+
+    pub fn setup_queue(&mut self, index: u16, max_size: u16) -> Result<Virtqueue, VirtioError> {
+        self.common.write::<u16>(COMMON_QUEUE_SELECT, index);
+        let device_size = self.common.read::<u16>(COMMON_QUEUE_SIZE);
+        if device_size == 0 {
+            return Err(VirtioError::QueueUnavailable(index));
+        }
+        let size = device_size.min(max_size);
+
+        let notify_offset = self.common.read::<u16>(COMMON_QUEUE_NOTIFY_OFF) as usize;
+        let notify_address = self.notify.base + notify_offset * self.notify_offset_multiplier as usize;
+        let queue = Virtqueue::new(index, size, notify_address)?;
+
+        self.common.write::<u16>(COMMON_QUEUE_SIZE, size);
+        self.common.write::<u16>(COMMON_QUEUE_MSIX_VECTOR, NO_VECTOR);
+        self.common.write::<u64>(COMMON_QUEUE_DESC, queue.descriptors_address().as_u64());
+        self.common.write::<u64>(COMMON_QUEUE_DRIVER, queue.available_ring_address().as_u64());
+        self.common.write::<u64>(COMMON_QUEUE_DEVICE, queue.used_ring_address().as_u64());
+        self.common.write::<u16>(COMMON_QUEUE_ENABLE, 1);
+        Ok(queue)
+    }
+
+    pub fn finish_initialization(&mut self) {
+        let status = self.read_status() | DeviceStatus::DRIVER_OK;
+        self.write_status(status);
+    }
+
+    pub fn get_pci_device(&self) -> &PciDevice {
+        &self.pci_device
+    }
+
+    pub fn get_device_config(&self) -> ConfigRegion {
+        self.device
+    }
+
+    /// Returns ISR region, reading it acknowledges the interrupt
+    pub fn get_isr(&self) -> ConfigRegion {
+        self.isr
+    }
+
+    fn read_device_features(&mut self) -> u64 {
+        self.common.write::<u32>(COMMON_DEVICE_FEATURE_SELECT, 0);
+        let low = self.common.read::<u32>(COMMON_DEVICE_FEATURE);
+        self.common.write::<u32>(COMMON_DEVICE_FEATURE_SELECT, 1);
+        let high = self.common.read::<u32>(COMMON_DEVICE_FEATURE);
+        (high as u64) << 32 | low as u64
+    }
+
+    fn fail(&mut self) {
+        let status = self.read_status() | DeviceStatus::FAILED;
+        self.write_status(status);
+    }
+
+    fn read_status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_truncate(self.common.read::<u8>(COMMON_DEVICE_STATUS))
+    }
+
+    fn write_status(&self, status: DeviceStatus) {
+        self.common.write::<u8>(COMMON_DEVICE_STATUS, status.bits());
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
+
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::memory::{self, DmaRegion};
+use crate::virtio::VirtioError;
+
+const DESCRIPTOR_SIZE: usize = 16;
+const USED_ELEMENT_SIZE: usize = 8;
+
+const DESCRIPTOR_F_NEXT: u16 = 1;
+const DESCRIPTOR_F_WRITE: u16 = 2;
+
+/// Buffer in the DMA memory passed to the device
+#[derive(Debug, Clone, Copy)]
+pub struct VirtqueueBuffer {
+    pub address: PhysAddr,
+    pub length: u32,
+    pub device_writable: bool,
+}
+
+/// Split virtqueue, the descriptor table and both rings are placed in a single DMA region
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    memory: DmaRegion,
+    notify_address: VirtAddr,
+    free_descriptors: Vec<u16>,
+    available_index: u16,
+    last_used_index: u16,
+}
+
+impl Virtqueue {
+    pub(super) fn new(index: u16, size: u16, notify_address: VirtAddr) -> Result<Self, VirtioError> {
+        let memory = memory::allocate_dma(Self::used_ring_offset(size) + 6 + USED_ELEMENT_SIZE * size as usize)
+            .ok_or(VirtioError::NoMemory)?;
+        Ok(Self {
+            index,
+            size,
+            memory,
+            notify_address,
+            free_descriptors: (0..size).rev().collect(),
+            available_index: 0,
+            last_used_index: 0,
+        })
+    }
+
+    fn available_ring_offset(size: u16) -> usize {
+        DESCRIPTOR_SIZE * size as usize
+    }
+
+    fn used_ring_offset(size: u16) -> usize {
+        let available_ring_end = Self::available_ring_offset(size) + 6 + 2 * size as usize;
+        (available_ring_end + 3) & !3
+    }
+}
+
+impl Virtqueue {
+    pub fn get_size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn descriptors_address(&self) -> PhysAddr {
+        self.memory.physical_address
+    }
+
+    pub fn available_ring_address(&self) -> PhysAddr {
+        self.memory.physical_address + Self::available_ring_offset(self.size)
+    }
+
+    pub fn used_ring_address(&self) -> PhysAddr {
+        self.memory.physical_address + Self::used_ring_offset(self.size)
+    }
+
+    /// Places the chain of buffers in the available ring, returns the head descriptor
+    /// which is reported back by `pop_used` when the device finishes
+    pub fn push(&mut self, buffers: &[VirtqueueBuffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free_descriptors.len() {
+            return None;
+        }
+
+        let descriptors: Vec<u16> = (0..buffers.len())
+            .map(|_| self.free_descriptors.pop().unwrap())
+            .collect();
+        for (position, buffer) in buffers.iter().enumerate() {
+            let mut flags = if buffer.device_writable { DESCRIPTOR_F_WRITE } else { 0 };
+            let next = match descriptors.get(position + 1) {
+                Some(&next) => {
+                    flags |= DESCRIPTOR_F_NEXT;
+                    next
+                }
+                None => 0,
+            };
+            self.write_descriptor(descriptors[position], buffer.address.as_u64(), buffer.length, flags, next);
+        }
+
+        let head = descriptors[0];
+        let ring_offset = Self::available_ring_offset(self.size);
+        let slot = self.available_index % self.size;
+        self.write::<u16>(ring_offset + 4 + 2 * slot as usize, head);
+
+        fence(Ordering::SeqCst);
+        self.available_index = self.available_index.wrapping_add(1);
+        self.write::<u16>(ring_offset + 2, self.available_index);
+        fence(Ordering::SeqCst);
+        Some(head)
+    }
+
+    pub fn notify(&self) {
+        unsafe { core::ptr::write_volatile(self.notify_address.as_mut_ptr::<u16>(), self.index) }
+    }
+
+    /// Takes the next chain consumed by the device, returns its head descriptor and written length
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let ring_offset = Self::used_ring_offset(self.size);
+        fence(Ordering::SeqCst);
+        let used_index = self.read::<u16>(ring_offset + 2);
+        if used_index == self.last_used_index {
+            return None;
+        }
+
+        let slot = self.last_used_index % self.size;
+        let element_offset = ring_offset + 4 + USED_ELEMENT_SIZE * slot as usize;
+        let head = self.read::<u32>(element_offset) as u16;
+        let length = self.read::<u32>(element_offset + 4);
+        self.last_used_index = self.last_used_index.wrapping_add(1);
+
+        self.free_chain(head);
+        Some((head, length))
+    }
+
+    fn free_chain(&mut self, head: u16) {
+        let mut descriptor = head;
+        loop {
+            self.free_descriptors.push(descriptor);
+            let offset = DESCRIPTOR_SIZE * descriptor as usize;
+            let flags = self.read::<u16>(offset + 12);
+            if flags & DESCRIPTOR_F_NEXT == 0 {
+                break;
+            }
+            descriptor = self.read::<u16>(offset + 14);
+        }
+    }
+
+    fn write_descriptor(&mut self, descriptor: u16, address: u64, length: u32, flags: u16, next: u16) {
+        let offset = DESCRIPTOR_SIZE * descriptor as usize;
+        self.write::<u64>(offset, address);
+        self.write::<u32>(offset + 8, length);
+        self.write::<u16>(offset + 12, flags);
+        self.write::<u16>(offset + 14, next);
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { core::ptr::read_volatile(self.memory.as_ptr::<u8>().add(offset) as *const T) }
+    }
+
+    fn write<T: Copy>(&mut self, offset: usize, value: T) {
+        unsafe { core::ptr::write_volatile(self.memory.as_ptr::<u8>().add(offset) as *mut T, value) }
+    }
+}
@@ -0,0 +1,347 @@
+#!/usr/bin/env python3
+"""Writes the symbol table of the kernel into its `.symbols` section, used to symbolize backtraces.
+
+The section is a zeroed placeholder of a fixed size (`SYMBOL_TABLE_SIZE` in `src/backtrace/symbol_table.rs`),
+so it's patched in place and no address in the kernel changes.
+
+Format (little-endian):
+    header:  magic "JOSSYMS1" (8 bytes), count of symbols (u32), reserved (u32)
+    entries: address (u64), size (u32), offset of the name (u32), sorted by the address
+    names:   length (u8) and UTF-8 bytes, the offsets are relative to the start of the names
+"""
+
+import re
+import struct
+import sys
+
+SECTION_NAME = ".symbols"
+MAGIC = b"JOSSYMS1"
+MAX_NAME_LENGTH = 255
+
+SHT_SYMTAB = 2
+STT_FUNC = 2
+
+ESCAPES = {
+    "$SP$": "@", "$BP$": "*", "$RF$": "&", "$LT$": "<", "$GT$": ">", "$LP$": "(", "$RP$": ")", "$C$": ",",
+}
+
+
+def read_sections(elf):
+    if elf[:4] != b"\x7fELF" or elf[4] != 2:
+        raise ValueError("not an ELF64 file")
+    (section_headers_offset,) = struct.unpack_from("<Q", elf, 0x28)
+    section_header_size, sections_count, names_index = struct.unpack_from("<HHH", elf, 0x3A)
+    sections = []
+    for index in range(sections_count):
+        name, kind, _, _, offset, size, link, _, _, entry_size = struct.unpack_from(
+            "<IIQQQQIIQQ", elf, section_headers_offset + index * section_header_size)
+        sections.append({"name": name, "type": kind, "offset": offset, "size": size, "link": link})
+    names = sections[names_index]
+    for section in sections:
+        section["name"] = read_string(elf, names["offset"] + section["name"])
+    return sections
+
+
+def read_string(elf, offset):
+    return elf[offset:elf.index(b"\0", offset)].decode("utf-8", "replace")
+
+
+def demangle(name):
+    """Demangles Rust names of both manglings (`_R...` and the legacy `_ZN...E`) without the hashes,
+    other names are kept as they are"""
+    name = re.sub(r"\.llvm\.\d+$", "", name)
+    if re.match(r"_?_R", name):
+        try:
+            return V0Demangler(name[name.index("_R") + 2:]).demangle()
+        except (IndexError, ValueError):
+            return name
+    return demangle_legacy(name)
+
+
+def demangle_legacy(name):
+    match = re.fullmatch(r"_?_ZN(.*)E", name)
+    if not match:
+        return name
+    rest, components = match.group(1), []
+    while rest:
+        length = re.match(r"\d+", rest)
+        if not length:
+            return name
+        start = len(length.group(0))
+        components.append(rest[start:start + int(length.group(0))])
+        rest = rest[start + int(length.group(0)):]
+    if components and re.fullmatch(r"h[0-9a-f]{16}", components[-1]):
+        components.pop()
+    return "::".join(unescape(component) for component in components)
+
+
+def unescape(component):
+    if component.startswith("_$"):
+        component = component[1:]
+    for escape, character in ESCAPES.items():
+        component = component.replace(escape, character)
+    component = re.sub(r"\$u([0-9a-f]+)\$", lambda match: chr(int(match.group(1), 16)), component)
+    return component.replace("..", "::")
+
+
+BASIC_TYPES = {
+    "a": "i8", "b": "bool", "c": "char", "d": "f64", "e": "str", "f": "f32", "h": "u8", "i": "isize", "j": "usize",
+    "l": "i32", "m": "u32", "n": "i128", "o": "u128", "s": "i16", "t": "u16", "u": "()", "v": "...", "x": "i64",
+    "y": "u64", "z": "!", "p": "_",
+}
+
+
+class V0Demangler:
+    """Demangler of the v0 mangling (https://doc.rust-lang.org/rustc/symbol-mangling/v0.html), the crate
+    disambiguators, the instantiating crate and the generic arguments of the functions are left out"""
+
+    def __init__(self, symbol):
+        self.symbol = symbol
+        self.position = 0
+
+    def demangle(self):
+        # Encoding version, only the initial one exists
+        if self.peek().isdigit():
+            raise ValueError("unsupported encoding version")
+        return self.path(in_value=True)
+
+    def peek(self):
+        return self.symbol[self.position] if self.position < len(self.symbol) else ""
+
+    def next(self):
+        character = self.symbol[self.position]
+        self.position += 1
+        return character
+
+    def eat(self, character):
+        if self.peek() == character:
+            self.position += 1
+            return True
+        return False
+
+    def base62(self):
+        if self.eat("_"):
+            return 0
+        value = 0
+        while not self.eat("_"):
+            character = self.next()
+            digit = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ".index(character)
+            value = value * 62 + digit
+        return value + 1
+
+    def decimal(self):
+        if self.eat("0"):
+            return 0
+        start = self.position
+        while self.peek().isdigit():
+            self.position += 1
+        if start == self.position:
+            raise ValueError("expected a number")
+        return int(self.symbol[start:self.position])
+
+    def disambiguator(self):
+        return self.base62() + 1 if self.eat("s") else 0
+
+    def identifier(self):
+        self.disambiguator()
+        return self.undisambiguated_identifier()
+
+    def undisambiguated_identifier(self):
+        is_punycode = self.eat("u")
+        length = self.decimal()
+        self.eat("_")
+        text = self.symbol[self.position:self.position + length]
+        if len(text) != length:
+            raise ValueError("identifier out of the symbol")
+        self.position += length
+        if is_punycode:
+            # The delimiter of the basic characters is `_` instead of `-`
+            delimiter = text.rfind("_")
+            if delimiter >= 0:
+                text = text[:delimiter] + "-" + text[delimiter + 1:]
+            text = text.encode("ascii").decode("punycode")
+        return text
+
+    def backref(self, parse):
+        target = self.base62()
+        saved = self.position
+        self.position = target
+        result = parse()
+        self.position = saved
+        return result
+
+    def path(self, in_value):
+        tag = self.next()
+        if tag == "C":
+            return self.identifier()
+        if tag == "M":
+            self.disambiguator()
+            self.path(in_value=False)
+            return "<" + self.type() + ">"
+        if tag == "X":
+            self.disambiguator()
+            self.path(in_value=False)
+            implementing = self.type()
+            return "<" + implementing + " as " + self.path(in_value=False) + ">"
+        if tag == "Y":
+            implementing = self.type()
+            return "<" + implementing + " as " + self.path(in_value=False) + ">"
+        if tag == "N":
+            namespace = self.next()
+            parent = self.path(in_value)
+            disambiguator = self.disambiguator()
+            name = self.undisambiguated_identifier()
+            if namespace.islower():
+                return parent + "::" + name if name else parent
+            kind = {"C": "closure", "S": "shim"}.get(namespace, namespace)
+            return parent + "::{" + kind + (":" + name if name else "") + "#" + str(disambiguator) + "}"
+        if tag == "I":
+            base = self.path(in_value)
+            arguments = []
+            while not self.eat("E"):
+                argument = self.generic_argument()
+                if argument is not None:
+                    arguments.append(argument)
+            # The arguments of the functions are left out like in the legacy mangling, they'd double the table
+            if not arguments or in_value:
+                return base
+            return base + "<" + ", ".join(arguments) + ">"
+        if tag == "B":
+            return self.backref(lambda: self.path(in_value))
+        raise ValueError("unknown path")
+
+    def generic_argument(self):
+        if self.eat("L"):
+            self.base62()
+            return None
+        if self.eat("K"):
+            return self.const()
+        return self.type()
+
+    def type(self):
+        tag = self.peek()
+        if tag in BASIC_TYPES:
+            self.position += 1
+            return BASIC_TYPES[tag]
+        if tag in "CMXYNI":
+            return self.path(in_value=False)
+        self.position += 1
+        if tag == "A":
+            element = self.type()
+            return "[" + element + "; " + self.const() + "]"
+        if tag == "S":
+            return "[" + self.type() + "]"
+        if tag == "T":
+            elements = []
+            while not self.eat("E"):
+                elements.append(self.type())
+            return "(" + ", ".join(elements) + ("," if len(elements) == 1 else "") + ")"
+        if tag in "RQ":
+            if self.eat("L"):
+                self.base62()
+            return ("&" if tag == "R" else "&mut ") + self.type()
+        if tag in "PO":
+            return ("*const " if tag == "P" else "*mut ") + self.type()
+        if tag == "F":
+            return self.function_signature()
+        if tag == "D":
+            bounds = self.dyn_bounds()
+            self.eat("L") and self.base62()
+            return "dyn " + bounds
+        if tag == "B":
+            return self.backref(self.type)
+        raise ValueError("unknown type")
+
+    def function_signature(self):
+        if self.eat("G"):
+            self.base62()
+        prefix = "unsafe " if self.eat("U") else ""
+        if self.eat("K"):
+            abi = "C" if self.eat("C") else self.undisambiguated_identifier().replace("_", "-")
+            prefix += 'extern "' + abi + '" '
+        parameters = []
+        while not self.eat("E"):
+            parameters.append(self.type())
+        result = self.type()
+        return prefix + "fn(" + ", ".join(parameters) + ")" + ("" if result == "()" else " -> " + result)
+
+    def dyn_bounds(self):
+        if self.eat("G"):
+            self.base62()
+        traits = []
+        while not self.eat("E"):
+            trait = self.path(in_value=False)
+            bindings = []
+            while self.eat("p"):
+                name = self.undisambiguated_identifier()
+                bindings.append(name + " = " + self.type())
+            if bindings:
+                trait = (trait[:-1] + ", " if trait.endswith(">") else trait + "<") + ", ".join(bindings) + ">"
+            traits.append(trait)
+        return " + ".join(traits)
+
+    def const(self):
+        if self.eat("p"):
+            return "_"
+        if self.peek() == "B":
+            self.position += 1
+            return self.backref(self.const)
+        kind = self.next()
+        if kind not in "abcfhijlmnostxy":
+            raise ValueError("unsupported constant")
+        negative = self.eat("n")
+        start = self.position
+        while not self.eat("_"):
+            self.next()
+        value = int(self.symbol[start:self.position - 1] or "0", 16)
+        if kind == "b":
+            return "true" if value else "false"
+        if kind == "c":
+            return repr(chr(value))
+        return str(-value if negative else value)
+
+
+def read_functions(elf, sections):
+    functions = {}
+    for symbols in (section for section in sections if section["type"] == SHT_SYMTAB):
+        names = sections[symbols["link"]]
+        for offset in range(symbols["offset"], symbols["offset"] + symbols["size"], 24):
+            name, info, _, section_index, address, size = struct.unpack_from("<IBBHQQ", elf, offset)
+            if info & 0xF != STT_FUNC or address == 0 or section_index == 0:
+                continue
+            functions.setdefault(address, (size, demangle(read_string(elf, names["offset"] + name))))
+    return sorted((address, size, name) for address, (size, name) in functions.items())
+
+
+def build_table(functions):
+    entries, names = bytearray(), bytearray()
+    for address, size, name in functions:
+        encoded = name.encode("utf-8")[:MAX_NAME_LENGTH].decode("utf-8", "ignore").encode("utf-8")
+        entries += struct.pack("<QII", address, min(size, 0xFFFF_FFFF), len(names))
+        names += bytes([len(encoded)]) + encoded
+    return MAGIC + struct.pack("<II", len(functions), 0) + entries + names
+
+
+def embed_symbols(path):
+    with open(path, "rb") as file:
+        elf = bytearray(file.read())
+    sections = read_sections(elf)
+    section = next((section for section in sections if section["name"] == SECTION_NAME), None)
+    if section is None:
+        raise ValueError(f"no {SECTION_NAME} section in {path}")
+    table = build_table(read_functions(elf, sections))
+    if len(table) > section["size"]:
+        raise ValueError(f"the symbol table takes {len(table)} bytes, but {SECTION_NAME} has only {section['size']}, "
+                         f"increase SYMBOL_TABLE_SIZE")
+    elf[section["offset"]:section["offset"] + section["size"]] = table.ljust(section["size"], b"\0")
+    with open(path, "wb") as file:
+        file.write(elf)
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 2:
+        sys.exit(f"Usage: {sys.argv[0]} <kernel>")
+    try:
+        embed_symbols(sys.argv[1])
+    except ValueError as error:
+        sys.exit(f"embed_symbols: {error}")
@@ -0,0 +1,5 @@
+#!/bin/sh
+# Cargo runner: embeds the symbol table into the kernel and starts it with bootimage
+set -e
+python3 "$(dirname "$0")/embed_symbols.py" "$1"
+exec bootimage runner "$@"